@@ -1,28 +1,82 @@
+mod adaptive;
+mod admit;
+mod dry_run;
+mod fetch;
+mod halt;
+mod history;
+mod job_log;
 mod metrics;
 mod path_cache;
+mod placeholder_cmd;
+mod resume;
+mod retry;
+mod schedule;
+mod script;
+mod skip;
+mod slot;
+mod speculative;
+mod statsd;
+mod tmpdir;
+mod trace;
 
 use anyhow::Context;
 
 use tokio::sync::Semaphore;
 
-use tracing::{debug, error, info, instrument, span_enabled, trace, Level, Span};
+use tokio_util::sync::CancellationToken;
 
-use std::sync::Arc;
+use tracing::{debug, error, info, instrument, span_enabled, trace, warn, Level, Span};
+
+use std::{
+    borrow::Cow,
+    process::Output,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    command_line_args::CommandLineArgs,
-    common::OwnedCommandAndArgs,
+    command_line_args::{CommandLineArgs, DeadlineFrom},
+    common::{JobOptions, OwnedCommandAndArgs},
     input::{InputLineNumber, InputMessage, InputProducer},
-    output::{OutputSender, OutputWriter},
-    process::ChildProcessFactory,
+    output::{CommandOutput, CommandStatus, OutputSender, OutputStream, OutputWriter},
+    process::{
+        audit::ChildAuditor, exit_signal, ChildProcess, ChildProcessExecutionError,
+        ChildProcessFactory,
+    },
     progress::Progress,
 };
 
-use self::{metrics::CommandMetrics, path_cache::CommandPathCache};
+use self::{
+    adaptive::AdaptiveJobs,
+    admit::{Admission, AdmitCommand, AdmitContext},
+    dry_run::DryRun,
+    fetch::Fetcher,
+    halt::HaltPolicy,
+    history::JobHistory,
+    job_log::JobLog,
+    metrics::CommandMetrics,
+    path_cache::CommandPathCache,
+    placeholder_cmd::PlaceholderCommands,
+    resume::Resume,
+    retry::RetryPolicy,
+    schedule::Scheduler,
+    script::{JobScript, ScriptResult},
+    skip::SkipIfExists,
+    slot::SlotPool,
+    speculative::Speculator,
+    statsd::StatsdClient,
+    tmpdir::JobTmpDirs,
+    trace::TraceRecorder,
+};
 
 #[derive(Debug)]
 struct Command {
+    /// Position of the input in the order inputs were read.
+    input_sequence: u64,
+    /// When the input was read and queued.
+    enqueued_at: Instant,
     command_and_args: OwnedCommandAndArgs,
+    job_options: JobOptions,
     input_line_number: InputLineNumber,
 }
 
@@ -37,53 +91,290 @@ impl Command {
             child_pid,
         ),
         level = "debug")]
-    async fn run(self, context: &CommandRunContext, output_sender: OutputSender) {
+    async fn run(
+        self,
+        context: &CommandRunContext,
+        output_sender: OutputSender,
+        slot: usize,
+    ) -> CommandStatus {
         debug!("begin run");
 
         let command_metrics = &context.command_metrics;
 
-        let OwnedCommandAndArgs { command_path, args } = &self.command_and_args;
-
         command_metrics.increment_commands_run();
+        context.statsd_client.command_started();
+
+        let start_time = Instant::now();
+
+        let queue_wait = start_time.saturating_duration_since(self.enqueued_at);
+        context.progress.record_queue_wait(queue_wait);
+
+        let result = match &context.fetcher {
+            Some(fetcher) => Ok(fetcher
+                .fetch(
+                    &self.command_and_args,
+                    &self.job_options,
+                    &context.cancellation_token,
+                )
+                .await),
+            None => {
+                self.spawn_and_await_with_retries(
+                    context,
+                    start_time,
+                    output_sender.output_stream(),
+                )
+                .await
+            }
+        };
 
-        let child_process = match context
-            .child_process_factory
-            .spawn(command_path, args)
-            .await
-        {
+        let result = match result {
             Err(e) => {
                 error!("spawn error command: {}: {}", self, e);
                 command_metrics.increment_spawn_errors();
-                return;
+
+                let command_output =
+                    CommandOutput::without_output(CommandStatus::SpawnError, start_time.elapsed());
+                context
+                    .statsd_client
+                    .command_finished(CommandStatus::SpawnError, command_output.duration);
+                context
+                    .trace_recorder
+                    .record(
+                        &self,
+                        slot,
+                        start_time,
+                        command_output.duration,
+                        CommandStatus::SpawnError,
+                    )
+                    .await;
+                if let Some(job_log) = &context.job_log {
+                    job_log
+                        .record(
+                            &self,
+                            queue_wait,
+                            command_output.duration,
+                            CommandStatus::SpawnError,
+                        )
+                        .await;
+                }
+                output_sender
+                    .send(
+                        command_output,
+                        self.command_and_args,
+                        self.input_line_number,
+                    )
+                    .await;
+                return CommandStatus::SpawnError;
             }
-            Ok(child_process) => child_process,
+            Ok(result) => result,
         };
 
-        if span_enabled!(Level::DEBUG) {
-            let child_pid = child_process.id();
-            Span::current().record("child_pid", child_pid);
+        let duration = start_time.elapsed();
+        command_metrics.record_duration(duration);
 
-            debug!("spawned child process, awaiting completion");
-        }
+        let command_output = match result {
+            Err(ChildProcessExecutionError::Timeout(e)) => {
+                debug!("command timeout: {} duration = {:?}", e, duration);
+                command_metrics.increment_timeouts();
 
-        match child_process.await_completion().await {
-            Err(e) => {
+                CommandOutput::without_output(CommandStatus::Timeout, duration)
+            }
+            Err(e @ ChildProcessExecutionError::DescendantLimit { .. }) => {
+                error!("killed command: {} error: {}", self, e);
+                command_metrics.increment_descendant_limit_kills();
+
+                CommandOutput::without_output(CommandStatus::DescendantLimit, duration)
+            }
+            Err(ChildProcessExecutionError::Cancelled) => {
+                warn!("killed command due to cancellation: {}", self);
+
+                CommandOutput::without_output(CommandStatus::Cancelled, duration)
+            }
+            Err(ChildProcessExecutionError::IOError(e)) => {
                 error!("child process error command: {} error: {}", self, e);
-                command_metrics.handle_child_process_execution_error(e);
+                command_metrics.increment_io_errors();
+
+                CommandOutput::without_output(CommandStatus::IOError, duration)
             }
             Ok(output) => {
-                debug!("command exit status = {}", output.status);
-                if !output.status.success() {
+                debug!(
+                    "command exit status = {} duration = {:?}",
+                    output.status, duration
+                );
+                if exit_signal(&output.status).is_some() {
+                    command_metrics.increment_signal_kills();
+                } else if !output.status.success() {
                     command_metrics.increment_exit_status_errors();
                 }
 
-                output_sender
-                    .send(output, self.command_and_args, self.input_line_number)
-                    .await;
+                CommandOutput::new(output, duration)
             }
         };
 
+        let status = command_output.status;
+        context.statsd_client.command_finished(status, duration);
+        context
+            .trace_recorder
+            .record(&self, slot, start_time, duration, status)
+            .await;
+        if let Some(job_log) = &context.job_log {
+            job_log.record(&self, queue_wait, duration, status).await;
+        }
+
+        output_sender
+            .send(
+                command_output,
+                self.command_and_args,
+                self.input_line_number,
+            )
+            .await;
+
         debug!("end run");
+
+        status
+    }
+
+    /// Spawn and wait for the command again after failures and timeouts with --retries.
+    ///
+    /// Only the result of the last attempt is returned.
+    async fn spawn_and_await_with_retries(
+        &self,
+        context: &CommandRunContext,
+        start_time: Instant,
+        output_stream: Option<OutputStream>,
+    ) -> std::io::Result<Result<Output, ChildProcessExecutionError>> {
+        let Some(retry_policy) = &context.retry_policy else {
+            return self
+                .spawn_and_await(context, start_time, output_stream)
+                .await;
+        };
+
+        let mut attempt_start_time = start_time;
+        let mut attempt = 0;
+
+        loop {
+            let result = self
+                .spawn_and_await(context, attempt_start_time, None)
+                .await?;
+            attempt += 1;
+
+            let Some(reason) = RetryPolicy::retry_reason(&result) else {
+                return Ok(result);
+            };
+
+            if !retry_policy
+                .wait_for_retry(attempt, &reason, self, &context.cancellation_token)
+                .await
+            {
+                return Ok(result);
+            }
+
+            attempt_start_time = Instant::now();
+        }
+    }
+
+    /// Job options for an attempt, with --deadline-from=enqueue the timeout is reduced by the
+    /// time since the input was queued.
+    fn attempt_job_options<'a>(&'a self, context: &CommandRunContext) -> Cow<'a, JobOptions> {
+        let timeout = match context.deadline_from {
+            DeadlineFrom::Spawn => None,
+            DeadlineFrom::Enqueue => self.job_options.timeout.or(context.timeout),
+        };
+
+        match timeout {
+            None => Cow::Borrowed(&self.job_options),
+            Some(timeout) => {
+                let mut job_options = self.job_options.clone();
+                job_options.timeout = Some(timeout.saturating_sub(self.enqueued_at.elapsed()));
+                Cow::Owned(job_options)
+            }
+        }
+    }
+
+    /// Spawn the child process and wait for it, the outer error is a spawn error.
+    ///
+    /// With --stream-output the output is forwarded to output_stream while the command runs.
+    async fn spawn_and_await(
+        &self,
+        context: &CommandRunContext,
+        start_time: Instant,
+        output_stream: Option<OutputStream>,
+    ) -> std::io::Result<Result<Output, ChildProcessExecutionError>> {
+        let OwnedCommandAndArgs { command_path, args } = &self.command_and_args;
+
+        let job_options = self.attempt_job_options(context);
+
+        let mut child_process = context
+            .child_process_factory
+            .spawn(command_path, args, &job_options)
+            .await?;
+
+        if let Some(output_stream) = output_stream {
+            child_process.stream_output(output_stream);
+        }
+
+        if span_enabled!(Level::DEBUG) {
+            let child_pid = child_process.id();
+            Span::current().record("child_pid", child_pid);
+
+            debug!("spawned child process, awaiting completion");
+        }
+
+        let result = match &context.speculator {
+            Some(speculator) => {
+                self.await_speculative(context, speculator, child_process, start_time)
+                    .await
+            }
+            None => child_process.await_completion().await,
+        };
+
+        Ok(result)
+    }
+
+    /// Wait for the command, racing it against a second copy if it becomes a straggler.
+    async fn await_speculative(
+        &self,
+        context: &CommandRunContext,
+        speculator: &Speculator,
+        child_process: ChildProcess,
+        start_time: Instant,
+    ) -> Result<Output, ChildProcessExecutionError> {
+        let first = child_process.await_completion();
+        tokio::pin!(first);
+
+        tokio::select! {
+            result = &mut first => return result,
+
+            _ = speculator.straggler(start_time) => {}
+        }
+
+        let OwnedCommandAndArgs { command_path, args } = &self.command_and_args;
+
+        let copy = match context
+            .child_process_factory
+            .spawn(command_path, args, &self.attempt_job_options(context))
+            .await
+        {
+            Ok(copy) => copy,
+            Err(e) => {
+                warn!("speculative copy spawn error command: {}: {}", self, e);
+                return first.await;
+            }
+        };
+
+        info!("speculatively re-running straggler command: {}", self);
+        speculator.copy_started();
+
+        // the losing child process is killed on drop
+        tokio::select! {
+            result = &mut first => result,
+
+            result = copy.await_completion() => {
+                debug!("speculative copy finished first");
+                speculator.copy_won();
+                result
+            }
+        }
     }
 }
 
@@ -100,73 +391,265 @@ impl std::fmt::Display for Command {
 pub struct CommandService {
     command_line_args: &'static CommandLineArgs,
     command_path_cache: CommandPathCache,
+    child_auditor: ChildAuditor,
+    dry_run: Option<DryRun>,
     command_semaphore: Arc<Semaphore>,
+    slot_pool: Arc<SlotPool>,
     context: Arc<CommandRunContext>,
     output_writer: OutputWriter,
+    resume: Option<Resume>,
+    skip_if_exists: SkipIfExists,
+    job_script: JobScript,
+    placeholder_commands: PlaceholderCommands,
+    job_tmpdirs: JobTmpDirs,
+    admit_command: AdmitCommand,
 }
 
 impl CommandService {
-    pub fn new(command_line_args: &'static CommandLineArgs, progress: Arc<Progress>) -> Self {
+    pub fn new(command_line_args: &'static CommandLineArgs) -> anyhow::Result<Self> {
+        // read before the joblog of the previous run is appended to
+        let resume = Resume::new(command_line_args)?;
+        let progress = Progress::new(
+            command_line_args,
+            resume.as_ref().map_or(0, Resume::completed),
+        )?;
+        let cancellation_token = CancellationToken::new();
+        let output_writer = OutputWriter::new(command_line_args, &cancellation_token)?;
+        let command_semaphore = Arc::new(Semaphore::new(command_line_args.jobs));
         let context = Arc::new(CommandRunContext {
-            child_process_factory: ChildProcessFactory::new(command_line_args),
+            child_process_factory: ChildProcessFactory::new(command_line_args)?,
             command_metrics: CommandMetrics::default(),
+            statsd_client: StatsdClient::new(command_line_args)?,
+            trace_recorder: TraceRecorder::new(command_line_args),
+            job_log: JobLog::new(command_line_args)?,
+            retry_policy: RetryPolicy::new(command_line_args),
+            halt_policy: HaltPolicy::new(command_line_args),
+            timeout: command_line_args
+                .timeout_seconds
+                .map(Duration::from_secs_f64),
+            deadline_from: command_line_args.deadline_from,
             progress,
+            adaptive_jobs: AdaptiveJobs::new(command_line_args, &command_semaphore),
+            job_history: JobHistory::new(command_line_args)?,
+            speculator: Speculator::new(command_line_args),
+            fetcher: Fetcher::new(command_line_args)?,
+            cancellation_token,
         });
-        Self {
+        Ok(Self {
             command_line_args,
             command_path_cache: CommandPathCache::new(command_line_args),
-            command_semaphore: Arc::new(Semaphore::new(command_line_args.jobs)),
+            child_auditor: ChildAuditor::new(command_line_args),
+            dry_run: if command_line_args.dry_run || command_line_args.explain {
+                Some(DryRun::new(command_line_args)?)
+            } else {
+                None
+            },
+            command_semaphore,
+            slot_pool: SlotPool::new(command_line_args.jobs),
             context,
-            output_writer: OutputWriter::new(command_line_args),
-        }
+            output_writer,
+            resume,
+            skip_if_exists: SkipIfExists::new(command_line_args)?,
+            job_script: JobScript::new(command_line_args)?,
+            placeholder_commands: PlaceholderCommands::new(command_line_args)?,
+            job_tmpdirs: JobTmpDirs::new(command_line_args),
+            admit_command: AdmitCommand::new(command_line_args),
+        })
     }
 
     async fn spawn_command(
         &self,
-        command_and_args: OwnedCommandAndArgs,
-        input_line_number: InputLineNumber,
+        mut command: Command,
+        input_data: String,
+        history_key: Option<String>,
+        input_output_sender: Option<OutputSender>,
     ) -> anyhow::Result<()> {
-        let command = Command {
-            command_and_args,
-            input_line_number,
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.report(&command, &input_data);
+            return Ok(());
+        }
+
+        let cancellation_token = &self.context.cancellation_token;
+
+        let permit = tokio::select! {
+            biased;
+
+            _ = cancellation_token.cancelled() => {
+                trace!("return from spawn_command due to cancellation");
+                return Ok(());
+            }
+
+            result = Arc::clone(&self.command_semaphore).acquire_owned() => {
+                result.context("command_semaphore.acquire_owned error")?
+            }
         };
 
-        if self.command_line_args.dry_run {
-            info!("{}", command);
-            return Ok(());
+        let slot = self.slot_pool.acquire();
+
+        let admit_context = AdmitContext {
+            command: command.command_and_args.to_string(),
+            input_data: &input_data,
+            line: command.input_line_number.to_string(),
+            slot: slot.number(),
+        };
+
+        match self
+            .admit_command
+            .admit(admit_context, cancellation_token)
+            .await
+        {
+            Admission::Run => {}
+            Admission::Skip => {
+                info!("skipping command not admitted by --admit-cmd: {}", command);
+                self.context
+                    .command_metrics
+                    .increment_skipped_by_admit_cmd();
+                self.context.progress.command_skipped(&input_data);
+                return Ok(());
+            }
+            Admission::Cancelled => return Ok(()),
         }
 
-        if self.command_line_args.exit_on_error && self.context.command_metrics.error_occurred() {
-            trace!("return from spawn_command due to exit_on_error");
-            return Ok(());
+        let job_tmpdir = match self.job_tmpdirs.create().await {
+            Ok(job_tmpdir) => job_tmpdir,
+            Err(e) => {
+                error!("job tmpdir error command: {}: {:#}", command, e);
+                self.context.command_metrics.increment_spawn_errors();
+                self.context.progress.command_skipped(&input_data);
+                return Ok(());
+            }
+        };
+
+        if let Some(job_tmpdir) = &job_tmpdir {
+            (command.command_and_args, command.job_options) =
+                job_tmpdir.apply(command.command_and_args, command.job_options);
         }
 
         let context_clone = Arc::clone(&self.context);
 
-        let output_sender = self.output_writer.sender();
+        let output_sender =
+            self.output_writer
+                .sender(input_output_sender, command.input_sequence, &input_data);
 
-        let permit = Arc::clone(&self.command_semaphore)
-            .acquire_owned()
-            .await
-            .context("command_semaphore.acquire_owned error")?;
+        context_clone.progress.command_started();
+
+        if let Some(speculator) = &context_clone.speculator {
+            speculator.command_started();
+        }
 
         tokio::spawn(async move {
-            command.run(&context_clone, output_sender).await;
+            let start_time = Instant::now();
+
+            let status = command
+                .run(&context_clone, output_sender, slot.number())
+                .await;
+
+            if let Some(halt_policy) = &context_clone.halt_policy {
+                halt_policy.check(
+                    context_clone.command_metrics.total_failures(),
+                    &context_clone.cancellation_token,
+                    &context_clone.child_process_factory,
+                );
+            }
 
-            drop(permit);
+            if let Some(job_tmpdir) = job_tmpdir {
+                job_tmpdir.cleanup(status.success()).await;
+            }
 
-            context_clone.progress.command_finished();
+            if let (Some(job_history), Some(history_key)) =
+                (&context_clone.job_history, history_key)
+            {
+                if status.success() {
+                    job_history.record(history_key, start_time.elapsed());
+                }
+            }
+
+            if let Some(speculator) = &context_clone.speculator {
+                speculator.command_finished(start_time.elapsed());
+            }
+
+            drop(slot);
+            match &context_clone.adaptive_jobs {
+                Some(adaptive_jobs) => {
+                    adaptive_jobs.command_finished(status, start_time.elapsed(), permit)
+                }
+                None => drop(permit),
+            }
+
+            context_clone
+                .progress
+                .command_finished(&input_data, status.success());
         });
 
         Ok(())
     }
 
+    #[instrument(
+        name = "CommandService::process_input_message",
+        skip_all,
+        level = "debug"
+    )]
     async fn process_input_message(&self, input_message: InputMessage) -> anyhow::Result<()> {
         let InputMessage {
+            sequence,
             command_and_args,
+            job_options: input_job_options,
             input_line_number,
+            input_data,
+            enqueued_at,
         } = input_message;
 
+        self.context.command_metrics.increment_commands_generated();
+
+        // held until the command starts, output is skipped if the input is skipped before that
+        let input_output_sender = self.output_writer.input_sender(sequence);
+
+        let history_key = self
+            .context
+            .job_history
+            .as_ref()
+            .map(|job_history| job_history.key(&command_and_args, &input_data));
+
+        if let Some(resume) = self.resume.as_ref().filter(|resume| resume.skip(sequence)) {
+            if resume.is_completed(sequence) {
+                self.context.progress.command_resumed(&input_data);
+            } else {
+                self.context.progress.command_skipped(&input_data);
+            }
+            return Ok(());
+        }
+
+        if self.skip_if_exists.should_skip(&input_data).await {
+            self.context.command_metrics.increment_skipped_up_to_date();
+            self.context.progress.command_skipped(&input_data);
+            return Ok(());
+        }
+
+        let (command_and_args, job_options) =
+            match self.job_script.apply(command_and_args, &input_data) {
+                ScriptResult::Skip => {
+                    self.context.command_metrics.increment_skipped_by_script();
+                    self.context.progress.command_skipped(&input_data);
+                    return Ok(());
+                }
+                ScriptResult::Run {
+                    command_and_args,
+                    job_options,
+                } => (command_and_args, job_options),
+            };
+
+        // options from a --script result override the input's job options column
+        let job_options = input_job_options.merge(job_options);
+
+        let Some(command_and_args) = self
+            .placeholder_commands
+            .resolve(command_and_args, &input_data)
+            .await
+        else {
+            self.context.progress.command_skipped(&input_data);
+            return Ok(());
+        };
+
         let Some(command_and_args) = self
             .command_path_cache
             .resolve_command_path(command_and_args)
@@ -175,17 +658,125 @@ impl CommandService {
             return Ok(());
         };
 
-        self.spawn_command(command_and_args, input_line_number)
+        let command = Command {
+            input_sequence: sequence,
+            enqueued_at,
+            command_and_args,
+            job_options,
+            input_line_number,
+        };
+
+        self.spawn_command(command, input_data, history_key, input_output_sender)
             .await?;
 
         Ok(())
     }
 
+    /// Read all inputs into the scheduler before any command starts, returns the number read.
+    async fn queue_all_inputs(
+        &self,
+        input_producer: &mut InputProducer,
+        scheduler: &mut Scheduler,
+    ) -> u64 {
+        let mut count = 0;
+
+        loop {
+            let input_message = tokio::select! {
+                _ = self.context.cancellation_token.cancelled() => break,
+
+                input_message = input_producer.receiver().recv() => match input_message {
+                    Some(input_message) => input_message,
+                    None => break,
+                },
+            };
+
+            count += 1;
+            if let Some(expected_duration) = scheduler.push(input_message) {
+                self.context
+                    .progress
+                    .add_expected_duration(expected_duration);
+            }
+        }
+
+        count
+    }
+
     async fn process_inputs(&self) -> anyhow::Result<()> {
-        let mut input_producer =
-            InputProducer::new(self.command_line_args, &self.context.progress)?;
+        let cancellation_token = &self.context.cancellation_token;
+
+        let mut input_producer = InputProducer::new(
+            self.command_line_args,
+            &self.context.progress,
+            cancellation_token,
+        )?;
+
+        let mut scheduler = Scheduler::new(
+            self.command_line_args,
+            self.context.job_history.as_ref().map(Arc::clone),
+        )?;
+
+        let mut input_done = false;
+
+        let drain_token = self.spawn_drain_handler();
+
+        let halt_policy = self.context.halt_policy.as_ref();
+
+        if self.command_line_args.reads_all_inputs_first().is_some() {
+            let jobs = self
+                .queue_all_inputs(&mut input_producer, &mut scheduler)
+                .await;
+            if let Some(expect_jobs) = self.command_line_args.expect_jobs {
+                if !cancellation_token.is_cancelled() && !expect_jobs.contains(jobs) {
+                    anyhow::bail!(
+                        "--expect-jobs: expected {} commands, inputs produced {}",
+                        expect_jobs,
+                        jobs
+                    );
+                }
+            }
+            if let Some(halt_policy) = halt_policy {
+                halt_policy.set_total_jobs(jobs);
+            }
+            input_done = true;
+        }
+
+        while !(input_done && scheduler.is_empty()) {
+            let input_message = tokio::select! {
+                biased;
+
+                _ = cancellation_token.cancelled() => {
+                    debug!("process_inputs cancelled");
+                    break;
+                }
+
+                // queued and running commands finish, inputs read before the drain are queued
+                _ = drain_token.cancelled(), if !input_producer.is_stopped() => {
+                    info!("draining: no more inputs are read, waiting for queued and running commands");
+                    input_producer.stop();
+                    continue;
+                }
+
+                input_message = input_producer.receiver().recv(), if !input_done => match input_message {
+                    Some(input_message) if scheduler.queueing() => {
+                        if let Some(expected_duration) = scheduler.push(input_message) {
+                            self.context.progress.add_expected_duration(expected_duration);
+                        }
+                        continue;
+                    }
+                    Some(input_message) => input_message,
+                    None => {
+                        input_done = true;
+                        continue;
+                    }
+                },
+
+                // dispatch a queued input once a command can start
+                result = self.command_semaphore.acquire(), if !scheduler.is_empty() => {
+                    drop(result.context("command_semaphore.acquire error")?);
+                    scheduler.pop().unwrap()
+                }
+            };
 
-        while let Some(input_message) = input_producer.receiver().recv().await {
             self.process_input_message(input_message).await?;
         }
 
@@ -194,22 +785,153 @@ impl CommandService {
         Ok(())
     }
 
+    /// With --drain-on-quit, a token cancelled by SIGQUIT, or Ctrl-Break on windows.
+    fn spawn_drain_handler(&self) -> CancellationToken {
+        let drain_token = CancellationToken::new();
+
+        if !self.command_line_args.drain_on_quit {
+            return drain_token;
+        }
+
+        #[cfg(unix)]
+        let signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::quit());
+        #[cfg(windows)]
+        let signal = tokio::signal::windows::ctrl_break();
+
+        let mut signal = match signal {
+            Ok(signal) => signal,
+            Err(e) => {
+                warn!("error installing drain signal handler: {}", e);
+                return drain_token;
+            }
+        };
+
+        let cancellation_token = self.context.cancellation_token.clone();
+        let drain_token_clone = drain_token.clone();
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = cancellation_token.cancelled() => {}
+
+                _ = signal.recv() => {
+                    warn!("received drain signal");
+                    drain_token_clone.cancel();
+                }
+            }
+        });
+
+        drain_token
+    }
+
+    /// The first Ctrl-C cancels the run and kills running commands, a second Ctrl-C exits.
+    fn spawn_ctrl_c_handler(&self) {
+        let context = Arc::clone(&self.context);
+
+        tokio::spawn(async move {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                warn!("ctrl_c error: {}", e);
+                return;
+            }
+            warn!("received ctrl-c, cancelling and killing running commands");
+            context.cancellation_token.cancel();
+            context.child_process_factory.kill_running_commands();
+
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                warn!("ctrl_c error: {}", e);
+                return;
+            }
+            warn!("received second ctrl-c, exiting");
+            std::process::exit(130);
+        });
+    }
+
     #[instrument(name = "CommandService::run_commands", skip_all, level = "debug")]
     pub async fn run_commands(self) -> anyhow::Result<()> {
         debug!("begin run_commands");
 
-        self.process_inputs().await?;
+        self.spawn_ctrl_c_handler();
+
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.log_explanation();
+        }
+
+        if let Err(e) = self.process_inputs().await {
+            self.context.cancellation_token.cancel();
+            return Err(e);
+        }
+
+        if let Some(speculator) = &self.context.speculator {
+            speculator.all_commands_started();
+        }
 
         debug!("before output_writer.wait_for_completion",);
 
         self.output_writer.wait_for_completion().await?;
 
-        self.context.progress.finish();
+        self.context.progress.finish().await;
+
+        self.context.trace_recorder.write().await?;
+
+        if let Some(job_history) = &self.context.job_history {
+            job_history.save()?;
+        }
+
+        let surviving_process_groups = self.context.child_process_factory.sweep_process_groups();
+        if surviving_process_groups > 0 {
+            warn!(
+                "{} process groups still running after commands exited",
+                surviving_process_groups
+            );
+        }
+
+        if let Some(resume) = &self.resume {
+            resume.log_summary();
+        }
+
+        let skipped_up_to_date = self.context.command_metrics.skipped_up_to_date();
+        if skipped_up_to_date > 0 {
+            info!("skipped {} up to date commands", skipped_up_to_date);
+        }
+
+        let skipped_by_script = self.context.command_metrics.skipped_by_script();
+        if skipped_by_script > 0 {
+            info!("skipped {} commands by script", skipped_by_script);
+        }
+
+        let skipped_by_admit_cmd = self.context.command_metrics.skipped_by_admit_cmd();
+        if skipped_by_admit_cmd > 0 {
+            info!("skipped {} commands by --admit-cmd", skipped_by_admit_cmd);
+        }
+
+        if let Some(summary) = self
+            .context
+            .speculator
+            .as_ref()
+            .and_then(Speculator::summary)
+        {
+            info!("{}", summary);
+        }
+
+        let leaked_child_processes = self.child_auditor.audit();
 
         if self.context.command_metrics.error_occurred() {
             anyhow::bail!("command failures: {}", self.context.command_metrics);
         }
 
+        if leaked_child_processes > 0 {
+            anyhow::bail!("{} leaked child processes", leaked_child_processes);
+        }
+
+        if self.context.cancellation_token.is_cancelled() {
+            anyhow::bail!("cancelled: {}", self.context.command_metrics);
+        }
+
+        if self.command_line_args.fail_if_empty
+            && self.context.command_metrics.commands_generated() == 0
+        {
+            anyhow::bail!("no commands were generated from the inputs");
+        }
+
         debug!(
             "end run_commands command_metrics = {}",
             self.context.command_metrics
@@ -222,5 +944,17 @@ impl CommandService {
 struct CommandRunContext {
     child_process_factory: ChildProcessFactory,
     command_metrics: CommandMetrics,
+    statsd_client: StatsdClient,
+    trace_recorder: TraceRecorder,
+    job_log: Option<JobLog>,
+    retry_policy: Option<RetryPolicy>,
+    halt_policy: Option<HaltPolicy>,
+    timeout: Option<Duration>,
+    deadline_from: DeadlineFrom,
     progress: Arc<Progress>,
+    adaptive_jobs: Option<AdaptiveJobs>,
+    job_history: Option<Arc<JobHistory>>,
+    speculator: Option<Speculator>,
+    fetcher: Option<Fetcher>,
+    cancellation_token: CancellationToken,
 }
@@ -0,0 +1,290 @@
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use tracing::info;
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{command_line_args::CommandLineArgs, output::CommandStatus};
+
+const LATENCY_WINDOW: usize = 20;
+
+const LATENCY_DEGRADED_FACTOR: u32 = 2;
+
+#[derive(Debug, Eq, PartialEq)]
+enum Adjustment {
+    None,
+    Increase,
+    Decrease(&'static str),
+}
+
+#[derive(Debug)]
+struct State {
+    max_limit: usize,
+    limit: usize,
+    permits_to_forget: usize,
+    healthy_completions: usize,
+    completions_since_decrease: usize,
+    durations: VecDeque<Duration>,
+    baseline_p95: Option<Duration>,
+}
+
+impl State {
+    fn new(max_limit: usize) -> Self {
+        Self {
+            max_limit,
+            limit: max_limit,
+            permits_to_forget: 0,
+            healthy_completions: 0,
+            completions_since_decrease: max_limit,
+            durations: VecDeque::with_capacity(LATENCY_WINDOW),
+            baseline_p95: None,
+        }
+    }
+
+    /// p95 of the last LATENCY_WINDOW durations, None until the window is full.
+    fn record_duration(&mut self, duration: Duration) -> Option<Duration> {
+        if self.durations.len() == LATENCY_WINDOW {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(duration);
+
+        if self.durations.len() < LATENCY_WINDOW {
+            return None;
+        }
+
+        let mut sorted: Vec<_> = self.durations.iter().copied().collect();
+        sorted.sort();
+
+        Some(sorted[(sorted.len() * 95).div_ceil(100) - 1])
+    }
+
+    fn latency_degraded(&mut self, duration: Duration) -> bool {
+        let Some(p95) = self.record_duration(duration) else {
+            return false;
+        };
+
+        let baseline_p95 = self
+            .baseline_p95
+            .map_or(p95, |baseline_p95| baseline_p95.min(p95));
+        self.baseline_p95 = Some(baseline_p95);
+
+        p95 > baseline_p95 * LATENCY_DEGRADED_FACTOR
+    }
+
+    /// Additive increase after limit healthy completions, multiplicative decrease on trouble.
+    ///
+    /// The limit is decreased at most once per limit completions, so one burst of failures from
+    /// commands already running only halves it once.
+    fn record(&mut self, failed: bool, duration: Duration) -> Adjustment {
+        self.completions_since_decrease += 1;
+
+        let reason = if failed {
+            Some("command failed")
+        } else if self.latency_degraded(duration) {
+            Some("p95 latency degraded")
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => {
+                self.healthy_completions = 0;
+
+                if self.limit == 1 || self.completions_since_decrease < self.limit {
+                    return Adjustment::None;
+                }
+
+                let new_limit = (self.limit / 2).max(1);
+                self.permits_to_forget += self.limit - new_limit;
+                self.limit = new_limit;
+                self.completions_since_decrease = 0;
+                self.durations.clear();
+
+                Adjustment::Decrease(reason)
+            }
+            None => {
+                self.healthy_completions += 1;
+
+                if self.limit == self.max_limit || self.healthy_completions < self.limit {
+                    return Adjustment::None;
+                }
+
+                self.limit += 1;
+                self.healthy_completions = 0;
+
+                Adjustment::Increase
+            }
+        }
+    }
+}
+
+/// Adjusts the number of running commands with --adaptive-jobs, between 1 and --jobs.
+pub struct AdaptiveJobs {
+    command_semaphore: Arc<Semaphore>,
+    state: Mutex<State>,
+}
+
+impl AdaptiveJobs {
+    pub fn new(
+        command_line_args: &CommandLineArgs,
+        command_semaphore: &Arc<Semaphore>,
+    ) -> Option<Self> {
+        if !command_line_args.adaptive_jobs {
+            return None;
+        }
+
+        Some(Self {
+            command_semaphore: Arc::clone(command_semaphore),
+            state: Mutex::new(State::new(command_line_args.jobs)),
+        })
+    }
+
+    /// Record a finished command and release its permit, forgetting it when lowering the limit.
+    pub fn command_finished(
+        &self,
+        status: CommandStatus,
+        duration: Duration,
+        permit: OwnedSemaphorePermit,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        if !matches!(status, CommandStatus::Cancelled) {
+            let old_limit = state.limit;
+
+            match state.record(!status.success(), duration) {
+                Adjustment::None => {}
+                Adjustment::Increase => {
+                    info!(
+                        "adaptive jobs: raising concurrency from {} to {}",
+                        old_limit, state.limit
+                    );
+                    if state.permits_to_forget > 0 {
+                        state.permits_to_forget -= 1;
+                    } else {
+                        self.command_semaphore.add_permits(1);
+                    }
+                }
+                Adjustment::Decrease(reason) => {
+                    info!(
+                        "adaptive jobs: lowering concurrency from {} to {}: {}",
+                        old_limit, state.limit, reason
+                    );
+                }
+            }
+        }
+
+        if state.permits_to_forget > 0 {
+            state.permits_to_forget -= 1;
+            permit.forget();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FAST: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn test_decrease_on_failure() {
+        let mut state = State::new(8);
+
+        assert_eq!(
+            state.record(true, FAST),
+            Adjustment::Decrease("command failed")
+        );
+        assert_eq!(state.limit, 4);
+        assert_eq!(state.permits_to_forget, 4);
+
+        // failures of commands already running do not decrease again
+        for _ in 0..3 {
+            assert_eq!(state.record(true, FAST), Adjustment::None);
+        }
+        assert_eq!(
+            state.record(true, FAST),
+            Adjustment::Decrease("command failed")
+        );
+        assert_eq!(state.limit, 2);
+
+        assert_eq!(state.record(true, FAST), Adjustment::None);
+        assert_eq!(
+            state.record(true, FAST),
+            Adjustment::Decrease("command failed")
+        );
+        assert_eq!(state.limit, 1);
+
+        assert_eq!(state.record(true, FAST), Adjustment::None);
+        assert_eq!(state.limit, 1);
+    }
+
+    #[test]
+    fn test_increase_when_healthy() {
+        let mut state = State::new(3);
+        state.record(true, FAST);
+        assert_eq!(state.limit, 1);
+
+        assert_eq!(state.record(false, FAST), Adjustment::Increase);
+        assert_eq!(state.limit, 2);
+
+        assert_eq!(state.record(false, FAST), Adjustment::None);
+        assert_eq!(state.record(false, FAST), Adjustment::Increase);
+        assert_eq!(state.limit, 3);
+
+        for _ in 0..10 {
+            assert_eq!(state.record(false, FAST), Adjustment::None);
+        }
+        assert_eq!(state.limit, 3);
+    }
+
+    #[test]
+    fn test_decrease_on_latency() {
+        let mut state = State::new(4);
+
+        for _ in 0..LATENCY_WINDOW {
+            assert_eq!(state.record(false, FAST), Adjustment::None);
+        }
+
+        let adjustments: Vec<_> = (0..2).map(|_| state.record(false, FAST * 10)).collect();
+
+        assert_eq!(
+            adjustments,
+            vec![
+                Adjustment::None,
+                Adjustment::Decrease("p95 latency degraded")
+            ]
+        );
+        assert_eq!(state.limit, 2);
+    }
+
+    #[tokio::test]
+    async fn test_command_finished_forgets_permits() {
+        let command_line_args = CommandLineArgs {
+            adaptive_jobs: true,
+            jobs: 2,
+            ..Default::default()
+        };
+        let command_semaphore = Arc::new(Semaphore::new(2));
+
+        let adaptive_jobs = AdaptiveJobs::new(&command_line_args, &command_semaphore).unwrap();
+
+        let permit1 = Arc::clone(&command_semaphore)
+            .acquire_owned()
+            .await
+            .unwrap();
+        let permit2 = Arc::clone(&command_semaphore)
+            .acquire_owned()
+            .await
+            .unwrap();
+
+        adaptive_jobs.command_finished(CommandStatus::SpawnError, FAST, permit1);
+        assert_eq!(command_semaphore.available_permits(), 0);
+
+        adaptive_jobs.command_finished(CommandStatus::SpawnError, FAST, permit2);
+        assert_eq!(command_semaphore.available_permits(), 1);
+    }
+}
@@ -0,0 +1,177 @@
+use tokio::{process::Command, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+
+use tracing::{debug, warn};
+
+use std::process::Stdio;
+
+use crate::command_line_args::{CommandLineArgs, OnAdmitFail, ShellKind};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Admission {
+    Run,
+    Skip,
+    Cancelled,
+}
+
+/// Job context passed to the --admit-cmd in environment variables.
+pub struct AdmitContext<'a> {
+    pub command: String,
+    pub input_data: &'a str,
+    pub line: String,
+    pub slot: usize,
+}
+
+impl AdmitContext<'_> {
+    fn env(&self) -> [(&'static str, String); 4] {
+        [
+            ("PARALLEL_COMMAND", self.command.clone()),
+            ("PARALLEL_INPUT", self.input_data.to_owned()),
+            ("PARALLEL_LINE", self.line.clone()),
+            ("PARALLEL_SLOT", self.slot.to_string()),
+        ]
+    }
+}
+
+pub struct AdmitCommand {
+    command: Option<String>,
+    on_admit_fail: OnAdmitFail,
+    retry_interval: Duration,
+}
+
+impl AdmitCommand {
+    pub fn new(command_line_args: &CommandLineArgs) -> Self {
+        Self {
+            command: command_line_args.admit_cmd.clone(),
+            on_admit_fail: command_line_args.on_admit_fail,
+            retry_interval: Duration::from_secs_f64(command_line_args.admit_retry_seconds),
+        }
+    }
+
+    async fn run_command(command: &str, admit_context: &AdmitContext<'_>) -> bool {
+        let shell_kind = ShellKind::system();
+
+        let result = Command::new(shell_kind.default_path())
+            .args(shell_kind.default_arguments())
+            .arg(command)
+            .envs(admit_context.env())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .status()
+            .await;
+
+        match result {
+            Ok(exit_status) => {
+                debug!(
+                    "admit command for {} exit_status = {}",
+                    admit_context.command, exit_status
+                );
+                exit_status.success()
+            }
+            Err(e) => {
+                warn!("admit command {:?} error: {}", command, e);
+                false
+            }
+        }
+    }
+
+    /// Run the --admit-cmd until it admits the job, or it fails with --on-admit-fail=skip.
+    pub async fn admit(
+        &self,
+        admit_context: AdmitContext<'_>,
+        cancellation_token: &CancellationToken,
+    ) -> Admission {
+        let Some(command) = &self.command else {
+            return Admission::Run;
+        };
+
+        loop {
+            let admitted = tokio::select! {
+                biased;
+
+                _ = cancellation_token.cancelled() => return Admission::Cancelled,
+
+                admitted = Self::run_command(command, &admit_context) => admitted,
+            };
+
+            if admitted {
+                return Admission::Run;
+            }
+
+            if self.on_admit_fail == OnAdmitFail::Skip {
+                return Admission::Skip;
+            }
+
+            tokio::select! {
+                biased;
+
+                _ = cancellation_token.cancelled() => return Admission::Cancelled,
+
+                _ = tokio::time::sleep(self.retry_interval) => {},
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    fn admit_context(input_data: &str) -> AdmitContext<'_> {
+        AdmitContext {
+            command: "echo hello".to_owned(),
+            input_data,
+            line: "stdin:1".to_owned(),
+            slot: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admit() {
+        let admit_command = AdmitCommand::new(&CommandLineArgs {
+            admit_cmd: Some(r#"test "$PARALLEL_INPUT" = yes -a "$PARALLEL_SLOT" = 1"#.to_owned()),
+            on_admit_fail: OnAdmitFail::Skip,
+            admit_retry_seconds: 0.01,
+            ..Default::default()
+        });
+
+        let cancellation_token = CancellationToken::new();
+
+        assert_eq!(
+            admit_command
+                .admit(admit_context("yes"), &cancellation_token)
+                .await,
+            Admission::Run
+        );
+        assert_eq!(
+            admit_command
+                .admit(admit_context("no"), &cancellation_token)
+                .await,
+            Admission::Skip
+        );
+
+        let admit_command = AdmitCommand {
+            on_admit_fail: OnAdmitFail::Delay,
+            ..admit_command
+        };
+
+        cancellation_token.cancel();
+        assert_eq!(
+            admit_command
+                .admit(admit_context("no"), &cancellation_token)
+                .await,
+            Admission::Cancelled
+        );
+
+        let admit_command = AdmitCommand::new(&CommandLineArgs::default());
+        assert_eq!(
+            admit_command
+                .admit(admit_context("no"), &cancellation_token)
+                .await,
+            Admission::Run
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use itertools::Itertools;
+
+use tracing::info;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use crate::{command_line_args::CommandLineArgs, parser::regex::RegexProcessor};
+
+use super::Command;
+
+const EXPLAIN_SAMPLES: usize = 3;
+
+/// Logs each command for --dry-run along with the input and the placeholder values that produced it.
+///
+/// For --explain only the first few commands are logged, after the regex explanation.
+pub struct DryRun {
+    regex_processor: Arc<RegexProcessor>,
+    template: String,
+    explain: bool,
+    raw_exec: bool,
+    reported: AtomicUsize,
+}
+
+impl DryRun {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Self> {
+        let template = command_line_args.command_template();
+
+        Ok(Self {
+            regex_processor: RegexProcessor::new(command_line_args)?,
+            template,
+            explain: command_line_args.explain,
+            raw_exec: command_line_args.raw_exec,
+            reported: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn log_explanation(&self) {
+        if !self.explain {
+            return;
+        }
+
+        for line in self.regex_processor.explain() {
+            info!("explain: {}", line);
+        }
+        info!("explain: command template: {:?}", self.template);
+    }
+
+    fn format_substitutions(&self, input_data: &str) -> String {
+        self.regex_processor
+            .substitutions(&self.template, input_data)
+            .into_iter()
+            .map(|(match_key, value)| format!("{}={:?}", match_key, value))
+            .join(", ")
+    }
+
+    /// The exact argv of the command as a JSON array, for --raw-exec.
+    fn format_argv(command: &Command) -> String {
+        let argv: Vec<String> = std::iter::once(
+            command
+                .command_and_args
+                .command_path
+                .to_string_lossy()
+                .into_owned(),
+        )
+        .chain(command.command_and_args.args.iter().cloned())
+        .collect();
+
+        serde_json::Value::from(argv).to_string()
+    }
+
+    pub fn report(&self, command: &Command, input_data: &str) {
+        let reported = self.reported.fetch_add(1, Ordering::Relaxed);
+        if self.explain && reported >= EXPLAIN_SAMPLES {
+            return;
+        }
+
+        if self.raw_exec {
+            info!(
+                "argv={} line={} input={:?} substitutions=[{}]",
+                Self::format_argv(command),
+                command.input_line_number,
+                input_data,
+                self.format_substitutions(input_data)
+            );
+            return;
+        }
+
+        info!(
+            "{} input={:?} substitutions=[{}]",
+            command,
+            input_data,
+            self.format_substitutions(input_data)
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_substitutions() {
+        let command_line_args = CommandLineArgs {
+            regex: Some("(.*),(.*)".to_owned()),
+            command_and_initial_arguments: vec!["echo".to_owned(), "{2}".to_owned()],
+            ..Default::default()
+        };
+
+        let dry_run = DryRun::new(&command_line_args).unwrap();
+
+        assert_eq!(dry_run.template, "echo {2}");
+        assert_eq!(dry_run.format_substitutions("a,b"), r#"{2}="b""#);
+        assert_eq!(dry_run.format_substitutions("nomatch"), "");
+    }
+}
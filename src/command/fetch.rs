@@ -0,0 +1,242 @@
+use anyhow::Context;
+
+use tokio::{io::AsyncWriteExt, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+
+use tracing::{debug, warn};
+
+use std::{
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output},
+};
+
+use crate::{
+    command_line_args::CommandLineArgs,
+    common::{JobOptions, OwnedCommandAndArgs},
+    process::ChildProcessExecutionError,
+};
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+
+    ExitStatus::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+
+    ExitStatus::from_raw(code as u32)
+}
+
+/// Error from one fetch attempt.
+#[derive(thiserror::Error, Debug)]
+enum FetchError {
+    /// Connection errors, timeouts, 429 and 5xx responses are worth another attempt.
+    #[error("{0}")]
+    Retryable(String),
+
+    #[error("{0}")]
+    Permanent(String),
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_builder() || e.is_redirect() {
+            Self::Permanent(e.to_string())
+        } else {
+            Self::Retryable(e.to_string())
+        }
+    }
+}
+
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Downloads URLs with a built-in HTTP client for --fetch instead of spawning commands.
+pub struct Fetcher {
+    client: reqwest::Client,
+    retries: u32,
+    timeout: Option<Duration>,
+}
+
+impl Fetcher {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        if !command_line_args.fetch {
+            return Ok(None);
+        }
+
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("rust-parallel/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("error creating http client")?;
+
+        Ok(Some(Self {
+            client,
+            retries: command_line_args.fetch_retries,
+            timeout: command_line_args
+                .timeout_seconds
+                .map(Duration::from_secs_f64),
+        }))
+    }
+
+    /// Part file the body is written to before it is renamed to the output file.
+    fn part_path(output_file: &Path) -> PathBuf {
+        let mut part_path = output_file.as_os_str().to_owned();
+        part_path.push(".part");
+        PathBuf::from(part_path)
+    }
+
+    async fn write_body(
+        mut response: reqwest::Response,
+        output_file: &Path,
+    ) -> Result<(), FetchError> {
+        let part_path = Self::part_path(output_file);
+
+        let mut file = tokio::fs::File::create(&part_path)
+            .await
+            .map_err(|e| FetchError::Permanent(format!("error creating {:?}: {}", part_path, e)))?;
+
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await.map_err(|e| {
+                FetchError::Permanent(format!("error writing {:?}: {}", part_path, e))
+            })?;
+        }
+
+        file.flush()
+            .await
+            .and(tokio::fs::rename(&part_path, output_file).await)
+            .map_err(|e| FetchError::Permanent(format!("error writing {:?}: {}", output_file, e)))
+    }
+
+    /// Returns the response body, or an empty body if it was written to output_file.
+    async fn try_fetch(
+        &self,
+        url: &str,
+        output_file: Option<&Path>,
+    ) -> Result<Vec<u8>, FetchError> {
+        let response = self.client.get(url).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = format!("GET {} returned {}", url, status);
+            return Err(if is_retryable_status(status) {
+                FetchError::Retryable(message)
+            } else {
+                FetchError::Permanent(message)
+            });
+        }
+
+        match output_file {
+            Some(output_file) => {
+                Self::write_body(response, output_file).await?;
+                Ok(vec![])
+            }
+            None => Ok(response.bytes().await?.to_vec()),
+        }
+    }
+
+    /// Fetch with retries, the result is reported like the output of a command.
+    async fn fetch_with_retries(&self, url: &str, output_file: Option<&Path>) -> Output {
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 0;
+
+        let error = loop {
+            match self.try_fetch(url, output_file).await {
+                Ok(body) => {
+                    return Output {
+                        status: exit_status(0),
+                        stdout: body,
+                        stderr: vec![],
+                    }
+                }
+                Err(FetchError::Retryable(message)) if attempt < self.retries => {
+                    attempt += 1;
+                    warn!(
+                        "fetch {} failed, retry {}/{} in {:?}: {}",
+                        url, attempt, self.retries, retry_delay, message
+                    );
+                    tokio::time::sleep(retry_delay).await;
+                    retry_delay *= 2;
+                }
+                Err(e) => break e,
+            }
+        };
+
+        if let Some(output_file) = output_file {
+            let _ = tokio::fs::remove_file(Self::part_path(output_file)).await;
+        }
+
+        Output {
+            status: exit_status(1),
+            stdout: vec![],
+            stderr: format!("fetch {} failed: {}\n", url, error).into_bytes(),
+        }
+    }
+
+    /// Download the URL in the command path to the file in the first argument, or to stdout.
+    ///
+    /// The timeout applies to all attempts together, as it does to a command.
+    pub async fn fetch(
+        &self,
+        command_and_args: &OwnedCommandAndArgs,
+        job_options: &JobOptions,
+        cancellation_token: &CancellationToken,
+    ) -> Result<Output, ChildProcessExecutionError> {
+        let url = command_and_args.command_path.to_string_lossy();
+        let output_file = command_and_args.args.first().map(Path::new);
+
+        debug!("fetch url = {} output_file = {:?}", url, output_file);
+
+        let fetch = async {
+            match job_options.timeout.or(self.timeout) {
+                None => Ok(self.fetch_with_retries(&url, output_file).await),
+                Some(timeout) => Ok(tokio::time::timeout(
+                    timeout,
+                    self.fetch_with_retries(&url, output_file),
+                )
+                .await?),
+            }
+        };
+
+        tokio::select! {
+            result = fetch => result,
+
+            _ = cancellation_token.cancelled() => Err(ChildProcessExecutionError::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable_status() {
+        assert!(is_retryable_status(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(
+            reqwest::StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(!is_retryable_status(reqwest::StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(reqwest::StatusCode::OK));
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert!(exit_status(0).success());
+        assert_eq!(exit_status(1).code(), Some(1));
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            Fetcher::part_path(Path::new("out/a.html")),
+            PathBuf::from("out/a.html.part")
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use tokio_util::sync::CancellationToken;
+
+use tracing::warn;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    command_line_args::{CommandLineArgs, Halt, HaltFail, HaltWhen},
+    process::ChildProcessFactory,
+};
+
+/// Stops the run when enough commands fail, from --halt or --exit-on-error.
+pub struct HaltPolicy {
+    when: HaltWhen,
+    fail: HaltFail,
+    /// Failed commands that halt the run, set once all commands are known for a percentage.
+    max_failures: AtomicU64,
+}
+
+impl HaltPolicy {
+    pub fn new(command_line_args: &CommandLineArgs) -> Option<Self> {
+        let Halt { when, fail } = command_line_args.halt.or_else(|| {
+            command_line_args.exit_on_error.then_some(Halt {
+                when: HaltWhen::Soon,
+                fail: HaltFail::Count(1),
+            })
+        })?;
+
+        let max_failures = match fail {
+            HaltFail::Count(count) => count,
+            HaltFail::Percent(_) => u64::MAX,
+        };
+
+        Some(Self {
+            when,
+            fail,
+            max_failures: AtomicU64::new(max_failures),
+        })
+    }
+
+    pub fn set_total_jobs(&self, jobs: u64) {
+        if let HaltFail::Percent(percent) = self.fail {
+            let max_failures = ((jobs as f64 * percent / 100.0).ceil() as u64).max(1);
+            self.max_failures.store(max_failures, Ordering::SeqCst);
+        }
+    }
+
+    /// Cancel the run once failures reach the limit, with --halt now also kill running commands.
+    pub fn check(
+        &self,
+        failures: u64,
+        cancellation_token: &CancellationToken,
+        child_process_factory: &ChildProcessFactory,
+    ) {
+        if failures < self.max_failures.load(Ordering::SeqCst) || cancellation_token.is_cancelled()
+        {
+            return;
+        }
+
+        match self.when {
+            HaltWhen::Soon => warn!(
+                "halting after {} failed commands, no more commands are started",
+                failures
+            ),
+            HaltWhen::Now => {
+                warn!(
+                    "halting after {} failed commands, killing running commands",
+                    failures
+                );
+                child_process_factory.kill_running_commands();
+            }
+        }
+
+        cancellation_token.cancel();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_halt_policy(fail: HaltFail) -> HaltPolicy {
+        HaltPolicy::new(&CommandLineArgs {
+            halt: Some(Halt {
+                when: HaltWhen::Now,
+                fail,
+            }),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_exit_on_error() {
+        assert!(HaltPolicy::new(&CommandLineArgs::default()).is_none());
+
+        let halt_policy = HaltPolicy::new(&CommandLineArgs {
+            exit_on_error: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(halt_policy.when, HaltWhen::Soon);
+        assert_eq!(halt_policy.max_failures.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_check_count() {
+        let halt_policy = new_halt_policy(HaltFail::Count(2));
+
+        let cancellation_token = CancellationToken::new();
+        let child_process_factory = ChildProcessFactory::new(&CommandLineArgs::default()).unwrap();
+        halt_policy.check(1, &cancellation_token, &child_process_factory);
+        assert!(!cancellation_token.is_cancelled());
+        halt_policy.check(2, &cancellation_token, &child_process_factory);
+        assert!(cancellation_token.is_cancelled());
+    }
+
+    #[test]
+    fn test_check_percent() {
+        let halt_policy = new_halt_policy(HaltFail::Percent(10.0));
+
+        halt_policy.set_total_jobs(25);
+        assert_eq!(halt_policy.max_failures.load(Ordering::SeqCst), 3);
+
+        let cancellation_token = CancellationToken::new();
+        let child_process_factory = ChildProcessFactory::new(&CommandLineArgs::default()).unwrap();
+        halt_policy.check(2, &cancellation_token, &child_process_factory);
+        assert!(!cancellation_token.is_cancelled());
+        halt_policy.check(3, &cancellation_token, &child_process_factory);
+        assert!(cancellation_token.is_cancelled());
+
+        halt_policy.set_total_jobs(0);
+        assert_eq!(halt_policy.max_failures.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Context;
+
+use tracing::{debug, warn};
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    command_line_args::CommandLineArgs, common::OwnedCommandAndArgs, parser::regex::RegexProcessor,
+};
+
+/// Weight of the newest duration in the moving average stored per key.
+const NEW_DURATION_WEIGHT: f64 = 0.5;
+
+fn default_history_file() -> Option<PathBuf> {
+    let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(cache_home) if !cache_home.is_empty() => PathBuf::from(cache_home),
+        _ => {
+            PathBuf::from(std::env::var_os("HOME").filter(|home| !home.is_empty())?).join(".cache")
+        }
+    };
+
+    Some(cache_dir.join("rust-parallel").join("history.json"))
+}
+
+fn parse_durations(contents: &str) -> anyhow::Result<HashMap<String, f64>> {
+    let value: serde_json::Value = serde_json::from_str(contents)?;
+
+    let object = value.as_object().context("history is not a json object")?;
+
+    Ok(object
+        .iter()
+        .filter_map(|(key, seconds)| Some((key.clone(), seconds.as_f64()?)))
+        .filter(|(_, seconds)| seconds.is_finite() && *seconds >= 0.0)
+        .collect())
+}
+
+/// Durations of successful commands by key, persisted across runs with --use-history.
+pub struct JobHistory {
+    path: PathBuf,
+    key_template: Option<String>,
+    regex_processor: Arc<RegexProcessor>,
+    durations: Mutex<HashMap<String, f64>>,
+}
+
+impl JobHistory {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Arc<Self>>> {
+        if !command_line_args.use_history {
+            return Ok(None);
+        }
+
+        let path = match &command_line_args.history_file {
+            Some(history_file) => PathBuf::from(history_file),
+            None => default_history_file().context(
+                "unable to find a cache directory for --use-history, use --history-file",
+            )?,
+        };
+
+        let durations = match std::fs::read_to_string(&path) {
+            Ok(contents) => parse_durations(&contents).unwrap_or_else(|e| {
+                warn!("ignoring invalid history file {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("error reading history file {:?}", path))
+            }
+        };
+
+        debug!(
+            "loaded {} history durations from {:?}",
+            durations.len(),
+            path
+        );
+
+        Ok(Some(Arc::new(Self {
+            path,
+            key_template: command_line_args.history_key.clone(),
+            regex_processor: RegexProcessor::new(command_line_args)?,
+            durations: Mutex::new(durations),
+        })))
+    }
+
+    /// The --history-key expanded for the input, or the command and its arguments.
+    pub fn key(&self, command_and_args: &OwnedCommandAndArgs, input_data: &str) -> String {
+        let Some(key_template) = &self.key_template else {
+            return command_and_args.to_string();
+        };
+
+        let input_data = if self.regex_processor.regex_mode() {
+            input_data
+        } else {
+            input_data.trim()
+        };
+
+        self.regex_processor
+            .expand_template(key_template, input_data)
+            .unwrap_or_else(|| command_and_args.to_string())
+    }
+
+    pub fn expected_duration(&self, key: &str) -> Option<Duration> {
+        self.durations
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|seconds| Duration::from_secs_f64(*seconds))
+    }
+
+    pub fn record(&self, key: String, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        self.durations
+            .lock()
+            .unwrap()
+            .entry(key)
+            .and_modify(|average| {
+                *average = NEW_DURATION_WEIGHT * seconds + (1.0 - NEW_DURATION_WEIGHT) * *average
+            })
+            .or_insert(seconds);
+    }
+
+    fn write_file(path: &Path, contents: &str) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let contents = {
+            let durations = self.durations.lock().unwrap();
+            serde_json::to_string(&*durations).context("error serializing history")?
+        };
+
+        Self::write_file(&self.path, &contents)
+            .with_context(|| format!("error writing history file {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_durations() {
+        let durations = parse_durations(r#"{"a": 1.5, "b": "x", "c": -1, "d": 2}"#).unwrap();
+
+        assert_eq!(
+            durations,
+            HashMap::from([("a".to_owned(), 1.5), ("d".to_owned(), 2.0)])
+        );
+
+        assert!(parse_durations("[]").is_err());
+        assert!(parse_durations("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_job_history() {
+        let path = std::env::temp_dir().join(format!(
+            "rust_parallel_history_test_{}.json",
+            std::process::id()
+        ));
+
+        let command_line_args = CommandLineArgs {
+            use_history: true,
+            history_file: Some(path.to_str().unwrap().to_owned()),
+            history_key: Some("{}".to_owned()),
+            ..Default::default()
+        };
+
+        let job_history = JobHistory::new(&command_line_args).unwrap().unwrap();
+
+        let command_and_args = OwnedCommandAndArgs {
+            command_path: PathBuf::from("sleep"),
+            args: vec!["1".to_owned()],
+        };
+        let key = job_history.key(&command_and_args, " 1 ");
+        assert_eq!(key, "1");
+
+        assert_eq!(job_history.expected_duration(&key), None);
+
+        job_history.record(key.clone(), Duration::from_secs(4));
+        job_history.record(key.clone(), Duration::from_secs(2));
+        assert_eq!(
+            job_history.expected_duration(&key),
+            Some(Duration::from_secs(3))
+        );
+
+        job_history.save().unwrap();
+
+        let job_history = JobHistory::new(&command_line_args).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            job_history.expected_duration("1"),
+            Some(Duration::from_secs(3))
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::Context;
+
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+
+use tracing::warn;
+
+use std::{
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    command_line_args::CommandLineArgs, output::CommandStatus, process::exit_signal, redact,
+};
+
+use super::Command;
+
+const HEADER: &str = "Seq\tStarttime\tJobRuntime\tExitval\tSignal\tCommand\tQueueWait\n";
+
+/// Writes a tab separated line for each finished command to --joblog.
+pub struct JobLog {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl JobLog {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        let Some(path) = &command_line_args.joblog else {
+            return Ok(None);
+        };
+
+        // with --resume or --resume-failed lines are appended to the joblog of the previous run
+        let mut file = if command_line_args.resume || command_line_args.resume_failed {
+            std::fs::File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+        } else {
+            std::fs::File::create(path)
+        }
+        .with_context(|| format!("error opening joblog '{}'", path))?;
+
+        let empty = file
+            .metadata()
+            .with_context(|| format!("error reading joblog '{}'", path))?
+            .len()
+            == 0;
+        if empty {
+            file.write_all(HEADER.as_bytes())
+                .with_context(|| format!("error writing joblog '{}'", path))?;
+        }
+
+        Ok(Some(Self {
+            path: path.clone(),
+            file: Mutex::new(File::from_std(file)),
+        }))
+    }
+
+    /// Exit value and signal number, an exit value of -1 for commands that did not exit.
+    fn exit_value_and_signal(status: CommandStatus) -> (i32, i32) {
+        match status {
+            CommandStatus::Exited(exit_status) => match exit_signal(&exit_status) {
+                Some(signal) => (-1, signal),
+                None => (exit_status.code().unwrap_or_default(), 0),
+            },
+            CommandStatus::Timeout
+            | CommandStatus::SpawnError
+            | CommandStatus::IOError
+            | CommandStatus::DescendantLimit
+            | CommandStatus::Cancelled => (-1, 0),
+        }
+    }
+
+    fn format_line(
+        command: &Command,
+        start_time: SystemTime,
+        queue_wait: Duration,
+        duration: Duration,
+        status: CommandStatus,
+    ) -> String {
+        let (exit_value, signal) = Self::exit_value_and_signal(status);
+
+        let command_line = command
+            .command_and_args
+            .shell_quoted()
+            .replace(['\t', '\n'], " ");
+
+        format!(
+            "{}\t{:.3}\t{:.3}\t{}\t{}\t{}\t{:.3}\n",
+            command.input_sequence + 1,
+            start_time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            duration.as_secs_f64(),
+            exit_value,
+            signal,
+            redact::redactor().redact(&command_line),
+            queue_wait.as_secs_f64(),
+        )
+    }
+
+    /// Record a finished command that waited queue_wait to start and ran for duration.
+    pub async fn record(
+        &self,
+        command: &Command,
+        queue_wait: Duration,
+        duration: Duration,
+        status: CommandStatus,
+    ) {
+        let start_time = SystemTime::now()
+            .checked_sub(duration)
+            .unwrap_or(UNIX_EPOCH);
+
+        let line = Self::format_line(command, start_time, queue_wait, duration, status);
+
+        // flushed so the line is written before the run ends, for --resume
+        let mut file = self.file.lock().await;
+        if let Err(e) = async {
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await
+        }
+        .await
+        {
+            warn!("error writing joblog '{}': {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::{
+        os::unix::process::ExitStatusExt, path::PathBuf, process::ExitStatus, time::Instant,
+    };
+
+    use crate::{
+        common::{JobOptions, OwnedCommandAndArgs},
+        input::{Input, InputLineNumber},
+    };
+
+    #[test]
+    fn test_format_line() {
+        let command = Command {
+            input_sequence: 4,
+            enqueued_at: Instant::now(),
+            command_and_args: OwnedCommandAndArgs {
+                command_path: PathBuf::from("/bin/echo"),
+                args: vec!["a b".to_owned(), "c\td".to_owned()],
+            },
+            job_options: JobOptions::default(),
+            input_line_number: InputLineNumber {
+                input: Input::CommandLineArgs,
+                line_number: 5,
+            },
+        };
+
+        let start_time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+
+        assert_eq!(
+            JobLog::format_line(
+                &command,
+                start_time,
+                Duration::from_millis(40),
+                Duration::from_millis(1500),
+                CommandStatus::Exited(ExitStatus::from_raw(2 << 8)),
+            ),
+            "5\t1700000000.250\t1.500\t2\t0\t/bin/echo 'a b' 'c d'\t0.040\n"
+        );
+        assert_eq!(
+            JobLog::format_line(
+                &command,
+                start_time,
+                Duration::ZERO,
+                Duration::ZERO,
+                CommandStatus::Exited(ExitStatus::from_raw(9)),
+            ),
+            "5\t1700000000.250\t0.000\t-1\t9\t/bin/echo 'a b' 'c d'\t0.000\n"
+        );
+        assert_eq!(
+            JobLog::format_line(
+                &command,
+                start_time,
+                Duration::ZERO,
+                Duration::ZERO,
+                CommandStatus::Timeout
+            ),
+            "5\t1700000000.250\t0.000\t-1\t0\t/bin/echo 'a b' 'c d'\t0.000\n"
+        );
+    }
+}
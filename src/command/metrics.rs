@@ -1,20 +1,36 @@
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-
-use crate::process::ChildProcessExecutionError;
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
 
 const ORDERING: Ordering = Ordering::SeqCst;
 
 #[derive(Debug, Default)]
 pub struct CommandMetrics {
+    commands_generated: AtomicU64,
     commands_run: AtomicU64,
     error_occurred: AtomicBool,
     spawn_errors: AtomicU64,
     timeouts: AtomicU64,
     io_errors: AtomicU64,
     exit_status_errors: AtomicU64,
+    signal_kills: AtomicU64,
+    descendant_limit_kills: AtomicU64,
+    max_duration_micros: AtomicU64,
+    skipped_up_to_date: AtomicU64,
+    skipped_by_script: AtomicU64,
+    skipped_by_admit_cmd: AtomicU64,
 }
 
 impl CommandMetrics {
+    pub fn increment_commands_generated(&self) {
+        self.commands_generated.fetch_add(1, ORDERING);
+    }
+
+    pub fn commands_generated(&self) -> u64 {
+        self.commands_generated.load(ORDERING)
+    }
+
     pub fn increment_commands_run(&self) {
         self.commands_run.fetch_add(1, ORDERING);
     }
@@ -31,8 +47,13 @@ impl CommandMetrics {
         self.error_occurred.store(true, ORDERING);
     }
 
-    fn total_failures(&self) -> u64 {
-        self.spawn_errors() + self.timeouts() + self.io_errors() + self.exit_status_errors()
+    pub fn total_failures(&self) -> u64 {
+        self.spawn_errors()
+            + self.timeouts()
+            + self.io_errors()
+            + self.exit_status_errors()
+            + self.signal_kills()
+            + self.descendant_limit_kills()
     }
 
     pub fn increment_spawn_errors(&self) {
@@ -44,14 +65,7 @@ impl CommandMetrics {
         self.spawn_errors.load(ORDERING)
     }
 
-    pub fn handle_child_process_execution_error(&self, error: ChildProcessExecutionError) {
-        match error {
-            ChildProcessExecutionError::IOError(_) => self.increment_io_errors(),
-            ChildProcessExecutionError::Timeout(_) => self.increment_timeouts(),
-        }
-    }
-
-    fn increment_timeouts(&self) {
+    pub fn increment_timeouts(&self) {
         self.set_error_occurred();
         self.timeouts.fetch_add(1, ORDERING);
     }
@@ -60,7 +74,7 @@ impl CommandMetrics {
         self.timeouts.load(ORDERING)
     }
 
-    fn increment_io_errors(&self) {
+    pub fn increment_io_errors(&self) {
         self.set_error_occurred();
         self.io_errors.fetch_add(1, ORDERING);
     }
@@ -77,19 +91,76 @@ impl CommandMetrics {
     fn exit_status_errors(&self) -> u64 {
         self.exit_status_errors.load(ORDERING)
     }
+
+    pub fn increment_signal_kills(&self) {
+        self.set_error_occurred();
+        self.signal_kills.fetch_add(1, ORDERING);
+    }
+
+    fn signal_kills(&self) -> u64 {
+        self.signal_kills.load(ORDERING)
+    }
+
+    pub fn increment_descendant_limit_kills(&self) {
+        self.set_error_occurred();
+        self.descendant_limit_kills.fetch_add(1, ORDERING);
+    }
+
+    fn descendant_limit_kills(&self) -> u64 {
+        self.descendant_limit_kills.load(ORDERING)
+    }
+
+    pub fn increment_skipped_up_to_date(&self) {
+        self.skipped_up_to_date.fetch_add(1, ORDERING);
+    }
+
+    pub fn skipped_up_to_date(&self) -> u64 {
+        self.skipped_up_to_date.load(ORDERING)
+    }
+
+    pub fn increment_skipped_by_script(&self) {
+        self.skipped_by_script.fetch_add(1, ORDERING);
+    }
+
+    pub fn skipped_by_script(&self) -> u64 {
+        self.skipped_by_script.load(ORDERING)
+    }
+
+    pub fn increment_skipped_by_admit_cmd(&self) {
+        self.skipped_by_admit_cmd.fetch_add(1, ORDERING);
+    }
+
+    pub fn skipped_by_admit_cmd(&self) -> u64 {
+        self.skipped_by_admit_cmd.load(ORDERING)
+    }
+
+    pub fn record_duration(&self, duration: Duration) {
+        let micros = duration.as_micros().try_into().unwrap_or(u64::MAX);
+        self.max_duration_micros.fetch_max(micros, ORDERING);
+    }
+
+    fn max_duration(&self) -> Duration {
+        Duration::from_micros(self.max_duration_micros.load(ORDERING))
+    }
 }
 
 impl std::fmt::Display for CommandMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "commands_run={} total_failures={} spawn_errors={} timeouts={} io_errors={} exit_status_errors={}",
+            "commands_run={} total_failures={} spawn_errors={} timeouts={} io_errors={} exit_status_errors={} signal_kills={} descendant_limit_kills={} max_duration={:?} skipped_up_to_date={} skipped_by_script={} skipped_by_admit_cmd={}",
             self.commands_run(),
             self.total_failures(),
             self.spawn_errors(),
             self.timeouts(),
             self.io_errors(),
             self.exit_status_errors(),
+            self.signal_kills(),
+            self.descendant_limit_kills(),
+            self.max_duration(),
+            self.skipped_up_to_date(),
+            self.skipped_by_script(),
+            self.skipped_by_admit_cmd(),
         )
     }
 }
@@ -4,10 +4,78 @@ use tokio::sync::Mutex;
 
 use tracing::warn;
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 use crate::{command_line_args::CommandLineArgs, common::OwnedCommandAndArgs};
 
+/// Returns true if the path is a file this process may execute without a shell.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Returns true if the path is a file this process may execute without a shell.
+///
+/// Batch files need cmd.exe, so only .exe and .com files are executable.
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    let executable_extension = path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("exe") || extension.eq_ignore_ascii_case("com")
+    });
+
+    executable_extension && std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+/// Resolve a command like execvp for --raw-exec, the same way on every platform.
+///
+/// A command containing a path separator is used as is.  Otherwise each PATH entry is searched
+/// in order for the exact command name, an empty entry means the current directory.
+fn raw_exec_lookup(command_path: &Path, path_var: Option<&OsStr>) -> Result<PathBuf, String> {
+    let has_separator = command_path
+        .to_string_lossy()
+        .contains(|c| c == '/' || std::path::is_separator(c));
+
+    let candidates: Vec<PathBuf> = if has_separator {
+        vec![command_path.to_owned()]
+    } else {
+        path_var
+            .map(|path_var| {
+                std::env::split_paths(path_var)
+                    .map(|dir| {
+                        let dir = if dir.as_os_str().is_empty() {
+                            PathBuf::from(".")
+                        } else {
+                            dir
+                        };
+                        dir.join(command_path)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut found_not_executable = false;
+    for candidate in candidates {
+        if is_executable(&candidate) {
+            return Ok(candidate);
+        }
+        found_not_executable |= candidate.exists();
+    }
+
+    if found_not_executable {
+        Err("file is not executable".to_owned())
+    } else {
+        Err("file not found".to_owned())
+    }
+}
+
 enum CacheValue {
     NotResolvable,
 
@@ -16,13 +84,15 @@ enum CacheValue {
 
 pub struct CommandPathCache {
     enabled: bool,
+    raw_exec: bool,
     cache: Mutex<HashMap<PathBuf, CacheValue>>,
 }
 
 impl CommandPathCache {
     pub fn new(command_line_args: &CommandLineArgs) -> Self {
         Self {
-            enabled: !command_line_args.disable_path_cache,
+            enabled: !command_line_args.disable_path_cache && !command_line_args.fetch,
+            raw_exec: command_line_args.raw_exec,
             cache: Mutex::new(HashMap::new()),
         }
     }
@@ -53,11 +123,20 @@ impl CommandPathCache {
 
         let command_path_clone = command_path.clone();
 
-        let which_result = tokio::task::spawn_blocking(move || which::which(command_path_clone))
+        let lookup_result = if self.raw_exec {
+            tokio::task::spawn_blocking(move || {
+                raw_exec_lookup(&command_path_clone, std::env::var_os("PATH").as_deref())
+            })
+            .await
+        } else {
+            tokio::task::spawn_blocking(move || {
+                which::which(command_path_clone).map_err(|e| e.to_string())
+            })
             .await
-            .context("spawn_blocking error")?;
+        }
+        .context("spawn_blocking error")?;
 
-        let full_path = match which_result {
+        let full_path = match lookup_result {
             Ok(path) => path,
             Err(e) => {
                 warn!("error resolving path {:?}: {}", command_path, e);
@@ -76,3 +155,48 @@ impl CommandPathCache {
         Ok(Some(command_and_args))
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_raw_exec_lookup() {
+        let dir =
+            std::env::temp_dir().join(format!("rust_parallel_raw_exec_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let not_executable = dir.join("not_executable");
+        std::fs::write(&not_executable, "").unwrap();
+
+        let path_var = std::env::join_paths([dir.as_path(), Path::new("/bin")]).unwrap();
+        let path_var = Some(path_var.as_os_str());
+
+        assert_eq!(
+            raw_exec_lookup(Path::new("sh"), path_var),
+            Ok(PathBuf::from("/bin/sh"))
+        );
+        assert_eq!(
+            raw_exec_lookup(Path::new("/bin/sh"), None),
+            Ok(PathBuf::from("/bin/sh"))
+        );
+        assert_eq!(
+            raw_exec_lookup(Path::new("not_executable"), path_var),
+            Err("file is not executable".to_owned())
+        );
+        assert_eq!(
+            raw_exec_lookup(&not_executable, None),
+            Err("file is not executable".to_owned())
+        );
+        assert_eq!(
+            raw_exec_lookup(Path::new("no_such_command"), path_var),
+            Err("file not found".to_owned())
+        );
+        assert_eq!(
+            raw_exec_lookup(Path::new("sh"), None),
+            Err("file not found".to_owned())
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
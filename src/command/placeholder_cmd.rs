@@ -0,0 +1,255 @@
+use anyhow::Context;
+
+use itertools::Itertools;
+
+use tokio::{io::AsyncWriteExt, process::Command, sync::Mutex};
+
+use tracing::{debug, warn};
+
+use std::{collections::HashMap, process::Stdio};
+
+use crate::{
+    command_line_args::{CommandLineArgs, ShellKind},
+    common::OwnedCommandAndArgs,
+};
+
+type CacheKey = (String, String);
+
+pub struct PlaceholderCommands {
+    placeholder_regex: Option<regex::Regex>,
+    commands: HashMap<String, String>,
+    cache: Mutex<HashMap<CacheKey, Result<String, String>>>,
+}
+
+impl PlaceholderCommands {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Self> {
+        let commands: HashMap<_, _> = command_line_args
+            .placeholder_cmd
+            .iter()
+            .map(|named_command| (named_command.name.clone(), named_command.template.clone()))
+            .collect();
+
+        let placeholder_regex = if commands.is_empty() {
+            None
+        } else {
+            let name_regex = regex::Regex::new("^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+            if let Some(name) = commands.keys().find(|name| !name_regex.is_match(name)) {
+                anyhow::bail!("invalid --placeholder-cmd name {:?}", name);
+            }
+
+            let names = commands.keys().map(|name| regex::escape(name)).join("|");
+            Some(
+                regex::Regex::new(&format!(r"\{{({})(?::([^{{}}]*))?\}}", names))
+                    .context("error creating placeholder-cmd regex")?,
+            )
+        };
+
+        Ok(Self {
+            placeholder_regex,
+            commands,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn run_command(command: &str, stdin_data: &str) -> Result<String, String> {
+        let shell_kind = ShellKind::system();
+
+        let mut child = Command::new(shell_kind.default_path())
+            .args(shell_kind.default_arguments())
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("spawn error: {}", e))?;
+
+        let mut stdin = child.stdin.take();
+        let write_stdin = async move {
+            if let Some(stdin) = stdin.as_mut() {
+                // the command may exit without reading stdin
+                let _ = stdin.write_all(stdin_data.as_bytes()).await;
+            }
+            drop(stdin);
+        };
+
+        let ((), output) = tokio::join!(write_stdin, child.wait_with_output());
+        let output = output.map_err(|e| format!("i/o error: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "exit_status={} stderr={:?}",
+                output.status.code().unwrap_or_default(),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end_matches(['\r', '\n'])
+            .to_owned())
+    }
+
+    async fn resolve_value(&self, name: &str, stdin_data: &str) -> Result<String, String> {
+        let cache_key = (name.to_owned(), stdin_data.to_owned());
+
+        let mut cache = self.cache.lock().await;
+
+        if let Some(cached_value) = cache.get(&cache_key) {
+            return cached_value.clone();
+        }
+
+        let command = &self.commands[name];
+
+        let value = Self::run_command(command, stdin_data).await;
+        debug!(
+            "placeholder command {:?} stdin {:?} value = {:?}",
+            command, stdin_data, value
+        );
+
+        cache.insert(cache_key, value.clone());
+
+        value
+    }
+
+    async fn resolve_argument(&self, argument: String, input_data: &str) -> Result<String, String> {
+        let Some(placeholder_regex) = &self.placeholder_regex else {
+            return Ok(argument);
+        };
+
+        let mut result = String::with_capacity(argument.len());
+        let mut last_end = 0;
+
+        for captures in placeholder_regex.captures_iter(&argument) {
+            let placeholder = captures.get(0).unwrap();
+            let name = &captures[1];
+            let stdin_data = captures.get(2).map_or(input_data, |text| text.as_str());
+
+            let value = self
+                .resolve_value(name, stdin_data)
+                .await
+                .map_err(|e| format!("placeholder {}: {}", placeholder.as_str(), e))?;
+
+            result.push_str(&argument[last_end..placeholder.start()]);
+            result.push_str(&value);
+            last_end = placeholder.end();
+        }
+
+        if last_end == 0 {
+            return Ok(argument);
+        }
+
+        result.push_str(&argument[last_end..]);
+
+        Ok(result)
+    }
+
+    /// Replace placeholders with the output of their commands.
+    ///
+    /// Returns None if a command fails, the error is logged.
+    pub async fn resolve(
+        &self,
+        command_and_args: OwnedCommandAndArgs,
+        input_data: &str,
+    ) -> Option<OwnedCommandAndArgs> {
+        if self.placeholder_regex.is_none() {
+            return Some(command_and_args);
+        }
+
+        let mut args = Vec::with_capacity(command_and_args.args.len());
+        for arg in command_and_args.args {
+            match self.resolve_argument(arg, input_data).await {
+                Ok(arg) => args.push(arg),
+                Err(e) => {
+                    warn!(
+                        "placeholder command error, skipping input data: {}: {}",
+                        input_data, e
+                    );
+                    return None;
+                }
+            }
+        }
+
+        Some(OwnedCommandAndArgs {
+            command_path: command_and_args.command_path,
+            args,
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use crate::command_line_args::NamedCommand;
+
+    fn placeholder_commands(commands: &[(&str, &str)]) -> PlaceholderCommands {
+        PlaceholderCommands::new(&CommandLineArgs {
+            placeholder_cmd: commands
+                .iter()
+                .map(|(name, template)| NamedCommand {
+                    name: name.to_string(),
+                    template: template.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn command_and_args(args: &[&str]) -> OwnedCommandAndArgs {
+        OwnedCommandAndArgs {
+            command_path: PathBuf::from("echo"),
+            args: args.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let placeholder_commands =
+            placeholder_commands(&[("upper", "tr a-z A-Z"), ("fail", "cat; exit 3")]);
+
+        assert_eq!(
+            placeholder_commands
+                .resolve(
+                    command_and_args(&["{upper}", "x-{upper:abc}-{upper:abc}", "{other:abc}"]),
+                    "input",
+                )
+                .await,
+            Some(command_and_args(&["INPUT", "x-ABC-ABC", "{other:abc}"]))
+        );
+
+        assert_eq!(placeholder_commands.cache.lock().await.len(), 2);
+
+        assert_eq!(
+            placeholder_commands
+                .resolve(command_and_args(&["{fail:abc}"]), "input")
+                .await,
+            None
+        );
+
+        assert_eq!(
+            placeholder_commands
+                .resolve_argument("{fail}".to_owned(), "input")
+                .await,
+            Err("placeholder {fail}: exit_status=3 stderr=\"\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let result = PlaceholderCommands::new(&CommandLineArgs {
+            placeholder_cmd: vec![NamedCommand {
+                name: "a b".to_owned(),
+                template: "cat".to_owned(),
+            }],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "invalid --placeholder-cmd name \"a b\""
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::Context;
+
+use tracing::info;
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::command_line_args::CommandLineArgs;
+
+/// Skips inputs by their result in the --joblog of a previous run, for --resume and
+/// --resume-failed.
+pub struct Resume {
+    /// Whether the last joblog line for each sequence number, starting at 0, has exit value 0.
+    succeeded_by_sequence: HashMap<u64, bool>,
+    failed_only: bool,
+    skipped: AtomicU64,
+}
+
+impl Resume {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        let Some(path) = &command_line_args.joblog else {
+            return Ok(None);
+        };
+        if !command_line_args.resume && !command_line_args.resume_failed {
+            return Ok(None);
+        }
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("error reading joblog '{}'", path)),
+        };
+
+        Ok(Some(Self {
+            succeeded_by_sequence: Self::parse_joblog(&contents),
+            failed_only: command_line_args.resume_failed,
+            skipped: AtomicU64::new(0),
+        }))
+    }
+
+    fn parse_joblog(contents: &str) -> HashMap<u64, bool> {
+        contents
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split('\t');
+                let seq: u64 = columns.next()?.parse().ok()?;
+                let exit_value = columns.nth(2)?;
+                Some((seq.checked_sub(1)?, exit_value == "0"))
+            })
+            .collect()
+    }
+
+    /// Number of inputs that succeeded in the previous run, counted as done from the start.
+    pub fn completed(&self) -> u64 {
+        self.succeeded_by_sequence
+            .values()
+            .filter(|&&succeeded| succeeded)
+            .count() as u64
+    }
+
+    /// True if the input with this sequence number succeeded in the previous run, so it is
+    /// already counted in [`Resume::completed`].
+    pub fn is_completed(&self, sequence: u64) -> bool {
+        self.succeeded_by_sequence.get(&sequence) == Some(&true)
+    }
+
+    /// True if the input with this sequence number is skipped: with --resume if it succeeded
+    /// in the previous run, with --resume-failed unless it failed.
+    pub fn skip(&self, sequence: u64) -> bool {
+        let succeeded = self.succeeded_by_sequence.get(&sequence).copied();
+
+        let skip = if self.failed_only {
+            succeeded != Some(false)
+        } else {
+            succeeded == Some(true)
+        };
+
+        if skip {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+        }
+        skip
+    }
+
+    pub fn log_summary(&self) {
+        let skipped = self.skipped.load(Ordering::Relaxed);
+        if skipped == 0 {
+            return;
+        }
+
+        if self.failed_only {
+            info!(
+                "resume: skipped {} inputs that did not fail in joblog",
+                skipped
+            );
+        } else {
+            info!("resume: skipped {} inputs completed in joblog", skipped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const JOBLOG: &str = "Seq\tStarttime\tJobRuntime\tExitval\tSignal\tCommand\n\
+                          1\t1700000000.000\t0.010\t0\t0\techo a\n\
+                          2\t1700000000.000\t0.010\t1\t0\techo b\n\
+                          3\t1700000000.000\t0.010\t-1\t9\techo c\n\
+                          5\t1700000000.000\t0.010\t2\t0\techo e\n\
+                          5\t1700000001.000\t0.010\t0\t0\techo e\n\
+                          6\tpartial";
+
+    fn new_resume(failed_only: bool) -> Resume {
+        Resume {
+            succeeded_by_sequence: Resume::parse_joblog(JOBLOG),
+            failed_only,
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    #[test]
+    fn test_parse_joblog() {
+        assert_eq!(
+            Resume::parse_joblog(JOBLOG),
+            HashMap::from([(0, true), (1, false), (2, false), (4, true)])
+        );
+    }
+
+    #[test]
+    fn test_skip() {
+        let resume = new_resume(false);
+        let skipped: Vec<_> = (0..6).filter(|&sequence| resume.skip(sequence)).collect();
+        assert_eq!(skipped, vec![0, 4]);
+
+        let resume_failed = new_resume(true);
+        let skipped: Vec<_> = (0..6)
+            .filter(|&sequence| resume_failed.skip(sequence))
+            .collect();
+        assert_eq!(skipped, vec![0, 3, 4, 5]);
+        assert_eq!(resume_failed.skipped.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_completed() {
+        let resume = new_resume(true);
+        assert_eq!(resume.completed(), 2);
+
+        let completed: Vec<_> = (0..6)
+            .filter(|&sequence| resume.is_completed(sequence))
+            .collect();
+        assert_eq!(completed, vec![0, 4]);
+    }
+}
@@ -0,0 +1,89 @@
+use tokio::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use tracing::warn;
+
+use std::process::Output;
+
+use crate::{command_line_args::CommandLineArgs, process::ChildProcessExecutionError};
+
+/// Retries for commands that fail or time out, from --retries.
+pub struct RetryPolicy {
+    retries: u32,
+    delay: Duration,
+    backoff: f64,
+}
+
+impl RetryPolicy {
+    pub fn new(command_line_args: &CommandLineArgs) -> Option<Self> {
+        if command_line_args.retries == 0 {
+            return None;
+        }
+
+        Some(Self {
+            retries: command_line_args.retries,
+            delay: Duration::from_secs_f64(command_line_args.retry_delay),
+            backoff: command_line_args.retry_backoff,
+        })
+    }
+
+    /// Description of why an attempt should be retried, None if it succeeded or cannot be
+    /// retried.
+    pub fn retry_reason(result: &Result<Output, ChildProcessExecutionError>) -> Option<String> {
+        match result {
+            Ok(output) if !output.status.success() => Some(output.status.to_string()),
+            Err(ChildProcessExecutionError::Timeout(_)) => Some("timeout".to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Delay before retry number attempt, starting at 1.
+    fn delay(&self, attempt: u32) -> Duration {
+        self.delay
+            .mul_f64(self.backoff.powi(attempt.saturating_sub(1) as i32))
+    }
+
+    /// Wait before retry number attempt if it is allowed, false if the command should not be
+    /// retried or the run was cancelled.
+    pub async fn wait_for_retry(
+        &self,
+        attempt: u32,
+        reason: &str,
+        command: &impl std::fmt::Display,
+        cancellation_token: &CancellationToken,
+    ) -> bool {
+        if attempt > self.retries {
+            return false;
+        }
+
+        let delay = self.delay(attempt);
+        warn!(
+            "command failed, retry {}/{} in {:?}: {}: {}",
+            attempt, self.retries, delay, command, reason
+        );
+
+        tokio::select! {
+            _ = cancellation_token.cancelled() => false,
+            _ = tokio::time::sleep(delay) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let retry_policy = RetryPolicy {
+            retries: 3,
+            delay: Duration::from_millis(100),
+            backoff: 2.0,
+        };
+
+        assert_eq!(retry_policy.delay(1), Duration::from_millis(100));
+        assert_eq!(retry_policy.delay(2), Duration::from_millis(200));
+        assert_eq!(retry_policy.delay(3), Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,372 @@
+use tracing::warn;
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    command_line_args::{CommandLineArgs, Schedule},
+    input::InputMessage,
+    parser::regex::RegexProcessor,
+};
+
+use super::history::JobHistory;
+
+struct QueuedInput {
+    priority: f64,
+    sequence: u64,
+    input_message: InputMessage,
+}
+
+impl QueuedInput {
+    fn key(&self) -> (f64, u64) {
+        (self.priority, self.sequence)
+    }
+}
+
+impl PartialEq for QueuedInput {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedInput {}
+
+impl PartialOrd for QueuedInput {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedInput {
+    /// Lowest priority value first, then lowest sequence number, as BinaryHeap is a max heap.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (priority, sequence) = self.key();
+        let (other_priority, other_sequence) = other.key();
+
+        other_priority
+            .total_cmp(&priority)
+            .then(other_sequence.cmp(&sequence))
+    }
+}
+
+/// Orders inputs for --schedule=lifo, --schedule=sjf, --schedule=fair and --use-history, other
+/// fifo inputs are not queued.
+pub struct Scheduler {
+    schedule: Schedule,
+    job_cost_template: Option<String>,
+    regex_processor: Arc<RegexProcessor>,
+    job_history: Option<Arc<JobHistory>>,
+    next_sequence: u64,
+    queue: BinaryHeap<QueuedInput>,
+    /// For --schedule=fair, the priority of the last input queued from each input source.
+    fair_last_priority: HashMap<String, f64>,
+    /// For --schedule=fair, the priority of the last input run.
+    fair_virtual_time: f64,
+}
+
+impl Scheduler {
+    pub fn new(
+        command_line_args: &CommandLineArgs,
+        job_history: Option<Arc<JobHistory>>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            schedule: command_line_args.schedule,
+            job_cost_template: command_line_args.job_cost.clone(),
+            regex_processor: RegexProcessor::new(command_line_args)?,
+            job_history,
+            next_sequence: 0,
+            queue: BinaryHeap::new(),
+            fair_last_priority: HashMap::new(),
+            fair_virtual_time: 0.0,
+        })
+    }
+
+    /// True if inputs are queued with push instead of processed as they arrive.
+    pub fn queueing(&self) -> bool {
+        self.schedule != Schedule::Fifo || self.job_history.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn job_cost(&self, job_cost_template: &str, input_data: &str) -> f64 {
+        let input_data = if self.regex_processor.regex_mode() {
+            input_data
+        } else {
+            input_data.trim()
+        };
+
+        let job_cost = self
+            .regex_processor
+            .expand_template(job_cost_template, input_data);
+
+        match job_cost.as_deref().map(str::trim).map(str::parse::<f64>) {
+            Some(Ok(job_cost)) if !job_cost.is_nan() => job_cost,
+            _ => {
+                warn!(
+                    "invalid --job-cost {:?} for input {:?}, running it last",
+                    job_cost, input_data
+                );
+                f64::INFINITY
+            }
+        }
+    }
+
+    /// Start time fair queuing: each input source's inputs are numbered in turns, and a source
+    /// that had nothing queued starts at the current turn rather than catching up.
+    fn fair_priority(&mut self, input_message: &InputMessage) -> f64 {
+        let source = input_message.input_line_number.input.to_string();
+
+        let last_priority = self
+            .fair_last_priority
+            .get(&source)
+            .copied()
+            .unwrap_or_default();
+        let priority = last_priority.max(self.fair_virtual_time) + 1.0;
+
+        self.fair_last_priority.insert(source, priority);
+        priority
+    }
+
+    /// Queue an input, returning its historical duration if known.
+    pub fn push(&mut self, input_message: InputMessage) -> Option<Duration> {
+        self.next_sequence += 1;
+        let sequence = self.next_sequence;
+
+        let expected_duration = self.job_history.as_ref().and_then(|job_history| {
+            job_history.expected_duration(
+                &job_history.key(&input_message.command_and_args, &input_message.input_data),
+            )
+        });
+
+        // commands without history run first, so their durations are known sooner
+        let expected_seconds =
+            |no_history: f64| expected_duration.map_or(no_history, |d| d.as_secs_f64());
+
+        let priority = match (self.schedule, &self.job_cost_template) {
+            // longest first with --use-history
+            (Schedule::Fifo, _) if self.job_history.is_some() => -expected_seconds(f64::INFINITY),
+            (Schedule::Fifo, _) => 0.0,
+            // the newest input has the lowest priority value
+            (Schedule::Lifo, _) => -(sequence as f64),
+            (Schedule::Sjf, Some(job_cost_template)) => {
+                self.job_cost(job_cost_template, &input_message.input_data)
+            }
+            (Schedule::Sjf, None) => expected_seconds(f64::NEG_INFINITY),
+            (Schedule::Fair, _) => self.fair_priority(&input_message),
+        };
+
+        self.queue.push(QueuedInput {
+            priority,
+            sequence,
+            input_message,
+        });
+
+        expected_duration
+    }
+
+    pub fn pop(&mut self) -> Option<InputMessage> {
+        let queued_input = self.queue.pop()?;
+
+        if self.schedule == Schedule::Fair {
+            self.fair_virtual_time = queued_input.priority;
+        }
+
+        Some(queued_input.input_message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        common::{JobOptions, OwnedCommandAndArgs},
+        input::{BufferedInput, Input, InputLineNumber},
+    };
+
+    use std::path::PathBuf;
+
+    fn input_message(line_number: usize, input_data: &str) -> InputMessage {
+        input_message_from(Input::CommandLineArgs, line_number, input_data)
+    }
+
+    fn input_message_from(input: Input, line_number: usize, input_data: &str) -> InputMessage {
+        InputMessage {
+            sequence: line_number as u64,
+            command_and_args: OwnedCommandAndArgs {
+                command_path: PathBuf::from("echo"),
+                args: vec![input_data.to_owned()],
+            },
+            job_options: JobOptions::default(),
+            input_line_number: InputLineNumber { input, line_number },
+            input_data: input_data.to_owned(),
+            enqueued_at: std::time::Instant::now(),
+        }
+    }
+
+    fn drain_n(scheduler: &mut Scheduler, n: usize) -> Vec<String> {
+        (0..n)
+            .filter_map(|_| scheduler.pop())
+            .map(|input_message| input_message.input_data)
+            .collect()
+    }
+
+    fn drain(scheduler: &mut Scheduler) -> Vec<String> {
+        std::iter::from_fn(|| scheduler.pop())
+            .map(|input_message| input_message.input_data)
+            .collect()
+    }
+
+    #[test]
+    fn test_lifo() {
+        let mut scheduler = Scheduler::new(
+            &CommandLineArgs {
+                schedule: Schedule::Lifo,
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        assert!(scheduler.queueing());
+
+        for (i, input_data) in ["a", "b", "c"].into_iter().enumerate() {
+            scheduler.push(input_message(i + 1, input_data));
+        }
+
+        assert_eq!(drain(&mut scheduler), vec!["c", "b", "a"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_sjf() {
+        let mut scheduler = Scheduler::new(
+            &CommandLineArgs {
+                schedule: Schedule::Sjf,
+                regex: Some("(.*),(.*)".to_owned()),
+                job_cost: Some("{2}".to_owned()),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        for (i, input_data) in ["a,10", "b,bad", "c,1.5", "d,10", "e,-1"]
+            .into_iter()
+            .enumerate()
+        {
+            scheduler.push(input_message(i + 1, input_data));
+        }
+
+        assert_eq!(
+            drain(&mut scheduler),
+            vec!["e,-1", "c,1.5", "a,10", "d,10", "b,bad"]
+        );
+    }
+
+    #[test]
+    fn test_fair() {
+        let mut scheduler = Scheduler::new(
+            &CommandLineArgs {
+                schedule: Schedule::Fair,
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        assert!(scheduler.queueing());
+
+        let bulk = Input::Buffered(BufferedInput::File { file_name: "bulk" });
+        let interactive = Input::Buffered(BufferedInput::Stdin);
+
+        for i in 1..=4 {
+            scheduler.push(input_message_from(bulk, i, &format!("bulk{i}")));
+        }
+        scheduler.push(input_message_from(interactive, 1, "interactive1"));
+        scheduler.push(input_message_from(interactive, 2, "interactive2"));
+
+        assert_eq!(
+            drain_n(&mut scheduler, 4),
+            vec!["bulk1", "interactive1", "bulk2", "interactive2"]
+        );
+
+        // a source with nothing queued takes turns from now, it does not catch up
+        for i in 5..=6 {
+            scheduler.push(input_message_from(bulk, i, &format!("bulk{i}")));
+        }
+        scheduler.push(input_message_from(interactive, 3, "interactive3"));
+
+        assert_eq!(
+            drain(&mut scheduler),
+            vec!["bulk3", "interactive3", "bulk4", "bulk5", "bulk6"]
+        );
+    }
+
+    fn history_scheduler(schedule: Schedule, name: &str) -> (Scheduler, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "rust_parallel_schedule_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, r#"{"a": 1.0, "b": 3.0, "c": 2.0}"#).unwrap();
+
+        let command_line_args = CommandLineArgs {
+            schedule,
+            use_history: true,
+            history_file: Some(path.to_str().unwrap().to_owned()),
+            history_key: Some("{}".to_owned()),
+            ..Default::default()
+        };
+
+        let job_history = JobHistory::new(&command_line_args).unwrap();
+
+        (
+            Scheduler::new(&command_line_args, job_history).unwrap(),
+            path,
+        )
+    }
+
+    #[test]
+    fn test_history_longest_first() {
+        let (mut scheduler, path) = history_scheduler(Schedule::Fifo, "fifo");
+        std::fs::remove_file(path).unwrap();
+
+        assert!(scheduler.queueing());
+
+        let expected_durations: Vec<_> = ["a", "b", "new", "c"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, input_data)| scheduler.push(input_message(i + 1, input_data)))
+            .collect();
+
+        assert_eq!(
+            expected_durations,
+            vec![
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(3)),
+                None,
+                Some(Duration::from_secs(2)),
+            ]
+        );
+        assert_eq!(drain(&mut scheduler), vec!["new", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_history_sjf() {
+        let (mut scheduler, path) = history_scheduler(Schedule::Sjf, "sjf");
+        std::fs::remove_file(path).unwrap();
+
+        for (i, input_data) in ["b", "a", "c", "new"].into_iter().enumerate() {
+            scheduler.push(input_message(i + 1, input_data));
+        }
+
+        assert_eq!(drain(&mut scheduler), vec!["new", "a", "c", "b"]);
+    }
+}
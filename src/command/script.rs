@@ -0,0 +1,311 @@
+use anyhow::Context;
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use tracing::{debug, warn};
+
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    command_line_args::CommandLineArgs,
+    common::{JobOptions, OwnedCommandAndArgs},
+};
+
+const JOB_FUNCTION_NAME: &str = "job";
+
+#[derive(Debug, PartialEq)]
+pub enum ScriptResult {
+    Skip,
+
+    Run {
+        command_and_args: OwnedCommandAndArgs,
+        job_options: JobOptions,
+    },
+}
+
+struct CompiledScript {
+    engine: Engine,
+    ast: AST,
+}
+
+pub struct JobScript {
+    compiled_script: Option<CompiledScript>,
+}
+
+impl JobScript {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Self> {
+        let Some(path) = &command_line_args.script else {
+            return Ok(Self {
+                compiled_script: None,
+            });
+        };
+
+        let engine = Engine::new();
+
+        let ast = engine
+            .compile_file(path.into())
+            .with_context(|| format!("error compiling script {:?}", path))?;
+
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == JOB_FUNCTION_NAME && f.params.len() == 2)
+        {
+            anyhow::bail!(
+                "script {:?} does not define a {}(input, args) function",
+                path,
+                JOB_FUNCTION_NAME
+            );
+        }
+
+        Ok(Self {
+            compiled_script: Some(CompiledScript { engine, ast }),
+        })
+    }
+
+    /// Call the script for one input, script errors are logged and the job is skipped.
+    pub fn apply(&self, command_and_args: OwnedCommandAndArgs, input_data: &str) -> ScriptResult {
+        let Some(CompiledScript { engine, ast }) = &self.compiled_script else {
+            return ScriptResult::Run {
+                command_and_args,
+                job_options: JobOptions::default(),
+            };
+        };
+
+        let args: Array = std::iter::once(
+            command_and_args
+                .command_path
+                .to_string_lossy()
+                .into_owned()
+                .into(),
+        )
+        .chain(command_and_args.args.iter().map(|arg| arg.into()))
+        .collect();
+
+        let result = engine
+            .call_fn::<Dynamic>(
+                &mut Scope::new(),
+                ast,
+                JOB_FUNCTION_NAME,
+                (input_data.to_owned(), args),
+            )
+            .map_err(|e| e.to_string())
+            .and_then(|value| convert_result(value, command_and_args));
+
+        match result {
+            Ok(result) => {
+                debug!("script result = {:?}", result);
+                result
+            }
+            Err(e) => {
+                warn!("script error, skipping input data: {}: {}", input_data, e);
+                ScriptResult::Skip
+            }
+        }
+    }
+}
+
+fn convert_string(value: Dynamic) -> String {
+    if value.is_string() {
+        value.into_string().unwrap_or_default()
+    } else {
+        value.to_string()
+    }
+}
+
+fn convert_args(value: Dynamic) -> Result<OwnedCommandAndArgs, String> {
+    let args: Vec<String> = value
+        .into_array()
+        .map_err(|type_name| format!("args must be an array, got {}", type_name))?
+        .into_iter()
+        .map(convert_string)
+        .collect();
+
+    OwnedCommandAndArgs::try_from(args).map_err(|e| format!("args: {}", e))
+}
+
+fn convert_timeout(value: Dynamic) -> Result<Duration, String> {
+    let seconds = match value.as_int() {
+        Ok(seconds) => seconds as f64,
+        Err(_) => value
+            .as_float()
+            .map_err(|type_name| format!("timeout must be a number, got {}", type_name))?,
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("timeout: {}", e))
+}
+
+fn convert_result(
+    value: Dynamic,
+    command_and_args: OwnedCommandAndArgs,
+) -> Result<ScriptResult, String> {
+    let mut command_and_args = command_and_args;
+    let mut job_options = JobOptions::default();
+
+    if value.is_unit() {
+        // run unchanged
+    } else if value.is_bool() {
+        if !value.as_bool().unwrap_or_default() {
+            return Ok(ScriptResult::Skip);
+        }
+    } else if value.is_array() {
+        command_and_args = convert_args(value)?;
+    } else if value.is_map() {
+        let map = value.cast::<Map>();
+
+        for (key, value) in map {
+            match key.as_str() {
+                "args" => command_and_args = convert_args(value)?,
+                "env" => {
+                    let env = value
+                        .try_cast::<Map>()
+                        .ok_or_else(|| "env must be a map".to_owned())?;
+                    job_options.env = env
+                        .into_iter()
+                        .map(|(name, value)| (name.to_string(), convert_string(value)))
+                        .collect();
+                }
+                "cwd" => job_options.cwd = Some(PathBuf::from(convert_string(value))),
+                "timeout" => job_options.timeout = Some(convert_timeout(value)?),
+                "skip" => {
+                    if value.as_bool().unwrap_or_default() {
+                        return Ok(ScriptResult::Skip);
+                    }
+                }
+                key => return Err(format!("unknown key {:?} in result map", key)),
+            }
+        }
+    } else {
+        return Err(format!("unexpected result type {}", value.type_name()));
+    }
+
+    Ok(ScriptResult::Run {
+        command_and_args,
+        job_options,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn job_script(script: &str) -> JobScript {
+        let engine = Engine::new();
+        let ast = engine.compile(script).unwrap();
+        JobScript {
+            compiled_script: Some(CompiledScript { engine, ast }),
+        }
+    }
+
+    fn command_and_args(args: &[&str]) -> OwnedCommandAndArgs {
+        OwnedCommandAndArgs::try_from(args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_apply() {
+        let script = job_script(
+            r#"
+            fn job(input, args) {
+                if input == "skip" {
+                    return false;
+                }
+                if input == "unchanged" {
+                    return ();
+                }
+                if input == "array" {
+                    return ["echo", "replaced", 1];
+                }
+                if input == "error" {
+                    throw "bad input";
+                }
+                args.push(input.to_upper());
+                #{ args: args, env: #{ NAME: "value", N: 2 }, cwd: "/tmp", timeout: 1.5 }
+            }
+            "#,
+        );
+
+        assert_eq!(
+            script.apply(command_and_args(&["echo"]), "skip"),
+            ScriptResult::Skip
+        );
+
+        assert_eq!(
+            script.apply(command_and_args(&["echo", "a"]), "unchanged"),
+            ScriptResult::Run {
+                command_and_args: command_and_args(&["echo", "a"]),
+                job_options: JobOptions::default(),
+            }
+        );
+
+        assert_eq!(
+            script.apply(command_and_args(&["echo"]), "array"),
+            ScriptResult::Run {
+                command_and_args: command_and_args(&["echo", "replaced", "1"]),
+                job_options: JobOptions::default(),
+            }
+        );
+
+        assert_eq!(
+            script.apply(command_and_args(&["echo"]), "error"),
+            ScriptResult::Skip
+        );
+
+        let ScriptResult::Run {
+            command_and_args: result_command_and_args,
+            mut job_options,
+        } = script.apply(command_and_args(&["echo", "a"]), "map")
+        else {
+            panic!("expected ScriptResult::Run");
+        };
+
+        job_options.env.sort();
+
+        assert_eq!(
+            result_command_and_args,
+            command_and_args(&["echo", "a", "MAP"])
+        );
+        assert_eq!(
+            job_options,
+            JobOptions {
+                env: vec![
+                    ("N".to_owned(), "2".to_owned()),
+                    ("NAME".to_owned(), "value".to_owned())
+                ],
+                cwd: Some(PathBuf::from("/tmp")),
+                timeout: Some(Duration::from_millis(1500)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_convert_result_errors() {
+        let convert = |value: Dynamic| convert_result(value, command_and_args(&["echo"]));
+
+        assert_eq!(
+            convert(Dynamic::from(1_i64)),
+            Err("unexpected result type i64".to_owned())
+        );
+        assert_eq!(
+            convert(Dynamic::from(Array::new())),
+            Err("args: empty input".to_owned())
+        );
+
+        let mut map = Map::new();
+        map.insert("timeout".into(), Dynamic::from("soon"));
+        assert_eq!(
+            convert(Dynamic::from(map)),
+            Err("timeout must be a number, got string".to_owned())
+        );
+
+        let mut map = Map::new();
+        map.insert("retries".into(), Dynamic::from(1_i64));
+        assert_eq!(
+            convert(Dynamic::from(map)),
+            Err("unknown key \"retries\" in result map".to_owned())
+        );
+
+        let mut map = Map::new();
+        map.insert("skip".into(), Dynamic::from(true));
+        assert_eq!(convert(Dynamic::from(map)), Ok(ScriptResult::Skip));
+    }
+}
@@ -0,0 +1,78 @@
+use tracing::{debug, info};
+
+use std::{path::PathBuf, sync::Arc, time::SystemTime};
+
+use crate::{command_line_args::CommandLineArgs, parser::regex::RegexProcessor};
+
+pub struct SkipIfExists {
+    path_template: Option<String>,
+    newer_than_template: Option<String>,
+    regex_processor: Arc<RegexProcessor>,
+}
+
+impl SkipIfExists {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Self> {
+        Ok(Self {
+            path_template: command_line_args.skip_if_exists.clone(),
+            newer_than_template: command_line_args.newer_than.clone(),
+            regex_processor: RegexProcessor::new(command_line_args)?,
+        })
+    }
+
+    fn expand_path(&self, path_template: &str, input_data: &str) -> Option<PathBuf> {
+        let input_data = if self.regex_processor.regex_mode() {
+            input_data
+        } else {
+            input_data.trim()
+        };
+
+        self.regex_processor
+            .expand_template(path_template, input_data)
+            .map(PathBuf::from)
+    }
+
+    async fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+        match tokio::fs::metadata(path).await.and_then(|m| m.modified()) {
+            Ok(modified_time) => Some(modified_time),
+            Err(e) => {
+                debug!("modified_time error path {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub async fn should_skip(&self, input_data: &str) -> bool {
+        let Some(path_template) = &self.path_template else {
+            return false;
+        };
+
+        let Some(path) = self.expand_path(path_template, input_data) else {
+            return false;
+        };
+
+        let Some(output_modified_time) = Self::modified_time(&path).await else {
+            return false;
+        };
+
+        if let Some(newer_than_template) = &self.newer_than_template {
+            let Some(input_path) = self.expand_path(newer_than_template, input_data) else {
+                return false;
+            };
+
+            let Some(input_modified_time) = Self::modified_time(&input_path).await else {
+                return false;
+            };
+
+            if input_modified_time > output_modified_time {
+                debug!(
+                    "input path {:?} is newer than output path {:?}",
+                    input_path, path
+                );
+                return false;
+            }
+        }
+
+        info!("skipping command for up to date output path {:?}", path);
+        true
+    }
+}
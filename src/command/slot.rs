@@ -0,0 +1,74 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+/// Hands out the lowest free job slot number, from 1 up to the number of jobs.
+///
+/// A slot is always available once a command semaphore permit is held.
+#[derive(Debug)]
+pub struct SlotPool {
+    free_slots: Mutex<BTreeSet<usize>>,
+}
+
+impl SlotPool {
+    pub fn new(jobs: usize) -> Arc<Self> {
+        Arc::new(Self {
+            free_slots: Mutex::new((1..=jobs).collect()),
+        })
+    }
+
+    pub fn acquire(self: &Arc<Self>) -> Slot {
+        let number = self
+            .free_slots
+            .lock()
+            .unwrap()
+            .pop_first()
+            .unwrap_or_default();
+
+        Slot {
+            number,
+            pool: Arc::clone(self),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Slot {
+    number: usize,
+    pool: Arc<SlotPool>,
+}
+
+impl Slot {
+    pub fn number(&self) -> usize {
+        self.number
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.pool.free_slots.lock().unwrap().insert(self.number);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slot_pool() {
+        let slot_pool = SlotPool::new(3);
+
+        let slot1 = slot_pool.acquire();
+        let slot2 = slot_pool.acquire();
+        let slot3 = slot_pool.acquire();
+
+        assert_eq!(slot1.number(), 1);
+        assert_eq!(slot2.number(), 2);
+        assert_eq!(slot3.number(), 3);
+
+        drop(slot2);
+
+        assert_eq!(slot_pool.acquire().number(), 2);
+    }
+}
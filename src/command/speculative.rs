@@ -0,0 +1,160 @@
+use tokio_util::sync::CancellationToken;
+
+use tracing::info;
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::command_line_args::CommandLineArgs;
+
+/// Percent of commands done before stragglers are re-run.
+const TAIL_PERCENT: u64 = 90;
+
+const ORDERING: Ordering = Ordering::SeqCst;
+
+/// Re-runs the slowest remaining commands with --speculative once most commands are done.
+#[derive(Debug, Default)]
+pub struct Speculator {
+    started: AtomicU64,
+    finished: AtomicU64,
+    finished_micros: AtomicU64,
+    all_started: AtomicBool,
+    tail_reached: CancellationToken,
+    copies_started: AtomicU64,
+    copies_won: AtomicU64,
+}
+
+impl Speculator {
+    pub fn new(command_line_args: &CommandLineArgs) -> Option<Self> {
+        command_line_args.speculative.then(Self::default)
+    }
+
+    fn check_tail(&self) {
+        let started = self.started.load(ORDERING);
+        let finished = self.finished.load(ORDERING);
+
+        if self.all_started.load(ORDERING)
+            && finished < started
+            && finished * 100 >= started * TAIL_PERCENT
+            && !self.tail_reached.is_cancelled()
+        {
+            info!(
+                "speculative: {} of {} commands done, re-running stragglers",
+                finished, started
+            );
+            self.tail_reached.cancel();
+        }
+    }
+
+    pub fn command_started(&self) {
+        self.started.fetch_add(1, ORDERING);
+    }
+
+    pub fn command_finished(&self, duration: Duration) {
+        self.finished_micros.fetch_add(
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+            ORDERING,
+        );
+        self.finished.fetch_add(1, ORDERING);
+        self.check_tail();
+    }
+
+    /// Called once all inputs have been dispatched, so the total number of commands is known.
+    pub fn all_commands_started(&self) {
+        self.all_started.store(true, ORDERING);
+        self.check_tail();
+    }
+
+    fn mean_duration(&self) -> Duration {
+        let finished = self.finished.load(ORDERING).max(1);
+
+        Duration::from_micros(self.finished_micros.load(ORDERING) / finished)
+    }
+
+    /// Completes when a command started at start_time is a straggler: the tail of the run is
+    /// reached and the command has run longer than the mean duration of finished commands.
+    pub async fn straggler(&self, start_time: Instant) {
+        self.tail_reached.cancelled().await;
+
+        tokio::time::sleep_until((start_time + self.mean_duration()).into()).await;
+    }
+
+    pub fn copy_started(&self) {
+        self.copies_started.fetch_add(1, ORDERING);
+    }
+
+    pub fn copy_won(&self) {
+        self.copies_won.fetch_add(1, ORDERING);
+    }
+
+    /// Summary of speculative copies, None if no copies were started.
+    pub fn summary(&self) -> Option<String> {
+        let copies_started = self.copies_started.load(ORDERING);
+        if copies_started == 0 {
+            return None;
+        }
+
+        Some(format!(
+            "speculatively re-ran {} straggler commands, {} copies finished first",
+            copies_started,
+            self.copies_won.load(ORDERING)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_straggler() {
+        let speculator = Speculator::new(&CommandLineArgs {
+            speculative: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let start_time = Instant::now();
+
+        for _ in 0..10 {
+            speculator.command_started();
+        }
+        for _ in 0..9 {
+            speculator.command_finished(Duration::from_millis(50));
+        }
+        assert!(!speculator.tail_reached.is_cancelled());
+
+        speculator.all_commands_started();
+        assert!(speculator.tail_reached.is_cancelled());
+
+        speculator.straggler(start_time).await;
+        assert!(start_time.elapsed() >= Duration::from_millis(50));
+
+        assert_eq!(speculator.summary(), None);
+        speculator.copy_started();
+        assert_eq!(
+            speculator.summary().as_deref(),
+            Some("speculatively re-ran 1 straggler commands, 0 copies finished first")
+        );
+    }
+
+    #[test]
+    fn test_no_tail() {
+        let speculator = Speculator::default();
+
+        for _ in 0..10 {
+            speculator.command_started();
+        }
+        for _ in 0..8 {
+            speculator.command_finished(Duration::from_secs(1));
+        }
+        speculator.all_commands_started();
+
+        assert!(!speculator.tail_reached.is_cancelled());
+
+        speculator.command_finished(Duration::from_secs(1));
+        assert!(speculator.tail_reached.is_cancelled());
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::Context;
+
+use tracing::debug;
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use crate::{command_line_args::CommandLineArgs, output::CommandStatus};
+
+const METRIC_PREFIX: &str = "rust_parallel";
+
+pub struct StatsdClient {
+    socket: Option<UdpSocket>,
+    tags: String,
+}
+
+impl StatsdClient {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Self> {
+        let Some(statsd) = &command_line_args.statsd else {
+            return Ok(Self {
+                socket: None,
+                tags: String::new(),
+            });
+        };
+
+        let address = statsd
+            .to_socket_addrs()
+            .with_context(|| format!("error resolving statsd address '{}'", statsd))?
+            .next()
+            .with_context(|| format!("no addresses found for statsd address '{}'", statsd))?;
+
+        let bind_address: SocketAddr = match address {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+
+        let socket = UdpSocket::bind(bind_address).context("statsd socket bind error")?;
+        socket
+            .connect(address)
+            .context("statsd socket connect error")?;
+        socket
+            .set_nonblocking(true)
+            .context("statsd socket set_nonblocking error")?;
+
+        Ok(Self {
+            socket: Some(socket),
+            tags: Self::tags(command_line_args),
+        })
+    }
+
+    /// DogStatsD tags identifying the run.
+    fn tags(command_line_args: &CommandLineArgs) -> String {
+        let mut tags = format!("|#run_id:{}", command_line_args.run_id);
+        if let Some(run_name) = &command_line_args.run_name {
+            tags.push_str(&format!(",run_name:{}", run_name));
+        }
+        tags
+    }
+
+    fn send(&self, metric: String) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        // Metrics are best effort, never block or fail a command because of them.
+        if let Err(e) = socket.send(metric.as_bytes()) {
+            debug!("statsd send error: {}", e);
+        }
+    }
+
+    fn increment(&self, name: &str) {
+        self.send(format!("{}.{}:1|c{}", METRIC_PREFIX, name, self.tags));
+    }
+
+    pub fn command_started(&self) {
+        self.increment("jobs.started");
+    }
+
+    pub fn command_finished(&self, status: CommandStatus, duration: Duration) {
+        if self.socket.is_none() {
+            return;
+        }
+
+        match status {
+            CommandStatus::Exited(exit_status) if exit_status.success() => {
+                self.increment("jobs.succeeded")
+            }
+            CommandStatus::Timeout => self.increment("jobs.timeout"),
+            _ => self.increment("jobs.failed"),
+        }
+
+        self.send(format!(
+            "{}.jobs.duration:{}|ms{}",
+            METRIC_PREFIX,
+            duration.as_millis(),
+            self.tags,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_statsd_client() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let command_line_args = CommandLineArgs {
+            statsd: Some(server.local_addr().unwrap().to_string()),
+            run_id: "r1".to_owned(),
+            run_name: Some("nightly".to_owned()),
+            ..Default::default()
+        };
+
+        let statsd_client = StatsdClient::new(&command_line_args).unwrap();
+
+        statsd_client.command_started();
+        statsd_client.command_finished(CommandStatus::Timeout, Duration::from_millis(1500));
+
+        let receive = || {
+            let mut buffer = [0u8; 256];
+            let len = server.recv(&mut buffer).unwrap();
+            String::from_utf8(buffer[..len].to_vec()).unwrap()
+        };
+
+        assert_eq!(
+            receive(),
+            "rust_parallel.jobs.started:1|c|#run_id:r1,run_name:nightly"
+        );
+        assert_eq!(
+            receive(),
+            "rust_parallel.jobs.timeout:1|c|#run_id:r1,run_name:nightly"
+        );
+        assert_eq!(
+            receive(),
+            "rust_parallel.jobs.duration:1500|ms|#run_id:r1,run_name:nightly"
+        );
+    }
+
+    #[test]
+    fn test_statsd_client_disabled() {
+        let statsd_client = StatsdClient::new(&CommandLineArgs::default()).unwrap();
+
+        assert!(statsd_client.socket.is_none());
+
+        statsd_client.command_started();
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Context;
+
+use tracing::{debug, info, warn};
+
+use std::{
+    io::ErrorKind,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    command_line_args::CommandLineArgs,
+    common::{JobOptions, OwnedCommandAndArgs},
+};
+
+pub const TMPDIR_PLACEHOLDER: &str = "{tmpdir}";
+
+pub const TMPDIR_ENV_VAR: &str = "PARALLEL_TMPDIR";
+
+/// Creates a unique temp directory for each job with --job-tmpdir.
+pub struct JobTmpDirs {
+    enabled: bool,
+    keep_on_fail: bool,
+    parent_dir: PathBuf,
+    next_id: AtomicU64,
+}
+
+impl JobTmpDirs {
+    pub fn new(command_line_args: &CommandLineArgs) -> Self {
+        Self {
+            enabled: command_line_args.job_tmpdir,
+            keep_on_fail: command_line_args.keep_tmp_on_fail,
+            parent_dir: std::env::temp_dir(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Create the temp directory for the next job, None without --job-tmpdir.
+    pub async fn create(&self) -> anyhow::Result<Option<JobTmpDir>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        loop {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+            let path = self
+                .parent_dir
+                .join(format!("rust-parallel-{}-{}", std::process::id(), id));
+
+            match tokio::fs::create_dir(&path).await {
+                Ok(()) => {
+                    debug!("created job tmpdir {:?}", path);
+                    return Ok(Some(JobTmpDir {
+                        path,
+                        keep_on_fail: self.keep_on_fail,
+                    }));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("error creating job tmpdir {:?}", path));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JobTmpDir {
+    path: PathBuf,
+    keep_on_fail: bool,
+}
+
+impl JobTmpDir {
+    /// Replace {tmpdir} in the command and set $PARALLEL_TMPDIR for the job.
+    pub fn apply(
+        &self,
+        command_and_args: OwnedCommandAndArgs,
+        mut job_options: JobOptions,
+    ) -> (OwnedCommandAndArgs, JobOptions) {
+        let path = self.path.to_string_lossy();
+
+        let OwnedCommandAndArgs { command_path, args } = command_and_args;
+
+        let command_path = match command_path.to_str() {
+            Some(command) if command.contains(TMPDIR_PLACEHOLDER) => {
+                PathBuf::from(command.replace(TMPDIR_PLACEHOLDER, &path))
+            }
+            _ => command_path,
+        };
+
+        let args = args
+            .into_iter()
+            .map(|arg| arg.replace(TMPDIR_PLACEHOLDER, &path))
+            .collect();
+
+        job_options
+            .env
+            .push((TMPDIR_ENV_VAR.to_owned(), path.into_owned()));
+
+        (OwnedCommandAndArgs { command_path, args }, job_options)
+    }
+
+    /// Remove the directory, unless the job failed and --keep-tmp-on-fail is set.
+    pub async fn cleanup(self, success: bool) {
+        if !success && self.keep_on_fail {
+            info!("keeping job tmpdir {:?} of failed command", self.path);
+            return;
+        }
+
+        if let Err(e) = tokio::fs::remove_dir_all(&self.path).await {
+            warn!("error removing job tmpdir {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_job_tmpdir() {
+        let job_tmpdirs = JobTmpDirs::new(&CommandLineArgs {
+            job_tmpdir: true,
+            ..Default::default()
+        });
+
+        let job_tmpdir = job_tmpdirs.create().await.unwrap().unwrap();
+        let path = job_tmpdir.path.clone();
+        assert!(path.is_dir());
+
+        let (command_and_args, job_options) = job_tmpdir.apply(
+            OwnedCommandAndArgs {
+                command_path: PathBuf::from("touch"),
+                args: vec!["{tmpdir}/out".to_owned()],
+            },
+            JobOptions::default(),
+        );
+
+        let path_string = path.to_string_lossy().into_owned();
+        assert_eq!(command_and_args.args, vec![format!("{}/out", path_string)]);
+        assert_eq!(
+            job_options.env,
+            vec![(TMPDIR_ENV_VAR.to_owned(), path_string)]
+        );
+
+        std::fs::write(path.join("out"), "").unwrap();
+        job_tmpdir.cleanup(false).await;
+        assert!(!path.exists());
+
+        assert!(JobTmpDirs::new(&CommandLineArgs::default())
+            .create()
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::Context;
+
+use tokio::sync::Mutex;
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    command_line_args::CommandLineArgs,
+    common::run_json_fields,
+    output::CommandStatus,
+    process::{exit_signal, signal_name},
+};
+
+use super::Command;
+
+/// Collects a Chrome trace event for each command, written to --trace-json at the end of the run.
+pub struct TraceRecorder {
+    path: Option<String>,
+    run_fields: serde_json::Map<String, serde_json::Value>,
+    start_time: Instant,
+    events: Mutex<Vec<serde_json::Value>>,
+}
+
+impl TraceRecorder {
+    pub fn new(command_line_args: &CommandLineArgs) -> Self {
+        Self {
+            path: command_line_args.trace_json.clone(),
+            run_fields: run_json_fields(command_line_args),
+            start_time: Instant::now(),
+            events: Mutex::new(vec![]),
+        }
+    }
+
+    fn status_string(status: CommandStatus) -> String {
+        match status {
+            CommandStatus::Exited(exit_status) => match exit_signal(&exit_status) {
+                Some(signal) => format!("signal({})", signal_name(signal)),
+                None => format!("exited({})", exit_status.code().unwrap_or_default()),
+            },
+            CommandStatus::Timeout => "timeout".to_owned(),
+            CommandStatus::SpawnError => "spawn_error".to_owned(),
+            CommandStatus::IOError => "io_error".to_owned(),
+            CommandStatus::DescendantLimit => "descendant_limit".to_owned(),
+            CommandStatus::Cancelled => "cancelled".to_owned(),
+        }
+    }
+
+    fn build_event(
+        &self,
+        command: &Command,
+        slot: usize,
+        start_time: Instant,
+        duration: Duration,
+        status: CommandStatus,
+    ) -> serde_json::Value {
+        let mut name = command.command_and_args.command_path.display().to_string();
+        for arg in &command.command_and_args.args {
+            name.push(' ');
+            name.push_str(arg);
+        }
+
+        serde_json::json!({
+            "name": name,
+            "cat": "command",
+            "ph": "X",
+            "ts": start_time.saturating_duration_since(self.start_time).as_micros() as u64,
+            "dur": duration.as_micros() as u64,
+            "pid": slot,
+            "tid": slot,
+            "args": {
+                "line": command.input_line_number.to_string(),
+                "status": Self::status_string(status),
+                "queue_wait_us": start_time.saturating_duration_since(command.enqueued_at).as_micros() as u64,
+            },
+        })
+    }
+
+    pub async fn record(
+        &self,
+        command: &Command,
+        slot: usize,
+        start_time: Instant,
+        duration: Duration,
+        status: CommandStatus,
+    ) {
+        if self.path.is_none() {
+            return;
+        }
+
+        let event = self.build_event(command, slot, start_time, duration, status);
+
+        self.events.lock().await.push(event);
+    }
+
+    pub async fn write(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let events = std::mem::take(&mut *self.events.lock().await);
+
+        let contents = serde_json::json!({
+            "traceEvents": events,
+            "metadata": self.run_fields,
+        })
+        .to_string();
+
+        tokio::fs::write(path, contents)
+            .await
+            .with_context(|| format!("error writing trace json file '{}'", path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use crate::{
+        common::{JobOptions, OwnedCommandAndArgs},
+        input::{Input, InputLineNumber},
+    };
+
+    #[test]
+    fn test_build_event() {
+        let trace_recorder = TraceRecorder::new(&CommandLineArgs::default());
+
+        let command = Command {
+            input_sequence: 1,
+            enqueued_at: trace_recorder.start_time + Duration::from_millis(2),
+            command_and_args: OwnedCommandAndArgs {
+                command_path: PathBuf::from("/bin/sleep"),
+                args: vec!["1".to_owned()],
+            },
+            job_options: JobOptions::default(),
+            input_line_number: InputLineNumber {
+                input: Input::CommandLineArgs,
+                line_number: 2,
+            },
+        };
+
+        let event = trace_recorder.build_event(
+            &command,
+            3,
+            trace_recorder.start_time + Duration::from_millis(5),
+            Duration::from_millis(1000),
+            CommandStatus::Timeout,
+        );
+
+        assert_eq!(
+            event,
+            serde_json::json!({
+                "name": "/bin/sleep 1",
+                "cat": "command",
+                "ph": "X",
+                "ts": 5000,
+                "dur": 1000000,
+                "pid": 3,
+                "tid": 3,
+                "args": {
+                    "line": "command_line_args:2",
+                    "status": "timeout",
+                    "queue_wait_us": 3000,
+                },
+            })
+        );
+    }
+}
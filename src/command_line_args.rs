@@ -1,11 +1,18 @@
-use clap::{Parser, ValueEnum};
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+
+use itertools::Itertools;
 
 use tokio::sync::OnceCell;
 
+use std::io::IsTerminal;
+
 use tracing::debug;
 
 pub const COMMANDS_FROM_ARGS_SEPARATOR: &str = ":::";
 
+/// ::: groups producing more commands than this need --yes or confirmation on a terminal.
+const CONFIRM_COMMANDS_FROM_ARGS: u64 = 100_000;
+
 /// Execute commands in parallel
 ///
 /// By Aaron Riekenberg <aaron.riekenberg@gmail.com>
@@ -20,28 +27,289 @@ pub struct CommandLineArgs {
     pub discard_output: Option<DiscardOutput>,
 
     /// Input file or - for stdin.  Defaults to stdin if no inputs are specified.
+    ///
+    /// Gzip and zstd compressed inputs are decompressed while reading, detected by a .gz or .zst
+    /// extension or by the first bytes of the input.
     #[arg(short, long)]
     pub input_file: Vec<String>,
 
+    /// Action when an input file cannot be opened.
+    #[arg(long, value_enum, default_value_t)]
+    pub missing_input: MissingInput,
+
+    /// Allow reading inputs from stdin when it is a terminal.
+    ///
+    /// Otherwise this is an error, because the inputs were most likely forgotten.
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// Run ::: groups that produce more than 100000 commands without asking for confirmation.
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Run this command and use its stdout as input instead of input files or stdin.
+    ///
+    /// COMMAND is run with sh -c (cmd /c on windows), see --on-input-cmd-fail for when it exits
+    /// with non-zero status.
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["input_file", "preprocess", "header"])]
+    pub input_cmd: Option<String>,
+
+    /// Action when the --input-cmd exits with non-zero status.
+    #[arg(long, value_enum, default_value_t, requires = "input_cmd")]
+    pub on_input_cmd_fail: OnInputCmdFail,
+
+    /// Filter each buffered input through this command before parsing.
+    ///
+    /// COMMAND is run with sh -c (cmd /c on windows) with the input on stdin, decompressed if it
+    /// is gzip or zstd compressed, its stdout is parsed as input lines.
+    #[arg(long, value_name = "COMMAND")]
+    pub preprocess: Option<String>,
+
+    /// Link input files: line N of each input file is combined into one command.
+    ///
+    /// {1}, {2}, ... are the lines from each input file.  Without placeholders the lines
+    /// are appended as arguments.  Stops at the end of the shortest file.
+    #[arg(long, requires = "input_file")]
+    pub link: bool,
+
+    /// Cross input files: run one command for every combination of one line from each input file.
+    ///
+    /// {1}, {2}, ... are the lines from each input file, as with --link.
+    /// All input lines are read before commands are started.
+    #[arg(long, requires = "input_file", conflicts_with = "link")]
+    pub cross: bool,
+
+    /// The first line of each input is a header of tab separated column names.
+    ///
+    /// Input lines are split on tabs and each column is available as a named placeholder
+    /// like {host}.  With --link or --cross the columns of all input files are combined.
+    #[arg(long, conflicts_with_all = ["regex", "preprocess"])]
+    pub header: bool,
+
+    /// Column names read from the input headers for --header.
+    #[arg(skip)]
+    pub header_columns: Vec<String>,
+
     /// Maximum number of commands to run in parallel, defauts to num cpus
     #[arg(short, long, default_value_t = num_cpus::get(), value_parser = Self::parse_semaphore_permits)]
     pub jobs: usize,
 
+    /// Keep output in the same order as commands were started, same as --order-by=start.
+    ///
+    /// Output of each command is held until all earlier commands have completed.
+    #[arg(short, long)]
+    pub keep_order: bool,
+
+    /// Order in which command output is written.
+    #[arg(long, value_enum, default_value_t, conflicts_with = "keep_order")]
+    pub order_by: OrderBy,
+
+    /// Write command output as it is read instead of when the command finishes.
+    ///
+    /// Output is forwarded in chunks rather than held in memory, so commands can write more
+    /// output than fits in memory.  Output of commands running at the same time can be
+    /// interleaved.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "keep_order", "order_by", "verbose_commands", "results", "tag", "hash_output",
+            "detect_duplicate_output", "merge_output", "reduce", "speculative", "retries", "fetch",
+        ],
+    )]
+    pub stream_output: bool,
+
+    /// Order in which inputs are run.
+    ///
+    /// lifo, sjf and fair read ahead all available input to choose the next command.
+    #[arg(long, value_enum, default_value_t)]
+    pub schedule: Schedule,
+
+    /// Template expanded for each input to a number, the cost used by --schedule=sjf.
+    ///
+    /// Inputs whose cost is not a number run last. With --use-history the cost defaults to the
+    /// historical duration of the command.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub job_cost: Option<String>,
+
+    /// Remember command durations across runs, to start the longest commands first and to
+    /// estimate the remaining time before commands have finished.
+    ///
+    /// With --schedule=sjf the shortest known commands start first instead. Commands without
+    /// history start before all others.
+    #[arg(long)]
+    pub use_history: bool,
+
+    /// File storing durations for --use-history.
+    ///
+    /// Defaults to rust-parallel/history.json under $XDG_CACHE_HOME or $HOME/.cache.
+    #[arg(long, value_name = "PATH", requires = "use_history")]
+    pub history_file: Option<String>,
+
+    /// Template expanded for each input to the key durations are stored under, defaults to the
+    /// command and its arguments.
+    #[arg(long, value_name = "TEMPLATE", requires = "use_history")]
+    pub history_key: Option<String>,
+
+    /// Adjust the number of running commands between 1 and --jobs based on command results.
+    ///
+    /// Concurrency is halved when commands fail or time out or p95 latency doubles, and raised
+    /// by one after a full round of healthy commands.
+    #[arg(long)]
+    pub adaptive_jobs: bool,
+
+    /// Once all inputs have started and at least 90% of commands are done, start a second copy
+    /// of each command still running longer than the mean command duration.
+    ///
+    /// The first copy to finish provides the result and the other copy is killed.
+    #[arg(long)]
+    pub speculative: bool,
+
+    /// Each input line starts with a column of per-job options, ended by a tab.
+    ///
+    /// Options are whitespace separated KEY=VALUE pairs: timeout (seconds), cwd, and env.NAME to
+    /// set environment variable NAME.  Lines without a tab have no options.  Lines with invalid
+    /// options are skipped.
+    #[arg(long, conflicts_with_all = ["link", "cross"])]
+    pub job_options_column: bool,
+
     /// Use null separator for reading input files instead of newline.
     #[arg(short('0'), long)]
     pub null_separator: bool,
 
-    /// Display progress bar.
+    /// Maximum length in bytes of a buffered input line.
+    ///
+    /// At most this many bytes of a line are kept in memory, see --on-oversized-line.
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_line_bytes: Option<u64>,
+
+    /// Action for buffered input lines longer than --max-line-bytes.
+    #[arg(long, value_enum, default_value_t, requires = "max_line_bytes")]
+    pub on_oversized_line: OnOversizedLine,
+
+    /// Display progress bar, with counts of queued and running commands.
     #[arg(short, long)]
     pub progress_bar: bool,
 
+    /// Display a progress bar per group in addition to the overall progress bar.
+    ///
+    /// The group key template is expanded for each input the same way as command arguments.
+    #[arg(long, value_name = "TEMPLATE", requires = "progress_bar")]
+    pub progress_group_by: Option<String>,
+
+    /// Write periodic progress events as JSON lines to a file descriptor number or path.
+    ///
+    /// The event fields are described in the README. Summaries of skipped input lines and
+    /// queue wait times are logged at exit.
+    #[arg(long, value_name = "FD|PATH")]
+    pub progress_json: Option<String>,
+
+    /// Name of this run, to tell runs apart in metrics and output files.
+    ///
+    /// Commands get the name in $PARALLEL_RUN_NAME.
+    #[arg(long, value_name = "NAME")]
+    pub run_name: Option<String>,
+
+    /// Identifier of this run, defaults to a random UUID.
+    ///
+    /// Included with the run name in --progress-json events, --trace-json metadata, --audit-log
+    /// entries and statsd metric tags.  Commands get the identifier in $PARALLEL_RUN_ID.
+    #[arg(long, value_name = "ID", default_value_t = uuid::Uuid::new_v4().to_string(), hide_default_value = true)]
+    pub run_id: String,
+
+    /// Hold an advisory lock on this file for the whole run.
+    ///
+    /// Fails if another run holds the lock, so scheduled runs sharing output or state files do
+    /// not overlap.
+    #[arg(long, value_name = "FILE")]
+    pub lock_file: Option<String>,
+
+    /// Wait for the --lock-file lock instead of failing when another run holds it.
+    #[arg(long, requires = "lock_file")]
+    pub wait_for_lock: bool,
+
+    /// Send statsd metrics for each command to this UDP address.
+    #[arg(long, value_name = "HOST:PORT")]
+    pub statsd: Option<String>,
+
+    /// Write a Chrome trace event file with one event per command, viewable in Perfetto.
+    ///
+    /// The pid of each event is the job slot that ran the command.
+    #[arg(long, value_name = "FILE")]
+    pub trace_json: Option<String>,
+
+    /// Write a tab separated line for each finished command to FILE.
+    ///
+    /// Columns are Seq (the input's position starting at 1), Starttime (seconds since the
+    /// epoch), JobRuntime (seconds), Exitval (-1 if the command did not exit), Signal, Command
+    /// and QueueWait (seconds from reading the input to starting the command).
+    #[arg(long, value_name = "FILE")]
+    pub joblog: Option<String>,
+
+    /// Skip inputs that completed with exit value 0 in --joblog, and append to it.
+    ///
+    /// Inputs are matched by Seq, so inputs must be read in the same order as the previous run.
+    #[arg(long, requires = "joblog")]
+    pub resume: bool,
+
+    /// Run only inputs that failed or did not exit in --joblog, and append to it.
+    ///
+    /// Inputs are matched by Seq like --resume, and keep their Seq in the appended lines.
+    #[arg(
+        long,
+        alias = "retry-failed",
+        requires = "joblog",
+        conflicts_with = "resume"
+    )]
+    pub resume_failed: bool,
+
+    /// Append a JSON line for each spawned process to this file.
+    ///
+    /// Each line has the start time, pid, resolved program path, argv array, environment
+    /// variables set or removed for the process, whether the environment was cleared by
+    /// --minimal-env, and working directory.
+    #[arg(long, value_name = "FILE")]
+    pub audit_log: Option<String>,
+
+    /// Destination for log messages.
+    #[arg(long, value_enum, default_value_t)]
+    pub log_target: LogTarget,
+
+    /// Log the time the controller spends parsing inputs, scheduling, spawning and awaiting
+    /// commands, and writing output, at exit.
+    ///
+    /// Times are measured while each stage is running on a thread, so they show where the
+    /// controller itself is the bottleneck at high job rates.
+    #[arg(long)]
+    pub self_profile: bool,
+
+    /// Mask this value in log messages, including dry run output and errors.
+    ///
+    /// May be specified multiple times.
+    #[arg(long, value_name = "VALUE")]
+    pub redact: Vec<String>,
+
+    /// Mask the value of this environment variable in log messages.
+    ///
+    /// May be specified multiple times.
+    #[arg(long, value_name = "VAR")]
+    pub redact_env: Vec<String>,
+
     /// Apply regex pattern to inputs.
     #[arg(short, long)]
     pub regex: Option<String>,
 
+    /// Action for inputs that do not match the regex.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_no_match: OnNoMatch,
+
+    /// Fail if the command template has placeholders that are not match keys of the regex,
+    /// or if a placeholder remains unexpanded in a command.
+    #[arg(long)]
+    pub strict_placeholders: bool,
+
     /// Use shell mode for running commands.
     ///
-    /// Each command line is passed to "<shell-path> <shell-argument>" as a single argument.
+    /// Each command line is passed to "<shell-path> <shell-argument>..." as a single argument.
     #[arg(short, long)]
     pub shell: bool,
 
@@ -49,10 +317,85 @@ pub struct CommandLineArgs {
     #[arg(short, long, value_parser = Self::parse_timeout_seconds)]
     pub timeout_seconds: Option<f64>,
 
+    /// When the timeout of a command starts.
+    #[arg(long, value_enum, default_value_t)]
+    pub deadline_from: DeadlineFrom,
+
+    /// Run commands that exit with non-zero status or time out again, up to COUNT times.
+    ///
+    /// Only the output and status of the last attempt are reported.
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    pub retries: u32,
+
+    /// Seconds to wait before the first retry with --retries, 0 retries immediately.
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.0, value_parser = Self::parse_retry_delay)]
+    pub retry_delay: f64,
+
+    /// Factor the delay is multiplied by after each retry with --retries.
+    #[arg(long, value_name = "FACTOR", default_value_t = 2.0, value_parser = Self::parse_retry_backoff)]
+    pub retry_backoff: f64,
+
+    /// Run commands with only PATH, HOME and LANG from the environment.
+    ///
+    /// Per-job variables are still set.  On windows the variables needed to start programs, like
+    /// SYSTEMROOT and PATHEXT, are kept too.
+    #[arg(long)]
+    pub minimal_env: bool,
+
+    /// Kill commands whose process tree grows beyond this many descendant processes.
+    ///
+    /// The process tree is checked periodically by walking /proc, only supported on Linux.
+    #[arg(long, value_name = "COUNT")]
+    pub max_descendants: Option<usize>,
+
+    /// Suspend the most recently started commands while available memory is below SIZE.
+    ///
+    /// Suspended commands are resumed one at a time when more than twice SIZE is available,
+    /// at least one command keeps running.  SIZE is in bytes with an optional K, M, G or T suffix.
+    /// With --process-group the whole process group is suspended.  Only supported on Linux.
+    #[arg(long, value_name = "SIZE", value_parser = Self::parse_memory_size)]
+    pub memsuspend: Option<u64>,
+
+    /// Run each command in its own process group.
+    ///
+    /// Timeouts, --halt now and Ctrl-C kill the whole process group, and process groups still
+    /// running after the run are reported.  On windows each command runs in a job object instead, and
+    /// processes still running in a job object are killed at the end of the run.
+    #[arg(long)]
+    pub process_group: bool,
+
+    /// At exit, report descendant processes of commands that are still running and fail the run.
+    ///
+    /// Only supported on Linux.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "report"
+    )]
+    pub audit_children: Option<AuditChildren>,
+
     /// Input and output channel capacity, defaults to num cpus * 2
     #[arg(long, default_value_t = num_cpus::get() * 2, value_parser = Self::parse_semaphore_permits)]
     pub channel_capacity: usize,
 
+    /// Input channel capacity in commands, defaults to --channel-capacity.
+    #[arg(long, value_name = "N", value_parser = Self::parse_semaphore_permits)]
+    pub input_channel_capacity: Option<usize>,
+
+    /// Output channel capacity in command outputs, defaults to --channel-capacity.
+    #[arg(long, value_name = "N", value_parser = Self::parse_semaphore_permits)]
+    pub output_channel_capacity: Option<usize>,
+
+    /// Also bound the output channel by the stdout and stderr bytes of queued outputs.
+    ///
+    /// Commands wait to send output while SIZE bytes are queued.  An output larger than SIZE is
+    /// sent alone.  SIZE is in bytes with an optional K, M, G or T suffix, at most 4G.
+    #[arg(long, value_name = "SIZE", value_parser = Self::parse_channel_bytes)]
+    pub output_channel_bytes: Option<u32>,
+
     /// Disable command path cache
     #[arg(long)]
     pub disable_path_cache: bool,
@@ -63,23 +406,270 @@ pub struct CommandLineArgs {
     #[arg(long)]
     pub dry_run: bool,
 
-    /// Exit on error mode
+    /// Explain how the regex was chosen and show expansions of the first few inputs.
     ///
-    /// Exit immediately when a command fails.
+    /// Does not run commands.
     #[arg(long)]
+    pub explain: bool,
+
+    /// Exit on error mode
+    ///
+    /// Exit immediately when a command fails.  Same as --halt soon,fail=1.
+    #[arg(long, conflicts_with = "halt")]
     pub exit_on_error: bool,
 
+    /// Halt the run when commands fail: WHEN,fail=N or WHEN,fail=N%.
+    ///
+    /// WHEN is soon to stop starting commands, or now to also kill running commands.  N% is a
+    /// percentage of all commands, so all inputs are read before commands are started, and inputs
+    /// must be given after ::: or with --input-file, not on stdin or with --input-cmd.
+    #[arg(long, value_name = "WHEN,fail=N[%]", value_parser = Self::parse_halt)]
+    pub halt: Option<Halt>,
+
+    /// Drain on SIGQUIT (Ctrl-\\): stop reading inputs, finish queued and running commands, then
+    /// exit normally.
+    ///
+    /// On windows Ctrl-Break drains.
+    #[arg(long)]
+    pub drain_on_quit: bool,
+
+    /// Fail the run if the inputs produce no commands.
+    ///
+    /// Protects automation from a mistyped regex or an empty input silently doing nothing.
+    #[arg(long)]
+    pub fail_if_empty: bool,
+
+    /// Fail before running any command unless the inputs produce MIN to MAX commands.
+    ///
+    /// Either bound may be left out, a single number is a minimum.  All inputs are read before
+    /// commands are started, so inputs must be given after ::: or with --input-file, not on stdin
+    /// or with --input-cmd.
+    #[arg(long, value_name = "MIN[..MAX]", value_parser = Self::parse_expect_jobs)]
+    pub expect_jobs: Option<ExpectJobs>,
+
+    /// Log a result for every completed command.
+    ///
+    /// This includes successful commands that produced no output.
+    #[arg(long)]
+    pub verbose_results: bool,
+
+    /// Write each command line, shell quoted, to stdout before the command's output.
+    ///
+    /// Commands are echoed even if they produce no output.  With --merge-output or --reduce
+    /// command lines are written to stderr.
+    #[arg(long)]
+    pub verbose_commands: bool,
+
+    /// Also write the output of each command to files in DIR/SEQ/.
+    ///
+    /// SEQ is the position of the input starting at 1, the same as Seq in the --joblog.
+    /// Each directory has cmd with the command line, stdout, stderr, and exitcode with the exit
+    /// code, 128 + the signal number for commands killed by a signal, or why the command did
+    /// not exit (timeout, spawn_error, io_error, descendant_limit or cancelled).
+    ///
+    /// DIR/index.jsonl has a JSON line for each command with its seq, input, input_line,
+    /// exit_code, duration_secs and stdout and stderr paths.  With --resume lines are appended,
+    /// and the last line for a seq is the latest result.
+    #[arg(long, value_name = "DIR")]
+    pub results: Option<String>,
+
+    /// Keep the N most recent results directories next to --results, including this run's.
+    ///
+    /// At startup other directories in the parent of DIR that have an index.jsonl are removed,
+    /// oldest first, so repeated runs into dated directories like results/2024-01-31 need no
+    /// separate cleanup.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), requires = "results")]
+    pub results_keep: Option<u64>,
+
+    /// Remove results directories next to --results last written more than DAYS days ago.
+    ///
+    /// Directories are found like with --results-keep.
+    #[arg(long, value_name = "DAYS", requires = "results")]
+    pub results_keep_days: Option<u64>,
+
+    /// Prefix each line of stdout and stderr with the input that produced it and a tab.
+    #[arg(long)]
+    pub tag: bool,
+
+    /// Write a JSON line with a digest of each command's stdout instead of the stdout.
+    ///
+    /// The line has the input line, command, stdout length in bytes and digest.
+    /// A line is written for every command that exited, including commands with no output.
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    pub hash_output: Option<HashAlgorithm>,
+
+    /// Log groups of inputs whose commands wrote identical stdout, at the end of the run.
+    ///
+    /// Outputs are compared by SHA-256 digest, empty outputs are not compared.
+    #[arg(long)]
+    pub detect_duplicate_output: bool,
+
+    /// Write the stdout of all commands to FILE instead of stdout.
+    ///
+    /// With :KEY the outputs are sorted by KEY expanded from each command's input, using the
+    /// same placeholders as the command, then by start order.  Large outputs are sorted in
+    /// temporary files.  Without :KEY outputs are written in the --order-by order.
+    #[arg(long, value_name = "FILE[:KEY]", value_parser = Self::parse_merge_output)]
+    pub merge_output: Option<MergeOutput>,
+
+    /// Pipe the stdout of all commands to the stdin of COMMAND, run with the system shell.
+    ///
+    /// The output of COMMAND becomes the output of the run.  Outputs are sent in start order,
+    /// or in input order with --order-by=input.  The run fails if COMMAND fails.
+    #[arg(long, value_name = "COMMAND", conflicts_with = "merge_output")]
+    pub reduce: Option<String>,
+
+    /// Partition outputs by KEY expanded from each command's input, with one --reduce command
+    /// per partition.
+    ///
+    /// KEY uses the same placeholders as the command.  Each reduce command has its key in
+    /// $PARALLEL_REDUCE_KEY, and is started when the first output for its key is written.
+    #[arg(long, value_name = "KEY", requires = "reduce")]
+    pub reduce_key: Option<String>,
+
+    /// Skip commands whose output file already exists.
+    ///
+    /// The path template is expanded for each input the same way as command arguments.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub skip_if_exists: Option<String>,
+
+    /// Only skip commands for --skip-if-exists if the output is not older than this input path.
+    ///
+    /// The path template is expanded for each input the same way as command arguments.
+    #[arg(long, value_name = "TEMPLATE", requires = "skip_if_exists")]
+    pub newer_than: Option<String>,
+
     /// Do not run commands for empty buffered input lines.
     #[arg(long)]
     pub no_run_if_empty: bool,
 
-    /// Path to shell to use for shell mode
-    #[arg(long, default_value = Self::default_shell())]
-    pub shell_path: String,
+    /// Rhai script with a job(input, args) function called for each input.
+    ///
+    /// args is an array of the command and its arguments.  The function returns
+    /// true or () to run the command unchanged, false to skip it, an array to replace
+    /// the command and arguments, or a map with optional keys args, env, cwd,
+    /// timeout (seconds), and skip.
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<String>,
+
+    /// External command that resolves {NAME} and {NAME:TEXT} placeholders, in the form NAME=COMMAND.
+    ///
+    /// COMMAND is run with sh -c (cmd /c on windows) with TEXT, or the input if there is no TEXT,
+    /// on stdin.  Its stdout without trailing newlines replaces the placeholder.
+    /// Results are cached.  May be specified multiple times.
+    #[arg(long, value_name = "NAME=COMMAND", value_parser = Self::parse_named_command)]
+    pub placeholder_cmd: Vec<NamedCommand>,
+
+    /// Shell command run just before each command starts to decide if it may start.
+    ///
+    /// It is run with sh -c (cmd /c on windows) with $PARALLEL_COMMAND, $PARALLEL_INPUT,
+    /// $PARALLEL_LINE and $PARALLEL_SLOT set for the job.  Exit status 0 starts the command,
+    /// otherwise --on-admit-fail applies.
+    #[arg(long, value_name = "COMMAND")]
+    pub admit_cmd: Option<String>,
+
+    /// Action when the --admit-cmd exits with non-zero status.
+    #[arg(long, value_enum, default_value_t, requires = "admit_cmd")]
+    pub on_admit_fail: OnAdmitFail,
+
+    /// Seconds to wait before running the --admit-cmd again with --on-admit-fail=delay.
+    #[arg(long, default_value_t = 1.0, value_parser = Self::parse_timeout_seconds, requires = "admit_cmd")]
+    pub admit_retry_seconds: f64,
+
+    /// Create a unique temp directory for each job, removed after the job completes.
+    ///
+    /// The path replaces {tmpdir} in the command and is set in $PARALLEL_TMPDIR.
+    #[arg(long)]
+    pub job_tmpdir: bool,
+
+    /// Keep the --job-tmpdir directory of failed jobs for debugging.
+    #[arg(long, requires = "job_tmpdir")]
+    pub keep_tmp_on_fail: bool,
+
+    /// Fetch mode: download URLs with a built-in HTTP client instead of running commands.
+    ///
+    /// The first argument of each command is the URL and the optional second argument is the
+    /// output file, otherwise the response body is written to stdout.  Files are written to
+    /// FILE.part and renamed when complete.  -t limits the time for all attempts of a URL.
+    #[arg(long, conflicts_with_all = ["shell", "speculative"])]
+    pub fetch: bool,
+
+    /// Number of retries for --fetch after connection errors, timeouts, 429 and 5xx responses.
+    ///
+    /// The delay between attempts starts at 500ms and doubles after each retry.
+    #[arg(long, value_name = "COUNT", default_value_t = 3, requires = "fetch")]
+    pub fetch_retries: u32,
+
+    /// Raw exec mode: never use a shell, and resolve commands like execvp on every platform.
+    ///
+    /// A command containing a path separator is used as is, otherwise the PATH entries are
+    /// searched in order for the exact command name, without PATHEXT extensions on windows.
+    /// Commands that do not resolve to an executable file are not run, this includes batch files
+    /// on windows.  Dry run output shows the exact argv as a JSON array.
+    #[arg(long, conflicts_with_all = ["shell", "disable_path_cache", "fetch"])]
+    pub raw_exec: bool,
+
+    /// Kind of shell to use for shell mode.
+    ///
+    /// Selects the default shell path and shell arguments, defaults to bash on unix and cmd on windows.
+    #[arg(long, value_enum, default_value_t)]
+    pub shell_kind: ShellKind,
 
-    /// Argument to shell for shell mode
-    #[arg(long, default_value = Self::default_shell_argument())]
-    pub shell_argument: String,
+    /// Use the $SHELL environment variable as shell path for shell mode.
+    ///
+    /// The shell kind is taken from the file name of $SHELL, e.g. fish for /usr/bin/fish, and
+    /// it is an error if the file name is not a known shell.  Falls back to /bin/sh on unix and
+    /// cmd on windows if $SHELL is not set.
+    #[arg(long, conflicts_with_all = ["shell_path", "shell_kind"])]
+    pub shell_auto: bool,
+
+    /// Path to shell to use for shell mode, overrides the path from --shell-kind
+    #[arg(long)]
+    pub shell_path: Option<String>,
+
+    /// Argument to shell for shell mode, overrides the arguments from --shell-kind
+    ///
+    /// May be specified multiple times for shells that need more than one argument.
+    #[arg(long, allow_hyphen_values = true)]
+    pub shell_argument: Vec<String>,
+
+    /// Define the shell functions in $PARALLEL_ENV_FUNCTIONS at the start of each shell mode command.
+    ///
+    /// Export the functions from the calling shell first, with
+    /// export PARALLEL_ENV_FUNCTIONS="$(declare -f NAME...)" in bash or
+    /// export PARALLEL_ENV_FUNCTIONS="$(functions NAME...)" in zsh.
+    /// Only supported with bash, sh and zsh.
+    #[arg(long, requires = "shell")]
+    pub env_functions: bool,
+
+    /// Source FILE at the start of each shell mode command.
+    ///
+    /// Use this for helper functions and variables shared by all commands.
+    /// Only supported with bash, sh and zsh.
+    #[arg(long, value_name = "FILE", requires = "shell", value_parser = Self::parse_init_file)]
+    pub init_file: Option<String>,
+
+    /// Read the command template from a file.
+    ///
+    /// The file contents are used before any command and initial arguments.
+    /// In shell mode the contents are passed to the shell as one script, which may span
+    /// multiple lines.  Otherwise the contents are split into words like sh does, so quoted
+    /// arguments may contain whitespace.
+    #[arg(long = "template-file", value_name = "FILE", value_parser = Self::parse_template_file)]
+    pub template: Option<String>,
+
+    /// Named command template for dispatch mode, in the form NAME=TEMPLATE.
+    ///
+    /// May be specified multiple times.
+    #[arg(long = "cmd", value_parser = Self::parse_named_command)]
+    pub named_commands: Vec<NamedCommand>,
+
+    /// Dispatch mode
+    ///
+    /// The first column of each buffered input line selects the --cmd template to run,
+    /// the rest of the line is used as input for that template.
+    #[arg(long, requires = "named_commands")]
+    pub dispatch: bool,
 
     /// Optional command and initial arguments.
     ///
@@ -95,7 +685,58 @@ impl CommandLineArgs {
 
         INSTANCE
             .get_or_init(|| async move {
-                let command_line_args = CommandLineArgs::parse();
+                let mut command_line_args = CommandLineArgs::parse();
+
+                if let Err(e) = command_line_args.apply_template() {
+                    Self::command().error(ErrorKind::ValueValidation, e).exit();
+                }
+
+                if let Err(e) = command_line_args.apply_shell_auto() {
+                    Self::command().error(ErrorKind::ValueValidation, e).exit();
+                }
+
+                if let Err(e) = command_line_args.confirm_commands_from_args() {
+                    Self::command().error(ErrorKind::ValueValidation, e).exit();
+                }
+
+                if command_line_args.reads_stdin()
+                    && !command_line_args.interactive
+                    && std::io::stdin().is_terminal()
+                {
+                    Self::command()
+                        .error(
+                            ErrorKind::MissingRequiredArgument,
+                            "stdin is a terminal, give inputs after ::: or with --input-file, or pass --interactive to type them",
+                        )
+                        .exit();
+                }
+
+                if let Some(option) = command_line_args.reads_all_inputs_first() {
+                    if command_line_args.reads_stdin() || command_line_args.input_cmd.is_some() {
+                        Self::command()
+                            .error(
+                                ErrorKind::ArgumentConflict,
+                                format!("{option} reads all inputs before starting commands, give inputs after ::: or with --input-file instead of stdin or --input-cmd"),
+                            )
+                            .exit();
+                    }
+                }
+
+                if let Err(e) = command_line_args.read_header_columns().await {
+                    Self::command().error(ErrorKind::Io, e).exit();
+                }
+
+                if command_line_args.schedule == Schedule::Sjf
+                    && command_line_args.job_cost.is_none()
+                    && !command_line_args.use_history
+                {
+                    Self::command()
+                        .error(
+                            ErrorKind::MissingRequiredArgument,
+                            "--schedule=sjf requires --job-cost <TEMPLATE> or --use-history",
+                        )
+                        .exit();
+                }
 
                 debug!("command_line_args = {:?}", command_line_args);
 
@@ -104,12 +745,236 @@ impl CommandLineArgs {
             .await
     }
 
+    pub fn input_channel_capacity(&self) -> usize {
+        self.input_channel_capacity.unwrap_or(self.channel_capacity)
+    }
+
+    pub fn output_channel_capacity(&self) -> usize {
+        self.output_channel_capacity
+            .unwrap_or(self.channel_capacity)
+    }
+
+    /// Output order from --order-by or --keep-order.
+    pub fn output_order(&self) -> OrderBy {
+        if self.keep_order || (self.reduce.is_some() && self.order_by == OrderBy::Completion) {
+            OrderBy::Start
+        } else {
+            self.order_by
+        }
+    }
+
+    /// True if --link or --cross combines lines from input files into argument groups.
+    pub fn combine_input_files(&self) -> bool {
+        self.link || self.cross
+    }
+
+    /// True if inputs are read from stdin.
+    pub fn reads_stdin(&self) -> bool {
+        !self.commands_from_args_mode()
+            && self.input_cmd.is_none()
+            && (self.input_file.is_empty() || self.input_file.iter().any(|s| s == "-"))
+    }
+
+    /// The option that needs all inputs read before commands start, if any.
+    pub fn reads_all_inputs_first(&self) -> Option<&'static str> {
+        if self.expect_jobs.is_some() {
+            Some("--expect-jobs")
+        } else if matches!(
+            self.halt,
+            Some(Halt {
+                fail: HaltFail::Percent(_),
+                ..
+            })
+        ) {
+            Some("--halt with fail=N%")
+        } else {
+            None
+        }
+    }
+
     pub fn commands_from_args_mode(&self) -> bool {
         self.command_and_initial_arguments
             .iter()
             .any(|s| s == COMMANDS_FROM_ARGS_SEPARATOR)
     }
 
+    /// Number of commands the cartesian product of the ::: groups produces, saturating at u64::MAX.
+    pub fn commands_from_args_count(&self) -> u64 {
+        if !self.commands_from_args_mode() {
+            return 0;
+        }
+
+        self.command_and_initial_arguments
+            .split(|arg| arg == COMMANDS_FROM_ARGS_SEPARATOR)
+            .skip(1)
+            .filter(|group| !group.is_empty())
+            .fold(1u64, |count, group| {
+                count.saturating_mul(group.len().try_into().unwrap_or(u64::MAX))
+            })
+    }
+
+    /// Ask before running an enormous number of commands from ::: groups, unless --yes is given.
+    ///
+    /// Without a terminal to ask on this is an error.
+    fn confirm_commands_from_args(&self) -> Result<(), String> {
+        let count = self.commands_from_args_count();
+        if self.yes || count <= CONFIRM_COMMANDS_FROM_ARGS {
+            return Ok(());
+        }
+
+        let message = format!("::: groups produce {count} commands");
+
+        if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+            return Err(format!("{message}, pass --yes to run them"));
+        }
+
+        eprint!("{message}, run them? [y/N] ");
+
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| format!("error reading confirmation: {e}"))?;
+
+        if matches!(answer.trim(), "y" | "Y" | "yes") {
+            Ok(())
+        } else {
+            Err(format!("{message}, not confirmed"))
+        }
+    }
+
+    /// The command template: arguments before the first ::: and all --cmd templates.
+    pub fn command_template(&self) -> String {
+        self.command_and_initial_arguments
+            .iter()
+            .take_while(|arg| *arg != COMMANDS_FROM_ARGS_SEPARATOR)
+            .chain(
+                self.named_commands
+                    .iter()
+                    .map(|named_command| &named_command.template),
+            )
+            .join(" ")
+    }
+
+    fn apply_template(&mut self) -> Result<(), String> {
+        let Some(template) = &self.template else {
+            return Ok(());
+        };
+
+        let template_arguments: Vec<String> = if self.shell {
+            vec![template.clone()]
+        } else {
+            shlex::split(template)
+                .ok_or("--template-file has an unterminated quote or trailing backslash")?
+        };
+
+        self.command_and_initial_arguments
+            .splice(0..0, template_arguments);
+
+        Ok(())
+    }
+
+    /// With --shell-auto use $SHELL as shell path, with the shell kind from its file name.
+    fn apply_shell_auto(&mut self) -> Result<(), String> {
+        if !self.shell_auto {
+            return Ok(());
+        }
+
+        let (shell_kind, shell_path) = match std::env::var("SHELL") {
+            Ok(shell) if !shell.is_empty() => match ShellKind::from_path(&shell) {
+                Some(shell_kind) => (shell_kind, shell),
+                None => {
+                    return Err(format!(
+                        "--shell-auto cannot tell the kind of shell $SHELL '{shell}' is, use --shell-path with --shell-kind or --shell-argument instead"
+                    ))
+                }
+            },
+            _ => {
+                let shell_kind = ShellKind::system();
+                (shell_kind, shell_kind.default_path().to_owned())
+            }
+        };
+
+        self.shell_kind = shell_kind;
+        self.shell_path = Some(shell_path);
+
+        Ok(())
+    }
+
+    async fn read_header_line(input_name: &str, line_separator: u8) -> Result<String, String> {
+        use std::io::BufRead;
+
+        let result = if input_name == "-" {
+            let mut line = vec![];
+            std::io::stdin()
+                .lock()
+                .read_until(line_separator, &mut line)
+                .map(|_| line)
+                .map_err(anyhow::Error::from)
+        } else {
+            crate::input::read_first_segment(input_name, line_separator).await
+        };
+        let mut line =
+            result.map_err(|e| format!("error reading header from `{input_name}`: {e:#}"))?;
+
+        if line.last() == Some(&line_separator) {
+            line.pop();
+        }
+
+        String::from_utf8(line).map_err(|_| format!("header of `{input_name}` is not valid UTF-8"))
+    }
+
+    fn parse_header_line(line: &str) -> Result<Vec<String>, String> {
+        let name_regex = regex::Regex::new("^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+
+        line.trim_end_matches('\r')
+            .split('\t')
+            .map(|name| {
+                if name_regex.is_match(name) {
+                    Ok(name.to_owned())
+                } else {
+                    Err(format!("invalid header column name `{name}`"))
+                }
+            })
+            .collect()
+    }
+
+    async fn read_header_columns(&mut self) -> Result<(), String> {
+        if !self.header {
+            return Ok(());
+        }
+
+        let line_separator = if self.null_separator { 0u8 } else { b'\n' };
+
+        let input_names = if self.input_file.is_empty() {
+            vec!["-".to_owned()]
+        } else {
+            self.input_file.clone()
+        };
+
+        let mut headers = Vec::with_capacity(input_names.len());
+        for input_name in &input_names {
+            headers.push(Self::parse_header_line(
+                &Self::read_header_line(input_name, line_separator).await?,
+            )?);
+        }
+
+        let header_columns: Vec<String> = if self.combine_input_files() {
+            headers.concat()
+        } else if headers.iter().all_equal() {
+            headers.swap_remove(0)
+        } else {
+            return Err("input files have different headers".to_owned());
+        };
+
+        if let Some(duplicate) = header_columns.iter().duplicates().next() {
+            return Err(format!("duplicate header column name `{duplicate}`"));
+        }
+
+        self.header_columns = header_columns;
+
+        Ok(())
+    }
+
     fn parse_semaphore_permits(s: &str) -> Result<usize, String> {
         let range = 1..=tokio::sync::Semaphore::MAX_PERMITS;
 
@@ -121,32 +986,170 @@ impl CommandLineArgs {
         }
     }
 
-    fn parse_timeout_seconds(s: &str) -> Result<f64, String> {
+    fn parse_template_file(s: &str) -> Result<String, String> {
+        let template =
+            std::fs::read_to_string(s).map_err(|e| format!("error reading `{s}`: {e}"))?;
+
+        let template = template.trim_end();
+        if template.is_empty() {
+            Err(format!("`{s}` is empty"))
+        } else {
+            Ok(template.to_owned())
+        }
+    }
+
+    fn parse_init_file(s: &str) -> Result<String, String> {
+        let metadata = std::fs::metadata(s).map_err(|e| format!("error reading `{s}`: {e}"))?;
+        if !metadata.is_file() {
+            return Err(format!("`{s}` is not a file"));
+        }
+
+        // absolute so commands run in another directory find it.  Not canonicalize, which on
+        // windows returns a \\?\ path that shells cannot source.
+        let path = std::path::absolute(s).map_err(|e| format!("error reading `{s}`: {e}"))?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    fn parse_named_command(s: &str) -> Result<NamedCommand, String> {
+        match s.split_once('=') {
+            Some((name, template)) if !name.trim().is_empty() && !template.trim().is_empty() => {
+                Ok(NamedCommand {
+                    name: name.trim().to_owned(),
+                    template: template.to_owned(),
+                })
+            }
+            _ => Err(format!("`{s}` is not in the form NAME=TEMPLATE")),
+        }
+    }
+
+    fn parse_merge_output(s: &str) -> Result<MergeOutput, String> {
+        // a windows drive letter is part of the path, not a separator
+        let skip = if cfg!(windows) && s.as_bytes().get(1) == Some(&b':') {
+            2
+        } else {
+            0
+        };
+
+        let (path, sort_key) = match s[skip..].find(':') {
+            Some(index) => (&s[..skip + index], Some(&s[skip + index + 1..])),
+            None => (s, None),
+        };
+
+        if path.is_empty() || sort_key.is_some_and(str::is_empty) {
+            return Err(format!("`{s}` is not in the form FILE[:KEY]"));
+        }
+
+        Ok(MergeOutput {
+            path: path.to_owned(),
+            sort_key: sort_key.map(str::to_owned),
+        })
+    }
+
+    fn parse_expect_jobs(s: &str) -> Result<ExpectJobs, String> {
+        let parse_bound = |bound: &str| {
+            bound
+                .parse::<u64>()
+                .map_err(|_| format!("`{s}` is not in the form MIN[..MAX]"))
+        };
+
+        let (min, max) = match s.split_once("..") {
+            None => (parse_bound(s)?, None),
+            Some((min, max)) => (
+                if min.is_empty() { 0 } else { parse_bound(min)? },
+                if max.is_empty() {
+                    None
+                } else {
+                    Some(parse_bound(max)?)
+                },
+            ),
+        };
+
+        if max.is_some_and(|max| max < min) {
+            return Err(format!("`{s}` has MAX less than MIN"));
+        }
+
+        Ok(ExpectJobs { min, max })
+    }
+
+    fn parse_halt(s: &str) -> Result<Halt, String> {
+        let invalid = || format!("`{s}` is not in the form WHEN,fail=N[%]");
+
+        let (when, fail) = s.split_once(',').ok_or_else(invalid)?;
+
+        let when = match when {
+            "soon" => HaltWhen::Soon,
+            "now" => HaltWhen::Now,
+            _ => return Err(format!("`{when}` is not soon or now")),
+        };
+
+        let fail = fail.strip_prefix("fail=").ok_or_else(invalid)?;
+        let fail = match fail.strip_suffix('%') {
+            Some(percent) => match percent.parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => HaltFail::Percent(percent),
+                _ => return Err(format!("`{fail}` is not a percentage from 0 to 100")),
+            },
+            None => match fail.parse::<u64>() {
+                Ok(count) if count > 0 => HaltFail::Count(count),
+                _ => return Err(format!("`{fail}` is not a count greater than 0")),
+            },
+        };
+
+        Ok(Halt { when, fail })
+    }
+
+    fn parse_memory_size(s: &str) -> Result<u64, String> {
+        let (digits, multiplier) = match s.char_indices().last() {
+            Some((i, suffix)) if suffix.is_ascii_alphabetic() => {
+                let multiplier: u64 = match suffix.to_ascii_uppercase() {
+                    'K' => 1 << 10,
+                    'M' => 1 << 20,
+                    'G' => 1 << 30,
+                    'T' => 1 << 40,
+                    _ => return Err(format!("`{s}` has an unknown size suffix")),
+                };
+                (&s[..i], multiplier)
+            }
+            _ => (s, 1),
+        };
+
+        let value: u64 = digits.parse().map_err(|_| format!("`{s}` isn't a size"))?;
+
+        match value.checked_mul(multiplier) {
+            Some(size) if size > 0 => Ok(size),
+            Some(_) => Err("value not greater than 0".to_string()),
+            None => Err(format!("`{s}` is too large")),
+        }
+    }
+
+    fn parse_channel_bytes(s: &str) -> Result<u32, String> {
+        let size = Self::parse_memory_size(s)?;
+        u32::try_from(size).map_err(|_| format!("`{s}` is larger than 4G"))
+    }
+
+    fn parse_retry_delay(s: &str) -> Result<f64, String> {
         let value: f64 = s.parse().map_err(|_| format!("`{s}` isn't a number"))?;
-        if value > 0f64 {
+        if value >= 0f64 && value.is_finite() {
             Ok(value)
         } else {
-            Err("value not greater than 0".to_string())
+            Err("value less than 0".to_string())
         }
     }
 
-    fn default_shell() -> &'static str {
-        if cfg!(unix) {
-            "/bin/bash"
-        } else if cfg!(windows) {
-            "cmd"
+    fn parse_retry_backoff(s: &str) -> Result<f64, String> {
+        let value: f64 = s.parse().map_err(|_| format!("`{s}` isn't a number"))?;
+        if value >= 1.0 && value.is_finite() {
+            Ok(value)
         } else {
-            unreachable!()
+            Err("value less than 1".to_string())
         }
     }
 
-    fn default_shell_argument() -> &'static str {
-        if cfg!(unix) {
-            "-c"
-        } else if cfg!(windows) {
-            "/c"
+    fn parse_timeout_seconds(s: &str) -> Result<f64, String> {
+        let value: f64 = s.parse().map_err(|_| format!("`{s}` isn't a number"))?;
+        if value > 0f64 {
+            Ok(value)
         } else {
-            unreachable!()
+            Err("value not greater than 0".to_string())
         }
     }
 }
@@ -161,6 +1164,260 @@ pub enum DiscardOutput {
     All,
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OnNoMatch {
+    /// Silently skip the input
+    Skip,
+    /// Log a warning and skip the input
+    #[default]
+    Warn,
+    /// Log an error and stop processing inputs, failing the run
+    Fail,
+    /// Run the command without regex substitutions
+    RunUnmodified,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OrderBy {
+    /// Order of the inputs, output is held until all earlier inputs have finished or been skipped
+    Input,
+    /// Order commands were started, output is held until all earlier commands have finished
+    Start,
+    /// Order commands finished, commands finishing together are written in start order
+    #[default]
+    Completion,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum HashAlgorithm {
+    /// SHA-256
+    Sha256,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Schedule {
+    /// Run inputs in the order they are read
+    #[default]
+    Fifo,
+    /// Run the most recently read input first
+    Lifo,
+    /// Shortest job first, run the input with the lowest --job-cost first
+    Sjf,
+    /// Take turns between input files with queued inputs, input files are read concurrently
+    Fair,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum DeadlineFrom {
+    /// When the command is spawned
+    #[default]
+    Spawn,
+    /// When the input is read and queued, so the timeout includes time waiting to start
+    Enqueue,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OnAdmitFail {
+    /// Wait --admit-retry-seconds and run the --admit-cmd again, later commands wait too
+    #[default]
+    Delay,
+    /// Skip the command
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum MissingInput {
+    /// Stop reading inputs and fail the run
+    #[default]
+    Error,
+    /// Log a warning and continue with the next input
+    Warn,
+    /// Continue with the next input
+    Ignore,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OnInputCmdFail {
+    /// Stop reading inputs and fail the run
+    #[default]
+    Fail,
+    /// Log a warning and end the input
+    Warn,
+    /// Log a warning and run the --input-cmd again after one second, the input continues
+    Restart,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OnOversizedLine {
+    /// Stop reading inputs and fail the run
+    Error,
+    /// Log a warning and use the first --max-line-bytes bytes of the line
+    Truncate,
+    /// Log a warning and skip the line
+    #[default]
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum AuditChildren {
+    /// Report leaked processes
+    Report,
+    /// Report and kill leaked processes
+    Kill,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum LogTarget {
+    /// Log to stdout
+    #[default]
+    Stdout,
+    /// Log to stderr
+    Stderr,
+    /// Log to the local syslog daemon at /dev/log
+    Syslog,
+    /// Log to the systemd journal
+    Journald,
+}
+
+/// Inclusive range of command counts for --expect-jobs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExpectJobs {
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
+impl ExpectJobs {
+    pub fn contains(&self, jobs: u64) -> bool {
+        jobs >= self.min && self.max.is_none_or(|max| jobs <= max)
+    }
+}
+
+impl std::fmt::Display for ExpectJobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) => write!(f, "{}..{}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// When and after how many failed commands --halt stops the run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Halt {
+    pub when: HaltWhen,
+    pub fail: HaltFail,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HaltWhen {
+    /// Stop starting commands, running commands finish
+    Soon,
+    /// Stop starting commands and kill running commands
+    Now,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HaltFail {
+    /// Number of failed commands
+    Count(u64),
+    /// Percentage of all commands that failed
+    Percent(f64),
+}
+
+/// File and optional sort key template for --merge-output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MergeOutput {
+    pub path: String,
+    pub sort_key: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NamedCommand {
+    pub name: String,
+    pub template: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ShellKind {
+    /// /bin/bash -c
+    Bash,
+    /// /bin/sh -c
+    Sh,
+    /// zsh -c
+    Zsh,
+    /// fish -c
+    Fish,
+    /// python3 -u -c
+    Python,
+    /// pwsh -NoProfile -Command
+    Pwsh,
+    /// cmd /c
+    Cmd,
+}
+
+impl ShellKind {
+    /// The platform shell used for helper commands: sh on unix and cmd on windows.
+    pub fn system() -> Self {
+        if cfg!(windows) {
+            Self::Cmd
+        } else {
+            Self::Sh
+        }
+    }
+
+    /// The kind of shell from the file name of its path, e.g. zsh for /usr/bin/zsh.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let name = std::path::Path::new(path)
+            .file_stem()?
+            .to_str()?
+            .to_ascii_lowercase();
+
+        match name.as_str() {
+            "bash" => Some(Self::Bash),
+            "sh" | "dash" | "ash" | "ksh" => Some(Self::Sh),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            "python" | "python3" => Some(Self::Python),
+            "pwsh" | "powershell" => Some(Self::Pwsh),
+            "cmd" => Some(Self::Cmd),
+            _ => None,
+        }
+    }
+
+    pub fn default_path(&self) -> &'static str {
+        match self {
+            Self::Bash => "/bin/bash",
+            Self::Sh => "/bin/sh",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+            Self::Python => "python3",
+            Self::Pwsh => "pwsh",
+            Self::Cmd => "cmd",
+        }
+    }
+
+    pub fn default_arguments(&self) -> &'static [&'static str] {
+        match self {
+            Self::Bash | Self::Sh | Self::Zsh | Self::Fish => &["-c"],
+            Self::Python => &["-u", "-c"],
+            Self::Pwsh => &["-NoProfile", "-Command"],
+            Self::Cmd => &["/c"],
+        }
+    }
+}
+
+impl Default for ShellKind {
+    fn default() -> Self {
+        if cfg!(unix) {
+            Self::Bash
+        } else if cfg!(windows) {
+            Self::Cmd
+        } else {
+            unreachable!()
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -171,4 +1428,291 @@ mod test {
 
         CommandLineArgs::command().debug_assert()
     }
+
+    #[test]
+    fn test_reads_stdin() {
+        assert!(CommandLineArgs::default().reads_stdin());
+
+        let command_line_args = CommandLineArgs {
+            input_file: vec!["file.txt".to_owned(), "-".to_owned()],
+            ..Default::default()
+        };
+        assert!(command_line_args.reads_stdin());
+
+        let command_line_args = CommandLineArgs {
+            input_file: vec!["file.txt".to_owned()],
+            ..Default::default()
+        };
+        assert!(!command_line_args.reads_stdin());
+
+        let command_line_args = CommandLineArgs {
+            command_and_initial_arguments: vec!["echo".to_owned(), ":::".to_owned()],
+            ..Default::default()
+        };
+        assert!(!command_line_args.reads_stdin());
+
+        let command_line_args = CommandLineArgs {
+            input_cmd: Some("ls".to_owned()),
+            ..Default::default()
+        };
+        assert!(!command_line_args.reads_stdin());
+    }
+
+    #[test]
+    fn test_reads_all_inputs_first() {
+        assert_eq!(CommandLineArgs::default().reads_all_inputs_first(), None);
+
+        let command_line_args = CommandLineArgs {
+            halt: Some(Halt {
+                when: HaltWhen::Soon,
+                fail: HaltFail::Count(1),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(command_line_args.reads_all_inputs_first(), None);
+
+        let command_line_args = CommandLineArgs {
+            halt: Some(Halt {
+                when: HaltWhen::Soon,
+                fail: HaltFail::Percent(10.0),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            command_line_args.reads_all_inputs_first(),
+            Some("--halt with fail=N%")
+        );
+
+        let command_line_args = CommandLineArgs {
+            expect_jobs: Some(ExpectJobs { min: 1, max: None }),
+            ..Default::default()
+        };
+        assert_eq!(
+            command_line_args.reads_all_inputs_first(),
+            Some("--expect-jobs")
+        );
+    }
+
+    #[test]
+    fn test_commands_from_args_count() {
+        let command_line_args = |args: &[&str]| CommandLineArgs {
+            command_and_initial_arguments: args.iter().map(|arg| arg.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(command_line_args(&["echo"]).commands_from_args_count(), 0);
+        assert_eq!(
+            command_line_args(&["echo", ":::", "a", "b", ":::", "1", "2", "3"])
+                .commands_from_args_count(),
+            6
+        );
+        assert_eq!(
+            command_line_args(&[":::", "a", ":::", ":::", "b", "c"]).commands_from_args_count(),
+            2
+        );
+
+        assert!(command_line_args(&[":::", "a", "b", ":::", "1", "2"])
+            .confirm_commands_from_args()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_header_line() {
+        assert_eq!(
+            CommandLineArgs::parse_header_line("host\tport_1\r"),
+            Ok(vec!["host".to_owned(), "port_1".to_owned()])
+        );
+        assert_eq!(
+            CommandLineArgs::parse_header_line("host\tmy port"),
+            Err("invalid header column name `my port`".to_owned())
+        );
+        assert_eq!(
+            CommandLineArgs::parse_header_line(""),
+            Err("invalid header column name ``".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_halt() {
+        assert_eq!(
+            CommandLineArgs::parse_halt("soon,fail=1"),
+            Ok(Halt {
+                when: HaltWhen::Soon,
+                fail: HaltFail::Count(1),
+            })
+        );
+        assert_eq!(
+            CommandLineArgs::parse_halt("now,fail=10%"),
+            Ok(Halt {
+                when: HaltWhen::Now,
+                fail: HaltFail::Percent(10.0),
+            })
+        );
+        assert!(CommandLineArgs::parse_halt("now").is_err());
+        assert!(CommandLineArgs::parse_halt("later,fail=1").is_err());
+        assert!(CommandLineArgs::parse_halt("soon,fail=0").is_err());
+        assert!(CommandLineArgs::parse_halt("soon,fail=150%").is_err());
+        assert!(CommandLineArgs::parse_halt("soon,success=1").is_err());
+    }
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(CommandLineArgs::parse_memory_size("4096"), Ok(4096));
+        assert_eq!(CommandLineArgs::parse_memory_size("2k"), Ok(2048));
+        assert_eq!(CommandLineArgs::parse_memory_size("1G"), Ok(1 << 30));
+        assert!(CommandLineArgs::parse_memory_size("0M").is_err());
+        assert!(CommandLineArgs::parse_memory_size("1X").is_err());
+        assert!(CommandLineArgs::parse_memory_size("G").is_err());
+        assert!(CommandLineArgs::parse_memory_size("99999999T").is_err());
+    }
+
+    #[test]
+    fn test_parse_channel_bytes() {
+        assert_eq!(CommandLineArgs::parse_channel_bytes("64K"), Ok(64 << 10));
+        assert_eq!(CommandLineArgs::parse_channel_bytes("3G"), Ok(3 << 30));
+        assert!(CommandLineArgs::parse_channel_bytes("4G").is_err());
+        assert!(CommandLineArgs::parse_channel_bytes("0").is_err());
+    }
+
+    #[test]
+    fn test_parse_retry_delay() {
+        assert_eq!(CommandLineArgs::parse_retry_delay("0"), Ok(0.0));
+        assert_eq!(CommandLineArgs::parse_retry_delay("0.5"), Ok(0.5));
+        assert!(CommandLineArgs::parse_retry_delay("-1").is_err());
+        assert!(CommandLineArgs::parse_retry_delay("inf").is_err());
+        assert!(CommandLineArgs::parse_retry_delay("x").is_err());
+    }
+
+    #[test]
+    fn test_parse_merge_output() {
+        assert_eq!(
+            CommandLineArgs::parse_merge_output("out.txt"),
+            Ok(MergeOutput {
+                path: "out.txt".to_owned(),
+                sort_key: None,
+            })
+        );
+        assert_eq!(
+            CommandLineArgs::parse_merge_output("out.txt:{2}:{1}"),
+            Ok(MergeOutput {
+                path: "out.txt".to_owned(),
+                sort_key: Some("{2}:{1}".to_owned()),
+            })
+        );
+        assert!(CommandLineArgs::parse_merge_output(":{1}").is_err());
+        assert!(CommandLineArgs::parse_merge_output("out.txt:").is_err());
+    }
+
+    #[test]
+    fn test_parse_expect_jobs() {
+        let expect_jobs = |min, max| Ok(ExpectJobs { min, max });
+
+        assert_eq!(
+            CommandLineArgs::parse_expect_jobs("5"),
+            expect_jobs(5, None)
+        );
+        assert_eq!(
+            CommandLineArgs::parse_expect_jobs("2..10"),
+            expect_jobs(2, Some(10))
+        );
+        assert_eq!(
+            CommandLineArgs::parse_expect_jobs("..10"),
+            expect_jobs(0, Some(10))
+        );
+        assert_eq!(
+            CommandLineArgs::parse_expect_jobs("3.."),
+            expect_jobs(3, None)
+        );
+        assert!(CommandLineArgs::parse_expect_jobs("10..2").is_err());
+        assert!(CommandLineArgs::parse_expect_jobs("x").is_err());
+        assert!(CommandLineArgs::parse_expect_jobs("1..2..3").is_err());
+
+        let range = ExpectJobs {
+            min: 2,
+            max: Some(3),
+        };
+        assert!(!range.contains(1));
+        assert!(range.contains(2));
+        assert!(range.contains(3));
+        assert!(!range.contains(4));
+    }
+
+    #[test]
+    fn test_parse_init_file() {
+        let init_file = CommandLineArgs::parse_init_file("Cargo.toml").unwrap();
+        assert!(std::path::Path::new(&init_file).is_absolute());
+        assert!(init_file.ends_with("Cargo.toml"));
+        assert!(!init_file.starts_with(r"\\?\"));
+
+        assert!(CommandLineArgs::parse_init_file("src").is_err());
+        assert!(CommandLineArgs::parse_init_file("does_not_exist.sh").is_err());
+    }
+
+    #[test]
+    fn test_shell_kind_from_path() {
+        assert_eq!(ShellKind::from_path("/bin/bash"), Some(ShellKind::Bash));
+        assert_eq!(ShellKind::from_path("/usr/bin/dash"), Some(ShellKind::Sh));
+        assert_eq!(ShellKind::from_path("zsh"), Some(ShellKind::Zsh));
+        assert_eq!(ShellKind::from_path("/usr/bin/fish"), Some(ShellKind::Fish));
+        assert_eq!(
+            ShellKind::from_path("/usr/local/bin/pwsh"),
+            Some(ShellKind::Pwsh)
+        );
+        assert_eq!(
+            ShellKind::from_path("PowerShell.exe"),
+            Some(ShellKind::Pwsh)
+        );
+        assert_eq!(ShellKind::from_path("cmd.exe"), Some(ShellKind::Cmd));
+        assert_eq!(ShellKind::from_path("/bin/tcsh"), None);
+        assert_eq!(ShellKind::from_path(""), None);
+    }
+
+    #[test]
+    fn test_apply_template() {
+        let mut command_line_args = CommandLineArgs {
+            template: Some("echo  hello\nworld".to_owned()),
+            command_and_initial_arguments: vec![":::".to_owned(), "A".to_owned()],
+            ..Default::default()
+        };
+
+        assert_eq!(command_line_args.apply_template(), Ok(()));
+
+        assert_eq!(
+            command_line_args.command_and_initial_arguments,
+            vec!["echo", "hello", "world", ":::", "A"]
+        );
+
+        let mut command_line_args = CommandLineArgs {
+            shell: true,
+            template: Some("echo  hello\nworld".to_owned()),
+            command_and_initial_arguments: vec![":::".to_owned(), "A".to_owned()],
+            ..Default::default()
+        };
+
+        assert_eq!(command_line_args.apply_template(), Ok(()));
+
+        assert_eq!(
+            command_line_args.command_and_initial_arguments,
+            vec!["echo  hello\nworld", ":::", "A"]
+        );
+
+        let mut command_line_args = CommandLineArgs {
+            template: Some("printf '%s|%s\\n' \"a b\" c\\ d".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(command_line_args.apply_template(), Ok(()));
+
+        assert_eq!(
+            command_line_args.command_and_initial_arguments,
+            vec!["printf", "%s|%s\\n", "a b", "c d"]
+        );
+
+        let mut command_line_args = CommandLineArgs {
+            template: Some("echo 'unterminated".to_owned()),
+            ..Default::default()
+        };
+
+        assert!(command_line_args.apply_template().is_err());
+    }
 }
@@ -1,4 +1,6 @@
-use std::{collections::VecDeque, path::PathBuf};
+use std::{collections::VecDeque, path::PathBuf, time::Duration};
+
+use crate::command_line_args::CommandLineArgs;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct OwnedCommandAndArgs {
@@ -12,6 +14,68 @@ impl std::fmt::Display for OwnedCommandAndArgs {
     }
 }
 
+impl OwnedCommandAndArgs {
+    /// The command line quoted so it can be pasted into a sh compatible shell.
+    pub fn shell_quoted(&self) -> String {
+        std::iter::once(sh_quote(&self.command_path.to_string_lossy()))
+            .chain(self.args.iter().map(|arg| sh_quote(arg)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Quote a string as a single word for sh compatible shells, if it has special characters.
+pub fn sh_quote(s: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+
+    if !s.is_empty() && s.chars().all(is_plain) {
+        s.to_owned()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+/// Settings for a single job that override the defaults from the command line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JobOptions {
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<PathBuf>,
+    pub timeout: Option<Duration>,
+}
+
+impl JobOptions {
+    /// Options set in other replace these, environment variables are added.
+    pub fn merge(mut self, other: JobOptions) -> Self {
+        self.env.extend(other.env);
+        self.cwd = other.cwd.or(self.cwd);
+        self.timeout = other.timeout.or(self.timeout);
+        self
+    }
+}
+
+/// Fields identifying the run from --run-id and --run-name, added to JSON output.
+pub fn run_json_fields(
+    command_line_args: &CommandLineArgs,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut fields = serde_json::Map::new();
+    fields.insert("run_id".into(), command_line_args.run_id.clone().into());
+    fields.insert("run_name".into(), command_line_args.run_name.clone().into());
+    fields
+}
+
+/// Reason an input line did not produce a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkippedInput {
+    /// Empty line or empty command.
+    Empty,
+    /// Line is not valid UTF-8.
+    InvalidUtf8,
+    /// Line did not match the regex.
+    Unmatched,
+    /// Line was rejected by another filter, e.g. an unknown dispatch name or placeholder error.
+    Filtered,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum OwnedCommandAndArgsConversionError {
     #[error("empty input")]
@@ -40,3 +104,30 @@ impl TryFrom<Vec<String>> for OwnedCommandAndArgs {
         Self::try_from(VecDeque::from(vec))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sh_quote() {
+        assert_eq!(sh_quote("plain-word_1.txt"), "plain-word_1.txt");
+        assert_eq!(sh_quote(""), "''");
+        assert_eq!(sh_quote("a b"), "'a b'");
+        assert_eq!(sh_quote("$HOME"), "'$HOME'");
+        assert_eq!(sh_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_shell_quoted() {
+        let command_and_args = OwnedCommandAndArgs {
+            command_path: PathBuf::from("/bin/bash"),
+            args: vec!["-c".to_owned(), "echo \"a b\" > out".to_owned()],
+        };
+
+        assert_eq!(
+            command_and_args.shell_quoted(),
+            r#"/bin/bash -c 'echo "a b" > out'"#
+        );
+    }
+}
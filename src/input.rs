@@ -8,17 +8,27 @@ use tokio::{
     task::JoinHandle,
 };
 
+use tokio_util::sync::CancellationToken;
+
 use tracing::debug;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
+
+use crate::{
+    command_line_args::CommandLineArgs,
+    common::{JobOptions, OwnedCommandAndArgs},
+    progress::Progress,
+};
 
-use crate::{command_line_args::CommandLineArgs, common::OwnedCommandAndArgs, progress::Progress};
+pub use self::buffered_reader::read_first_segment;
 
 #[derive(Debug, Clone, Copy)]
 pub enum BufferedInput {
     Stdin,
 
     File { file_name: &'static str },
+
+    Command { command: &'static str },
 }
 
 impl std::fmt::Display for BufferedInput {
@@ -26,6 +36,7 @@ impl std::fmt::Display for BufferedInput {
         match self {
             Self::Stdin => write!(f, "stdin"),
             Self::File { file_name } => write!(f, "{}", file_name),
+            Self::Command { .. } => write!(f, "input_cmd"),
         }
     }
 }
@@ -35,6 +46,10 @@ pub enum Input {
     Buffered(BufferedInput),
 
     CommandLineArgs,
+
+    LinkedInputFiles,
+
+    CrossedInputFiles,
 }
 
 impl std::fmt::Display for Input {
@@ -42,6 +57,8 @@ impl std::fmt::Display for Input {
         match self {
             Self::Buffered(b) => write!(f, "{}", b),
             Self::CommandLineArgs => write!(f, "command_line_args"),
+            Self::LinkedInputFiles => write!(f, "linked_input_files"),
+            Self::CrossedInputFiles => write!(f, "crossed_input_files"),
         }
     }
 }
@@ -59,7 +76,11 @@ impl std::fmt::Display for InputLineNumber {
 }
 
 enum InputList {
-    BufferedInputList(Vec<BufferedInput>),
+    Buffered(Vec<BufferedInput>),
+
+    Linked(Vec<BufferedInput>),
+
+    Crossed(Vec<BufferedInput>),
 
     CommandLineArgs,
 }
@@ -67,56 +88,79 @@ enum InputList {
 fn build_input_list(command_line_args: &'static CommandLineArgs) -> InputList {
     if command_line_args.commands_from_args_mode() {
         InputList::CommandLineArgs
+    } else if let Some(command) = &command_line_args.input_cmd {
+        InputList::Buffered(vec![BufferedInput::Command { command }])
     } else if command_line_args.input_file.is_empty() {
-        InputList::BufferedInputList(vec![BufferedInput::Stdin])
+        InputList::Buffered(vec![BufferedInput::Stdin])
     } else {
-        InputList::BufferedInputList(
-            command_line_args
-                .input_file
-                .iter()
-                .map(|input_name| {
-                    if input_name == "-" {
-                        BufferedInput::Stdin
-                    } else {
-                        BufferedInput::File {
-                            file_name: input_name,
-                        }
+        let buffered_inputs = command_line_args
+            .input_file
+            .iter()
+            .map(|input_name| {
+                if input_name == "-" {
+                    BufferedInput::Stdin
+                } else {
+                    BufferedInput::File {
+                        file_name: input_name,
                     }
-                })
-                .collect(),
-        )
+                }
+            })
+            .collect();
+
+        if command_line_args.link {
+            InputList::Linked(buffered_inputs)
+        } else if command_line_args.cross {
+            InputList::Crossed(buffered_inputs)
+        } else {
+            InputList::Buffered(buffered_inputs)
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct InputMessage {
+    /// Position of the input in the order inputs were read.
+    pub sequence: u64,
     pub command_and_args: OwnedCommandAndArgs,
+    pub job_options: JobOptions,
     pub input_line_number: InputLineNumber,
+    pub input_data: String,
+    /// When the input was read and queued.
+    pub enqueued_at: Instant,
 }
 
 pub struct InputProducer {
-    input_task_join_handle: JoinHandle<()>,
+    input_task_join_handle: JoinHandle<anyhow::Result<()>>,
     receiver: Receiver<InputMessage>,
+    input_cancellation_token: CancellationToken,
 }
 
 impl InputProducer {
     pub fn new(
         command_line_args: &'static CommandLineArgs,
         progress: &Arc<Progress>,
+        cancellation_token: &CancellationToken,
     ) -> anyhow::Result<Self> {
-        let (sender, receiver) = channel(command_line_args.channel_capacity);
-        debug!(
-            "created input channel with capacity {}",
-            command_line_args.channel_capacity
-        );
+        let channel_capacity = command_line_args.input_channel_capacity();
+        let (sender, receiver) = channel(channel_capacity);
+        debug!("created input channel with capacity {}", channel_capacity);
+
+        // cancelled with the run, or alone to stop reading inputs
+        let input_cancellation_token = cancellation_token.child_token();
 
-        let input_sender_task = task::InputTask::new(command_line_args, sender, progress)?;
+        let input_sender_task = task::InputTask::new(
+            command_line_args,
+            sender,
+            progress,
+            &input_cancellation_token,
+        )?;
 
         let input_task_join_handle = tokio::spawn(input_sender_task.run());
 
         Ok(Self {
             input_task_join_handle,
             receiver,
+            input_cancellation_token,
         })
     }
 
@@ -124,11 +168,18 @@ impl InputProducer {
         &mut self.receiver
     }
 
+    /// Stop reading inputs, inputs already read are still received.
+    pub fn stop(&self) {
+        self.input_cancellation_token.cancel();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.input_cancellation_token.is_cancelled()
+    }
+
     pub async fn wait_for_completion(self) -> anyhow::Result<()> {
         self.input_task_join_handle
             .await
-            .context("InputProducer::wait_for_completion: input_task_join_handle.await error")?;
-
-        Ok(())
+            .context("InputProducer::wait_for_completion: input_task_join_handle.await error")?
     }
 }
@@ -1,16 +1,226 @@
 use anyhow::Context;
 
-use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Split};
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
 
-use crate::command_line_args::CommandLineArgs;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+    process::{Child, Command},
+    task::JoinHandle,
+};
+
+use tracing::{info, warn};
+
+use std::{
+    io::IsTerminal,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    command_line_args::{CommandLineArgs, OnInputCmdFail, OnOversizedLine, ShellKind},
+    common::SkippedInput,
+    progress::Progress,
+};
 
 use super::{BufferedInput, Input, InputLineNumber};
 
 type AsyncBufReadBox = Box<dyn AsyncBufRead + Unpin + Send>;
 
+#[derive(thiserror::Error, Debug)]
+#[error("input line {input_line_number} is longer than --max-line-bytes {max_line_bytes}")]
+pub struct OversizedLineError {
+    input_line_number: InputLineNumber,
+    max_line_bytes: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("input command {command:?} failed: exit_status={}", exit_status.code().unwrap_or_default())]
+pub struct InputCommandError {
+    command: &'static str,
+    exit_status: ExitStatus,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("error opening input file file_name = '{file_name}': {error}")]
+pub struct MissingInputError {
+    file_name: &'static str,
+    error: std::io::Error,
+}
+
+const INPUT_COMMAND_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Running --preprocess command, fed the decompressed input by a copy task.
+struct Preprocess {
+    child: Child,
+    input_copy: JoinHandle<std::io::Result<u64>>,
+}
+
+/// Running --input-cmd producer whose stdout is read as input.
+struct InputCommand {
+    command: &'static str,
+    child: Child,
+    on_input_cmd_fail: OnInputCmdFail,
+}
+
+impl InputCommand {
+    async fn spawn(command: &'static str) -> anyhow::Result<(Child, AsyncBufReadBox)> {
+        let shell_kind = ShellKind::system();
+
+        let mut child = Command::new(shell_kind.default_path())
+            .args(shell_kind.default_arguments())
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("error spawning input command '{}'", command))?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .context("input command stdout missing")?;
+
+        let buf_reader = decompress(BufReader::new(stdout), None).await?;
+
+        Ok((child, buf_reader))
+    }
+}
+
+/// Reads separated segments keeping at most max_segment_bytes of each in memory.
+struct SegmentReader {
+    buf_reader: AsyncBufReadBox,
+    separator: u8,
+    max_segment_bytes: Option<usize>,
+}
+
+impl SegmentReader {
+    /// Returns the next segment and true if it was cut to max_segment_bytes.
+    async fn next_segment(&mut self) -> std::io::Result<Option<(Vec<u8>, bool)>> {
+        let mut segment = vec![];
+        let mut oversized = false;
+        let mut read_any = false;
+
+        loop {
+            let available = self.buf_reader.fill_buf().await?;
+            if available.is_empty() {
+                return Ok(read_any.then_some((segment, oversized)));
+            }
+            read_any = true;
+
+            let (data, consumed, found_separator) =
+                match available.iter().position(|b| *b == self.separator) {
+                    Some(i) => (&available[..i], i + 1, true),
+                    None => (available, available.len(), false),
+                };
+
+            let keep = match self.max_segment_bytes {
+                Some(max) if segment.len() + data.len() > max => {
+                    oversized = true;
+                    max - segment.len()
+                }
+                _ => data.len(),
+            };
+            segment.extend_from_slice(&data[..keep]);
+
+            self.buf_reader.consume(consumed);
+
+            if found_separator {
+                return Ok(Some((segment, oversized)));
+            }
+        }
+    }
+}
+
+/// Compression format of an input file, decompressed while it is read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_file_name(file_name: &str) -> Option<Self> {
+        if file_name.ends_with(".gz") {
+            Some(Self::Gzip)
+        } else if file_name.ends_with(".zst") {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// Wrap the reader in a decoder if the input is compressed.
+///
+/// The compression is detected from the file name extension, or else from the first bytes.
+async fn decompress<R>(
+    mut buf_reader: R,
+    file_name: Option<&str>,
+) -> std::io::Result<AsyncBufReadBox>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    let compression = match file_name.and_then(Compression::from_file_name) {
+        Some(compression) => Some(compression),
+        None => Compression::from_magic_bytes(buf_reader.fill_buf().await?),
+    };
+
+    Ok(match compression {
+        None => Box::new(buf_reader),
+        Some(Compression::Gzip) => {
+            let mut decoder = GzipDecoder::new(buf_reader);
+            decoder.multiple_members(true);
+            Box::new(BufReader::new(decoder))
+        }
+        Some(Compression::Zstd) => {
+            let mut decoder = ZstdDecoder::new(buf_reader);
+            decoder.multiple_members(true);
+            Box::new(BufReader::new(decoder))
+        }
+    })
+}
+
+/// Read the first segment of an input file, decompressing it if needed.
+pub async fn read_first_segment(file_name: &str, separator: u8) -> anyhow::Result<Vec<u8>> {
+    let file = tokio::fs::File::open(file_name)
+        .await
+        .with_context(|| format!("error opening input file file_name = '{}'", file_name))?;
+
+    let mut buf_reader = decompress(BufReader::new(file), Some(file_name)).await?;
+
+    let mut segment = vec![];
+    buf_reader.read_until(separator, &mut segment).await?;
+
+    Ok(segment)
+}
+
+/// Cut a truncated segment back to the last complete UTF-8 character.
+fn truncate_to_char_boundary(segment: &mut Vec<u8>) {
+    if let Err(e) = std::str::from_utf8(segment) {
+        if e.error_len().is_none() {
+            segment.truncate(e.valid_up_to());
+        }
+    }
+}
+
 pub struct BufferedInputReader {
     buffered_input: BufferedInput,
-    split: Split<AsyncBufReadBox>,
+    segment_reader: SegmentReader,
+    on_oversized_line: OnOversizedLine,
+    progress: Arc<Progress>,
+    preprocess: Option<Preprocess>,
+    input_command: Option<InputCommand>,
     next_line_number: usize,
 }
 
@@ -18,8 +228,39 @@ impl BufferedInputReader {
     pub async fn new(
         buffered_input: BufferedInput,
         command_line_args: &CommandLineArgs,
+        progress: &Arc<Progress>,
     ) -> anyhow::Result<Self> {
-        let buf_reader = Self::create_buf_reader(buffered_input).await?;
+        let mut input_command = None;
+
+        let (buf_reader, preprocess) = match (&command_line_args.preprocess, buffered_input) {
+            (_, BufferedInput::Command { command }) => {
+                let (child, buf_reader) = InputCommand::spawn(command).await?;
+                input_command = Some(InputCommand {
+                    command,
+                    child,
+                    on_input_cmd_fail: command_line_args.on_input_cmd_fail,
+                });
+                (buf_reader, None)
+            }
+            (None, _) => (Self::create_buf_reader(buffered_input).await?, None),
+            (Some(preprocess), _) => {
+                // compressed inputs are decompressed before they are piped to the command
+                let mut input = Self::create_buf_reader(buffered_input).await?;
+                let mut child = Self::spawn_preprocess(preprocess)?;
+                let mut stdin = child
+                    .stdin
+                    .take()
+                    .context("preprocess command stdin missing")?;
+                let stdout = child
+                    .stdout
+                    .take()
+                    .context("preprocess command stdout missing")?;
+                let input_copy =
+                    tokio::spawn(async move { tokio::io::copy_buf(&mut input, &mut stdin).await });
+                let buf_reader: AsyncBufReadBox = Box::new(BufReader::new(stdout));
+                (buf_reader, Some(Preprocess { child, input_copy }))
+            }
+        };
 
         let line_separator = if command_line_args.null_separator {
             0u8
@@ -27,48 +268,310 @@ impl BufferedInputReader {
             b'\n'
         };
 
-        let split = buf_reader.split(line_separator);
+        let mut segment_reader = SegmentReader {
+            buf_reader,
+            separator: line_separator,
+            max_segment_bytes: command_line_args
+                .max_line_bytes
+                .map(|max_line_bytes| max_line_bytes.try_into().unwrap_or(usize::MAX)),
+        };
+
+        let mut next_line_number = 0;
+
+        if command_line_args.header {
+            // the header of stdin was already consumed when reading the header columns
+            if let BufferedInput::File { .. } = buffered_input {
+                segment_reader.next_segment().await?;
+            }
+            next_line_number = 1;
+        }
 
         Ok(Self {
             buffered_input,
-            split,
-            next_line_number: 0,
+            segment_reader,
+            on_oversized_line: command_line_args.on_oversized_line,
+            progress: Arc::clone(progress),
+            preprocess,
+            input_command,
+            next_line_number,
         })
     }
 
+    fn spawn_preprocess(preprocess: &str) -> anyhow::Result<Child> {
+        let shell_kind = ShellKind::system();
+
+        Command::new(shell_kind.default_path())
+            .args(shell_kind.default_arguments())
+            .arg(preprocess)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("error spawning preprocess command '{}'", preprocess))
+    }
+
+    /// Wait for the --preprocess command to exit after its output was read, fails if it failed.
+    pub async fn wait_for_preprocess(&mut self) -> anyhow::Result<()> {
+        let Some(Preprocess {
+            mut child,
+            input_copy,
+        }) = self.preprocess.take()
+        else {
+            return Ok(());
+        };
+
+        let exit_status = child
+            .wait()
+            .await
+            .context("preprocess command wait error")?;
+
+        match input_copy
+            .await
+            .context("preprocess input copy join error")?
+        {
+            // the command may exit without reading all of its input
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "error reading input {} for preprocess command",
+                        self.buffered_input
+                    )
+                });
+            }
+            _ => {}
+        }
+
+        if !exit_status.success() {
+            anyhow::bail!(
+                "preprocess command failed for input {}: exit_status={}",
+                self.buffered_input,
+                exit_status.code().unwrap_or_default()
+            );
+        }
+
+        Ok(())
+    }
+
     async fn create_buf_reader(buffered_input: BufferedInput) -> anyhow::Result<AsyncBufReadBox> {
         match buffered_input {
             BufferedInput::Stdin => {
+                if std::io::stdin().is_terminal() {
+                    info!(
+                        "reading inputs from the terminal, end with {}",
+                        if cfg!(windows) {
+                            "Ctrl-Z Enter"
+                        } else {
+                            "Ctrl-D"
+                        }
+                    );
+                }
+
                 let buf_reader = BufReader::new(tokio::io::stdin());
 
-                Ok(Box::new(buf_reader))
+                Ok(decompress(buf_reader, None).await?)
             }
             BufferedInput::File { file_name } => {
-                let file = tokio::fs::File::open(file_name).await.with_context(|| {
-                    format!("error opening input file file_name = '{}'", file_name)
-                })?;
+                let file = tokio::fs::File::open(file_name)
+                    .await
+                    .map_err(|error| MissingInputError { file_name, error })?;
                 let buf_reader = BufReader::new(file);
 
-                Ok(Box::new(buf_reader))
+                Ok(decompress(buf_reader, Some(file_name)).await?)
+            }
+            BufferedInput::Command { .. } => unreachable!("spawned by InputCommand::spawn"),
+        }
+    }
+
+    /// Wait for the --input-cmd at the end of its output, returns true if it was restarted.
+    async fn input_command_finished(&mut self) -> anyhow::Result<bool> {
+        let Some(mut input_command) = self.input_command.take() else {
+            return Ok(false);
+        };
+
+        let exit_status = input_command
+            .child
+            .wait()
+            .await
+            .context("input command wait error")?;
+
+        if exit_status.success() {
+            return Ok(false);
+        }
+
+        let error = InputCommandError {
+            command: input_command.command,
+            exit_status,
+        };
+
+        match input_command.on_input_cmd_fail {
+            OnInputCmdFail::Fail => Err(error.into()),
+            OnInputCmdFail::Warn => {
+                warn!("{}", error);
+                Ok(false)
+            }
+            OnInputCmdFail::Restart => {
+                warn!("{}, restarting in {:?}", error, INPUT_COMMAND_RESTART_DELAY);
+                tokio::time::sleep(INPUT_COMMAND_RESTART_DELAY).await;
+
+                let (child, buf_reader) = InputCommand::spawn(input_command.command).await?;
+                input_command.child = child;
+                self.segment_reader.buf_reader = buf_reader;
+                self.input_command = Some(input_command);
+                Ok(true)
             }
         }
     }
 
     pub async fn next_segment(&mut self) -> anyhow::Result<Option<(InputLineNumber, Vec<u8>)>> {
-        let segment = self.split.next_segment().await?;
+        loop {
+            let Some((mut segment, oversized)) = self.segment_reader.next_segment().await? else {
+                if self.input_command_finished().await? {
+                    continue;
+                }
+                return Ok(None);
+            };
 
-        match segment {
-            None => Ok(None),
-            Some(segment) => {
-                self.next_line_number += 1;
+            self.next_line_number += 1;
 
-                let input_line_number = InputLineNumber {
-                    input: Input::Buffered(self.buffered_input),
-                    line_number: self.next_line_number,
-                };
+            let input_line_number = InputLineNumber {
+                input: Input::Buffered(self.buffered_input),
+                line_number: self.next_line_number,
+            };
 
-                Ok(Some((input_line_number, segment)))
+            if oversized {
+                let max_line_bytes = segment.len();
+                match self.on_oversized_line {
+                    OnOversizedLine::Error => {
+                        return Err(OversizedLineError {
+                            input_line_number,
+                            max_line_bytes,
+                        }
+                        .into());
+                    }
+                    OnOversizedLine::Truncate => {
+                        warn!(
+                            "truncating input line {} to --max-line-bytes {}",
+                            input_line_number, max_line_bytes
+                        );
+                        truncate_to_char_boundary(&mut segment);
+                    }
+                    OnOversizedLine::Skip => {
+                        warn!(
+                            "skipping input line {} longer than --max-line-bytes {}",
+                            input_line_number, max_line_bytes
+                        );
+                        self.progress.input_skipped(SkippedInput::Filtered);
+                        continue;
+                    }
+                }
             }
+
+            return Ok(Some((input_line_number, segment)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn read_all(
+        input: &'static [u8],
+        max_segment_bytes: Option<usize>,
+    ) -> Vec<(Vec<u8>, bool)> {
+        let mut segment_reader = SegmentReader {
+            buf_reader: Box::new(BufReader::with_capacity(4, input)),
+            separator: b'\n',
+            max_segment_bytes,
+        };
+
+        let mut result = vec![];
+        while let Some(segment) = segment_reader.next_segment().await.unwrap() {
+            result.push(segment);
         }
+        result
+    }
+
+    #[tokio::test]
+    async fn test_segment_reader() {
+        assert_eq!(
+            read_all(b"ab\n\ncdefghij\nk", None).await,
+            vec![
+                (b"ab".to_vec(), false),
+                (b"".to_vec(), false),
+                (b"cdefghij".to_vec(), false),
+                (b"k".to_vec(), false),
+            ]
+        );
+
+        assert_eq!(
+            read_all(b"ab\ncdefghij\nklm\n", Some(3)).await,
+            vec![
+                (b"ab".to_vec(), false),
+                (b"cde".to_vec(), true),
+                (b"klm".to_vec(), false),
+            ]
+        );
+
+        assert!(read_all(b"", Some(3)).await.is_empty());
+    }
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(
+            Compression::from_file_name("urls.txt.gz"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_file_name("urls.zst"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::from_file_name("urls.txt"), None);
+
+        assert_eq!(
+            Compression::from_magic_bytes(&[0x1f, 0x8b, 0x08]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_magic_bytes(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::from_magic_bytes(b"http://"), None);
+        assert_eq!(Compression::from_magic_bytes(b""), None);
+    }
+
+    #[tokio::test]
+    async fn test_decompress() {
+        use async_compression::tokio::bufread::GzipEncoder;
+        use tokio::io::AsyncReadExt;
+
+        let mut compressed = vec![];
+        GzipEncoder::new(&b"a\nb\n"[..])
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        let mut buf_reader = decompress(BufReader::new(std::io::Cursor::new(compressed)), None)
+            .await
+            .unwrap();
+        let mut decompressed = String::new();
+        buf_reader.read_to_string(&mut decompressed).await.unwrap();
+        assert_eq!(decompressed, "a\nb\n");
+
+        let mut buf_reader = decompress(&b"plain\n"[..], None).await.unwrap();
+        let mut plain = String::new();
+        buf_reader.read_to_string(&mut plain).await.unwrap();
+        assert_eq!(plain, "plain\n");
+    }
+
+    #[test]
+    fn test_truncate_to_char_boundary() {
+        let mut segment = "aé".as_bytes()[..2].to_vec();
+        truncate_to_char_boundary(&mut segment);
+        assert_eq!(segment, b"a");
+
+        let mut segment = b"abc".to_vec();
+        truncate_to_char_boundary(&mut segment);
+        assert_eq!(segment, b"abc");
     }
 }
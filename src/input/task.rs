@@ -1,20 +1,33 @@
 use anyhow::Context;
 
-use tokio::sync::mpsc::Sender;
+use itertools::Itertools;
+
+use tokio::{sync::mpsc::Sender, task::JoinSet};
+
+use tokio_util::sync::CancellationToken;
 
 use tracing::{debug, instrument, warn};
 
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use crate::{
-    command_line_args::CommandLineArgs,
+    command_line_args::{CommandLineArgs, MissingInput, Schedule},
+    common::{JobOptions, OwnedCommandAndArgs, SkippedInput},
     parser::{buffered::BufferedInputLineParser, command_line::CommandLineArgsParser, Parsers},
     progress::Progress,
 };
 
 use super::{
-    buffered_reader::BufferedInputReader, BufferedInput, Input, InputLineNumber, InputList,
-    InputMessage,
+    buffered_reader::{
+        BufferedInputReader, InputCommandError, MissingInputError, OversizedLineError,
+    },
+    BufferedInput, Input, InputLineNumber, InputList, InputMessage,
 };
 
 pub struct InputTask {
@@ -22,6 +35,8 @@ pub struct InputTask {
     command_line_args: &'static CommandLineArgs,
     progress: Arc<Progress>,
     parsers: Parsers,
+    next_sequence: AtomicU64,
+    cancellation_token: CancellationToken,
 }
 
 impl InputTask {
@@ -29,6 +44,7 @@ impl InputTask {
         command_line_args: &'static CommandLineArgs,
         sender: Sender<InputMessage>,
         progress: &Arc<Progress>,
+        cancellation_token: &CancellationToken,
     ) -> anyhow::Result<Self> {
         let parsers = Parsers::new(command_line_args)?;
         Ok(Self {
@@ -36,14 +52,42 @@ impl InputTask {
             command_line_args,
             progress: Arc::clone(progress),
             parsers,
+            next_sequence: AtomicU64::new(0),
+            cancellation_token: cancellation_token.clone(),
         })
     }
 
-    async fn send(&self, input_message: InputMessage) {
-        self.progress.increment_total_commands(1);
+    async fn send(
+        &self,
+        command_and_args: OwnedCommandAndArgs,
+        job_options: JobOptions,
+        input_line_number: InputLineNumber,
+        input_data: String,
+    ) {
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+
+        let input_message = InputMessage {
+            sequence,
+            command_and_args,
+            job_options,
+            input_line_number,
+            input_data,
+            enqueued_at: Instant::now(),
+        };
+
+        self.progress
+            .increment_total_commands(&input_message.input_data);
 
-        if let Err(e) = self.sender.send(input_message).await {
-            warn!("input sender send error: {}", e);
+        tokio::select! {
+            _ = self.cancellation_token.cancelled() => {
+                debug!("input sender cancelled");
+            }
+
+            result = self.sender.send(input_message) => {
+                if let Err(e) = result {
+                    warn!("input sender send error: {}", e);
+                }
+            }
         }
     }
 
@@ -60,12 +104,30 @@ impl InputTask {
         input_line_number: InputLineNumber,
         segment: Vec<u8>,
     ) {
-        if let Some(command_and_args) = parser.parse_segment(segment) {
-            self.send(InputMessage {
-                command_and_args,
-                input_line_number,
-            })
-            .await
+        let Ok(input_line) = std::str::from_utf8(&segment) else {
+            self.progress.input_skipped(SkippedInput::InvalidUtf8);
+            return;
+        };
+
+        let (job_options, input_line) = match parser.split_job_options(input_line) {
+            Ok(split) => split,
+            Err(skipped_input) => {
+                self.progress.input_skipped(skipped_input);
+                return;
+            }
+        };
+
+        match parser.parse_line(input_line) {
+            Ok(command_and_args) => {
+                self.send(
+                    command_and_args,
+                    job_options,
+                    input_line_number,
+                    input_line.to_owned(),
+                )
+                .await
+            }
+            Err(skipped_input) => self.progress.input_skipped(skipped_input),
         }
     }
 
@@ -76,22 +138,32 @@ impl InputTask {
         );
 
         let mut input_reader =
-            BufferedInputReader::new(buffered_input, self.command_line_args).await?;
+            BufferedInputReader::new(buffered_input, self.command_line_args, &self.progress)
+                .await?;
 
         let parser = self.parsers.buffered_input_line_parser().await;
 
         loop {
-            match input_reader
-                .next_segment()
-                .await
-                .context("next_segment error")?
-            {
+            let next_segment = tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    debug!("process_buffered_input cancelled");
+                    break;
+                }
+
+                next_segment = input_reader.next_segment() => next_segment,
+            };
+
+            match next_segment.context("next_segment error")? {
                 Some((input_line_number, segment)) => {
                     self.process_buffered_input_line(parser, input_line_number, segment)
-                        .await
+                        .await;
+                    if self.parsers.input_failure().is_some() {
+                        break;
+                    }
                 }
                 None => {
                     debug!("input_reader.next_segment EOF");
+                    input_reader.wait_for_preprocess().await?;
                     break;
                 }
             }
@@ -112,23 +184,33 @@ impl InputTask {
         parser: &mut CommandLineArgsParser,
         input_line_number: InputLineNumber,
     ) {
-        if let Some(command_and_args) = parser.parse_next_argument_group() {
-            self.send(InputMessage {
-                command_and_args,
-                input_line_number,
-            })
-            .await
-        };
+        let input_data = parser.next_argument_group_input().unwrap_or_default();
+
+        match parser.parse_next_argument_group() {
+            Ok(command_and_args) => {
+                self.send(
+                    command_and_args,
+                    JobOptions::default(),
+                    input_line_number,
+                    input_data,
+                )
+                .await
+            }
+            Err(skipped_input) => self.progress.input_skipped(skipped_input),
+        }
     }
 
-    async fn process_command_line_args_input(self) {
+    async fn process_command_line_args_input(&self) {
         debug!("begin process_command_line_args_input");
 
         let mut parser = self.parsers.command_line_args_parser();
 
         let mut line_number = 0;
 
-        while parser.has_remaining_argument_groups() {
+        while parser.has_remaining_argument_groups()
+            && !self.cancellation_token.is_cancelled()
+            && self.parsers.input_failure().is_none()
+        {
             line_number += 1;
 
             let input_line_number = InputLineNumber {
@@ -141,24 +223,271 @@ impl InputTask {
         }
     }
 
+    /// Read the next line from each input, None at the end of any input.
+    async fn next_linked_row(
+        input_readers: &mut [BufferedInputReader],
+    ) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        let mut row = Vec::with_capacity(input_readers.len());
+
+        for (i, input_reader) in input_readers.iter_mut().enumerate() {
+            match input_reader
+                .next_segment()
+                .await
+                .context("next_segment error")?
+            {
+                Some((_, segment)) => row.push(segment),
+                None => {
+                    let mut remaining_lines = i > 0;
+                    for input_reader in &mut input_readers[i + 1..] {
+                        remaining_lines |= matches!(input_reader.next_segment().await, Ok(Some(_)));
+                    }
+                    if remaining_lines {
+                        warn!("linked input files have different numbers of lines, stopping at the shortest");
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(Some(row))
+    }
+
+    async fn open_input_readers(
+        &self,
+        buffered_inputs: Vec<BufferedInput>,
+    ) -> anyhow::Result<Vec<BufferedInputReader>> {
+        let mut input_readers = Vec::with_capacity(buffered_inputs.len());
+        for buffered_input in buffered_inputs {
+            input_readers.push(
+                BufferedInputReader::new(buffered_input, self.command_line_args, &self.progress)
+                    .await?,
+            );
+        }
+        Ok(input_readers)
+    }
+
+    async fn send_argument_group(
+        &self,
+        parser: &CommandLineArgsParser,
+        input_line_number: InputLineNumber,
+        argument_group: Vec<String>,
+    ) {
+        let input_data = parser.argument_group_input(&argument_group);
+
+        match parser.parse_argument_group(argument_group) {
+            Ok(command_and_args) => {
+                self.send(
+                    command_and_args,
+                    JobOptions::default(),
+                    input_line_number,
+                    input_data,
+                )
+                .await
+            }
+            Err(skipped_input) => self.progress.input_skipped(skipped_input),
+        }
+    }
+
+    async fn process_linked_input_files(
+        &self,
+        buffered_inputs: Vec<BufferedInput>,
+    ) -> anyhow::Result<()> {
+        debug!("begin process_linked_input_files");
+
+        let mut input_readers = self.open_input_readers(buffered_inputs).await?;
+
+        let parser = self.parsers.command_line_args_parser();
+
+        let mut line_number = 0;
+
+        while !self.cancellation_token.is_cancelled() && self.parsers.input_failure().is_none() {
+            let Some(row) = Self::next_linked_row(&mut input_readers).await? else {
+                break;
+            };
+
+            line_number += 1;
+
+            let Ok(argument_group) = row
+                .into_iter()
+                .map(String::from_utf8)
+                .collect::<Result<Vec<_>, _>>()
+            else {
+                self.progress.input_skipped(SkippedInput::InvalidUtf8);
+                continue;
+            };
+
+            let input_line_number = InputLineNumber {
+                input: Input::LinkedInputFiles,
+                line_number,
+            };
+
+            self.send_argument_group(&parser, input_line_number, argument_group)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn process_crossed_input_files(
+        &self,
+        buffered_inputs: Vec<BufferedInput>,
+    ) -> anyhow::Result<()> {
+        debug!("begin process_crossed_input_files");
+
+        let mut all_lines = Vec::with_capacity(buffered_inputs.len());
+
+        for mut input_reader in self.open_input_readers(buffered_inputs).await? {
+            let mut lines = vec![];
+            while let Some((_, segment)) = input_reader
+                .next_segment()
+                .await
+                .context("next_segment error")?
+            {
+                match String::from_utf8(segment) {
+                    Ok(line) => lines.push(line),
+                    Err(_) => self.progress.input_skipped(SkippedInput::InvalidUtf8),
+                }
+            }
+            input_reader.wait_for_preprocess().await?;
+            all_lines.push(lines);
+        }
+
+        let parser = self.parsers.command_line_args_parser();
+
+        for (i, argument_group) in all_lines.into_iter().multi_cartesian_product().enumerate() {
+            if self.cancellation_token.is_cancelled() || self.parsers.input_failure().is_some() {
+                break;
+            }
+
+            let input_line_number = InputLineNumber {
+                input: Input::CrossedInputFiles,
+                line_number: i + 1,
+            };
+
+            self.send_argument_group(&parser, input_line_number, argument_group)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Process a buffered input, errors other than fatal input errors are logged.
+    async fn process_buffered_input_logging_errors(
+        &self,
+        buffered_input: BufferedInput,
+    ) -> anyhow::Result<()> {
+        match self.process_buffered_input(buffered_input).await {
+            Ok(()) => Ok(()),
+            Err(e) => self.handle_input_error(
+                e,
+                format_args!(
+                    "process_buffered_input error buffered_input = {}",
+                    buffered_input
+                ),
+            ),
+        }
+    }
+
+    /// Fail the run for fatal input errors, other errors are logged and the input ends.
+    fn handle_input_error(
+        &self,
+        e: anyhow::Error,
+        message: std::fmt::Arguments<'_>,
+    ) -> anyhow::Result<()> {
+        if e.is::<OversizedLineError>() || e.is::<InputCommandError>() {
+            return Err(e);
+        }
+
+        if e.is::<MissingInputError>() {
+            match self.command_line_args.missing_input {
+                MissingInput::Error => return Err(e),
+                MissingInput::Warn => {}
+                MissingInput::Ignore => {
+                    debug!("{}: {}", message, e);
+                    return Ok(());
+                }
+            }
+        }
+
+        warn!("{}: {}", message, e);
+        Ok(())
+    }
+
+    /// Read all buffered inputs at the same time for --schedule=fair, so the scheduler can take
+    /// turns between them.
+    async fn process_buffered_inputs_concurrently(
+        self: Arc<Self>,
+        buffered_inputs: Vec<BufferedInput>,
+    ) -> anyhow::Result<()> {
+        let mut join_set = JoinSet::new();
+
+        for buffered_input in buffered_inputs {
+            let input_task = Arc::clone(&self);
+            join_set.spawn(async move {
+                input_task
+                    .process_buffered_input_logging_errors(buffered_input)
+                    .await
+            });
+        }
+
+        while let Some(result) = join_set.join_next().await {
+            result.context("buffered input task join error")??;
+        }
+
+        Ok(())
+    }
+
     #[instrument(skip_all, name = "InputTask::run", level = "debug")]
-    pub async fn run(self) {
+    pub async fn run(self) -> anyhow::Result<()> {
         debug!("begin run");
 
         match super::build_input_list(self.command_line_args) {
-            InputList::BufferedInputList(buffered_inputs) => {
+            InputList::Buffered(buffered_inputs)
+                if buffered_inputs.len() > 1
+                    && self.command_line_args.schedule == Schedule::Fair =>
+            {
+                let input_task = Arc::new(self);
+                Arc::clone(&input_task)
+                    .process_buffered_inputs_concurrently(buffered_inputs)
+                    .await?;
+
+                return input_task.finish();
+            }
+            InputList::Buffered(buffered_inputs) => {
                 for buffered_input in buffered_inputs {
-                    if let Err(e) = self.process_buffered_input(buffered_input).await {
-                        warn!(
-                            "process_buffered_input error buffered_input = {}: {}",
-                            buffered_input, e
-                        );
+                    if self.cancellation_token.is_cancelled()
+                        || self.parsers.input_failure().is_some()
+                    {
+                        debug!("buffered input processing cancelled");
+                        break;
                     }
+                    self.process_buffered_input_logging_errors(buffered_input)
+                        .await?;
+                }
+            }
+            InputList::Linked(buffered_inputs) => {
+                if let Err(e) = self.process_linked_input_files(buffered_inputs).await {
+                    self.handle_input_error(e, format_args!("process_linked_input_files error"))?;
+                }
+            }
+            InputList::Crossed(buffered_inputs) => {
+                if let Err(e) = self.process_crossed_input_files(buffered_inputs).await {
+                    self.handle_input_error(e, format_args!("process_crossed_input_files error"))?;
                 }
             }
             InputList::CommandLineArgs => self.process_command_line_args_input().await,
         }
 
+        self.finish()
+    }
+
+    fn finish(&self) -> anyhow::Result<()> {
         debug!("end run");
+
+        if let Some(input_failure) = self.parsers.input_failure() {
+            anyhow::bail!(input_failure);
+        }
+
+        Ok(())
     }
 }
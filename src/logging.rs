@@ -0,0 +1,350 @@
+use tracing::{Level, Metadata};
+
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
+};
+
+use std::io::Write;
+
+use crate::{
+    command_line_args::{CommandLineArgs, LogTarget},
+    redact::{self, Redactor},
+    self_profile::SelfProfileLayer,
+};
+
+const IDENTIFIER: &str = "rust-parallel";
+
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// Format a message for the syslog protocol with facility user.
+fn format_syslog_message(level: &Level, message: &str) -> Vec<u8> {
+    const FACILITY_USER: u8 = 1;
+
+    format!(
+        "<{}>{}[{}]: {}",
+        FACILITY_USER * 8 + severity(level),
+        IDENTIFIER,
+        std::process::id(),
+        message.trim_end(),
+    )
+    .into_bytes()
+}
+
+/// Format a message for the journald native protocol.
+fn format_journald_message(level: &Level, message: &str) -> Vec<u8> {
+    let message = message.trim_end();
+
+    let mut result = format!(
+        "PRIORITY={}\nSYSLOG_IDENTIFIER={}\n",
+        severity(level),
+        IDENTIFIER
+    )
+    .into_bytes();
+
+    if message.contains('\n') {
+        // Multi-line values use the binary length-prefixed form.
+        result.extend_from_slice(b"MESSAGE\n");
+        result.extend_from_slice(&(message.len() as u64).to_le_bytes());
+        result.extend_from_slice(message.as_bytes());
+        result.push(b'\n');
+    } else {
+        result.extend_from_slice(format!("MESSAGE={}\n", message).as_bytes());
+    }
+
+    result
+}
+
+#[cfg(unix)]
+mod datagram {
+    use anyhow::Context;
+
+    use tracing::{Level, Metadata};
+
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use std::{io::Write, os::unix::net::UnixDatagram, sync::Arc};
+
+    type FormatFn = fn(&Level, &str) -> Vec<u8>;
+
+    /// Sends each formatted log event as one datagram to a local socket.
+    #[derive(Clone)]
+    pub struct DatagramMakeWriter {
+        socket: Arc<UnixDatagram>,
+        format: FormatFn,
+    }
+
+    impl DatagramMakeWriter {
+        pub fn connect(path: &str, format: FormatFn) -> anyhow::Result<Self> {
+            let socket = UnixDatagram::unbound().context("UnixDatagram::unbound error")?;
+            socket
+                .connect(path)
+                .with_context(|| format!("error connecting to log socket '{}'", path))?;
+
+            Ok(Self {
+                socket: Arc::new(socket),
+                format,
+            })
+        }
+    }
+
+    pub struct DatagramWriter {
+        socket: Arc<UnixDatagram>,
+        format: FormatFn,
+        level: Level,
+        buffer: Vec<u8>,
+    }
+
+    impl Write for DatagramWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for DatagramWriter {
+        fn drop(&mut self) {
+            if self.buffer.is_empty() {
+                return;
+            }
+
+            let message = String::from_utf8_lossy(&self.buffer);
+            let _ = self.socket.send(&(self.format)(&self.level, &message));
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for DatagramMakeWriter {
+        type Writer = DatagramWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.make_writer_for_level(Level::INFO)
+        }
+
+        fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+            self.make_writer_for_level(*meta.level())
+        }
+    }
+
+    impl DatagramMakeWriter {
+        fn make_writer_for_level(&self, level: Level) -> DatagramWriter {
+            DatagramWriter {
+                socket: Arc::clone(&self.socket),
+                format: self.format,
+                level,
+                buffer: vec![],
+            }
+        }
+    }
+}
+
+/// Masks secrets from --redact and --redact-env in each write.
+///
+/// The fmt layer writes each formatted event with one write call.
+struct RedactingMakeWriter<W> {
+    inner: W,
+    redactor: &'static Redactor,
+}
+
+struct RedactingWriter<W> {
+    inner: W,
+    redactor: &'static Redactor,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(s) => self.inner.write_all(self.redactor.redact(s).as_bytes())?,
+            Err(_) => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<W> {
+    type Writer = RedactingWriter<W::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: self.redactor,
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer_for(meta),
+            redactor: self.redactor,
+        }
+    }
+}
+
+fn init_with_writer<W>(make_writer: W, ansi: bool, self_profile: bool)
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let redactor = redact::redactor();
+
+    if redactor.is_empty() {
+        init_subscriber(make_writer, ansi, self_profile);
+    } else {
+        init_subscriber(
+            RedactingMakeWriter {
+                inner: make_writer,
+                redactor,
+            },
+            ansi,
+            self_profile,
+        );
+    }
+}
+
+/// Log events at info and above.  With --self-profile debug spans are also enabled for
+/// SelfProfileLayer, without being logged.
+fn init_subscriber<W>(make_writer: W, ansi: bool, self_profile: bool)
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(make_writer)
+        .with_ansi(ansi);
+
+    let fmt_layer = if ansi {
+        fmt_layer.boxed()
+    } else {
+        fmt_layer.without_time().boxed()
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(LevelFilter::INFO))
+        .with(self_profile.then_some(SelfProfileLayer))
+        .init();
+}
+
+#[cfg(unix)]
+fn init_datagram(
+    path: &str,
+    format: fn(&Level, &str) -> Vec<u8>,
+    self_profile: bool,
+) -> anyhow::Result<()> {
+    init_with_writer(
+        datagram::DatagramMakeWriter::connect(path, format)?,
+        false,
+        self_profile,
+    );
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn init_datagram(
+    _path: &str,
+    _format: fn(&Level, &str) -> Vec<u8>,
+    _self_profile: bool,
+) -> anyhow::Result<()> {
+    anyhow::bail!("log target is not supported on this platform")
+}
+
+/// Initialize tracing for the selected --log-target.
+///
+/// On error logging falls back to stdout so the error can still be reported.
+pub fn init(command_line_args: &CommandLineArgs) -> anyhow::Result<()> {
+    let self_profile = command_line_args.self_profile;
+
+    let result = match command_line_args.log_target {
+        LogTarget::Stdout => {
+            init_with_writer(std::io::stdout, true, self_profile);
+            Ok(())
+        }
+        LogTarget::Stderr => {
+            init_with_writer(std::io::stderr, true, self_profile);
+            Ok(())
+        }
+        LogTarget::Syslog => init_datagram("/dev/log", format_syslog_message, self_profile),
+        LogTarget::Journald => init_datagram(
+            "/run/systemd/journal/socket",
+            format_journald_message,
+            self_profile,
+        ),
+    };
+
+    if result.is_err() {
+        init_with_writer(std::io::stdout, true, self_profile);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_syslog_message() {
+        assert_eq!(
+            String::from_utf8(format_syslog_message(&Level::WARN, "hello\n")).unwrap(),
+            format!("<12>rust-parallel[{}]: hello", std::process::id())
+        );
+        assert_eq!(
+            String::from_utf8(format_syslog_message(&Level::ERROR, "x")).unwrap(),
+            format!("<11>rust-parallel[{}]: x", std::process::id())
+        );
+    }
+
+    #[test]
+    fn test_format_journald_message() {
+        assert_eq!(
+            format_journald_message(&Level::INFO, "hello\n"),
+            b"PRIORITY=6\nSYSLOG_IDENTIFIER=rust-parallel\nMESSAGE=hello\n"
+        );
+
+        let mut expected = b"PRIORITY=3\nSYSLOG_IDENTIFIER=rust-parallel\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(format_journald_message(&Level::ERROR, "a\nb"), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_datagram_make_writer() {
+        use std::{io::Write, os::unix::net::UnixDatagram};
+
+        let path =
+            std::env::temp_dir().join(format!("rust_parallel_log_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let make_writer =
+            datagram::DatagramMakeWriter::connect(path.to_str().unwrap(), format_syslog_message)
+                .unwrap();
+
+        let mut writer = make_writer.make_writer();
+        writer.write_all(b"INFO part one").unwrap();
+        writer.write_all(b" part two\n").unwrap();
+        drop(writer);
+
+        let mut buffer = [0u8; 256];
+        let len = server.recv(&mut buffer).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&buffer[..len]).unwrap(),
+            format!(
+                "<14>rust-parallel[{}]: INFO part one part two",
+                std::process::id()
+            )
+        );
+    }
+}
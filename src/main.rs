@@ -1,38 +1,58 @@
 use tracing::{debug, error, instrument};
 
+use std::time::Instant;
+
 use crate::command_line_args::CommandLineArgs;
 
 mod command;
 mod command_line_args;
 mod common;
 mod input;
+mod logging;
 mod output;
 mod parser;
 mod process;
 mod progress;
+mod redact;
+mod run_lock;
+mod self_profile;
 
 #[instrument(skip_all, name = "try_main", level = "debug")]
 async fn try_main() -> anyhow::Result<()> {
+    let command_line_args = CommandLineArgs::instance().await;
+
+    redact::init(command_line_args);
+
+    logging::init(command_line_args)?;
+
     debug!("begin try_main");
 
-    let command_line_args = CommandLineArgs::instance().await;
+    let start_time = Instant::now();
 
-    let progress = progress::Progress::new(command_line_args)?;
+    let _run_lock = run_lock::RunLock::acquire(command_line_args).await?;
 
-    let command_service = command::CommandService::new(command_line_args, progress);
+    let command_service = command::CommandService::new(command_line_args)?;
 
     command_service.run_commands().await?;
 
+    if command_line_args.self_profile {
+        self_profile::report(start_time.elapsed());
+    }
+
     debug!("end try_main");
 
     Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::fmt::init();
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().expect("error creating tokio runtime");
+
+    let result = runtime.block_on(try_main());
+
+    // do not wait for a blocked stdin read, as after --drain-on-quit stops reading inputs
+    runtime.shutdown_background();
 
-    if let Err(err) = try_main().await {
+    if let Err(err) = result {
         error!("fatal error in main: {:#}", err);
         std::process::exit(1);
     }
@@ -1,92 +1,488 @@
+mod duplicates;
+mod hash;
+mod key;
+mod merge;
+mod reduce;
+mod results;
+mod tag;
 mod task;
 
 use anyhow::Context;
 
 use tokio::{
-    sync::mpsc::{channel, Sender},
+    io::{AsyncRead, AsyncReadExt},
+    sync::{
+        mpsc::{channel, Sender},
+        OwnedSemaphorePermit, Semaphore,
+    },
     task::JoinHandle,
 };
 
+use tokio_util::sync::CancellationToken;
+
 use tracing::{debug, warn};
 
-use std::process::{ExitStatus, Output};
+use std::{
+    process::{ExitStatus, Output},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
-    command_line_args::CommandLineArgs, common::OwnedCommandAndArgs, input::InputLineNumber,
+    command_line_args::{CommandLineArgs, HashAlgorithm, OrderBy},
+    common::OwnedCommandAndArgs,
+    input::InputLineNumber,
 };
 
+use self::{key::OutputKey, merge::MergeOutputWriter, reduce::Reducers, results::ResultsWriter};
+
+#[derive(Clone, Copy, Debug)]
+pub enum CommandStatus {
+    Exited(ExitStatus),
+
+    Timeout,
+
+    SpawnError,
+
+    IOError,
+
+    DescendantLimit,
+
+    Cancelled,
+}
+
+impl CommandStatus {
+    pub fn success(&self) -> bool {
+        matches!(self, Self::Exited(exit_status) if exit_status.success())
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub status: CommandStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+}
+
+impl CommandOutput {
+    pub fn new(output: Output, duration: Duration) -> Self {
+        Self {
+            status: CommandStatus::Exited(output.status),
+            stdout: output.stdout,
+            stderr: output.stderr,
+            duration,
+        }
+    }
+
+    pub fn without_output(status: CommandStatus, duration: Duration) -> Self {
+        Self {
+            status,
+            stdout: vec![],
+            stderr: vec![],
+            duration,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct OutputMessage {
-    exit_status: ExitStatus,
-    stdout: Vec<u8>,
-    stderr: Vec<u8>,
+    sequence: u64,
+    /// Position of the input in the order inputs were read.
+    input_sequence: u64,
+    completed_at: Instant,
+    command_output: CommandOutput,
     command_and_args: OwnedCommandAndArgs,
     input_line_number: InputLineNumber,
+    /// Input for the --results index.
+    input: Option<String>,
+    /// Key from --merge-output or --reduce-key.
+    key: Option<String>,
+    stdout_digest: Option<hash::Sha256Digest>,
+    /// Bytes held against --output-channel-bytes until the output is received or written.
+    channel_bytes_permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Size of the chunks --stream-output reads from a command.
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Part of the output of a running command with --stream-output.
+#[derive(Debug)]
+struct OutputChunk {
+    sequence: u64,
+    stream: Stream,
+    data: Vec<u8>,
+    channel_bytes_permit: Option<OwnedSemaphorePermit>,
+}
+
+#[derive(Debug)]
+enum OutputEvent {
+    Output(Box<OutputMessage>),
+
+    Chunk(OutputChunk),
+
+    /// Nothing will be written for this sequence, sent so ordered output does not wait for it.
+    Skipped(u64),
+}
+
+impl OutputEvent {
+    fn sequence(&self) -> u64 {
+        match self {
+            Self::Output(output_message) => output_message.sequence,
+            Self::Chunk(output_chunk) => output_chunk.sequence,
+            Self::Skipped(sequence) => *sequence,
+        }
+    }
+
+    /// Stop counting this output against --output-channel-bytes.
+    fn release_channel_bytes(&mut self) {
+        match self {
+            Self::Output(output_message) => output_message.channel_bytes_permit = None,
+            Self::Chunk(output_chunk) => output_chunk.channel_bytes_permit = None,
+            Self::Skipped(_) => {}
+        }
+    }
 }
 
 pub struct OutputSender {
-    sender: Sender<OutputMessage>,
+    sender: Sender<OutputEvent>,
+    sequence: u64,
+    input_sequence: u64,
+    send_all_results: bool,
+    hash_output: Option<HashAlgorithm>,
+    detect_duplicate_output: bool,
+    ordered: bool,
+    sent: bool,
+    /// Key from --merge-output or --reduce-key.
+    key: Option<String>,
+    /// Input for --tag.
+    tag: Option<String>,
+    /// Input for the --results index.
+    input: Option<String>,
+    channel_bytes: Option<ChannelBytes>,
+    stream_output: bool,
 }
 
 impl OutputSender {
+    /// With --stream-output, the stream for the command's output while it runs.
+    pub fn output_stream(&self) -> Option<OutputStream> {
+        self.stream_output.then(|| OutputStream {
+            sender: self.sender.clone(),
+            sequence: self.sequence,
+            channel_bytes: self.channel_bytes.clone(),
+        })
+    }
+
     pub async fn send(
-        self,
-        output: Output,
+        mut self,
+        mut command_output: CommandOutput,
         command_and_args: OwnedCommandAndArgs,
         input_line_number: InputLineNumber,
     ) {
-        if output.status.success() && output.stdout.is_empty() && output.stderr.is_empty() {
+        let exited = matches!(command_output.status, CommandStatus::Exited(_));
+
+        let stdout_digest =
+            (self.detect_duplicate_output && exited && !command_output.stdout.is_empty())
+                .then(|| hash::sha256(&command_output.stdout));
+
+        // commands that did not exit have no complete output to hash
+        if let (Some(algorithm), true) = (self.hash_output, exited) {
+            command_output.stdout = hash::digest_line(
+                algorithm,
+                &command_output.stdout,
+                &command_and_args,
+                &input_line_number,
+            );
+        }
+
+        if let Some(tag) = &self.tag {
+            command_output.stdout = tag::tag_lines(tag, &command_output.stdout);
+            command_output.stderr = tag::tag_lines(tag, &command_output.stderr);
+        }
+
+        if !self.send_all_results
+            && command_output.status.success()
+            && command_output.stdout.is_empty()
+            && command_output.stderr.is_empty()
+        {
             return;
         }
 
+        self.sent = true;
+
+        let channel_bytes_permit = match &self.channel_bytes {
+            Some(channel_bytes) => {
+                channel_bytes
+                    .acquire(command_output.stdout.len() + command_output.stderr.len())
+                    .await
+            }
+            None => None,
+        };
+
         let output_message = OutputMessage {
-            exit_status: output.status,
-            stdout: output.stdout,
-            stderr: output.stderr,
+            sequence: self.sequence,
+            input_sequence: self.input_sequence,
+            completed_at: Instant::now(),
+            command_output,
             command_and_args,
             input_line_number,
+            input: self.input.take(),
+            key: self.key.take(),
+            stdout_digest,
+            channel_bytes_permit,
         };
 
-        if let Err(e) = self.sender.send(output_message).await {
+        if let Err(e) = self
+            .sender
+            .send(OutputEvent::Output(Box::new(output_message)))
+            .await
+        {
             warn!("sender.send error: {}", e);
         }
     }
 }
 
+impl Drop for OutputSender {
+    fn drop(&mut self) {
+        if self.sent || !self.ordered {
+            return;
+        }
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let sender = self.sender.clone();
+        let skipped = OutputEvent::Skipped(self.sequence);
+        runtime.spawn(async move {
+            if let Err(e) = sender.send(skipped).await {
+                warn!("sender.send error: {}", e);
+            }
+        });
+    }
+}
+
+/// Forwards the stdout and stderr of a running command to the output task in chunks, for
+/// --stream-output.
+#[derive(Debug)]
+pub struct OutputStream {
+    sender: Sender<OutputEvent>,
+    sequence: u64,
+    channel_bytes: Option<ChannelBytes>,
+}
+
+impl OutputStream {
+    pub async fn forward_stdout(
+        &self,
+        reader: Option<impl AsyncRead + Unpin>,
+    ) -> std::io::Result<()> {
+        self.forward(reader, Stream::Stdout).await
+    }
+
+    pub async fn forward_stderr(
+        &self,
+        reader: Option<impl AsyncRead + Unpin>,
+    ) -> std::io::Result<()> {
+        self.forward(reader, Stream::Stderr).await
+    }
+
+    /// Read until the end of the output, reading waits while the output channel is full.
+    async fn forward(
+        &self,
+        reader: Option<impl AsyncRead + Unpin>,
+        stream: Stream,
+    ) -> std::io::Result<()> {
+        let Some(mut reader) = reader else {
+            return Ok(());
+        };
+
+        let mut buffer = vec![0; STREAM_CHUNK_BYTES];
+
+        loop {
+            let bytes = reader.read(&mut buffer).await?;
+            if bytes == 0 {
+                return Ok(());
+            }
+
+            let channel_bytes_permit = match &self.channel_bytes {
+                Some(channel_bytes) => channel_bytes.acquire(bytes).await,
+                None => None,
+            };
+
+            let output_chunk = OutputChunk {
+                sequence: self.sequence,
+                stream,
+                data: buffer[..bytes].to_vec(),
+                channel_bytes_permit,
+            };
+
+            if let Err(e) = self.sender.send(OutputEvent::Chunk(output_chunk)).await {
+                warn!("sender.send error: {}", e);
+            }
+        }
+    }
+}
+
+/// Bounds the stdout and stderr bytes of outputs queued in the output channel.
+#[derive(Clone, Debug)]
+struct ChannelBytes {
+    semaphore: Arc<Semaphore>,
+    max_bytes: u32,
+}
+
+impl ChannelBytes {
+    fn new(max_bytes: u32) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_bytes as usize)),
+            max_bytes,
+        }
+    }
+
+    /// Wait until the output fits, an output larger than the bound waits for all of it.
+    async fn acquire(&self, bytes: usize) -> Option<OwnedSemaphorePermit> {
+        let permits =
+            u32::try_from(bytes).map_or(self.max_bytes, |bytes| bytes.min(self.max_bytes));
+
+        // the semaphore is never closed
+        Arc::clone(&self.semaphore)
+            .acquire_many_owned(permits)
+            .await
+            .ok()
+    }
+}
+
 pub struct OutputWriter {
-    sender: Sender<OutputMessage>,
-    output_task_join_handle: JoinHandle<()>,
+    sender: Sender<OutputEvent>,
+    next_sequence: AtomicU64,
+    order_by: OrderBy,
+    send_all_results: bool,
+    hash_output: Option<HashAlgorithm>,
+    detect_duplicate_output: bool,
+    output_key: Option<OutputKey>,
+    tag: bool,
+    /// With --results, inputs are kept for the index.
+    results: bool,
+    channel_bytes: Option<ChannelBytes>,
+    stream_output: bool,
+    output_task_join_handle: JoinHandle<anyhow::Result<()>>,
 }
 
 impl OutputWriter {
-    pub fn new(command_line_args: &CommandLineArgs) -> Self {
-        let (sender, receiver) = channel(command_line_args.channel_capacity);
+    pub fn new(
+        command_line_args: &CommandLineArgs,
+        cancellation_token: &CancellationToken,
+    ) -> anyhow::Result<Self> {
+        let merge_output = command_line_args
+            .merge_output
+            .as_ref()
+            .map(MergeOutputWriter::new)
+            .transpose()?;
+
+        let reducers = command_line_args
+            .reduce
+            .as_deref()
+            .map(|command| Reducers::new(command, command_line_args.reduce_key.is_some()))
+            .transpose()?;
+
+        let results = ResultsWriter::new(command_line_args)?;
+
+        let channel_capacity = command_line_args.output_channel_capacity();
+        let (sender, receiver) = channel(channel_capacity);
         debug!(
-            "created output channel with capacity {}",
-            command_line_args.channel_capacity,
+            "created output channel with capacity {} bytes {:?}",
+            channel_capacity, command_line_args.output_channel_bytes,
         );
 
-        let output_task_join_handle = tokio::spawn(task::OutputTask::new(receiver).run());
+        let output_task_join_handle = tokio::spawn(
+            task::OutputTask::new(
+                receiver,
+                command_line_args,
+                merge_output,
+                reducers,
+                results,
+                cancellation_token,
+            )
+            .run(),
+        );
 
-        Self {
+        Ok(Self {
             sender,
+            next_sequence: AtomicU64::new(0),
+            order_by: command_line_args.output_order(),
+            send_all_results: command_line_args.verbose_results
+                || command_line_args.verbose_commands
+                || command_line_args.results.is_some(),
+            hash_output: command_line_args.hash_output,
+            detect_duplicate_output: command_line_args.detect_duplicate_output,
+            output_key: OutputKey::new(command_line_args)?,
+            tag: command_line_args.tag,
+            results: command_line_args.results.is_some(),
+            channel_bytes: command_line_args
+                .output_channel_bytes
+                .map(ChannelBytes::new),
+            stream_output: command_line_args.stream_output,
             output_task_join_handle,
-        }
+        })
     }
 
-    pub fn sender(&self) -> OutputSender {
+    fn new_sender(&self, sequence: u64) -> OutputSender {
         OutputSender {
             sender: self.sender.clone(),
+            sequence,
+            input_sequence: sequence,
+            send_all_results: self.send_all_results,
+            hash_output: self.hash_output,
+            detect_duplicate_output: self.detect_duplicate_output,
+            ordered: self.order_by != OrderBy::Completion,
+            sent: false,
+            key: None,
+            tag: None,
+            input: None,
+            channel_bytes: self.channel_bytes.clone(),
+            stream_output: self.stream_output,
         }
     }
 
+    /// With --order-by=input, the sender for the input with this sequence number.
+    ///
+    /// Must be called for every input read, dropping the sender skips its output.
+    pub fn input_sender(&self, input_sequence: u64) -> Option<OutputSender> {
+        (self.order_by == OrderBy::Input).then(|| self.new_sender(input_sequence))
+    }
+
+    /// Sender for a command being started, ordered by start unless it is the input's sender.
+    pub fn sender(
+        &self,
+        input_sender: Option<OutputSender>,
+        input_sequence: u64,
+        input_data: &str,
+    ) -> OutputSender {
+        let mut sender = input_sender
+            .unwrap_or_else(|| self.new_sender(self.next_sequence.fetch_add(1, Ordering::Relaxed)));
+        sender.input_sequence = input_sequence;
+        sender.key = self
+            .output_key
+            .as_ref()
+            .map(|output_key| output_key.expand(input_data));
+        sender.tag = self.tag.then(|| input_data.trim().to_owned());
+        sender.input = self.results.then(|| input_data.to_owned());
+        sender
+    }
+
     pub async fn wait_for_completion(self) -> anyhow::Result<()> {
         drop(self.sender);
 
         self.output_task_join_handle
             .await
-            .context("OutputWriter::wait_for_completion: output_task_join_handle.await error")?;
-
-        Ok(())
+            .context("OutputWriter::wait_for_completion: output_task_join_handle.await error")?
     }
 }
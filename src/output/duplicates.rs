@@ -0,0 +1,85 @@
+use tracing::info;
+
+use std::collections::HashMap;
+
+use super::hash::{self, Sha256Digest};
+
+/// Groups inputs by the digest of their command's stdout for --detect-duplicate-output.
+#[derive(Default)]
+pub struct DuplicateOutputs {
+    inputs_by_digest: HashMap<Sha256Digest, Vec<String>>,
+    /// Digests in order of first output.
+    digests: Vec<Sha256Digest>,
+}
+
+impl DuplicateOutputs {
+    pub fn record(&mut self, digest: Sha256Digest, input: String) {
+        let inputs = self.inputs_by_digest.entry(digest).or_insert_with(|| {
+            self.digests.push(digest);
+            vec![]
+        });
+        inputs.push(input);
+    }
+
+    /// Groups of two or more inputs with identical output, in order of first output.
+    fn duplicate_groups(&self) -> Vec<(Sha256Digest, &[String])> {
+        self.digests
+            .iter()
+            .map(|digest| (*digest, self.inputs_by_digest[digest].as_slice()))
+            .filter(|(_, inputs)| inputs.len() > 1)
+            .collect()
+    }
+
+    pub fn report(&self) {
+        let groups = self.duplicate_groups();
+
+        for (digest, inputs) in &groups {
+            info!(
+                "duplicate output sha256={} from {} commands: {}",
+                hash::hex(digest),
+                inputs.len(),
+                inputs.join(", ")
+            );
+        }
+
+        info!(
+            "found {} groups of commands with duplicate output",
+            groups.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_groups() {
+        let mut duplicate_outputs = DuplicateOutputs::default();
+
+        duplicate_outputs.record(hash::sha256(b"b"), "input:1".to_owned());
+        duplicate_outputs.record(hash::sha256(b"a"), "input:2".to_owned());
+        duplicate_outputs.record(hash::sha256(b"c"), "input:3".to_owned());
+        duplicate_outputs.record(hash::sha256(b"a"), "input:4".to_owned());
+        duplicate_outputs.record(hash::sha256(b"b"), "input:5".to_owned());
+        duplicate_outputs.record(hash::sha256(b"b"), "input:6".to_owned());
+
+        assert_eq!(
+            duplicate_outputs.duplicate_groups(),
+            vec![
+                (
+                    hash::sha256(b"b"),
+                    &[
+                        "input:1".to_owned(),
+                        "input:5".to_owned(),
+                        "input:6".to_owned()
+                    ][..]
+                ),
+                (
+                    hash::sha256(b"a"),
+                    &["input:2".to_owned(), "input:4".to_owned()][..]
+                ),
+            ]
+        );
+    }
+}
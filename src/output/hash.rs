@@ -0,0 +1,63 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    command_line_args::HashAlgorithm, common::OwnedCommandAndArgs, input::InputLineNumber,
+};
+
+pub type Sha256Digest = [u8; 32];
+
+pub fn sha256(data: &[u8]) -> Sha256Digest {
+    Sha256::digest(data).into()
+}
+
+pub fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hex digest of data with the algorithm.
+fn hex_digest(algorithm: HashAlgorithm, data: &[u8]) -> String {
+    match algorithm {
+        HashAlgorithm::Sha256 => hex(&sha256(data)),
+    }
+}
+
+/// JSON line reporting the digest of a command's stdout for --hash-output, written in place of
+/// the stdout.
+pub fn digest_line(
+    algorithm: HashAlgorithm,
+    stdout: &[u8],
+    command_and_args: &OwnedCommandAndArgs,
+    input_line_number: &InputLineNumber,
+) -> Vec<u8> {
+    let algorithm_name = match algorithm {
+        HashAlgorithm::Sha256 => "sha256",
+    };
+
+    let mut line = serde_json::json!({
+        "line": input_line_number.to_string(),
+        "command": command_and_args.shell_quoted(),
+        "bytes": stdout.len(),
+        algorithm_name: hex_digest(algorithm, stdout),
+    })
+    .to_string()
+    .into_bytes();
+    line.push(b'\n');
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hex_digest() {
+        assert_eq!(
+            hex_digest(HashAlgorithm::Sha256, b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex_digest(HashAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
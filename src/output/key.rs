@@ -0,0 +1,47 @@
+use tracing::warn;
+
+use std::sync::Arc;
+
+use crate::{command_line_args::CommandLineArgs, parser::regex::RegexProcessor};
+
+/// Expands the --merge-output sort key or --reduce-key from each command's input.
+pub struct OutputKey {
+    template: String,
+    regex_processor: Arc<RegexProcessor>,
+}
+
+impl OutputKey {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        let Some(template) = command_line_args
+            .merge_output
+            .as_ref()
+            .and_then(|merge_output| merge_output.sort_key.as_ref())
+            .or(command_line_args.reduce_key.as_ref())
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            template: template.clone(),
+            regex_processor: RegexProcessor::new(command_line_args)?,
+        }))
+    }
+
+    pub fn expand(&self, input_data: &str) -> String {
+        let input_data = if self.regex_processor.regex_mode() {
+            input_data
+        } else {
+            input_data.trim()
+        };
+
+        self.regex_processor
+            .expand_template(&self.template, input_data)
+            .unwrap_or_else(|| {
+                warn!(
+                    "error expanding output key {:?} for input: {}",
+                    self.template, input_data
+                );
+                String::new()
+            })
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::Context;
+
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use tracing::{debug, warn};
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::command_line_args::MergeOutput;
+
+/// Sorted output held in memory before it is written to a temporary file.
+const SORT_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Writes the stdout of all commands to the --merge-output file.
+pub struct MergeOutputWriter {
+    path: String,
+    file: BufWriter<File>,
+    sorter: Option<Sorter>,
+}
+
+impl MergeOutputWriter {
+    pub fn new(merge_output: &MergeOutput) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(&merge_output.path)
+            .with_context(|| format!("error creating merge output '{}'", merge_output.path))?;
+
+        Ok(Self {
+            path: merge_output.path.clone(),
+            file: BufWriter::new(File::from_std(file)),
+            sorter: merge_output.sort_key.as_ref().map(|_| Sorter::default()),
+        })
+    }
+
+    pub async fn write(
+        &mut self,
+        sequence: u64,
+        key: Option<&str>,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        match &mut self.sorter {
+            None => self.file.write_all(data).await,
+            Some(sorter) => {
+                sorter
+                    .push(Record {
+                        key: key.unwrap_or_default().to_owned(),
+                        sequence,
+                        data: data.to_vec(),
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Write sorted output and flush the file.
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        let result = async {
+            if let Some(sorter) = self.sorter.take() {
+                sorter.finish(&mut self.file).await?;
+            }
+            self.file.flush().await
+        }
+        .await;
+
+        result.with_context(|| format!("error writing merge output '{}'", self.path))
+    }
+}
+
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct Record {
+    key: String,
+    sequence: u64,
+    data: Vec<u8>,
+}
+
+impl Record {
+    async fn write_to(&self, writer: &mut (impl AsyncWrite + Unpin)) -> std::io::Result<()> {
+        writer.write_u64_le(self.key.len() as u64).await?;
+        writer.write_all(self.key.as_bytes()).await?;
+        writer.write_u64_le(self.sequence).await?;
+        writer.write_u64_le(self.data.len() as u64).await?;
+        writer.write_all(&self.data).await
+    }
+
+    /// Read the next record, None at the end of the file.
+    async fn read_from(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Option<Self>> {
+        let key_len = match reader.read_u64_le().await {
+            Ok(key_len) => key_len,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut key = vec![0; key_len as usize];
+        reader.read_exact(&mut key).await?;
+        let key =
+            String::from_utf8(key).map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+
+        let sequence = reader.read_u64_le().await?;
+
+        let mut data = vec![0; reader.read_u64_le().await? as usize];
+        reader.read_exact(&mut data).await?;
+
+        Ok(Some(Self {
+            key,
+            sequence,
+            data,
+        }))
+    }
+}
+
+/// Sorts records by key then sequence, in memory up to SORT_MEMORY_BYTES and by merging
+/// sorted runs written to temporary files beyond that.
+#[derive(Default)]
+struct Sorter {
+    records: Vec<Record>,
+    bytes: usize,
+    runs: Vec<PathBuf>,
+}
+
+impl Sorter {
+    async fn push(&mut self, record: Record) -> std::io::Result<()> {
+        self.bytes += record.key.len() + record.data.len();
+        self.records.push(record);
+
+        if self.bytes > SORT_MEMORY_BYTES {
+            self.write_run().await?;
+        }
+        Ok(())
+    }
+
+    async fn create_run_file() -> std::io::Result<(PathBuf, File)> {
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "rust-parallel-merge-{}-{}",
+                std::process::id(),
+                NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            match File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => return Ok((path, file)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Sort the records in memory and write them to a temporary file.
+    async fn write_run(&mut self) -> std::io::Result<()> {
+        self.records.sort_unstable();
+
+        let (path, file) = Self::create_run_file().await?;
+        debug!(
+            "writing {} sorted records to {:?}",
+            self.records.len(),
+            path
+        );
+        self.runs.push(path);
+
+        let mut writer = BufWriter::new(file);
+        for record in self.records.drain(..) {
+            record.write_to(&mut writer).await?;
+        }
+        writer.flush().await?;
+
+        self.bytes = 0;
+        Ok(())
+    }
+
+    async fn finish(mut self, output: &mut (impl AsyncWrite + Unpin)) -> std::io::Result<()> {
+        if self.runs.is_empty() {
+            self.records.sort_unstable();
+            for record in &self.records {
+                output.write_all(&record.data).await?;
+            }
+            return Ok(());
+        }
+
+        if !self.records.is_empty() {
+            self.write_run().await?;
+        }
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        for path in &self.runs {
+            readers.push(BufReader::new(File::open(path).await?));
+        }
+
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (index, reader) in readers.iter_mut().enumerate() {
+            if let Some(record) = Record::read_from(reader).await? {
+                heap.push(Reverse((record, index)));
+            }
+        }
+
+        while let Some(Reverse((record, index))) = heap.pop() {
+            output.write_all(&record.data).await?;
+
+            if let Some(record) = Record::read_from(&mut readers[index]).await? {
+                heap.push(Reverse((record, index)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Sorter {
+    fn drop(&mut self) {
+        for path in &self.runs {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("error removing merge output run {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(key: &str, sequence: u64) -> Record {
+        Record {
+            key: key.to_owned(),
+            sequence,
+            data: format!("{key}-{sequence}\n").into_bytes(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sorter_in_memory() {
+        let mut sorter = Sorter::default();
+        for record in [
+            record("b", 0),
+            record("a", 1),
+            record("b", 2),
+            record("", 3),
+        ] {
+            sorter.push(record).await.unwrap();
+        }
+
+        let mut output = vec![];
+        sorter.finish(&mut output).await.unwrap();
+
+        assert_eq!(output, b"-3\na-1\nb-0\nb-2\n");
+    }
+
+    #[tokio::test]
+    async fn test_sorter_merges_runs() {
+        let mut sorter = Sorter::default();
+        for (i, key) in ["d", "a", "c", "b", "a", "e", "c"].into_iter().enumerate() {
+            sorter.push(record(key, i as u64)).await.unwrap();
+            if i % 3 == 2 {
+                sorter.write_run().await.unwrap();
+            }
+        }
+
+        let runs = sorter.runs.clone();
+        assert_eq!(runs.len(), 2);
+
+        let mut output = vec![];
+        sorter.finish(&mut output).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a-1\na-4\nb-3\nc-2\nc-6\nd-0\ne-5\n"
+        );
+        assert!(runs.iter().all(|path| !path.exists()));
+    }
+}
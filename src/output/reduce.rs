@@ -0,0 +1,137 @@
+use anyhow::Context;
+
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, ChildStdin, Command},
+};
+
+use tracing::debug;
+
+use std::{collections::BTreeMap, process::Stdio};
+
+use crate::command_line_args::ShellKind;
+
+/// Environment variable with the --reduce-key of a reduce command's partition.
+const REDUCE_KEY_ENV_VAR: &str = "PARALLEL_REDUCE_KEY";
+
+/// The --reduce commands, one for all output or one per --reduce-key.
+pub struct Reducers {
+    command: String,
+    keyed: bool,
+    reducers: BTreeMap<String, Reducer>,
+}
+
+impl Reducers {
+    pub fn new(command: &str, keyed: bool) -> anyhow::Result<Self> {
+        let mut reducers = BTreeMap::new();
+
+        // without keys the reduce command runs even if no command produces output
+        if !keyed {
+            reducers.insert(String::new(), Reducer::spawn(command, None)?);
+        }
+
+        Ok(Self {
+            command: command.to_owned(),
+            keyed,
+            reducers,
+        })
+    }
+
+    pub async fn write(&mut self, key: Option<&str>, data: &[u8]) -> std::io::Result<()> {
+        let key = key.unwrap_or_default();
+
+        let reducer = match self.reducers.get_mut(key) {
+            Some(reducer) => reducer,
+            None => {
+                let reducer = Reducer::spawn(&self.command, self.keyed.then_some(key))
+                    .map_err(|e| std::io::Error::other(format!("{:#}", e)))?;
+                self.reducers.entry(key.to_owned()).or_insert(reducer)
+            }
+        };
+
+        reducer.write(data).await
+    }
+
+    /// Wait for each reduce command in key order, fails if any failed.
+    pub async fn finish(self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+
+        for (_, reducer) in self.reducers {
+            let reducer_result = reducer.finish().await;
+            if result.is_ok() {
+                result = reducer_result;
+            }
+        }
+
+        result
+    }
+}
+
+/// A reduce command, which reads the stdout of commands on its stdin.
+///
+/// Its stdout and stderr are inherited, so its output becomes the output of the run.
+struct Reducer {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+}
+
+impl Reducer {
+    fn spawn(command: &str, key: Option<&str>) -> anyhow::Result<Self> {
+        let shell_kind = ShellKind::system();
+
+        let mut reduce_command = Command::new(shell_kind.default_path());
+        reduce_command
+            .args(shell_kind.default_arguments())
+            .arg(command)
+            .stdin(Stdio::piped());
+        if let Some(key) = key {
+            reduce_command.env(REDUCE_KEY_ENV_VAR, key);
+        }
+
+        let mut child = reduce_command
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("error spawning reduce command '{}'", command))?;
+
+        let stdin = child.stdin.take().context("reduce command stdin missing")?;
+
+        debug!("spawned reduce command '{}' key {:?}", command, key);
+
+        Ok(Self {
+            command: command.to_owned(),
+            child,
+            stdin,
+        })
+    }
+
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.stdin.write_all(data).await
+    }
+
+    /// Close the reduce command's stdin and wait for it, fails if it failed.
+    async fn finish(self) -> anyhow::Result<()> {
+        let Self {
+            command,
+            mut child,
+            stdin,
+        } = self;
+
+        drop(stdin);
+
+        let exit_status = child
+            .wait()
+            .await
+            .with_context(|| format!("reduce command '{}' wait error", command))?;
+
+        if !exit_status.success() {
+            anyhow::bail!(
+                "reduce command '{}' failed: exit_status={}",
+                command,
+                exit_status.code().unwrap_or_default()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::Context;
+
+use tokio::io::AsyncWriteExt;
+
+use tracing::{info, warn};
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{command_line_args::CommandLineArgs, process::exit_signal, redact};
+
+use super::{CommandStatus, OutputMessage};
+
+const INDEX_FILE_NAME: &str = "index.jsonl";
+
+/// Writes each command's output to a directory under --results named by the input's Seq, as in
+/// --joblog, so a --resume run writes to the same directories.
+pub struct ResultsWriter {
+    dir: PathBuf,
+    /// DIR/index.jsonl, a line for each command written.
+    index: tokio::fs::File,
+}
+
+impl ResultsWriter {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        let Some(dir) = &command_line_args.results else {
+            return Ok(None);
+        };
+
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("error creating results directory '{}'", dir))?;
+
+        let dir = PathBuf::from(dir);
+
+        if command_line_args.results_keep.is_some() || command_line_args.results_keep_days.is_some()
+        {
+            Self::remove_old_results(
+                &dir,
+                command_line_args.results_keep,
+                command_line_args.results_keep_days,
+            );
+        }
+
+        let index_path = dir.join(INDEX_FILE_NAME);
+
+        // with --resume or --resume-failed lines are appended to the index of the previous run
+        let index = if command_line_args.resume || command_line_args.resume_failed {
+            std::fs::File::options()
+                .create(true)
+                .append(true)
+                .open(&index_path)
+        } else {
+            std::fs::File::create(&index_path)
+        }
+        .with_context(|| format!("error opening results index '{}'", index_path.display()))?;
+
+        Ok(Some(Self {
+            dir,
+            index: tokio::fs::File::from_std(index),
+        }))
+    }
+
+    /// Results directories next to dir, other than dir, with the time their index was last
+    /// written.
+    fn other_results(dir: &Path) -> std::io::Result<Vec<(PathBuf, SystemTime)>> {
+        let dir = std::path::absolute(dir)?;
+        let Some(parent) = dir.parent() else {
+            return Ok(vec![]);
+        };
+
+        let mut other_results = vec![];
+        for entry in std::fs::read_dir(parent)? {
+            let path = entry?.path();
+            if path == dir {
+                continue;
+            }
+            // only directories written by --results are removed
+            if let Ok(metadata) = std::fs::metadata(path.join(INDEX_FILE_NAME)) {
+                other_results.push((path, metadata.modified()?));
+            }
+        }
+        Ok(other_results)
+    }
+
+    /// Which of the other results directories to remove for --results-keep and
+    /// --results-keep-days.
+    fn results_to_remove(
+        mut other_results: Vec<(PathBuf, SystemTime)>,
+        keep: Option<u64>,
+        keep_days: Option<u64>,
+        now: SystemTime,
+    ) -> Vec<PathBuf> {
+        // newest first
+        other_results.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+
+        // this run's directory is one of the kept directories
+        let keep_others = keep.map_or(usize::MAX, |keep| {
+            usize::try_from(keep - 1).unwrap_or(usize::MAX)
+        });
+        let max_age = keep_days.map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+
+        other_results
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (_, modified))| {
+                *i >= keep_others
+                    || max_age.is_some_and(|max_age| {
+                        now.duration_since(*modified).unwrap_or_default() > max_age
+                    })
+            })
+            .map(|(_, (path, _))| path)
+            .collect()
+    }
+
+    fn remove_old_results(dir: &Path, keep: Option<u64>, keep_days: Option<u64>) {
+        let other_results = match Self::other_results(dir) {
+            Ok(other_results) => other_results,
+            Err(e) => {
+                warn!("error listing old results directories: {}", e);
+                return;
+            }
+        };
+
+        for path in Self::results_to_remove(other_results, keep, keep_days, SystemTime::now()) {
+            match std::fs::remove_dir_all(&path) {
+                Ok(()) => info!("removed old results directory '{}'", path.display()),
+                Err(e) => warn!(
+                    "error removing old results directory '{}': {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Contents of the exitcode file: the exit code, 128 + the signal number for commands
+    /// killed by a signal as in shells, or the reason a command did not exit.
+    fn exit_code(status: CommandStatus) -> String {
+        match status {
+            CommandStatus::Exited(exit_status) => match exit_signal(&exit_status) {
+                Some(signal) => (128 + signal).to_string(),
+                None => exit_status.code().unwrap_or_default().to_string(),
+            },
+            CommandStatus::Timeout => "timeout".to_owned(),
+            CommandStatus::SpawnError => "spawn_error".to_owned(),
+            CommandStatus::IOError => "io_error".to_owned(),
+            CommandStatus::DescendantLimit => "descendant_limit".to_owned(),
+            CommandStatus::Cancelled => "cancelled".to_owned(),
+        }
+    }
+
+    /// Exit code for the index, a number, or the reason as a string for commands that did not
+    /// exit.
+    fn exit_code_value(status: CommandStatus) -> serde_json::Value {
+        let exit_code = Self::exit_code(status);
+        match exit_code.parse::<i32>() {
+            Ok(code) => code.into(),
+            Err(_) => exit_code.into(),
+        }
+    }
+
+    fn job_dir(&self, input_sequence: u64) -> PathBuf {
+        self.dir.join((input_sequence + 1).to_string())
+    }
+
+    async fn write_file(dir: &Path, name: &str, contents: &[u8]) -> std::io::Result<()> {
+        tokio::fs::write(dir.join(name), contents).await
+    }
+
+    /// Write the cmd, stdout, stderr and exitcode files for one command, and its index line.
+    pub async fn write(&mut self, output_message: &OutputMessage) -> std::io::Result<()> {
+        let job_dir = self.job_dir(output_message.input_sequence);
+        tokio::fs::create_dir_all(&job_dir).await?;
+
+        let command_output = &output_message.command_output;
+
+        let command_line = format!("{}\n", output_message.command_and_args.shell_quoted());
+        let command_line = redact::redactor().redact(&command_line);
+
+        Self::write_file(&job_dir, "cmd", command_line.as_bytes()).await?;
+        Self::write_file(&job_dir, "stdout", &command_output.stdout).await?;
+        Self::write_file(&job_dir, "stderr", &command_output.stderr).await?;
+        Self::write_file(
+            &job_dir,
+            "exitcode",
+            format!("{}\n", Self::exit_code(command_output.status)).as_bytes(),
+        )
+        .await?;
+
+        let index_line = serde_json::json!({
+            "seq": output_message.input_sequence + 1,
+            "input": output_message
+                .input
+                .as_deref()
+                .map(|input| redact::redactor().redact(input)),
+            "input_line": output_message.input_line_number.to_string(),
+            "exit_code": Self::exit_code_value(command_output.status),
+            "duration_secs": command_output.duration.as_secs_f64(),
+            "stdout": job_dir.join("stdout").display().to_string(),
+            "stderr": job_dir.join("stderr").display().to_string(),
+        });
+        self.index
+            .write_all(format!("{}\n", index_line).as_bytes())
+            .await?;
+        self.index.flush().await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn test_results_to_remove() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100 * 24 * 60 * 60);
+        let days_ago = |days: u64| now - Duration::from_secs(days * 24 * 60 * 60);
+        let other_results = vec![
+            (PathBuf::from("old"), days_ago(10)),
+            (PathBuf::from("new"), days_ago(1)),
+            (PathBuf::from("middle"), days_ago(5)),
+        ];
+
+        assert_eq!(
+            ResultsWriter::results_to_remove(other_results.clone(), Some(2), None, now),
+            vec![PathBuf::from("middle"), PathBuf::from("old")]
+        );
+        assert_eq!(
+            ResultsWriter::results_to_remove(other_results.clone(), None, Some(7), now),
+            vec![PathBuf::from("old")]
+        );
+        assert_eq!(
+            ResultsWriter::results_to_remove(other_results.clone(), Some(1), None, now).len(),
+            3
+        );
+        assert!(ResultsWriter::results_to_remove(other_results, Some(5), Some(30), now).is_empty());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(
+            ResultsWriter::exit_code(CommandStatus::Exited(std::process::ExitStatus::from_raw(
+                3 << 8
+            ))),
+            "3"
+        );
+        assert_eq!(
+            ResultsWriter::exit_code(CommandStatus::Exited(std::process::ExitStatus::from_raw(9))),
+            "137"
+        );
+        assert_eq!(ResultsWriter::exit_code(CommandStatus::Timeout), "timeout");
+
+        assert_eq!(
+            ResultsWriter::exit_code_value(CommandStatus::Exited(
+                std::process::ExitStatus::from_raw(3 << 8)
+            )),
+            serde_json::json!(3)
+        );
+        assert_eq!(
+            ResultsWriter::exit_code_value(CommandStatus::Timeout),
+            serde_json::json!("timeout")
+        );
+    }
+}
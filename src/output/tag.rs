@@ -0,0 +1,24 @@
+/// Prefix each line of output with the --tag of the input that produced it and a tab.
+pub fn tag_lines(tag: &str, data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() + (tag.len() + 1) * 4);
+
+    for line in data.split_inclusive(|&byte| byte == b'\n') {
+        result.extend_from_slice(tag.as_bytes());
+        result.push(b'\t');
+        result.extend_from_slice(line);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tag_lines() {
+        assert_eq!(tag_lines("a b", b""), b"");
+        assert_eq!(tag_lines("a b", b"x\n"), b"a b\tx\n");
+        assert_eq!(tag_lines("in", b"x\n\ny"), b"in\tx\nin\t\nin\ty");
+    }
+}
@@ -1,49 +1,308 @@
-use tokio::{io::AsyncWrite, sync::mpsc::Receiver};
+use tokio::{
+    io::{AsyncWrite, Stderr, Stdout},
+    sync::mpsc::Receiver,
+};
 
-use tracing::{debug, error, instrument, trace};
+use tokio_util::sync::CancellationToken;
 
-use super::OutputMessage;
+use tracing::{debug, error, info, instrument, trace, warn};
+
+use std::collections::BTreeMap;
+
+use crate::{
+    command_line_args::{CommandLineArgs, OrderBy},
+    process::{exit_signal, signal_name},
+    redact,
+};
+
+use super::{
+    duplicates::DuplicateOutputs, merge::MergeOutputWriter, reduce::Reducers,
+    results::ResultsWriter, CommandStatus, OutputChunk, OutputEvent, OutputMessage, Stream,
+};
 
 pub struct OutputTask {
-    receiver: Receiver<OutputMessage>,
+    receiver: Receiver<OutputEvent>,
+    order_by: OrderBy,
+    verbose_results: bool,
+    verbose_commands: bool,
+    detect_duplicate_output: bool,
+    merge_output: Option<MergeOutputWriter>,
+    reducers: Option<Reducers>,
+    results: Option<ResultsWriter>,
+    cancellation_token: CancellationToken,
 }
 
 impl OutputTask {
-    pub fn new(receiver: Receiver<OutputMessage>) -> Self {
-        Self { receiver }
+    pub fn new(
+        receiver: Receiver<OutputEvent>,
+        command_line_args: &CommandLineArgs,
+        merge_output: Option<MergeOutputWriter>,
+        reducers: Option<Reducers>,
+        results: Option<ResultsWriter>,
+        cancellation_token: &CancellationToken,
+    ) -> Self {
+        Self {
+            receiver,
+            order_by: command_line_args.output_order(),
+            verbose_results: command_line_args.verbose_results,
+            verbose_commands: command_line_args.verbose_commands,
+            detect_duplicate_output: command_line_args.detect_duplicate_output,
+            merge_output,
+            reducers,
+            results,
+            cancellation_token: cancellation_token.clone(),
+        }
     }
 
     #[instrument(skip_all, name = "OutputTask::run", level = "debug")]
-    pub async fn run(self) {
+    pub async fn run(self) -> anyhow::Result<()> {
         debug!("begin run");
 
-        async fn copy(mut buffer: &[u8], output_stream: &mut (impl AsyncWrite + Unpin)) {
-            let result = tokio::io::copy(&mut buffer, &mut *output_stream).await;
-            trace!("copy result = {:?}", result);
+        let receiver = self.receiver;
+
+        let mut output_processor = OutputProcessor {
+            stdout: tokio::io::stdout(),
+            stderr: tokio::io::stderr(),
+            write_error: false,
+            verbose_results: self.verbose_results,
+            verbose_commands: self.verbose_commands,
+            merge_output: self.merge_output,
+            reducers: self.reducers,
+            results: self.results,
+            duplicate_outputs: self.detect_duplicate_output.then(DuplicateOutputs::default),
+            cancellation_token: self.cancellation_token,
+        };
+
+        match self.order_by {
+            OrderBy::Completion => Self::write_by_completion(receiver, &mut output_processor).await,
+            OrderBy::Input | OrderBy::Start => {
+                Self::write_by_sequence(receiver, &mut output_processor).await
+            }
+        }
+
+        if let Some(merge_output) = output_processor.merge_output {
+            merge_output.finish().await?;
+        }
+
+        if let Some(reducers) = output_processor.reducers {
+            reducers.finish().await?;
+        }
+
+        if let Some(duplicate_outputs) = output_processor.duplicate_outputs {
+            duplicate_outputs.report();
+        }
+
+        debug!("end run");
+
+        Ok(())
+    }
+
+    /// Write output as it is received.  Messages that are ready together are written in order
+    /// of completion time, then start order.
+    async fn write_by_completion(
+        mut receiver: Receiver<OutputEvent>,
+        output_processor: &mut OutputProcessor,
+    ) {
+        while let Some(output_event) = receiver.recv().await {
+            let mut output_messages = vec![];
+
+            let mut next_event = Some(output_event);
+            while let Some(output_event) = next_event {
+                match output_event {
+                    OutputEvent::Output(output_message) => output_messages.push(output_message),
+                    // chunks of running commands are written in the order they were read
+                    OutputEvent::Chunk(output_chunk) => {
+                        output_processor.write_chunk(&output_chunk).await
+                    }
+                    OutputEvent::Skipped(_) => {}
+                }
+                next_event = receiver.try_recv().ok();
+            }
+
+            output_messages.sort_by_key(|output_message| {
+                (output_message.completed_at, output_message.sequence)
+            });
+
+            for output_message in output_messages {
+                output_processor.process(*output_message).await;
+            }
+        }
+    }
+
+    /// Hold output until the output for all lower sequence numbers is written or skipped.
+    async fn write_by_sequence(
+        mut receiver: Receiver<OutputEvent>,
+        output_processor: &mut OutputProcessor,
+    ) {
+        let mut pending_events = BTreeMap::new();
+        let mut next_sequence = 0;
+
+        while let Some(mut output_event) = receiver.recv().await {
+            // held for an earlier sequence, commands must not wait on the bytes of held outputs
+            output_event.release_channel_bytes();
+            pending_events.insert(output_event.sequence(), output_event);
+
+            while let Some(output_event) = pending_events.remove(&next_sequence) {
+                if let OutputEvent::Output(output_message) = output_event {
+                    output_processor.process(*output_message).await;
+                }
+                next_sequence += 1;
+            }
+        }
+
+        for (_, output_event) in pending_events {
+            if let OutputEvent::Output(output_message) = output_event {
+                output_processor.process(*output_message).await;
+            }
+        }
+    }
+}
+
+struct OutputProcessor {
+    stdout: Stdout,
+    stderr: Stderr,
+    write_error: bool,
+    verbose_results: bool,
+    verbose_commands: bool,
+    merge_output: Option<MergeOutputWriter>,
+    reducers: Option<Reducers>,
+    results: Option<ResultsWriter>,
+    duplicate_outputs: Option<DuplicateOutputs>,
+    cancellation_token: CancellationToken,
+}
+
+impl OutputProcessor {
+    async fn copy(
+        mut buffer: &[u8],
+        output_stream: &mut (impl AsyncWrite + Unpin),
+    ) -> std::io::Result<()> {
+        let result = tokio::io::copy(&mut buffer, &mut *output_stream).await;
+        trace!("copy result = {:?}", result);
+        result.map(|_| ())
+    }
+
+    async fn write_chunk(&mut self, output_chunk: &OutputChunk) {
+        if self.write_error {
+            return;
+        }
+
+        let result = match output_chunk.stream {
+            Stream::Stdout => Self::copy(&output_chunk.data, &mut self.stdout).await,
+            Stream::Stderr => Self::copy(&output_chunk.data, &mut self.stderr).await,
+        };
+        if let Err(e) = result {
+            warn!("output write error, cancelling: {}", e);
+            self.write_error = true;
+            self.cancellation_token.cancel();
+        }
+    }
+
+    async fn write_output(&mut self, output_message: &OutputMessage) {
+        // After a write error stop writing and cancel the remaining work.
+        // The output task keeps receiving so that command tasks never block on send.
+        if self.write_error {
+            return;
+        }
+
+        let command_output = &output_message.command_output;
+
+        let mut result = Ok(());
+        if self.verbose_commands {
+            let command_line = format!("{}\n", output_message.command_and_args.shell_quoted());
+            let command_line = redact::redactor().redact(&command_line);
+            // stdout of commands goes to --merge-output or --reduce, keep it free of command lines
+            result = if self.merge_output.is_some() || self.reducers.is_some() {
+                Self::copy(command_line.as_bytes(), &mut self.stderr).await
+            } else {
+                Self::copy(command_line.as_bytes(), &mut self.stdout).await
+            };
+        }
+        if result.is_ok() && !command_output.stdout.is_empty() {
+            result = match (&mut self.merge_output, &mut self.reducers) {
+                (Some(merge_output), _) => {
+                    merge_output
+                        .write(
+                            output_message.sequence,
+                            output_message.key.as_deref(),
+                            &command_output.stdout,
+                        )
+                        .await
+                }
+                (None, Some(reducers)) => {
+                    reducers
+                        .write(output_message.key.as_deref(), &command_output.stdout)
+                        .await
+                }
+                (None, None) => Self::copy(&command_output.stdout, &mut self.stdout).await,
+            };
+        }
+        if result.is_ok() && !command_output.stderr.is_empty() {
+            result = Self::copy(&command_output.stderr, &mut self.stderr).await;
         }
+        if let (Ok(()), Some(results)) = (&result, &mut self.results) {
+            result = results.write(output_message).await;
+        }
+        if let Err(e) = result {
+            warn!("output write error, cancelling: {}", e);
+            self.write_error = true;
+            self.cancellation_token.cancel();
+        }
+    }
 
-        let mut stdout = tokio::io::stdout();
-        let mut stderr = tokio::io::stderr();
+    async fn process(&mut self, output_message: OutputMessage) {
+        self.write_output(&output_message).await;
+
+        if let (Some(duplicate_outputs), Some(stdout_digest)) =
+            (&mut self.duplicate_outputs, output_message.stdout_digest)
+        {
+            duplicate_outputs.record(stdout_digest, output_message.input_line_number.to_string());
+        }
 
-        let mut receiver = self.receiver;
+        let command_output = &output_message.command_output;
 
-        while let Some(output_message) = receiver.recv().await {
-            if !output_message.stdout.is_empty() {
-                copy(&output_message.stdout, &mut stdout).await;
+        match command_output.status {
+            CommandStatus::Exited(exit_status) if exit_signal(&exit_status).is_some() => {
+                error!(
+                    "command killed by signal: {},line={} signal={} duration={:?}",
+                    output_message.command_and_args,
+                    output_message.input_line_number,
+                    signal_name(exit_signal(&exit_status).unwrap_or_default()),
+                    command_output.duration,
+                );
             }
-            if !output_message.stderr.is_empty() {
-                copy(&output_message.stderr, &mut stderr).await;
+            CommandStatus::Exited(exit_status) if !exit_status.success() => {
+                error!(
+                    "command failed: {},line={} exit_status={} duration={:?}",
+                    output_message.command_and_args,
+                    output_message.input_line_number,
+                    exit_status.code().unwrap_or_default(),
+                    command_output.duration,
+                );
             }
-            if !output_message.exit_status.success() {
+            CommandStatus::Exited(_) => {
+                if self.verbose_results {
+                    info!(
+                        "command succeeded: {},line={} duration={:?}",
+                        output_message.command_and_args,
+                        output_message.input_line_number,
+                        command_output.duration,
+                    );
+                }
+            }
+            CommandStatus::Timeout => {
                 error!(
-                    "command failed: {},line={} exit_status={}",
+                    "command timed out: {},line={} duration={:?}",
                     output_message.command_and_args,
                     output_message.input_line_number,
-                    output_message.exit_status.code().unwrap_or_default(),
+                    command_output.duration,
                 );
             }
+            CommandStatus::SpawnError
+            | CommandStatus::IOError
+            | CommandStatus::DescendantLimit
+            | CommandStatus::Cancelled => {
+                // already logged by the command task
+            }
         }
-
-        debug!("end run");
     }
 }
@@ -1,45 +1,130 @@
 pub mod buffered;
 pub mod command_line;
-mod regex;
+pub mod job_options;
+pub mod regex;
+
+use anyhow::Context;
 
 use tokio::sync::OnceCell;
 
-use std::sync::Arc;
+use std::{env, sync::Arc};
 
-use crate::{command_line_args::CommandLineArgs, common::OwnedCommandAndArgs};
+use crate::{
+    command_line_args::{CommandLineArgs, ShellKind},
+    common::{sh_quote, OwnedCommandAndArgs, SkippedInput},
+};
 
 use self::{
     buffered::BufferedInputLineParser, command_line::CommandLineArgsParser, regex::RegexProcessor,
 };
 
-struct ShellCommandAndArgs(Option<Vec<String>>);
+/// Environment variable with shell function definitions for --env-functions.
+pub const ENV_FUNCTIONS_VAR: &str = "PARALLEL_ENV_FUNCTIONS";
+
+struct ShellCommandAndArgs {
+    shell_command_and_args: Option<Vec<String>>,
+    /// Script run before each shell mode command.
+    script_prefix: String,
+    env_functions: bool,
+    init_file: bool,
+    shell_kind: ShellKind,
+}
 
 impl ShellCommandAndArgs {
     fn new(command_line_args: &CommandLineArgs) -> Self {
-        Self(if command_line_args.shell {
-            Some(vec![
-                command_line_args.shell_path.clone(),
-                command_line_args.shell_argument.clone(),
-            ])
+        let mut script_prefix = String::new();
+        if let Some(init_file) = &command_line_args.init_file {
+            script_prefix.push_str(&format!(". {}\n", sh_quote(init_file)));
+        }
+        if command_line_args.env_functions {
+            script_prefix.push_str(&format!("eval \"${}\"\n", ENV_FUNCTIONS_VAR));
+        }
+
+        Self {
+            shell_command_and_args: Self::shell_command_and_args(command_line_args),
+            script_prefix,
+            env_functions: command_line_args.env_functions,
+            init_file: command_line_args.init_file.is_some(),
+            shell_kind: command_line_args.shell_kind,
+        }
+    }
+
+    fn shell_command_and_args(command_line_args: &CommandLineArgs) -> Option<Vec<String>> {
+        if command_line_args.shell {
+            let shell_kind = command_line_args.shell_kind;
+
+            // also set by --shell-auto
+            let shell_path = match &command_line_args.shell_path {
+                Some(shell_path) => shell_path.clone(),
+                None => shell_kind.default_path().to_owned(),
+            };
+
+            let shell_arguments = if command_line_args.shell_argument.is_empty() {
+                shell_kind
+                    .default_arguments()
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect()
+            } else {
+                command_line_args.shell_argument.clone()
+            };
+
+            Some([vec![shell_path], shell_arguments].concat())
         } else {
             None
-        })
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(shell_path) = self.shell_command_and_args.as_ref().and_then(|v| v.first()) {
+            which::which(shell_path).with_context(|| {
+                format!("shell path '{}' is not an executable file", shell_path)
+            })?;
+        }
+
+        let sh_compatible = matches!(
+            self.shell_kind,
+            ShellKind::Bash | ShellKind::Sh | ShellKind::Zsh
+        );
+
+        if self.init_file && !sh_compatible {
+            anyhow::bail!("--init-file is only supported with bash, sh and zsh");
+        }
+
+        if self.env_functions {
+            if !sh_compatible {
+                anyhow::bail!("--env-functions is only supported with bash, sh and zsh");
+            }
+            if env::var_os(ENV_FUNCTIONS_VAR).is_none() {
+                anyhow::bail!(
+                    "--env-functions requires ${} with function definitions, e.g. export {}=\"$(declare -f NAME...)\"",
+                    ENV_FUNCTIONS_VAR,
+                    ENV_FUNCTIONS_VAR,
+                );
+            }
+        }
+
+        Ok(())
     }
 }
 
 fn build_owned_command_and_args(
     shell_command_and_args: &ShellCommandAndArgs,
     command_and_args: Vec<String>,
-) -> Option<OwnedCommandAndArgs> {
-    match &shell_command_and_args.0 {
-        None => OwnedCommandAndArgs::try_from(command_and_args).ok(),
-        Some(shell_command_and_args) => {
-            let mut result = Vec::with_capacity(shell_command_and_args.len() + 1);
+) -> Result<OwnedCommandAndArgs, SkippedInput> {
+    match &shell_command_and_args.shell_command_and_args {
+        None => OwnedCommandAndArgs::try_from(command_and_args).map_err(|_| SkippedInput::Empty),
+        Some(shell_and_args) => {
+            let mut result = Vec::with_capacity(shell_and_args.len() + 1);
 
-            result.extend(shell_command_and_args.iter().cloned());
-            result.push(command_and_args.join(" "));
+            result.extend(shell_and_args.iter().cloned());
+            result.push(format!(
+                "{}{}",
+                shell_command_and_args.script_prefix,
+                command_and_args.join(" ")
+            ));
 
-            OwnedCommandAndArgs::try_from(result).ok()
+            OwnedCommandAndArgs::try_from(result).map_err(|_| SkippedInput::Empty)
         }
     }
 }
@@ -52,6 +137,8 @@ pub struct Parsers {
 
 impl Parsers {
     pub fn new(command_line_args: &'static CommandLineArgs) -> anyhow::Result<Self> {
+        ShellCommandAndArgs::new(command_line_args).validate()?;
+
         let regex_processor = RegexProcessor::new(command_line_args)?;
 
         Ok(Self {
@@ -69,6 +156,10 @@ impl Parsers {
             .await
     }
 
+    pub fn input_failure(&self) -> Option<&'static str> {
+        self.regex_processor.input_failure()
+    }
+
     pub fn command_line_args_parser(&self) -> CommandLineArgsParser {
         CommandLineArgsParser::new(self.command_line_args, &self.regex_processor)
     }
@@ -1,18 +1,26 @@
 use itertools::Itertools;
 
-use std::sync::Arc;
+use tracing::warn;
+
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     command_line_args::CommandLineArgs,
-    common::OwnedCommandAndArgs,
-    parser::{regex::RegexProcessor, ShellCommandAndArgs},
+    common::{JobOptions, OwnedCommandAndArgs, SkippedInput},
+    parser::{
+        job_options::{parse_job_options, JOB_OPTIONS_COLUMN_SEPARATOR},
+        regex::RegexProcessor,
+        ShellCommandAndArgs,
+    },
 };
 
 pub struct BufferedInputLineParser {
     no_run_if_empty: bool,
+    job_options_column: bool,
     split_whitespace: bool,
     shell_command_and_args: ShellCommandAndArgs,
     command_and_initial_arguments: Vec<String>,
+    dispatch_commands: Option<HashMap<String, Vec<String>>>,
     regex_processor: Arc<RegexProcessor>,
 }
 
@@ -24,28 +32,99 @@ impl BufferedInputLineParser {
 
         let shell_command_and_args = ShellCommandAndArgs::new(command_line_args);
 
+        let dispatch_commands = if command_line_args.dispatch {
+            Some(Self::build_dispatch_commands(command_line_args))
+        } else {
+            None
+        };
+
         Self {
             no_run_if_empty: command_line_args.no_run_if_empty,
+            job_options_column: command_line_args.job_options_column,
             split_whitespace,
             shell_command_and_args,
             command_and_initial_arguments,
+            dispatch_commands,
             regex_processor: Arc::clone(regex_processor),
         }
     }
 
-    pub fn parse_segment(&self, segment: Vec<u8>) -> Option<OwnedCommandAndArgs> {
-        if let Ok(input_line) = std::str::from_utf8(&segment) {
-            self.parse_line(input_line)
-        } else {
-            None
+    fn build_dispatch_commands(
+        command_line_args: &CommandLineArgs,
+    ) -> HashMap<String, Vec<String>> {
+        command_line_args
+            .named_commands
+            .iter()
+            .map(|named_command| {
+                let template_arguments = if command_line_args.shell {
+                    vec![named_command.template.clone()]
+                } else {
+                    named_command
+                        .template
+                        .split_whitespace()
+                        .map_into()
+                        .collect()
+                };
+                (named_command.name.clone(), template_arguments)
+            })
+            .collect()
+    }
+
+    fn select_command_and_initial_arguments<'a>(
+        &'a self,
+        input_line: &'a str,
+    ) -> Result<(&'a Vec<String>, &'a str), SkippedInput> {
+        let Some(dispatch_commands) = &self.dispatch_commands else {
+            return Ok((&self.command_and_initial_arguments, input_line));
+        };
+
+        let input_line = input_line.trim_start();
+
+        let (name, remaining_input) = input_line
+            .split_once(char::is_whitespace)
+            .unwrap_or((input_line, ""));
+
+        match dispatch_commands.get(name) {
+            Some(template_arguments) => Ok((template_arguments, remaining_input)),
+            None => {
+                warn!("unknown dispatch command name '{}': {}", name, input_line);
+                Err(SkippedInput::Filtered)
+            }
+        }
+    }
+
+    /// Split the per-job options column from the input line with --job-options-column.
+    ///
+    /// Lines without a column separator have no options.
+    pub fn split_job_options<'a>(
+        &self,
+        input_line: &'a str,
+    ) -> Result<(JobOptions, &'a str), SkippedInput> {
+        if !self.job_options_column {
+            return Ok((JobOptions::default(), input_line));
+        }
+
+        let Some((column, input_line)) = input_line.split_once(JOB_OPTIONS_COLUMN_SEPARATOR) else {
+            return Ok((JobOptions::default(), input_line));
+        };
+
+        match parse_job_options(column) {
+            Ok(job_options) => Ok((job_options, input_line)),
+            Err(e) => {
+                warn!("invalid job options column: {}: {}", e, column);
+                Err(SkippedInput::Filtered)
+            }
         }
     }
 
-    pub fn parse_line(&self, input_line: &str) -> Option<OwnedCommandAndArgs> {
+    pub fn parse_line(&self, input_line: &str) -> Result<OwnedCommandAndArgs, SkippedInput> {
         if self.no_run_if_empty && input_line.trim().is_empty() {
-            return None;
+            return Err(SkippedInput::Empty);
         }
 
+        let (command_and_initial_arguments, input_line) =
+            self.select_command_and_initial_arguments(input_line)?;
+
         let cmd_and_args = if !self.regex_processor.regex_mode() {
             let mut cmd_and_args = if self.split_whitespace {
                 input_line.split_whitespace().map_into().collect()
@@ -53,15 +132,16 @@ impl BufferedInputLineParser {
                 vec![input_line.into()]
             };
 
-            if !self.command_and_initial_arguments.is_empty() {
-                cmd_and_args = [self.command_and_initial_arguments.clone(), cmd_and_args].concat();
+            if !command_and_initial_arguments.is_empty() {
+                cmd_and_args = [command_and_initial_arguments.clone(), cmd_and_args].concat();
             }
 
             cmd_and_args
         } else {
             let apply_regex_result = self
                 .regex_processor
-                .apply_regex_to_arguments(&self.command_and_initial_arguments, input_line)?;
+                .apply_regex_to_arguments(command_and_initial_arguments, input_line)
+                .ok_or_else(|| self.regex_processor.skipped_input(input_line))?;
             apply_regex_result.arguments
         };
 
@@ -73,6 +153,8 @@ impl BufferedInputLineParser {
 mod test {
     use super::*;
 
+    use crate::command_line_args::{NamedCommand, ShellKind};
+
     use std::{default::Default, path::PathBuf};
 
     #[test]
@@ -93,7 +175,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("echo"),
                 args: vec!["hi", "there"].into_iter().map_into().collect(),
             })
@@ -103,7 +185,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("echo"),
                 args: vec!["hi", "there"].into_iter().map_into().collect(),
             })
@@ -113,7 +195,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("/bin/echo"),
                 args: vec![],
             })
@@ -121,7 +203,7 @@ mod test {
 
         let result = parser.parse_line("");
 
-        assert_eq!(result, None);
+        assert_eq!(result, Err(SkippedInput::Empty));
     }
 
     #[test]
@@ -142,7 +224,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("gzip"),
                 args: vec!["-k", "file with spaces"]
                     .into_iter()
@@ -158,8 +240,8 @@ mod test {
             null_separator: false,
             shell: true,
             command_and_initial_arguments: vec![],
-            shell_path: "/bin/bash".to_owned(),
-            shell_argument: "-c".to_owned(),
+            shell_path: Some("/bin/bash".to_owned()),
+            shell_argument: vec!["-c".to_owned()],
             ..Default::default()
         };
 
@@ -172,7 +254,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("/bin/bash"),
                 args: vec!["-c", "awesomebashfunction 1 2 3"]
                     .into_iter()
@@ -185,8 +267,8 @@ mod test {
             null_separator: false,
             shell: true,
             command_and_initial_arguments: vec![],
-            shell_path: "/bin/zsh".to_owned(),
-            shell_argument: "-c".to_owned(),
+            shell_path: Some("/bin/zsh".to_owned()),
+            shell_argument: vec!["-c".to_owned()],
             ..Default::default()
         };
 
@@ -199,7 +281,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("/bin/zsh"),
                 args: vec!["-c", "awesomebashfunction 1 2 3"]
                     .into_iter()
@@ -209,6 +291,174 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_shell_env_functions() {
+        let command_line_args = CommandLineArgs {
+            shell: true,
+            env_functions: true,
+            shell_path: Some("/bin/bash".to_owned()),
+            shell_argument: vec!["-c".to_owned()],
+            ..Default::default()
+        };
+
+        let parser = BufferedInputLineParser::new(
+            &command_line_args,
+            &RegexProcessor::new(&command_line_args).unwrap(),
+        );
+
+        let result = parser.parse_line("awesomebashfunction 1 2 3");
+
+        assert_eq!(
+            result,
+            Ok(OwnedCommandAndArgs {
+                command_path: PathBuf::from("/bin/bash"),
+                args: vec![
+                    "-c",
+                    "eval \"$PARALLEL_ENV_FUNCTIONS\"\nawesomebashfunction 1 2 3"
+                ]
+                .into_iter()
+                .map_into()
+                .collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_shell_init_file() {
+        let command_line_args = CommandLineArgs {
+            shell: true,
+            init_file: Some("/tmp/it's here.sh".to_owned()),
+            env_functions: true,
+            shell_path: Some("/bin/bash".to_owned()),
+            shell_argument: vec!["-c".to_owned()],
+            ..Default::default()
+        };
+
+        let parser = BufferedInputLineParser::new(
+            &command_line_args,
+            &RegexProcessor::new(&command_line_args).unwrap(),
+        );
+
+        let result = parser.parse_line("greet 1");
+
+        assert_eq!(
+            result,
+            Ok(OwnedCommandAndArgs {
+                command_path: PathBuf::from("/bin/bash"),
+                args: vec![
+                    "-c",
+                    ". '/tmp/it'\\''s here.sh'\neval \"$PARALLEL_ENV_FUNCTIONS\"\ngreet 1"
+                ]
+                .into_iter()
+                .map_into()
+                .collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_shell_kind() {
+        let command_line_args = CommandLineArgs {
+            shell: true,
+            shell_kind: ShellKind::Python,
+            ..Default::default()
+        };
+
+        let parser = BufferedInputLineParser::new(
+            &command_line_args,
+            &RegexProcessor::new(&command_line_args).unwrap(),
+        );
+
+        let result = parser.parse_line("print(1)");
+
+        assert_eq!(
+            result,
+            Ok(OwnedCommandAndArgs {
+                command_path: PathBuf::from("python3"),
+                args: vec!["-u", "-c", "print(1)"]
+                    .into_iter()
+                    .map_into()
+                    .collect(),
+            })
+        );
+
+        let command_line_args = CommandLineArgs {
+            shell: true,
+            shell_kind: ShellKind::Python,
+            shell_path: Some("/usr/bin/env".to_owned()),
+            shell_argument: vec!["-S".to_owned(), "python3 -c".to_owned()],
+            ..Default::default()
+        };
+
+        let parser = BufferedInputLineParser::new(
+            &command_line_args,
+            &RegexProcessor::new(&command_line_args).unwrap(),
+        );
+
+        let result = parser.parse_line("print(1)");
+
+        assert_eq!(
+            result,
+            Ok(OwnedCommandAndArgs {
+                command_path: PathBuf::from("/usr/bin/env"),
+                args: vec!["-S", "python3 -c", "print(1)"]
+                    .into_iter()
+                    .map_into()
+                    .collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let command_line_args = CommandLineArgs {
+            dispatch: true,
+            named_commands: vec![
+                NamedCommand {
+                    name: "compress".to_owned(),
+                    template: "gzip -k".to_owned(),
+                },
+                NamedCommand {
+                    name: "checksum".to_owned(),
+                    template: "md5sum".to_owned(),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let parser = BufferedInputLineParser::new(
+            &command_line_args,
+            &RegexProcessor::new(&command_line_args).unwrap(),
+        );
+
+        let result = parser.parse_line("compress file1 file2");
+
+        assert_eq!(
+            result,
+            Ok(OwnedCommandAndArgs {
+                command_path: PathBuf::from("gzip"),
+                args: vec!["-k", "file1", "file2"]
+                    .into_iter()
+                    .map_into()
+                    .collect(),
+            })
+        );
+
+        let result = parser.parse_line(" checksum file1");
+
+        assert_eq!(
+            result,
+            Ok(OwnedCommandAndArgs {
+                command_path: PathBuf::from("md5sum"),
+                args: vec!["file1"].into_iter().map_into().collect(),
+            })
+        );
+
+        let result = parser.parse_line("unknown file1");
+
+        assert_eq!(result, Err(SkippedInput::Filtered));
+    }
+
     #[test]
     fn test_no_run_if_empty() {
         let command_line_args = CommandLineArgs {
@@ -226,11 +476,47 @@ mod test {
 
         let result = parser.parse_line("");
 
-        assert_eq!(result, None);
+        assert_eq!(result, Err(SkippedInput::Empty));
 
         let result = parser.parse_line(" \n\r\t ");
 
-        assert_eq!(result, None);
+        assert_eq!(result, Err(SkippedInput::Empty));
+    }
+
+    #[test]
+    fn test_split_job_options() {
+        let command_line_args = CommandLineArgs {
+            command_and_initial_arguments: vec!["echo".into()],
+            job_options_column: true,
+            ..Default::default()
+        };
+
+        let parser = BufferedInputLineParser::new(
+            &command_line_args,
+            &RegexProcessor::new(&command_line_args).unwrap(),
+        );
+
+        assert_eq!(
+            parser.split_job_options("timeout=30 env.TAG=web\thello world"),
+            Ok((
+                JobOptions {
+                    env: vec![("TAG".into(), "web".into())],
+                    timeout: Some(std::time::Duration::from_secs(30)),
+                    ..Default::default()
+                },
+                "hello world"
+            ))
+        );
+
+        assert_eq!(
+            parser.split_job_options("hello world"),
+            Ok((JobOptions::default(), "hello world"))
+        );
+
+        assert_eq!(
+            parser.split_job_options("tag=web\thello"),
+            Err(SkippedInput::Filtered)
+        );
     }
 
     #[test]
@@ -251,7 +537,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("md5"),
                 args: vec!["-s", "stuff"].into_iter().map_into().collect(),
             })
@@ -261,7 +547,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("md5"),
                 args: vec!["-s", "stuff", "things"]
                     .into_iter()
@@ -291,7 +577,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("echo"),
                 args: vec!["got arg1=foo arg2=bar"]
                     .into_iter()
@@ -299,6 +585,10 @@ mod test {
                     .collect(),
             })
         );
+
+        let result = parser.parse_line("nomatch");
+
+        assert_eq!(result, Err(SkippedInput::Unmatched));
     }
 
     #[test]
@@ -321,7 +611,7 @@ mod test {
 
         assert_eq!(
             result,
-            Some(OwnedCommandAndArgs {
+            Ok(OwnedCommandAndArgs {
                 command_path: PathBuf::from("echo"),
                 args: vec!["got arg1=bar arg2=foo arg3=foo,bar"]
                     .into_iter()
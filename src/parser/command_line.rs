@@ -1,21 +1,90 @@
 use itertools::Itertools;
 
-use std::{collections::VecDeque, sync::Arc};
+use std::sync::Arc;
 
 use crate::{
     command_line_args::{CommandLineArgs, COMMANDS_FROM_ARGS_SEPARATOR},
-    common::OwnedCommandAndArgs,
+    common::{OwnedCommandAndArgs, SkippedInput},
     parser::{regex::RegexProcessor, ShellCommandAndArgs},
 };
 
+/// Cartesian product of the ::: groups, generated one argument group at a time.
+#[derive(Debug)]
+struct ArgumentGroupProduct {
+    groups: Vec<Vec<String>>,
+    /// Index into each group for the next argument group, None after the last one.
+    indices: Option<Vec<usize>>,
+}
+
+impl ArgumentGroupProduct {
+    fn new(groups: Vec<Vec<String>>) -> Self {
+        let indices = if groups.iter().any(Vec::is_empty) {
+            None
+        } else {
+            Some(vec![0; groups.len()])
+        };
+
+        Self { groups, indices }
+    }
+
+    fn peek(&self) -> Option<Vec<String>> {
+        let indices = self.indices.as_ref()?;
+
+        Some(
+            self.groups
+                .iter()
+                .zip(indices)
+                .map(|(group, &index)| group[index].clone())
+                .collect(),
+        )
+    }
+
+    /// Advance the indices like an odometer, the last group changes fastest.
+    fn advance(&mut self) {
+        let Some(indices) = &mut self.indices else {
+            return;
+        };
+
+        for (index, group) in indices.iter_mut().zip(&self.groups).rev() {
+            *index += 1;
+            if *index < group.len() {
+                return;
+            }
+            *index = 0;
+        }
+
+        self.indices = None;
+    }
+}
+
+impl Iterator for ArgumentGroupProduct {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let argument_group = self.peek()?;
+        self.advance();
+        Some(argument_group)
+    }
+}
+
 #[derive(Debug)]
 struct ArgumentGroups {
     first_command_and_args: Vec<String>,
-    all_argument_groups: VecDeque<Vec<String>>,
+    all_argument_groups: ArgumentGroupProduct,
+}
+
+/// Separator used to join an argument group into input for the regex.
+fn argument_group_separator(command_line_args: &CommandLineArgs) -> &'static str {
+    if command_line_args.combine_input_files() {
+        "\t"
+    } else {
+        " "
+    }
 }
 
 pub struct CommandLineArgsParser {
     argument_groups: ArgumentGroups,
+    separator: &'static str,
     shell_command_and_args: ShellCommandAndArgs,
     regex_processor: Arc<RegexProcessor>,
 }
@@ -28,6 +97,7 @@ impl CommandLineArgsParser {
 
         Self {
             argument_groups,
+            separator: argument_group_separator(command_line_args),
             shell_command_and_args,
             regex_processor: Arc::clone(regex_processor),
         }
@@ -58,28 +128,32 @@ impl CommandLineArgsParser {
             }
         }
 
-        let all_argument_groups = remaining_argument_groups
-            .into_iter()
-            .multi_cartesian_product()
-            .collect();
-
         ArgumentGroups {
             first_command_and_args,
-            all_argument_groups,
+            all_argument_groups: ArgumentGroupProduct::new(remaining_argument_groups),
         }
     }
 
-    fn parse_argument_group(&self, argument_group: Vec<String>) -> Option<OwnedCommandAndArgs> {
+    /// Input data for an argument group.
+    pub fn argument_group_input(&self, argument_group: &[String]) -> String {
+        argument_group.join(self.separator)
+    }
+
+    pub fn parse_argument_group(
+        &self,
+        argument_group: Vec<String>,
+    ) -> Result<OwnedCommandAndArgs, SkippedInput> {
         let first_command_and_args = &self.argument_groups.first_command_and_args;
 
         let cmd_and_args = if !self.regex_processor.regex_mode() {
             [first_command_and_args.clone(), argument_group].concat()
         } else {
-            let input_line = argument_group.join(" ");
+            let input_line = self.argument_group_input(&argument_group);
 
             let apply_regex_result = self
                 .regex_processor
-                .apply_regex_to_arguments(first_command_and_args, &input_line)?;
+                .apply_regex_to_arguments(first_command_and_args, &input_line)
+                .ok_or_else(|| self.regex_processor.skipped_input(&input_line))?;
 
             if apply_regex_result.modified_arguments {
                 apply_regex_result.arguments
@@ -92,11 +166,20 @@ impl CommandLineArgsParser {
     }
 
     pub fn has_remaining_argument_groups(&self) -> bool {
-        !self.argument_groups.all_argument_groups.is_empty()
+        self.argument_groups.all_argument_groups.indices.is_some()
+    }
+
+    pub fn next_argument_group_input(&self) -> Option<String> {
+        self.argument_groups
+            .all_argument_groups
+            .peek()
+            .map(|argument_group| self.argument_group_input(&argument_group))
     }
 
-    pub fn parse_next_argument_group(&mut self) -> Option<OwnedCommandAndArgs> {
-        let argument_group = self.argument_groups.all_argument_groups.pop_front()?;
+    pub fn parse_next_argument_group(&mut self) -> Result<OwnedCommandAndArgs, SkippedInput> {
+        let Some(argument_group) = self.argument_groups.all_argument_groups.next() else {
+            return Err(SkippedInput::Empty);
+        };
         self.parse_argument_group(argument_group)
     }
 }
@@ -111,7 +194,7 @@ mod test {
         let mut result = vec![];
 
         while parser.has_remaining_argument_groups() {
-            let Some(cmd_and_args) = parser.parse_next_argument_group() else {
+            let Ok(cmd_and_args) = parser.parse_next_argument_group() else {
                 continue;
             };
 
@@ -223,6 +306,45 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_argument_group_product() {
+        let groups = |groups: &[&[&str]]| -> Vec<Vec<String>> {
+            groups
+                .iter()
+                .map(|group| group.iter().map(|arg| arg.to_string()).collect())
+                .collect()
+        };
+
+        assert_eq!(
+            ArgumentGroupProduct::new(groups(&[&["a", "b"], &["1", "2", "3"]])).collect_vec(),
+            groups(&[
+                &["a", "1"],
+                &["a", "2"],
+                &["a", "3"],
+                &["b", "1"],
+                &["b", "2"],
+                &["b", "3"],
+            ])
+        );
+
+        assert_eq!(
+            ArgumentGroupProduct::new(vec![]).collect_vec(),
+            vec![Vec::<String>::new()]
+        );
+
+        assert_eq!(
+            ArgumentGroupProduct::new(groups(&[&["a"], &[]])).collect_vec(),
+            Vec::<Vec<String>>::new()
+        );
+
+        // groups are combined as they are read, not materialized up front
+        let digits: Vec<&str> = vec!["0"; 100];
+        let mut product = ArgumentGroupProduct::new(groups(&[&digits, &digits, &digits]));
+        assert_eq!(product.peek(), Some(groups(&[&["0", "0", "0"]])[0].clone()));
+        assert_eq!(product.nth(9_999).map(|group| group.len()), Some(3));
+        assert_eq!(product.indices, Some(vec![1, 0, 0]));
+    }
+
     #[test]
     fn test_parse_command_line_args_empty() {
         let command_line_args = CommandLineArgs {
@@ -269,8 +391,8 @@ mod test {
             .into_iter()
             .map_into()
             .collect(),
-            shell_path: "/bin/bash".to_owned(),
-            shell_argument: "-c".to_owned(),
+            shell_path: Some("/bin/bash".to_owned()),
+            shell_argument: vec!["-c".to_owned()],
             ..Default::default()
         };
 
@@ -320,8 +442,8 @@ mod test {
                 .into_iter()
                 .map_into()
                 .collect(),
-            shell_path: "/bin/bash".to_owned(),
-            shell_argument: "-c".to_owned(),
+            shell_path: Some("/bin/bash".to_owned()),
+            shell_argument: vec!["-c".to_owned()],
             ..Default::default()
         };
 
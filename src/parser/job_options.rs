@@ -0,0 +1,98 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::common::JobOptions;
+
+/// Separates the per-job options column from the rest of an input line with --job-options-column.
+pub const JOB_OPTIONS_COLUMN_SEPARATOR: char = '\t';
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum JobOptionsError {
+    #[error("expected KEY=VALUE, got {0:?}")]
+    MissingValue(String),
+
+    #[error("invalid timeout {0:?}")]
+    InvalidTimeout(String),
+
+    #[error("unknown job option {0:?}")]
+    UnknownKey(String),
+}
+
+/// Parse whitespace separated KEY=VALUE job options.
+///
+/// Keys are timeout (seconds), cwd, and env.NAME to set environment variable NAME.
+pub fn parse_job_options(column: &str) -> Result<JobOptions, JobOptionsError> {
+    let mut job_options = JobOptions::default();
+
+    for option in column.split_whitespace() {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| JobOptionsError::MissingValue(option.to_owned()))?;
+
+        match key {
+            "timeout" => {
+                let timeout = value
+                    .parse()
+                    .ok()
+                    .filter(|seconds: &f64| *seconds > 0.0)
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .ok_or_else(|| JobOptionsError::InvalidTimeout(value.to_owned()))?;
+                job_options.timeout = Some(timeout);
+            }
+            "cwd" => job_options.cwd = Some(PathBuf::from(value)),
+            _ => match key.strip_prefix("env.") {
+                Some(name) if !name.is_empty() => {
+                    job_options.env.push((name.to_owned(), value.to_owned()))
+                }
+                _ => return Err(JobOptionsError::UnknownKey(key.to_owned())),
+            },
+        }
+    }
+
+    Ok(job_options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_job_options() {
+        assert_eq!(parse_job_options(""), Ok(JobOptions::default()));
+
+        assert_eq!(
+            parse_job_options("timeout=2.5 cwd=/tmp env.STAGE=web env.X=a=b"),
+            Ok(JobOptions {
+                env: vec![
+                    ("STAGE".to_owned(), "web".to_owned()),
+                    ("X".to_owned(), "a=b".to_owned()),
+                ],
+                cwd: Some(PathBuf::from("/tmp")),
+                timeout: Some(Duration::from_secs_f64(2.5)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_job_options_errors() {
+        assert_eq!(
+            parse_job_options("timeout"),
+            Err(JobOptionsError::MissingValue("timeout".to_owned()))
+        );
+        assert_eq!(
+            parse_job_options("timeout=0"),
+            Err(JobOptionsError::InvalidTimeout("0".to_owned()))
+        );
+        assert_eq!(
+            parse_job_options("timeout=soon"),
+            Err(JobOptionsError::InvalidTimeout("soon".to_owned()))
+        );
+        assert_eq!(
+            parse_job_options("retries=2"),
+            Err(JobOptionsError::UnknownKey("retries".to_owned()))
+        );
+        assert_eq!(
+            parse_job_options("env.=1"),
+            Err(JobOptionsError::UnknownKey("env.".to_owned()))
+        );
+    }
+}
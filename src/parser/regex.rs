@@ -1,12 +1,26 @@
+mod expression;
+mod format;
+
 use anyhow::Context;
 
 use itertools::Itertools;
 
-use tracing::warn;
+use tracing::{error, warn};
+
+use std::{
+    borrow::Cow,
+    sync::{Arc, OnceLock},
+};
 
-use std::{borrow::Cow, sync::Arc};
+use crate::{
+    command_line_args::{CommandLineArgs, OnNoMatch, COMMANDS_FROM_ARGS_SEPARATOR},
+    common::SkippedInput,
+};
 
-use crate::command_line_args::{CommandLineArgs, COMMANDS_FROM_ARGS_SEPARATOR};
+use self::{
+    expression::{expression_placeholder_regex, ExpressionError},
+    format::{format_placeholder_regex, FormatError, FormatSpec},
+};
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct ApplyRegexToArgumentsResult {
@@ -14,8 +28,19 @@ pub struct ApplyRegexToArgumentsResult {
     pub modified_arguments: bool,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum RegexSource {
+    Explicit,
+    AutoGenerated { argument_groups: usize },
+    CombinedInputFiles { input_files: usize },
+    Header { columns: usize },
+}
+
 pub struct RegexProcessor {
-    command_line_regex: Option<CommandLineRegex>,
+    command_line_regex: Option<(RegexSource, CommandLineRegex)>,
+    on_no_match: OnNoMatch,
+    strict_placeholders: bool,
+    input_failure: OnceLock<&'static str>,
 }
 
 impl RegexProcessor {
@@ -23,24 +48,49 @@ impl RegexProcessor {
         let auto_regex = AutoCommandLineArgsRegex::new(command_line_args);
 
         let command_line_regex = match (auto_regex, &command_line_args.regex) {
-            (Some(auto_regex), _) => Some(CommandLineRegex::new(&auto_regex.0)?),
-            (_, Some(cla_regex)) => Some(CommandLineRegex::new(cla_regex)?),
+            (Some(auto_regex), _) => {
+                Some((auto_regex.source, CommandLineRegex::new(&auto_regex.regex)?))
+            }
+            (_, Some(cla_regex)) => {
+                Some((RegexSource::Explicit, CommandLineRegex::new(cla_regex)?))
+            }
             _ => None,
         };
 
-        Ok(Arc::new(Self { command_line_regex }))
+        if let Some((_, command_line_regex)) = &command_line_regex {
+            command_line_regex.check_template(&command_line_args.command_template())?;
+        }
+
+        if command_line_args.strict_placeholders {
+            check_placeholders(
+                command_line_args,
+                command_line_regex.as_ref().map(|(_, regex)| regex),
+            )?;
+        }
+
+        Ok(Arc::new(Self {
+            command_line_regex,
+            on_no_match: command_line_args.on_no_match,
+            strict_placeholders: command_line_args.strict_placeholders,
+            input_failure: OnceLock::new(),
+        }))
     }
 
     pub fn regex_mode(&self) -> bool {
         self.command_line_regex.is_some()
     }
 
+    /// Reason processing inputs must stop, set by --on-no-match=fail or --strict-placeholders.
+    pub fn input_failure(&self) -> Option<&'static str> {
+        self.input_failure.get().copied()
+    }
+
     pub fn apply_regex_to_arguments(
         &self,
         arguments: &Vec<String>,
         input_data: &str,
     ) -> Option<ApplyRegexToArgumentsResult> {
-        let command_line_regex = self.command_line_regex.as_ref()?;
+        let (_, command_line_regex) = self.command_line_regex.as_ref()?;
 
         let mut results: Vec<String> = Vec::with_capacity(arguments.len());
         let mut found_input_data_match = false;
@@ -56,11 +106,44 @@ impl RegexProcessor {
                 Err(ExpandError::RegexDoesNotMatchInputData) => {
                     results.push(argument.clone());
                 }
+                Err(e @ (ExpandError::Format(_) | ExpandError::Expression(_))) => {
+                    warn!("placeholder error: {} input data: {}", e, input_data);
+                    return None;
+                }
             };
         }
 
         if !found_input_data_match {
-            warn!("regex did not match input data: {}", input_data);
+            match self.on_no_match {
+                OnNoMatch::Skip => None,
+                OnNoMatch::Warn => {
+                    warn!("regex did not match input data: {}", input_data);
+                    None
+                }
+                OnNoMatch::Fail => {
+                    error!("regex did not match input data: {}", input_data);
+                    let _ = self
+                        .input_failure
+                        .set("input did not match regex with --on-no-match=fail");
+                    None
+                }
+                OnNoMatch::RunUnmodified => Some(ApplyRegexToArgumentsResult {
+                    arguments: results,
+                    modified_arguments: false,
+                }),
+            }
+        } else if self.strict_placeholders
+            && results
+                .iter()
+                .any(|argument| command_line_regex.contains_match_key(argument))
+        {
+            error!(
+                "unexpanded placeholder in command {:?} for input data: {}",
+                results, input_data
+            );
+            let _ = self
+                .input_failure
+                .set("unexpanded placeholder with --strict-placeholders");
             None
         } else {
             Some(ApplyRegexToArgumentsResult {
@@ -69,6 +152,67 @@ impl RegexProcessor {
             })
         }
     }
+
+    /// Reason input_data was skipped after apply_regex_to_arguments returned None.
+    pub fn skipped_input(&self, input_data: &str) -> SkippedInput {
+        match &self.command_line_regex {
+            Some((_, command_line_regex)) if !command_line_regex.regex.is_match(input_data) => {
+                SkippedInput::Unmatched
+            }
+            _ => SkippedInput::Filtered,
+        }
+    }
+
+    pub fn expand_template(&self, template: &str, input_data: &str) -> Option<String> {
+        match &self.command_line_regex {
+            Some((_, command_line_regex)) => command_line_regex
+                .expand(template.into(), input_data)
+                .ok()
+                .map(|result| result.argument.into_owned()),
+            None => Some(template.replace("{0}", "{}").replace("{}", input_data)),
+        }
+    }
+
+    /// Match keys referenced in template and the values they are replaced with for input_data.
+    pub fn substitutions(&self, template: &str, input_data: &str) -> Vec<(String, String)> {
+        match &self.command_line_regex {
+            Some((_, command_line_regex)) => command_line_regex.substitutions(template, input_data),
+            None => vec![],
+        }
+    }
+
+    /// Describe how the regex was chosen and which match keys it provides.
+    pub fn explain(&self) -> Vec<String> {
+        let Some((source, command_line_regex)) = &self.command_line_regex else {
+            return vec!["no regex: input is appended to the command as arguments".to_owned()];
+        };
+
+        let source = match source {
+            RegexSource::Explicit => "explicit regex from -r/--regex".to_owned(),
+            RegexSource::AutoGenerated { argument_groups } => format!(
+                "auto-generated regex from {} ::: argument groups",
+                argument_groups
+            ),
+            RegexSource::Header { columns } => {
+                format!("auto-generated regex from {} --header columns", columns)
+            }
+            RegexSource::CombinedInputFiles { input_files } => format!(
+                "auto-generated regex from {} --link/--cross input files",
+                input_files
+            ),
+        };
+
+        let match_keys: Vec<_> = command_line_regex.match_keys().collect();
+
+        vec![
+            format!("{}: {:?}", source, command_line_regex.regex.as_str()),
+            format!(
+                "capture groups: {} match keys: {}",
+                command_line_regex.regex.captures_len() - 1,
+                match_keys.join(" ")
+            ),
+        ]
+    }
 }
 
 #[derive(Debug)]
@@ -81,6 +225,12 @@ struct ExpandResult<'a> {
 enum ExpandError {
     #[error("regex does not match input data")]
     RegexDoesNotMatchInputData,
+
+    #[error(transparent)]
+    Format(#[from] FormatError),
+
+    #[error(transparent)]
+    Expression(#[from] ExpressionError),
 }
 
 struct CommandLineRegex {
@@ -130,6 +280,77 @@ impl CommandLineRegex {
         let mut argument = argument;
         let mut modified_argument = false;
 
+        // expression placeholders, placeholders inside the expression are expanded before evaluation
+        if argument.contains("{=") {
+            let mut expression_error = None;
+
+            let evaluated_argument = expression_placeholder_regex().replace_all(
+                &argument,
+                |expression_captures: &regex::Captures| {
+                    let result = self
+                        .expand(expression_captures[1].into(), input_data)
+                        .map_err(|e| match e {
+                            ExpandError::Expression(e) => e,
+                            e => ExpressionError::new(&expression_captures[1], e.to_string()),
+                        })
+                        .and_then(|expanded| expression::evaluate(&expanded.argument));
+
+                    result.unwrap_or_else(|e| {
+                        expression_error.get_or_insert(e);
+                        expression_captures[0].to_owned()
+                    })
+                },
+            );
+
+            if let Some(e) = expression_error {
+                return Err(e.into());
+            }
+
+            if let Cow::Owned(evaluated_argument) = evaluated_argument {
+                argument = Cow::from(evaluated_argument);
+                modified_argument = true;
+            }
+        }
+
+        // placeholders with format specs, before plain placeholders so their values are not reformatted
+        if argument.contains(':') {
+            let mut format_error = None;
+
+            let formatted_argument = format_placeholder_regex().replace_all(
+                &argument,
+                |format_captures: &regex::Captures| {
+                    let placeholder = format_captures[0].to_owned();
+
+                    let Some(match_value) =
+                        self.format_placeholder_value(format_captures, &captures)
+                    else {
+                        return placeholder;
+                    };
+
+                    match FormatSpec::parse(&format_captures[3])
+                        .and_then(|format_spec| format_spec.apply(match_value))
+                    {
+                        Ok(formatted_value) => formatted_value,
+                        Err(e) => {
+                            format_error.get_or_insert(e);
+                            placeholder
+                        }
+                    }
+                },
+            );
+
+            if let Some(e) = format_error {
+                return Err(e.into());
+            }
+
+            if let Cow::Owned(formatted_argument) = formatted_argument {
+                if formatted_argument != argument {
+                    argument = Cow::from(formatted_argument);
+                    modified_argument = true;
+                }
+            }
+        }
+
         let mut update_argument = |match_key, match_value| {
             if argument.contains(match_key) {
                 argument = Cow::from(argument.replace(match_key, match_value));
@@ -162,20 +383,239 @@ impl CommandLineRegex {
             modified_argument,
         })
     }
+
+    fn match_keys(&self) -> impl Iterator<Item = &str> {
+        std::iter::once("{}")
+            .chain(self.numbered_group_match_keys.iter().map(String::as_str))
+            .chain(
+                self.named_group_to_match_key
+                    .iter()
+                    .map(|(_, match_key)| match_key.as_str()),
+            )
+    }
+
+    fn is_match_key(&self, key: &str) -> bool {
+        let placeholder = format!("{{{}}}", key);
+        self.match_keys().any(|match_key| match_key == placeholder)
+    }
+
+    fn contains_match_key(&self, argument: &str) -> bool {
+        self.match_keys()
+            .any(|match_key| argument.contains(match_key))
+            || format_placeholders(argument).any(|(key, _)| self.is_match_key(key))
+    }
+
+    /// Value of the capture group referenced by a format placeholder, if the group participated in the match.
+    fn format_placeholder_value<'h>(
+        &self,
+        format_captures: &regex::Captures,
+        captures: &regex::Captures<'h>,
+    ) -> Option<&'h str> {
+        if !format_captures[1].is_empty() {
+            return None;
+        }
+
+        let key = &format_captures[2];
+        let match_value = match key.parse::<usize>() {
+            _ if key.is_empty() => captures.get(0),
+            Ok(i) => captures.get(i),
+            Err(_) => captures.name(key),
+        };
+
+        match_value.map(|match_value| match_value.as_str())
+    }
+
+    /// Fail if template has an expression with invalid syntax,
+    /// or a format placeholder for a match key with an invalid format spec.
+    fn check_template(&self, template: &str) -> anyhow::Result<()> {
+        static PLACEHOLDER_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+        let placeholder_regex =
+            PLACEHOLDER_REGEX.get_or_init(|| regex::Regex::new(r"\{[^{}]*\}").unwrap());
+
+        for expression_captures in expression_placeholder_regex().captures_iter(template) {
+            // placeholders are only known per input, check the syntax with a number in their place
+            expression::check(&placeholder_regex.replace_all(&expression_captures[1], "1"))
+                .with_context(|| format!("invalid placeholder {}", &expression_captures[0]))?;
+        }
+
+        for (key, spec) in format_placeholders(template) {
+            if self.is_match_key(key) {
+                FormatSpec::parse(spec)
+                    .with_context(|| format!("invalid placeholder {{{}:{}}}", key, spec))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn substitutions(&self, template: &str, input_data: &str) -> Vec<(String, String)> {
+        let Some(captures) = self.regex.captures(input_data) else {
+            return vec![];
+        };
+
+        let mut substitutions = vec![];
+
+        // numbered capture groups
+        for (i, match_option) in captures.iter().enumerate() {
+            if let (Some(match_value), Some(match_key)) =
+                (match_option, self.numbered_group_match_keys.get(i))
+            {
+                if i == 0 && template.contains("{}") {
+                    substitutions.push(("{}".to_owned(), match_value.as_str().to_owned()));
+                }
+                if template.contains(match_key.as_str()) {
+                    substitutions.push((match_key.clone(), match_value.as_str().to_owned()));
+                }
+            }
+        }
+
+        // named capture groups
+        for (group_name, match_key) in self.named_group_to_match_key.iter() {
+            if let Some(match_value) = captures.name(group_name) {
+                if template.contains(match_key.as_str()) {
+                    substitutions.push((match_key.clone(), match_value.as_str().to_owned()));
+                }
+            }
+        }
+
+        // expression placeholders
+        for expression_captures in expression_placeholder_regex().captures_iter(template) {
+            if let Ok(expanded) = self.expand(expression_captures[0].into(), input_data) {
+                substitutions.push((
+                    expression_captures[0].to_owned(),
+                    expanded.argument.into_owned(),
+                ));
+            }
+        }
+
+        // placeholders with format specs
+        for format_captures in format_placeholder_regex().captures_iter(template) {
+            let Some(match_value) = self.format_placeholder_value(&format_captures, &captures)
+            else {
+                continue;
+            };
+            if let Ok(formatted_value) = FormatSpec::parse(&format_captures[3])
+                .and_then(|format_spec| format_spec.apply(match_value))
+            {
+                substitutions.push((format_captures[0].to_owned(), formatted_value));
+            }
+        }
+
+        substitutions.into_iter().unique().collect()
+    }
+}
+
+/// Key and format spec of each format placeholder in argument.
+///
+/// Shell variable references like ${NAME:-default} are not placeholders.
+fn format_placeholders(argument: &str) -> impl Iterator<Item = (&str, &str)> {
+    format_placeholder_regex()
+        .captures_iter(argument)
+        .filter(|captures| captures[1].is_empty())
+        .map(|captures| {
+            let (_, [_, key, spec]) = captures.extract();
+            (key, spec)
+        })
+}
+
+/// Fail if the command template has placeholders that are not match keys of the regex
+/// or --placeholder-cmd names.
+///
+/// Shell variable references like ${NAME} are not placeholders.
+fn check_placeholders(
+    command_line_args: &CommandLineArgs,
+    command_line_regex: Option<&CommandLineRegex>,
+) -> anyhow::Result<()> {
+    static PLACEHOLDER_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+    let placeholder_regex = PLACEHOLDER_REGEX.get_or_init(|| {
+        regex::Regex::new(r"(\$?)(\{(?:[A-Za-z_][A-Za-z0-9_]*|[0-9]+)?\})").unwrap()
+    });
+
+    let is_known_placeholder = |placeholder: &str| {
+        command_line_regex.is_some_and(|command_line_regex| {
+            command_line_regex
+                .match_keys()
+                .any(|match_key| match_key == placeholder)
+        }) || command_line_args
+            .placeholder_cmd
+            .iter()
+            .any(|named_command| format!("{{{}}}", named_command.name) == placeholder)
+            || (command_line_args.job_tmpdir && placeholder == "{tmpdir}")
+    };
+
+    let template = command_line_args.command_template();
+
+    let unknown_placeholders: Vec<_> = placeholder_regex
+        .captures_iter(&template)
+        .filter(|captures| captures[1].is_empty())
+        .map(|captures| captures[2].to_owned())
+        .filter(|placeholder| !is_known_placeholder(placeholder))
+        .chain(
+            format_placeholders(&template)
+                .filter(|(key, _)| !is_known_placeholder(&format!("{{{}}}", key)))
+                .map(|(key, spec)| format!("{{{}:{}}}", key, spec)),
+        )
+        .unique()
+        .collect();
+
+    if !unknown_placeholders.is_empty() {
+        anyhow::bail!(
+            "unknown placeholders in command template with --strict-placeholders: {}",
+            unknown_placeholders.join(" ")
+        );
+    }
+
+    Ok(())
 }
 
 #[derive(Debug)]
-struct AutoCommandLineArgsRegex(String);
+struct AutoCommandLineArgsRegex {
+    regex: String,
+    source: RegexSource,
+}
 
 impl AutoCommandLineArgsRegex {
     fn new(command_line_args: &CommandLineArgs) -> Option<Self> {
-        if command_line_args.regex.is_none() && command_line_args.commands_from_args_mode() {
+        if command_line_args.regex.is_some() {
+            None
+        } else if command_line_args.commands_from_args_mode() {
             Self::new_auto_interpolate_commands_from_args(command_line_args)
+        } else if !command_line_args.header_columns.is_empty() {
+            Some(Self::new_header(command_line_args))
+        } else if command_line_args.combine_input_files() {
+            Some(Self::new_combined_input_files(command_line_args))
         } else {
             None
         }
     }
 
+    /// One named capture group per tab separated header column.
+    fn new_header(command_line_args: &CommandLineArgs) -> Self {
+        let header_columns = &command_line_args.header_columns;
+
+        Self {
+            regex: header_columns
+                .iter()
+                .map(|name| format!("(?P<{}>[^\t]*)", name))
+                .join("\t"),
+            source: RegexSource::Header {
+                columns: header_columns.len(),
+            },
+        }
+    }
+
+    /// One capture group per input file, lines from each file are joined with tabs.
+    fn new_combined_input_files(command_line_args: &CommandLineArgs) -> Self {
+        let input_files = command_line_args.input_file.len();
+
+        Self {
+            regex: vec!["([^\t]*)"; input_files].join("\t"),
+            source: RegexSource::CombinedInputFiles { input_files },
+        }
+    }
+
     fn new_auto_interpolate_commands_from_args(
         command_line_args: &CommandLineArgs,
     ) -> Option<Self> {
@@ -208,11 +648,17 @@ impl AutoCommandLineArgsRegex {
             generated_regex.push_str("(.*)");
         }
 
-        Some(Self(generated_regex))
+        Some(Self {
+            regex: generated_regex,
+            source: RegexSource::AutoGenerated {
+                argument_groups: argument_group_count,
+            },
+        })
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::*;
 
@@ -234,6 +680,35 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_expand_template() {
+        let command_line_args = CommandLineArgs {
+            regex: None,
+            ..Default::default()
+        };
+
+        let regex_processor = RegexProcessor::new(&command_line_args).unwrap();
+
+        assert_eq!(
+            regex_processor.expand_template("{}.out {0}.log", "input"),
+            Some("input.out input.log".to_string()),
+        );
+
+        let command_line_args = CommandLineArgs {
+            regex: Some("(?P<name>.*)\\.(.*)".to_string()),
+            ..Default::default()
+        };
+
+        let regex_processor = RegexProcessor::new(&command_line_args).unwrap();
+
+        assert_eq!(
+            regex_processor.expand_template("{name}.{2}.gz", "file.txt"),
+            Some("file.txt.gz".to_string()),
+        );
+
+        assert_eq!(regex_processor.expand_template("{name}.gz", "nodot"), None);
+    }
+
     #[test]
     fn test_regex_numbered_groups() {
         let command_line_args = CommandLineArgs {
@@ -474,6 +949,319 @@ mod test {
         let auto_regex = AutoCommandLineArgsRegex::new(&command_line_args);
 
         assert!(auto_regex.is_some());
-        assert_eq!(auto_regex.unwrap().0, "(.*) (.*)");
+        let auto_regex = auto_regex.unwrap();
+        assert_eq!(auto_regex.regex, "(.*) (.*)");
+        assert_eq!(
+            auto_regex.source,
+            RegexSource::AutoGenerated { argument_groups: 2 }
+        );
+    }
+
+    #[test]
+    fn test_auto_regex_combined_input_files() {
+        let command_line_args = CommandLineArgs {
+            regex: None,
+            cross: true,
+            input_file: vec!["a.txt".to_owned(), "b.txt".to_owned()],
+            command_and_initial_arguments: vec!["echo".to_owned()],
+            ..Default::default()
+        };
+
+        let auto_regex = AutoCommandLineArgsRegex::new(&command_line_args).unwrap();
+        assert_eq!(auto_regex.regex, "([^\t]*)\t([^\t]*)");
+        assert_eq!(
+            auto_regex.source,
+            RegexSource::CombinedInputFiles { input_files: 2 }
+        );
+
+        let regex_processor = RegexProcessor::new(&command_line_args).unwrap();
+        assert_eq!(
+            regex_processor.expand_template("{2}/{1}", "a b\tc d"),
+            Some("c d/a b".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_substitutions() {
+        let command_line_args = CommandLineArgs {
+            regex: Some("(?P<name>.*),(.*)".to_owned()),
+            ..Default::default()
+        };
+
+        let regex_processor = RegexProcessor::new(&command_line_args).unwrap();
+
+        assert_eq!(
+            regex_processor.substitutions("echo {name} {2} {3}", "hello,world"),
+            vec![
+                ("{2}".to_owned(), "world".to_owned()),
+                ("{name}".to_owned(), "hello".to_owned()),
+            ]
+        );
+        assert_eq!(
+            regex_processor.substitutions("echo {}", "a,b"),
+            vec![("{}".to_owned(), "a,b".to_owned())]
+        );
+        assert!(regex_processor
+            .substitutions("echo {1}", "no match")
+            .is_empty());
+
+        let regex_processor = RegexProcessor::new(&CommandLineArgs::default()).unwrap();
+
+        assert!(regex_processor.substitutions("echo {1}", "a,b").is_empty());
+    }
+
+    #[test]
+    fn test_explain() {
+        let command_line_args = CommandLineArgs {
+            regex: Some("(?P<name>.*),(.*)".to_owned()),
+            ..Default::default()
+        };
+
+        let regex_processor = RegexProcessor::new(&command_line_args).unwrap();
+
+        assert_eq!(
+            regex_processor.explain(),
+            vec![
+                r#"explicit regex from -r/--regex: "(?P<name>.*),(.*)""#.to_owned(),
+                "capture groups: 2 match keys: {} {0} {1} {2} {name}".to_owned(),
+            ]
+        );
+
+        let command_line_args = CommandLineArgs {
+            command_and_initial_arguments: ["echo", ":::", "A", "B", ":::", "C", "D"]
+                .into_iter()
+                .map(|s| s.to_owned())
+                .collect(),
+            ..Default::default()
+        };
+
+        let regex_processor = RegexProcessor::new(&command_line_args).unwrap();
+
+        assert_eq!(
+            regex_processor.explain()[0],
+            r#"auto-generated regex from 2 ::: argument groups: "(.*) (.*)""#
+        );
+
+        let regex_processor = RegexProcessor::new(&CommandLineArgs::default()).unwrap();
+
+        assert_eq!(
+            regex_processor.explain(),
+            vec!["no regex: input is appended to the command as arguments"]
+        );
+    }
+
+    #[test]
+    fn test_on_no_match() {
+        let arguments = vec!["echo".to_owned(), "{1}".to_owned()];
+
+        let new_regex_processor = |on_no_match| {
+            RegexProcessor::new(&CommandLineArgs {
+                regex: Some("(.*),(.*)".to_owned()),
+                on_no_match,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        for on_no_match in [OnNoMatch::Skip, OnNoMatch::Warn] {
+            let regex_processor = new_regex_processor(on_no_match);
+            assert_eq!(
+                regex_processor.apply_regex_to_arguments(&arguments, "nomatch"),
+                None
+            );
+            assert_eq!(regex_processor.input_failure(), None);
+        }
+
+        let regex_processor = new_regex_processor(OnNoMatch::Fail);
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "nomatch"),
+            None
+        );
+        assert_eq!(
+            regex_processor.input_failure(),
+            Some("input did not match regex with --on-no-match=fail")
+        );
+
+        let regex_processor = new_regex_processor(OnNoMatch::RunUnmodified);
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "nomatch"),
+            Some(ApplyRegexToArgumentsResult {
+                arguments: arguments.clone(),
+                modified_arguments: false,
+            })
+        );
+        assert_eq!(regex_processor.input_failure(), None);
+    }
+
+    #[test]
+    fn test_strict_placeholders() {
+        let new_command_line_args = |template: &[&str]| CommandLineArgs {
+            regex: Some("(?P<file>.*),(.*)".to_owned()),
+            strict_placeholders: true,
+            command_and_initial_arguments: template.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert!(RegexProcessor::new(&new_command_line_args(&[
+            "echo",
+            "{file}",
+            "{2}",
+            "{}",
+            "${HOME}",
+            "{print $1}"
+        ]))
+        .is_ok());
+
+        let result = RegexProcessor::new(&new_command_line_args(&["echo", "{flie}", "{3}"]));
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "unknown placeholders in command template with --strict-placeholders: {flie} {3}"
+        );
+
+        let result = RegexProcessor::new(&CommandLineArgs {
+            strict_placeholders: true,
+            command_and_initial_arguments: vec!["echo".to_owned(), "{1}".to_owned()],
+            ..Default::default()
+        });
+        assert!(result.is_err());
+
+        let regex_processor = RegexProcessor::new(&CommandLineArgs {
+            regex: Some("(a)?(.*)".to_owned()),
+            strict_placeholders: true,
+            command_and_initial_arguments: vec!["echo".to_owned(), "{1}".to_owned()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let arguments = vec!["echo".to_owned(), "{1}".to_owned()];
+
+        assert!(regex_processor
+            .apply_regex_to_arguments(&arguments, "abc")
+            .is_some());
+        assert_eq!(regex_processor.input_failure(), None);
+
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "xyz"),
+            None
+        );
+        assert_eq!(
+            regex_processor.input_failure(),
+            Some("unexpanded placeholder with --strict-placeholders")
+        );
+    }
+
+    #[test]
+    fn test_format_specs() {
+        let regex_processor = RegexProcessor::new(&CommandLineArgs {
+            regex: Some("(?P<id>.*),(.*),(.*)".to_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let arguments = vec![
+            "echo".to_owned(),
+            "file-{id:04}-{2:int}.txt".to_owned(),
+            "{3:%Y/%m/%d}".to_owned(),
+            "${HOME:-x}".to_owned(),
+            "{other:03}".to_owned(),
+        ];
+
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "7,12.5,20240131"),
+            Some(ApplyRegexToArgumentsResult {
+                arguments: vec![
+                    "echo".to_owned(),
+                    "file-0007-12.txt".to_owned(),
+                    "2024/01/31".to_owned(),
+                    "${HOME:-x}".to_owned(),
+                    "{other:03}".to_owned(),
+                ],
+                modified_arguments: true,
+            })
+        );
+
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "7,abc,20240131"),
+            None
+        );
+
+        assert_eq!(
+            regex_processor.substitutions("echo {id:03} {id:03} {1}", "7,1,20240131"),
+            vec![
+                ("{1}".to_owned(), "7".to_owned()),
+                ("{id:03}".to_owned(), "007".to_owned()),
+            ]
+        );
+
+        let result = RegexProcessor::new(&CommandLineArgs {
+            regex: Some("(.*)".to_owned()),
+            command_and_initial_arguments: vec!["echo".to_owned(), "{1:float}".to_owned()],
+            ..Default::default()
+        });
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "invalid placeholder {1:float}"
+        );
+
+        let result = RegexProcessor::new(&CommandLineArgs {
+            regex: Some("(.*)".to_owned()),
+            strict_placeholders: true,
+            command_and_initial_arguments: vec![
+                "echo".to_owned(),
+                "{1:03}".to_owned(),
+                "{2:03}".to_owned(),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "unknown placeholders in command template with --strict-placeholders: {2:03}"
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let regex_processor = RegexProcessor::new(&CommandLineArgs {
+            regex: Some("(?P<n>.*),(.*)".to_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let arguments = vec![
+            "echo".to_owned(),
+            "{= {n} * 2 =}".to_owned(),
+            "part-{= ({2} + 100) % 7 =}-of-{2}".to_owned(),
+        ];
+
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "21,3"),
+            Some(ApplyRegexToArgumentsResult {
+                arguments: vec!["echo".to_owned(), "42".to_owned(), "part-5-of-3".to_owned(),],
+                modified_arguments: true,
+            })
+        );
+
+        assert_eq!(
+            regex_processor.apply_regex_to_arguments(&arguments, "abc,3"),
+            None
+        );
+
+        assert_eq!(
+            regex_processor.substitutions("echo {= {n} - 1 =}", "21,3"),
+            vec![
+                ("{n}".to_owned(), "21".to_owned()),
+                ("{= {n} - 1 =}".to_owned(), "20".to_owned()),
+            ]
+        );
+
+        let result = RegexProcessor::new(&CommandLineArgs {
+            regex: Some("(.*)".to_owned()),
+            command_and_initial_arguments: vec!["echo".to_owned(), "{= {1} * =}".to_owned()],
+            ..Default::default()
+        });
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "invalid placeholder {= {1} * =}"
+        );
     }
 }
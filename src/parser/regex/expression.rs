@@ -0,0 +1,304 @@
+use std::sync::OnceLock;
+
+/// Matches expression placeholders like {= {1} * 2 =}.
+///
+/// Capture group 1 is the expression.
+pub fn expression_placeholder_regex() -> &'static regex::Regex {
+    static EXPRESSION_PLACEHOLDER_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+    EXPRESSION_PLACEHOLDER_REGEX.get_or_init(|| regex::Regex::new(r"\{=(.*?)=\}").unwrap())
+}
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+#[error("invalid expression {expression:?}: {reason}")]
+pub struct ExpressionError {
+    expression: String,
+    reason: String,
+}
+
+impl ExpressionError {
+    pub fn new(expression: &str, reason: String) -> Self {
+        Self {
+            expression: expression.trim().to_owned(),
+            reason,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Int(i128),
+    Float(f64),
+}
+
+impl Value {
+    fn as_float(self) -> f64 {
+        match self {
+            Self::Int(i) => i as f64,
+            Self::Float(f) => f,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(float) => write!(f, "{}", float),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Number(Value),
+    Operator(char),
+    LeftParen,
+    RightParen,
+}
+
+#[derive(Debug)]
+enum Expression {
+    Number(Value),
+    Negate(Box<Expression>),
+    Binary(char, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    fn evaluate(&self) -> Result<Value, &'static str> {
+        match self {
+            Self::Number(value) => Ok(*value),
+            Self::Negate(operand) => match operand.evaluate()? {
+                Value::Int(i) => i.checked_neg().map(Value::Int).ok_or("overflow"),
+                Value::Float(f) => Ok(Value::Float(-f)),
+            },
+            Self::Binary(operator, left, right) => {
+                binary_operation(*operator, left.evaluate()?, right.evaluate()?)
+            }
+        }
+    }
+}
+
+fn binary_operation(operator: char, left: Value, right: Value) -> Result<Value, &'static str> {
+    let (Value::Int(l), Value::Int(r)) = (left, right) else {
+        let (l, r) = (left.as_float(), right.as_float());
+        if matches!(operator, '/' | '%') && r == 0.0 {
+            return Err("division by zero");
+        }
+        let result = match operator {
+            '+' => l + r,
+            '-' => l - r,
+            '*' => l * r,
+            '/' => l / r,
+            _ => l % r,
+        };
+        return if result.is_finite() {
+            Ok(Value::Float(result))
+        } else {
+            Err("result is not finite")
+        };
+    };
+
+    if matches!(operator, '/' | '%') && r == 0 {
+        return Err("division by zero");
+    }
+
+    let result = match operator {
+        '+' => l.checked_add(r),
+        '-' => l.checked_sub(r),
+        '*' => l.checked_mul(r),
+        '/' if l % r != 0 => return Ok(Value::Float(l as f64 / r as f64)),
+        '/' => l.checked_div(r),
+        _ => l.checked_rem(r),
+    };
+
+    result.map(Value::Int).ok_or("overflow")
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '+' | '-' | '*' | '/' | '%' => tokens.push(Token::Operator(c)),
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            '0'..='9' | '.' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    end = i + c.len_utf8();
+                }
+
+                let number = &expression[start..end];
+                let value = match number.parse() {
+                    Ok(i) => Value::Int(i),
+                    Err(_) => Value::Float(
+                        number
+                            .parse()
+                            .map_err(|_| format!("invalid number {:?}", number))?,
+                    ),
+                };
+                tokens.push(Token::Number(value));
+            }
+            c => return Err(format!("unexpected character {:?}", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser for `+ - * / %`, unary minus, and parentheses.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn parse_binary(
+        &mut self,
+        operators: &[char],
+        parse_operand: fn(&mut Self) -> Result<Expression, String>,
+    ) -> Result<Expression, String> {
+        let mut expression = parse_operand(self)?;
+
+        while let Some(Token::Operator(operator)) = self.peek() {
+            if !operators.contains(&operator) {
+                break;
+            }
+            self.next();
+            let right = parse_operand(self)?;
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_sum(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&['+', '-'], Self::parse_product)
+    }
+
+    fn parse_product(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&['*', '/', '%'], Self::parse_unary)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.peek() {
+            Some(Token::Operator('-')) => {
+                self.next();
+                Ok(Expression::Negate(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Operator('+')) => {
+                self.next();
+                self.parse_unary()
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::LeftParen) => {
+                let expression = self.parse_sum()?;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(expression),
+                    _ => Err("missing )".to_owned()),
+                }
+            }
+            Some(token) => Err(format!("unexpected token {:?}", token)),
+            None => Err("unexpected end of expression".to_owned()),
+        }
+    }
+}
+
+fn parse(expression: &str) -> Result<Expression, ExpressionError> {
+    let invalid_expression = |reason| ExpressionError::new(expression, reason);
+
+    let mut parser = Parser {
+        tokens: tokenize(expression).map_err(invalid_expression)?,
+        position: 0,
+    };
+
+    let result = parser.parse_sum().map_err(invalid_expression)?;
+
+    match parser.peek() {
+        None => Ok(result),
+        Some(token) => Err(invalid_expression(format!("unexpected token {:?}", token))),
+    }
+}
+
+/// Check the syntax of an expression without evaluating it.
+pub fn check(expression: &str) -> Result<(), ExpressionError> {
+    parse(expression).map(|_| ())
+}
+
+/// Evaluate an arithmetic expression after placeholders have been expanded.
+pub fn evaluate(expression: &str) -> Result<String, ExpressionError> {
+    parse(expression)?
+        .evaluate()
+        .map(|value| value.to_string())
+        .map_err(|reason| ExpressionError::new(expression, reason.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate(" 3 * 2 "), Ok("6".to_owned()));
+        assert_eq!(evaluate("1 + 2 * 3"), Ok("7".to_owned()));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok("9".to_owned()));
+        assert_eq!(evaluate("10 - -3"), Ok("13".to_owned()));
+        assert_eq!(evaluate("-(2 + 3) % 3"), Ok("-2".to_owned()));
+        assert_eq!(evaluate("8 / 2"), Ok("4".to_owned()));
+        assert_eq!(evaluate("7 / 2"), Ok("3.5".to_owned()));
+        assert_eq!(evaluate("2.5 * 2"), Ok("5".to_owned()));
+        assert_eq!(evaluate("0.1 + 1"), Ok("1.1".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let error = |expression: &str, reason: &str| {
+            Err(ExpressionError {
+                expression: expression.to_owned(),
+                reason: reason.to_owned(),
+            })
+        };
+
+        assert_eq!(evaluate("1 / 0"), error("1 / 0", "division by zero"));
+        assert_eq!(evaluate("1.5 % 0"), error("1.5 % 0", "division by zero"));
+        assert_eq!(
+            evaluate("170141183460469231731687303715884105727 + 1"),
+            error("170141183460469231731687303715884105727 + 1", "overflow")
+        );
+        assert_eq!(
+            evaluate("1 +"),
+            error("1 +", "unexpected end of expression")
+        );
+        assert_eq!(evaluate("(1 + 2"), error("(1 + 2", "missing )"));
+        assert_eq!(
+            evaluate("1 2"),
+            error("1 2", "unexpected token Number(Int(2))")
+        );
+        assert_eq!(
+            evaluate("{x} + 1"),
+            error("{x} + 1", "unexpected character '{'")
+        );
+        assert_eq!(evaluate("1..2"), error("1..2", "invalid number \"1..2\""));
+
+        assert_eq!(check("1 / 0"), Ok(()));
+    }
+}
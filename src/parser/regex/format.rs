@@ -0,0 +1,198 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDate, NaiveDateTime,
+};
+
+use std::{fmt::Write, sync::OnceLock};
+
+/// Matches placeholders with a format spec like {1:03}, {name:int}, or {:%Y-%m-%d}.
+///
+/// Capture group 1 is a leading $ for shell variable references, capture group 2 is the
+/// match key name or number (empty for the whole match), capture group 3 is the format spec.
+pub fn format_placeholder_regex() -> &'static regex::Regex {
+    static FORMAT_PLACEHOLDER_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+    FORMAT_PLACEHOLDER_REGEX.get_or_init(|| {
+        regex::Regex::new(r"(\$?)\{([A-Za-z_][A-Za-z0-9_]*|[0-9]*):([^{}]+)\}").unwrap()
+    })
+}
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum FormatError {
+    #[error("invalid format spec {0:?}")]
+    InvalidSpec(String),
+
+    #[error("value {value:?} is not an integer for format spec {spec:?}")]
+    NotAnInteger { value: String, spec: String },
+
+    #[error("value {value:?} is not a date for format spec {spec:?}")]
+    NotADate { value: String, spec: String },
+}
+
+const INPUT_DATE_TIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y%m%dT%H%M%S",
+    "%Y/%m/%d %H:%M:%S",
+];
+
+const INPUT_DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y%m%d", "%Y/%m/%d"];
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum FormatSpec {
+    /// {1:03} pads the value with zeros to a width of 3.
+    ZeroPad(usize),
+
+    /// {1:int} converts the value to an integer, truncating any fraction.
+    Int,
+
+    /// {1:%Y-%m-%d} parses the value as a date and formats it with strftime syntax.
+    Date(String),
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<Self, FormatError> {
+        let invalid_spec = || FormatError::InvalidSpec(spec.to_owned());
+
+        if spec == "int" {
+            Ok(Self::Int)
+        } else if spec.contains('%') {
+            if StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) {
+                return Err(invalid_spec());
+            }
+            Ok(Self::Date(spec.to_owned()))
+        } else if spec.len() > 1 && spec.starts_with('0') {
+            spec[1..]
+                .parse()
+                .map(Self::ZeroPad)
+                .map_err(|_| invalid_spec())
+        } else {
+            Err(invalid_spec())
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> Result<String, FormatError> {
+        match self {
+            Self::ZeroPad(width) => Ok(match value.parse::<i128>() {
+                Ok(integer) => format!("{:0width$}", integer, width = width),
+                Err(_) => format!("{:0>width$}", value, width = width),
+            }),
+            Self::Int => parse_integer(value)
+                .map(|integer| integer.to_string())
+                .ok_or_else(|| FormatError::NotAnInteger {
+                    value: value.to_owned(),
+                    spec: "int".to_owned(),
+                }),
+            Self::Date(spec) => {
+                let not_a_date = || FormatError::NotADate {
+                    value: value.to_owned(),
+                    spec: spec.clone(),
+                };
+
+                let date_time = parse_date_time(value).ok_or_else(not_a_date)?;
+
+                let mut result = String::new();
+                write!(result, "{}", date_time.format(spec)).map_err(|_| not_a_date())?;
+                Ok(result)
+            }
+        }
+    }
+}
+
+fn parse_integer(value: &str) -> Option<i128> {
+    let value = value.trim();
+
+    value.parse().ok().or_else(|| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|float| float.is_finite())
+            .map(|float| float.trunc() as i128)
+    })
+}
+
+fn parse_date_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+
+    INPUT_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            INPUT_DATE_FORMATS
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(FormatSpec::parse("03"), Ok(FormatSpec::ZeroPad(3)));
+        assert_eq!(FormatSpec::parse("int"), Ok(FormatSpec::Int));
+        assert_eq!(
+            FormatSpec::parse("%Y-%m-%d"),
+            Ok(FormatSpec::Date("%Y-%m-%d".to_owned()))
+        );
+
+        assert_eq!(
+            FormatSpec::parse("0"),
+            Err(FormatError::InvalidSpec("0".to_owned()))
+        );
+        assert_eq!(
+            FormatSpec::parse("0x"),
+            Err(FormatError::InvalidSpec("0x".to_owned()))
+        );
+        assert_eq!(
+            FormatSpec::parse("float"),
+            Err(FormatError::InvalidSpec("float".to_owned()))
+        );
+        assert_eq!(
+            FormatSpec::parse("%Q"),
+            Err(FormatError::InvalidSpec("%Q".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let zero_pad = FormatSpec::ZeroPad(3);
+        assert_eq!(zero_pad.apply("7"), Ok("007".to_owned()));
+        assert_eq!(zero_pad.apply("-7"), Ok("-07".to_owned()));
+        assert_eq!(zero_pad.apply("1234"), Ok("1234".to_owned()));
+        assert_eq!(zero_pad.apply("ab"), Ok("0ab".to_owned()));
+
+        let int = FormatSpec::Int;
+        assert_eq!(int.apply("007"), Ok("7".to_owned()));
+        assert_eq!(int.apply(" 3.9 "), Ok("3".to_owned()));
+        assert_eq!(int.apply("-2.5"), Ok("-2".to_owned()));
+        assert_eq!(
+            int.apply("abc"),
+            Err(FormatError::NotAnInteger {
+                value: "abc".to_owned(),
+                spec: "int".to_owned(),
+            })
+        );
+
+        let date = FormatSpec::Date("%Y-%m-%d".to_owned());
+        assert_eq!(date.apply("20240131"), Ok("2024-01-31".to_owned()));
+        assert_eq!(date.apply("2024/01/31"), Ok("2024-01-31".to_owned()));
+        assert_eq!(
+            date.apply("2024-01-31T10:11:12"),
+            Ok("2024-01-31".to_owned())
+        );
+        assert_eq!(
+            FormatSpec::Date("%Y%m%d-%H%M".to_owned()).apply("2024-01-31 10:11:12"),
+            Ok("20240131-1011".to_owned())
+        );
+        assert_eq!(
+            date.apply("2024-13-01"),
+            Err(FormatError::NotADate {
+                value: "2024-13-01".to_owned(),
+                spec: "%Y-%m-%d".to_owned(),
+            })
+        );
+    }
+}
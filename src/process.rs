@@ -1,14 +1,128 @@
+pub mod audit;
+mod audit_log;
+mod descendants;
+mod group;
+mod memsuspend;
+#[cfg(any(windows, test))]
+mod windows_args;
+
 use tokio::{
     process::{Child, Command},
     time::Duration,
 };
 
+use tokio_util::sync::CancellationToken;
+
 use std::{
     ffi::OsStr,
-    process::{Output, Stdio},
+    process::{ExitStatus, Output, Stdio},
+    sync::Arc,
+};
+
+use crate::{
+    command_line_args::{CommandLineArgs, DiscardOutput},
+    common::JobOptions,
+    output::OutputStream,
+    parser::ENV_FUNCTIONS_VAR,
+};
+
+use self::{
+    audit_log::AuditLog,
+    group::{ProcessGroup, ProcessGroupTracker},
+    memsuspend::{MemorySuspendRegistration, MemorySuspender},
 };
 
-use crate::command_line_args::{CommandLineArgs, DiscardOutput};
+/// Create the command for a program and its arguments.
+#[cfg(not(windows))]
+fn new_command<C, AI, A>(program: C, args: AI) -> std::io::Result<Command>
+where
+    C: AsRef<OsStr>,
+    AI: IntoIterator<Item = A>,
+    A: AsRef<OsStr>,
+{
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Create the command for a program and its arguments.
+///
+/// Batch files are run with cmd.exe, with arguments quoted and escaped so they reach the batch
+/// file unchanged.
+#[cfg(windows)]
+fn new_command<C, AI, A>(program: C, args: AI) -> std::io::Result<Command>
+where
+    C: AsRef<OsStr>,
+    AI: IntoIterator<Item = A>,
+    A: AsRef<OsStr>,
+{
+    let program = std::path::Path::new(program.as_ref());
+
+    if !windows_args::is_batch_file(program) {
+        let mut command = Command::new(program);
+        command.args(args);
+        return Ok(command);
+    }
+
+    let args: Vec<String> = args
+        .into_iter()
+        .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+        .collect();
+
+    let cmd_path = std::env::var_os("COMSPEC").unwrap_or_else(|| "cmd.exe".into());
+
+    let mut command = Command::new(cmd_path);
+    command.raw_arg(windows_args::batch_file_arguments(
+        &program.to_string_lossy(),
+        &args,
+    )?);
+    Ok(command)
+}
+
+/// Environment variables passed to commands with --minimal-env.
+#[cfg(not(windows))]
+const MINIMAL_ENV_VARS: &[&str] = &["PATH", "HOME", "LANG"];
+
+#[cfg(windows)]
+const MINIMAL_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "LANG",
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "PATHEXT",
+    "TEMP",
+    "TMP",
+    "USERPROFILE",
+];
+
+/// Returns the signal that terminated a child process, if it was killed by a signal.
+#[cfg(unix)]
+pub fn exit_signal(exit_status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    exit_status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn exit_signal(_exit_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Returns a name like "SIGKILL" for a signal number.
+#[cfg(unix)]
+pub fn signal_name(signal: i32) -> String {
+    nix::sys::signal::Signal::try_from(signal)
+        .map(|signal| signal.as_str().to_owned())
+        .unwrap_or_else(|_| format!("signal {}", signal))
+}
+
+#[cfg(not(unix))]
+pub fn signal_name(signal: i32) -> String {
+    format!("signal {}", signal)
+}
 
 #[derive(thiserror::Error, Debug)]
 pub enum ChildProcessExecutionError {
@@ -17,13 +131,31 @@ pub enum ChildProcessExecutionError {
 
     #[error("i/o error: {0}")]
     IOError(#[from] std::io::Error),
+
+    #[error(
+        "descendant process limit exceeded: {descendants} descendants > max {max_descendants}"
+    )]
+    DescendantLimit {
+        descendants: usize,
+        max_descendants: usize,
+    },
+
+    #[error("cancelled")]
+    Cancelled,
 }
 
+const DESCENDANTS_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
 #[derive(Debug)]
 pub struct ChildProcess {
     child: Child,
     discard_all_output: bool,
     timeout: Option<Duration>,
+    max_descendants: Option<usize>,
+    process_group: Option<ProcessGroup>,
+    kill_token: CancellationToken,
+    output_stream: Option<OutputStream>,
+    _memory_suspend_registration: Option<MemorySuspendRegistration>,
 }
 
 impl ChildProcess {
@@ -31,7 +163,31 @@ impl ChildProcess {
         self.child.id()
     }
 
+    /// Forward output to output_stream as it is read, the output returned is empty.
+    pub fn stream_output(&mut self, output_stream: OutputStream) {
+        self.output_stream = Some(output_stream);
+    }
+
     async fn await_output(mut self) -> Result<Output, ChildProcessExecutionError> {
+        if let Some(output_stream) = self.output_stream.take() {
+            let stdout = self.child.stdout.take();
+            let stderr = self.child.stderr.take();
+
+            let (status, stdout_result, stderr_result) = tokio::join!(
+                self.child.wait(),
+                output_stream.forward_stdout(stdout),
+                output_stream.forward_stderr(stderr),
+            );
+            stdout_result?;
+            stderr_result?;
+
+            return Ok(Output {
+                status: status?,
+                stdout: vec![],
+                stderr: vec![],
+            });
+        }
+
         let output = if self.discard_all_output {
             Output {
                 status: self.child.wait().await?,
@@ -45,11 +201,44 @@ impl ChildProcess {
         Ok(output)
     }
 
-    pub async fn await_completion(self) -> Result<Output, ChildProcessExecutionError> {
+    async fn await_output_with_descendant_limit(
+        self,
+    ) -> Result<Output, ChildProcessExecutionError> {
+        let (Some(max_descendants), Some(pid)) = (self.max_descendants, self.id()) else {
+            return self.await_output().await;
+        };
+
+        let output_future = self.await_output();
+        tokio::pin!(output_future);
+
+        let mut interval = tokio::time::interval(DESCENDANTS_CHECK_INTERVAL);
+
+        loop {
+            tokio::select! {
+                result = &mut output_future => return result,
+
+                _ = interval.tick() => {
+                    let descendants = descendants::descendants(pid).len();
+                    if descendants > max_descendants {
+                        descendants::kill_tree(pid);
+                        let _ = output_future.await;
+                        return Err(ChildProcessExecutionError::DescendantLimit {
+                            descendants,
+                            max_descendants,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    async fn await_output_with_timeout(self) -> Result<Output, ChildProcessExecutionError> {
         match self.timeout {
-            None => self.await_output().await,
+            None => self.await_output_with_descendant_limit().await,
             Some(timeout) => {
-                let result = tokio::time::timeout(timeout, self.await_output()).await?;
+                let result =
+                    tokio::time::timeout(timeout, self.await_output_with_descendant_limit())
+                        .await?;
 
                 let output = result?;
 
@@ -57,6 +246,37 @@ impl ChildProcess {
             }
         }
     }
+
+    /// Wait for the command to complete.
+    ///
+    /// A timeout or ChildProcessFactory::kill_running_commands kills the child, and the whole
+    /// group when running in a process group or job object.
+    pub async fn await_completion(mut self) -> Result<Output, ChildProcessExecutionError> {
+        let kill_token = self.kill_token.clone();
+
+        let Some(process_group) = self.process_group.take() else {
+            // dropping the child kills it
+            return tokio::select! {
+                result = self.await_output_with_timeout() => result,
+
+                _ = kill_token.cancelled() => Err(ChildProcessExecutionError::Cancelled),
+            };
+        };
+
+        let result = tokio::select! {
+            result = self.await_output_with_timeout() => result,
+
+            _ = kill_token.cancelled() => Err(ChildProcessExecutionError::Cancelled),
+        };
+
+        if result.is_err() {
+            process_group.kill();
+        } else {
+            process_group.command_exited();
+        }
+
+        result
+    }
 }
 
 #[derive(Debug)]
@@ -64,11 +284,20 @@ pub struct ChildProcessFactory {
     discard_stdout: bool,
     discard_stderr: bool,
     timeout: Option<Duration>,
+    max_descendants: Option<usize>,
+    process_group_tracker: Option<Arc<ProcessGroupTracker>>,
+    memory_suspender: Option<Arc<MemorySuspender>>,
+    audit_log: Option<AuditLog>,
+    /// Variables kept from the environment with --minimal-env.
+    minimal_env: Option<Vec<&'static str>>,
+    run_env: Vec<(&'static str, String)>,
+    /// Cancelled to kill all running children.
+    kill_token: CancellationToken,
 }
 
 impl ChildProcessFactory {
-    pub fn new(command_line_args: &CommandLineArgs) -> Self {
-        Self {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Self> {
+        Ok(Self {
             discard_stdout: matches!(
                 command_line_args.discard_output,
                 Some(DiscardOutput::All) | Some(DiscardOutput::Stdout)
@@ -80,7 +309,43 @@ impl ChildProcessFactory {
             timeout: command_line_args
                 .timeout_seconds
                 .map(Duration::from_secs_f64),
-        }
+            max_descendants: command_line_args.max_descendants,
+            process_group_tracker: command_line_args
+                .process_group
+                .then(|| Arc::new(ProcessGroupTracker::default())),
+            memory_suspender: command_line_args
+                .memsuspend
+                .map(|limit| MemorySuspender::new(limit, command_line_args.process_group)),
+            audit_log: AuditLog::new(command_line_args)?,
+            minimal_env: command_line_args.minimal_env.then(|| {
+                let mut names = MINIMAL_ENV_VARS.to_vec();
+                if command_line_args.env_functions {
+                    names.push(ENV_FUNCTIONS_VAR);
+                }
+                names
+            }),
+            run_env: std::iter::once(("PARALLEL_RUN_ID", command_line_args.run_id.clone()))
+                .chain(
+                    command_line_args
+                        .run_name
+                        .clone()
+                        .map(|run_name| ("PARALLEL_RUN_NAME", run_name)),
+                )
+                .collect(),
+            kill_token: CancellationToken::new(),
+        })
+    }
+
+    /// Kill the children of running commands, for Ctrl-C and --halt now.
+    pub fn kill_running_commands(&self) {
+        self.kill_token.cancel();
+    }
+
+    /// Warn about process groups that outlived their commands, returns the number found.
+    pub fn sweep_process_groups(&self) -> usize {
+        self.process_group_tracker
+            .as_ref()
+            .map_or(0, |tracker| tracker.sweep())
     }
 
     fn stdout(&self) -> Stdio {
@@ -103,24 +368,88 @@ impl ChildProcessFactory {
         self.discard_stdout && self.discard_stderr
     }
 
-    pub async fn spawn<C, AI, A>(&self, command: C, args: AI) -> std::io::Result<ChildProcess>
+    pub async fn spawn<C, AI, A>(
+        &self,
+        command: C,
+        args: AI,
+        job_options: &JobOptions,
+    ) -> std::io::Result<ChildProcess>
     where
         C: AsRef<OsStr>,
         AI: IntoIterator<Item = A>,
         A: AsRef<OsStr>,
     {
-        let child = Command::new(command)
-            .args(args)
+        let timeout = job_options.timeout.or(self.timeout);
+
+        let mut command = new_command(command, args)?;
+
+        if let Some(minimal_env) = &self.minimal_env {
+            command.env_clear().envs(
+                minimal_env
+                    .iter()
+                    .filter_map(|&name| Some((name, std::env::var_os(name)?))),
+            );
+        }
+
+        command
+            .envs(self.run_env.iter().map(|(name, value)| (name, value)))
+            .envs(job_options.env.iter().map(|(name, value)| (name, value)))
             .stdin(Stdio::null())
             .stdout(self.stdout())
             .stderr(self.stderr())
-            .kill_on_drop(self.timeout.is_some())
-            .spawn()?;
+            .kill_on_drop(true);
+
+        if let Some(cwd) = &job_options.cwd {
+            command.current_dir(cwd);
+        }
+
+        #[cfg(unix)]
+        if self.process_group_tracker.is_some() {
+            command.process_group(0);
+        }
+
+        let child = command.spawn()?;
+
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(command.as_std(), child.id());
+        }
+
+        let process_group = self.process_group_tracker.as_ref().and_then(|tracker| {
+            ProcessGroup::new(&child, format!("{:?}", command.as_std()), tracker)
+        });
+
+        let memory_suspend_registration = match (&self.memory_suspender, child.id()) {
+            (Some(memory_suspender), Some(id)) => Some(memory_suspender.register(id)),
+            _ => None,
+        };
 
         Ok(ChildProcess {
             child,
             discard_all_output: self.discard_all_output(),
-            timeout: self.timeout,
+            timeout,
+            max_descendants: self.max_descendants,
+            process_group,
+            kill_token: self.kill_token.clone(),
+            output_stream: None,
+            _memory_suspend_registration: memory_suspend_registration,
         })
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn test_exit_signal() {
+        assert_eq!(exit_signal(&ExitStatus::from_raw(0)), None);
+        assert_eq!(exit_signal(&ExitStatus::from_raw(1 << 8)), None);
+        assert_eq!(exit_signal(&ExitStatus::from_raw(9)), Some(9));
+
+        assert_eq!(signal_name(9), "SIGKILL");
+        assert_eq!(signal_name(11), "SIGSEGV");
+        assert_eq!(signal_name(1000), "signal 1000");
+    }
+}
@@ -0,0 +1,63 @@
+use tracing::{debug, warn};
+
+use crate::command_line_args::{AuditChildren, CommandLineArgs};
+
+use super::descendants;
+
+/// Finds descendant processes still running when the run completes.
+///
+/// On Linux this process becomes a child subreaper, so orphaned descendants of commands are
+/// reparented to it and can still be found.
+pub struct ChildAuditor {
+    mode: Option<AuditChildren>,
+}
+
+#[cfg(target_os = "linux")]
+fn enable_child_subreaper() {
+    if let Err(e) = nix::sys::prctl::set_child_subreaper(true) {
+        warn!("set_child_subreaper error: {}", e);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_child_subreaper() {
+    warn!("--audit-children is only supported on Linux");
+}
+
+impl ChildAuditor {
+    pub fn new(command_line_args: &CommandLineArgs) -> Self {
+        let mode = command_line_args.audit_children;
+
+        if mode.is_some() {
+            enable_child_subreaper();
+        }
+
+        Self { mode }
+    }
+
+    /// Report leaked descendant processes, killing them in kill mode.  Returns the number found.
+    pub fn audit(&self) -> usize {
+        let Some(mode) = self.mode else {
+            return 0;
+        };
+
+        let leaked_pids = descendants::descendants(std::process::id());
+        debug!("audit leaked_pids = {:?}", leaked_pids);
+
+        for &pid in &leaked_pids {
+            warn!(
+                "leaked child process pid={} command={:?}",
+                pid,
+                descendants::command_line(pid).unwrap_or_default(),
+            );
+        }
+
+        if mode == AuditChildren::Kill {
+            for &pid in &leaked_pids {
+                descendants::kill_tree(pid);
+            }
+        }
+
+        leaked_pids.len()
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Context;
+
+use tracing::warn;
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use crate::{command_line_args::CommandLineArgs, common::run_json_fields};
+
+/// Appends one JSON line per spawned process to --audit-log.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: String,
+    file: Mutex<File>,
+    env_cleared: bool,
+    run_fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl AuditLog {
+    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        let Some(path) = &command_line_args.audit_log else {
+            return Ok(None);
+        };
+
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("error opening audit log '{}'", path))?;
+
+        Ok(Some(Self {
+            path: path.clone(),
+            file: Mutex::new(file),
+            env_cleared: command_line_args.minimal_env,
+            run_fields: run_json_fields(command_line_args),
+        }))
+    }
+
+    /// The program path as it will be run, looked up in PATH if it is a bare name.
+    fn resolve_program(program: &Path) -> PathBuf {
+        if program.components().count() > 1 {
+            return program.to_owned();
+        }
+        which::which(program).unwrap_or_else(|_| program.to_owned())
+    }
+
+    fn build_entry(
+        command: &std::process::Command,
+        pid: Option<u32>,
+        env_cleared: bool,
+    ) -> serde_json::Value {
+        let program = Self::resolve_program(Path::new(command.get_program()));
+
+        let argv: Vec<_> = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy())
+            .collect();
+
+        // variables set for the command, with null for variables removed.  With env_clear
+        // these are the only variables the command has.
+        let env: serde_json::Map<_, _> = command
+            .get_envs()
+            .map(|(name, value)| {
+                (
+                    name.to_string_lossy().into_owned(),
+                    value.map_or(serde_json::Value::Null, |value| {
+                        value.to_string_lossy().into()
+                    }),
+                )
+            })
+            .collect();
+
+        let cwd = command
+            .get_current_dir()
+            .map(Path::to_owned)
+            .or_else(|| std::env::current_dir().ok());
+
+        let start_time = chrono::DateTime::<chrono::Utc>::from(SystemTime::now())
+            .to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+
+        serde_json::json!({
+            "start_time": start_time,
+            "pid": pid,
+            "program": program.to_string_lossy(),
+            "argv": argv,
+            "env": env,
+            "env_clear": env_cleared,
+            "cwd": cwd.map(|cwd| cwd.to_string_lossy().into_owned()),
+        })
+    }
+
+    /// Record a process that was just spawned.
+    pub fn record(&self, command: &std::process::Command, pid: Option<u32>) {
+        let mut entry = Self::build_entry(command, pid, self.env_cleared);
+        if let Some(entry) = entry.as_object_mut() {
+            entry.extend(self.run_fields.clone());
+        }
+
+        let mut line = entry.to_string();
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("error writing audit log '{}': {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_entry() {
+        let mut command = std::process::Command::new("/bin/echo");
+        command
+            .args(["a b", "\"c\""])
+            .env("JOB_ID", "1")
+            .env_remove("HOME")
+            .current_dir("/tmp");
+
+        let entry = AuditLog::build_entry(&command, Some(42), false);
+
+        assert_eq!(entry["pid"], 42);
+        assert_eq!(entry["program"], "/bin/echo");
+        assert_eq!(
+            entry["argv"],
+            serde_json::json!(["/bin/echo", "a b", "\"c\""])
+        );
+        assert_eq!(
+            entry["env"],
+            serde_json::json!({"HOME": null, "JOB_ID": "1"})
+        );
+        assert_eq!(entry["env_clear"], false);
+        assert_eq!(entry["cwd"], "/tmp");
+        assert!(entry["start_time"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_resolve_program() {
+        assert_eq!(
+            AuditLog::resolve_program(Path::new("./run.sh")),
+            PathBuf::from("./run.sh")
+        );
+        assert!(AuditLog::resolve_program(Path::new("sh")).is_absolute());
+        assert_eq!(
+            AuditLog::resolve_program(Path::new("no_such_command_xyz")),
+            PathBuf::from("no_such_command_xyz")
+        );
+    }
+}
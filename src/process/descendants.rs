@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+/// Parse the parent pid from /proc/PID/stat, returns None for zombie processes.
+#[cfg(target_os = "linux")]
+fn parse_parent_pid(stat: &str) -> Option<u32> {
+    // The command name in field 2 may contain spaces and parentheses, so parse after the last ')'.
+    let (_, rest) = stat.rsplit_once(')')?;
+    let mut fields = rest.split_whitespace();
+
+    let state = fields.next()?;
+    if state == "Z" || state == "X" {
+        return None;
+    }
+
+    fields.next()?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn children_by_parent() -> HashMap<u32, Vec<u32>> {
+    let mut children_by_parent: HashMap<u32, Vec<u32>> = HashMap::new();
+
+    let Ok(read_dir) = std::fs::read_dir("/proc") else {
+        return children_by_parent;
+    };
+
+    for entry in read_dir.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        // Processes may exit while walking /proc.
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+
+        if let Some(parent_pid) = parse_parent_pid(&stat) {
+            children_by_parent.entry(parent_pid).or_default().push(pid);
+        }
+    }
+
+    children_by_parent
+}
+
+#[cfg(not(target_os = "linux"))]
+fn children_by_parent() -> HashMap<u32, Vec<u32>> {
+    HashMap::new()
+}
+
+fn collect_descendants(children_by_parent: &HashMap<u32, Vec<u32>>, pid: u32) -> Vec<u32> {
+    let mut descendants = vec![];
+
+    let mut to_visit = vec![pid];
+    while let Some(pid) = to_visit.pop() {
+        if let Some(children) = children_by_parent.get(&pid) {
+            descendants.extend_from_slice(children);
+            to_visit.extend_from_slice(children);
+        }
+    }
+
+    descendants
+}
+
+/// Returns all descendant process ids of pid.  Only supported on Linux, elsewhere returns empty.
+pub fn descendants(pid: u32) -> Vec<u32> {
+    collect_descendants(&children_by_parent(), pid)
+}
+
+/// Returns the command line of a running process.  Only supported on Linux.
+#[cfg(target_os = "linux")]
+pub fn command_line(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+
+    Some(
+        cmdline
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn command_line(_pid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: nix::sys::signal::Signal) {
+    let Ok(pid) = i32::try_from(pid) else {
+        return;
+    };
+    let _ = nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), signal);
+}
+
+/// Kill pid and all of its descendants.
+///
+/// Descendants are stopped before being killed so a forking process tree cannot outrun the kill.
+#[cfg(unix)]
+pub fn kill_tree(pid: u32) {
+    use nix::sys::signal::Signal;
+
+    send_signal(pid, Signal::SIGSTOP);
+
+    let mut stopped = vec![pid];
+    loop {
+        let new_descendants: Vec<_> = descendants(pid)
+            .into_iter()
+            .filter(|pid| !stopped.contains(pid))
+            .collect();
+        if new_descendants.is_empty() {
+            break;
+        }
+        for pid in new_descendants {
+            send_signal(pid, Signal::SIGSTOP);
+            stopped.push(pid);
+        }
+    }
+
+    for pid in stopped {
+        send_signal(pid, Signal::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn kill_tree(_pid: u32) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_parent_pid() {
+        assert_eq!(
+            parse_parent_pid("1234 (sleep) S 42 1234 1234 0 -1 4194304"),
+            Some(42)
+        );
+        assert_eq!(
+            parse_parent_pid("1234 (my (odd) cmd) R 7 1234 1234 0 -1 4194304"),
+            Some(7)
+        );
+        assert_eq!(
+            parse_parent_pid("1234 (sleep) Z 42 1234 1234 0 -1 4194304"),
+            None
+        );
+        assert_eq!(parse_parent_pid("garbage"), None);
+    }
+
+    #[test]
+    fn test_collect_descendants() {
+        let children_by_parent =
+            HashMap::from([(1, vec![2, 3]), (2, vec![4]), (4, vec![5]), (6, vec![7])]);
+
+        let mut descendants = collect_descendants(&children_by_parent, 1);
+        descendants.sort();
+        assert_eq!(descendants, vec![2, 3, 4, 5]);
+
+        assert!(collect_descendants(&children_by_parent, 5).is_empty());
+    }
+}
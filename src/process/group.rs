@@ -0,0 +1,200 @@
+use tokio::process::Child;
+
+use tracing::warn;
+
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+fn signal_process_group(process_group_id: u32, signal: Option<nix::sys::signal::Signal>) -> bool {
+    let Ok(process_group_id) = i32::try_from(process_group_id) else {
+        return false;
+    };
+    nix::sys::signal::killpg(nix::unistd::Pid::from_raw(process_group_id), signal).is_ok()
+}
+
+/// Kill every process in the process group.
+#[cfg(unix)]
+pub fn kill_process_group(process_group_id: u32) {
+    signal_process_group(process_group_id, Some(nix::sys::signal::Signal::SIGKILL));
+}
+
+/// Returns true if any process in the process group is still running.
+#[cfg(unix)]
+pub fn process_group_alive(process_group_id: u32) -> bool {
+    signal_process_group(process_group_id, None)
+}
+
+/// Job object holding the process tree of a command on windows.
+///
+/// Closing the last handle to the job kills every process in it.
+#[cfg(windows)]
+#[derive(Debug)]
+struct JobObject(win32job::Job);
+
+#[cfg(windows)]
+impl JobObject {
+    fn assign(child: &Child) -> Option<Self> {
+        let raw_handle = child.raw_handle()?;
+
+        let mut info = win32job::ExtendedLimitInfo::new();
+        info.limit_kill_on_job_close();
+
+        // processes started by the child before it is assigned are not part of the job
+        let result = win32job::Job::create_with_limit_info(&info)
+            .and_then(|job| job.assign_process(raw_handle as isize).map(|_| job));
+
+        match result {
+            Ok(job) => Some(Self(job)),
+            Err(e) => {
+                warn!("job object error: {}", e);
+                None
+            }
+        }
+    }
+
+    fn alive(&self) -> bool {
+        self.0
+            .query_process_id_list()
+            .is_ok_and(|process_ids| !process_ids.is_empty())
+    }
+}
+
+#[derive(Debug)]
+struct SurvivingProcessGroup {
+    process_group_id: u32,
+    command: String,
+    #[cfg(windows)]
+    job_object: JobObject,
+}
+
+impl SurvivingProcessGroup {
+    #[cfg(unix)]
+    fn alive(&self) -> bool {
+        process_group_alive(self.process_group_id)
+    }
+
+    #[cfg(windows)]
+    fn alive(&self) -> bool {
+        self.job_object.alive()
+    }
+}
+
+/// Tracks process groups that still had running processes after their command exited.
+#[derive(Debug, Default)]
+pub struct ProcessGroupTracker {
+    survivors: Mutex<Vec<SurvivingProcessGroup>>,
+}
+
+impl ProcessGroupTracker {
+    fn command_exited(&self, survivor: SurvivingProcessGroup) {
+        if survivor.alive() {
+            self.survivors.lock().unwrap().push(survivor);
+        }
+    }
+
+    /// Warn about each process group that still has running processes, returns the number found.
+    ///
+    /// On windows the processes are killed when the job objects are closed here.
+    pub fn sweep(&self) -> usize {
+        let survivors = std::mem::take(&mut *self.survivors.lock().unwrap());
+
+        let mut count = 0;
+        for survivor in survivors {
+            if survivor.alive() {
+                warn!(
+                    "process group {} still has running processes after command exited: {}",
+                    survivor.process_group_id, survivor.command,
+                );
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// The process group of a command on unix, or the job object containing it on windows.
+#[derive(Debug)]
+pub struct ProcessGroup {
+    survivor: SurvivingProcessGroup,
+    tracker: Arc<ProcessGroupTracker>,
+}
+
+impl ProcessGroup {
+    /// The child must have been spawned with process_group(0) on unix.
+    #[cfg(unix)]
+    pub fn new(child: &Child, command: String, tracker: &Arc<ProcessGroupTracker>) -> Option<Self> {
+        Some(Self {
+            survivor: SurvivingProcessGroup {
+                process_group_id: child.id()?,
+                command,
+            },
+            tracker: Arc::clone(tracker),
+        })
+    }
+
+    #[cfg(windows)]
+    pub fn new(child: &Child, command: String, tracker: &Arc<ProcessGroupTracker>) -> Option<Self> {
+        Some(Self {
+            survivor: SurvivingProcessGroup {
+                process_group_id: child.id()?,
+                command,
+                job_object: JobObject::assign(child)?,
+            },
+            tracker: Arc::clone(tracker),
+        })
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub fn new(
+        _child: &Child,
+        _command: String,
+        _tracker: &Arc<ProcessGroupTracker>,
+    ) -> Option<Self> {
+        None
+    }
+
+    /// Kill every process in the group.
+    pub fn kill(self) {
+        #[cfg(unix)]
+        kill_process_group(self.survivor.process_group_id);
+
+        // on windows dropping the job object kills its processes
+    }
+
+    /// Remember the group for the final sweep if it still has running processes.
+    pub fn command_exited(self) {
+        self.tracker.command_exited(self.survivor);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::os::unix::process::CommandExt;
+
+    #[test]
+    fn test_process_group_tracker() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let process_group_id = child.id();
+
+        let tracker = ProcessGroupTracker::default();
+
+        tracker.command_exited(SurvivingProcessGroup {
+            process_group_id,
+            command: "sleep 30".to_owned(),
+        });
+        assert_eq!(tracker.survivors.lock().unwrap().len(), 1);
+
+        kill_process_group(process_group_id);
+        child.wait().unwrap();
+
+        assert!(!process_group_alive(process_group_id));
+        assert_eq!(tracker.sweep(), 0);
+        assert!(tracker.survivors.lock().unwrap().is_empty());
+    }
+}
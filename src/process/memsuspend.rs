@@ -0,0 +1,255 @@
+use tokio::time::Duration;
+
+use tracing::{debug, info};
+
+use std::sync::{Arc, Mutex};
+
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Parse MemAvailable in bytes from /proc/meminfo.
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?;
+
+    let kilobytes: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+
+    Some(kilobytes.saturating_mul(1024))
+}
+
+#[cfg(target_os = "linux")]
+fn mem_available() -> Option<u64> {
+    parse_mem_available(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mem_available() -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, process_group: bool, signal: nix::sys::signal::Signal) {
+    let Ok(pid) = i32::try_from(pid) else {
+        return;
+    };
+    let pid = nix::unistd::Pid::from_raw(pid);
+
+    let result = if process_group {
+        nix::sys::signal::killpg(pid, signal)
+    } else {
+        nix::sys::signal::kill(pid, signal)
+    };
+
+    if let Err(e) = result {
+        debug!("send_signal {} to {} error: {}", signal, pid, e);
+    }
+}
+
+#[cfg(unix)]
+fn stop(pid: u32, process_group: bool) {
+    send_signal(pid, process_group, nix::sys::signal::Signal::SIGSTOP);
+}
+
+#[cfg(unix)]
+fn cont(pid: u32, process_group: bool) {
+    send_signal(pid, process_group, nix::sys::signal::Signal::SIGCONT);
+}
+
+#[cfg(not(unix))]
+fn stop(_pid: u32, _process_group: bool) {}
+
+#[cfg(not(unix))]
+fn cont(_pid: u32, _process_group: bool) {}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Action {
+    None,
+    SuspendYoungest,
+    ResumeLatest,
+}
+
+/// Suspend below the limit while more than one job runs, resume above twice the limit.
+///
+/// A suspended job is always resumed when no jobs are running so the run makes progress.
+fn next_action(available: u64, limit: u64, running: usize, suspended: usize) -> Action {
+    if suspended > 0 && (running == 0 || available > limit.saturating_mul(2)) {
+        Action::ResumeLatest
+    } else if available < limit && running > 1 {
+        Action::SuspendYoungest
+    } else {
+        Action::None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Job {
+    id: u64,
+    pid: u32,
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    next_id: u64,
+    running: Vec<Job>,
+    suspended: Vec<Job>,
+}
+
+/// Suspends the most recently started jobs with SIGSTOP while available memory is low.
+#[derive(Debug)]
+pub struct MemorySuspender {
+    limit: u64,
+    process_group: bool,
+    jobs: Mutex<Jobs>,
+}
+
+impl MemorySuspender {
+    pub fn new(limit: u64, process_group: bool) -> Arc<Self> {
+        let memory_suspender = Arc::new(Self {
+            limit,
+            process_group,
+            jobs: Mutex::new(Jobs::default()),
+        });
+
+        tokio::spawn(Self::run_task(Arc::downgrade(&memory_suspender)));
+
+        memory_suspender
+    }
+
+    async fn run_task(memory_suspender: std::sync::Weak<Self>) {
+        debug!("begin memsuspend task");
+
+        let mut interval = tokio::time::interval(MEMORY_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let Some(memory_suspender) = memory_suspender.upgrade() else {
+                break;
+            };
+
+            if let Some(available) = mem_available() {
+                memory_suspender.check(available);
+            }
+        }
+
+        debug!("end memsuspend task");
+    }
+
+    fn check(&self, available: u64) {
+        let mut jobs = self.jobs.lock().unwrap();
+
+        match next_action(
+            available,
+            self.limit,
+            jobs.running.len(),
+            jobs.suspended.len(),
+        ) {
+            Action::None => {}
+            Action::SuspendYoungest => {
+                if let Some(job) = jobs.running.pop() {
+                    info!(
+                        "suspending job pid {} with {} bytes memory available below --memsuspend {}",
+                        job.pid, available, self.limit
+                    );
+                    stop(job.pid, self.process_group);
+                    jobs.suspended.push(job);
+                }
+            }
+            Action::ResumeLatest => {
+                if let Some(job) = jobs.suspended.pop() {
+                    info!(
+                        "resuming job pid {} with {} bytes memory available",
+                        job.pid, available
+                    );
+                    cont(job.pid, self.process_group);
+                    jobs.running.push(job);
+                }
+            }
+        }
+    }
+
+    /// Track a started job until the returned registration is dropped.
+    pub fn register(self: &Arc<Self>, pid: u32) -> MemorySuspendRegistration {
+        let mut jobs = self.jobs.lock().unwrap();
+
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.running.push(Job { id, pid });
+
+        MemorySuspendRegistration {
+            id,
+            memory_suspender: Arc::clone(self),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MemorySuspendRegistration {
+    id: u64,
+    memory_suspender: Arc<MemorySuspender>,
+}
+
+impl Drop for MemorySuspendRegistration {
+    fn drop(&mut self) {
+        let mut jobs = self.memory_suspender.jobs.lock().unwrap();
+
+        jobs.running.retain(|job| job.id != self.id);
+
+        if let Some(i) = jobs.suspended.iter().position(|job| job.id == self.id) {
+            // resume so a job killed while suspended can exit
+            let job = jobs.suspended.remove(i);
+            cont(job.pid, self.memory_suspender.process_group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_mem_available() {
+        let meminfo = "MemTotal:       16314428 kB\nMemFree:          812344 kB\nMemAvailable:    9442764 kB\n";
+
+        assert_eq!(parse_mem_available(meminfo), Some(9442764 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn test_next_action() {
+        assert_eq!(next_action(50, 100, 2, 0), Action::SuspendYoungest);
+        assert_eq!(next_action(50, 100, 1, 1), Action::None);
+        assert_eq!(next_action(50, 100, 0, 1), Action::ResumeLatest);
+        assert_eq!(next_action(150, 100, 2, 1), Action::None);
+        assert_eq!(next_action(250, 100, 2, 1), Action::ResumeLatest);
+        assert_eq!(next_action(250, 100, 2, 0), Action::None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_suspender() {
+        let memory_suspender = MemorySuspender::new(100, false);
+
+        let registration1 = memory_suspender.register(u32::MAX);
+        let registration2 = memory_suspender.register(u32::MAX);
+
+        memory_suspender.check(50);
+        {
+            let jobs = memory_suspender.jobs.lock().unwrap();
+            assert_eq!(jobs.running.len(), 1);
+            assert_eq!(jobs.running[0].id, registration1.id);
+            assert_eq!(jobs.suspended.len(), 1);
+            assert_eq!(jobs.suspended[0].id, registration2.id);
+        }
+
+        drop(registration1);
+        memory_suspender.check(50);
+        {
+            let jobs = memory_suspender.jobs.lock().unwrap();
+            assert_eq!(jobs.running.len(), 1);
+            assert!(jobs.suspended.is_empty());
+        }
+
+        drop(registration2);
+        assert!(memory_suspender.jobs.lock().unwrap().running.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+/// Characters cmd.exe interprets while parsing a command line.
+const CMD_METACHARACTERS: &[char] = &['(', ')', '%', '!', '^', '"', '<', '>', '&', '|'];
+
+/// Returns true if the program is a batch file, which windows runs with cmd.exe.
+pub fn is_batch_file(program: &Path) -> bool {
+    program.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("bat") || extension.eq_ignore_ascii_case("cmd")
+    })
+}
+
+/// Quote an argument so CommandLineToArgvW and the MSVC runtime parse it back unchanged.
+///
+/// Arguments with , ; or = are quoted too, because batch files split arguments on them.
+pub fn quote_argument(argument: &str) -> String {
+    if !argument.is_empty() && !argument.contains([' ', '\t', '\n', '\x0b', '"', ',', ';', '=']) {
+        return argument.to_owned();
+    }
+
+    let mut quoted = String::with_capacity(argument.len() + 2);
+    quoted.push('"');
+
+    let mut backslashes = 0;
+    for c in argument.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // backslashes before a quote are escaped, then the quote itself
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+
+    // backslashes before the closing quote are escaped
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    quoted
+}
+
+/// Prefix each cmd.exe metacharacter with ^ so cmd.exe passes it through literally.
+///
+/// This also stops %NAME% expansion, because cmd.exe looks up NAME^ which is not defined.
+pub fn escape_cmd_metacharacters(command_line: &str) -> String {
+    let mut escaped = String::with_capacity(command_line.len() * 2);
+    for c in command_line.chars() {
+        if CMD_METACHARACTERS.contains(&c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Raw cmd.exe arguments running a batch file with arguments that reach it unchanged.
+///
+/// Fails for arguments containing line breaks or NUL, which cmd.exe cannot pass on.
+pub fn batch_file_arguments<A: AsRef<str>>(
+    batch_file: &str,
+    arguments: &[A],
+) -> std::io::Result<String> {
+    let mut command_line = quote_argument(batch_file);
+
+    for argument in arguments {
+        let argument = argument.as_ref();
+        if argument.contains(['\n', '\r', '\0']) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("batch file argument {:?} contains a line break", argument),
+            ));
+        }
+        command_line.push(' ');
+        command_line.push_str(&quote_argument(argument));
+    }
+
+    Ok(format!(
+        "/d /v:off /s /c \"{}\"",
+        escape_cmd_metacharacters(&command_line)
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_batch_file() {
+        assert!(is_batch_file(Path::new(r"C:\tools\build.bat")));
+        assert!(is_batch_file(Path::new("run.CMD")));
+        assert!(!is_batch_file(Path::new("run.exe")));
+        assert!(!is_batch_file(Path::new("bat")));
+    }
+
+    #[test]
+    fn test_quote_argument() {
+        assert_eq!(quote_argument("hello"), "hello");
+        assert_eq!(quote_argument(r"C:\dir\"), r"C:\dir\");
+        assert_eq!(quote_argument(""), r#""""#);
+        assert_eq!(quote_argument("a b"), r#""a b""#);
+        assert_eq!(quote_argument("a=b"), r#""a=b""#);
+        assert_eq!(quote_argument(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_argument(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_argument(r"C:\my dir\"), r#""C:\my dir\\""#);
+    }
+
+    #[test]
+    fn test_escape_cmd_metacharacters() {
+        assert_eq!(escape_cmd_metacharacters("plain text"), "plain text");
+        assert_eq!(
+            escape_cmd_metacharacters(r#"a&b|c^d%PATH%"e"(f)<g>!h"#),
+            r#"a^&b^|c^^d^%PATH^%^"e^"^(f^)^<g^>^!h"#
+        );
+    }
+
+    #[test]
+    fn test_batch_file_arguments() {
+        assert_eq!(
+            batch_file_arguments(r"C:\tools\run.bat", &["a&b", "100%", "x y"]).unwrap(),
+            r#"/d /v:off /s /c "C:\tools\run.bat a^&b 100^% ^"x y^"""#
+        );
+        assert_eq!(
+            batch_file_arguments("run.cmd", &[r#"say "hi""#]).unwrap(),
+            r#"/d /v:off /s /c "run.cmd ^"say \^"hi\^"^"""#
+        );
+        assert!(batch_file_arguments("run.cmd", &["a\nb"]).is_err());
+    }
+}
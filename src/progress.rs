@@ -1,52 +1,362 @@
+mod json;
+mod queue_wait;
+mod rolling;
 mod style;
 
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 
 use tokio::time::Duration;
 
-use std::sync::Arc;
+use tracing::info;
 
-use crate::command_line_args::CommandLineArgs;
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use crate::{
+    command_line_args::CommandLineArgs,
+    common::{run_json_fields, SkippedInput},
+    parser::regex::RegexProcessor,
+};
+
+use self::json::{ProgressCounters, ProgressJson};
+
+struct ProgressGroups {
+    multi_progress: MultiProgress,
+    group_by_template: String,
+    regex_processor: Arc<RegexProcessor>,
+    progress_style: ProgressStyle,
+    group_progress_bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl ProgressGroups {
+    fn new(
+        command_line_args: &CommandLineArgs,
+        progress_bar: &ProgressBar,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(group_by_template) = &command_line_args.progress_group_by else {
+            return Ok(None);
+        };
+
+        let multi_progress = MultiProgress::new();
+        multi_progress.add(progress_bar.clone());
+
+        Ok(Some(Self {
+            multi_progress,
+            group_by_template: group_by_template.clone(),
+            regex_processor: RegexProcessor::new(command_line_args)?,
+            progress_style: style::group_progress_style()?,
+            group_progress_bars: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn group_key(&self, input_data: &str) -> Option<String> {
+        let input_data = if self.regex_processor.regex_mode() {
+            input_data
+        } else {
+            input_data.trim()
+        };
+
+        self.regex_processor
+            .expand_template(&self.group_by_template, input_data)
+    }
+
+    fn with_group_progress_bar(&self, input_data: &str, f: impl FnOnce(&ProgressBar)) {
+        let Some(group_key) = self.group_key(input_data) else {
+            return;
+        };
+
+        let mut group_progress_bars = self.group_progress_bars.lock().unwrap();
+
+        let progress_bar = group_progress_bars
+            .entry(group_key)
+            .or_insert_with_key(|group_key| {
+                let progress_bar = self.multi_progress.add(ProgressBar::new(0));
+                progress_bar.set_style(self.progress_style.clone());
+                progress_bar.set_prefix(group_key.clone());
+                progress_bar
+            });
+
+        f(progress_bar);
+    }
+
+    fn finish(&self) {
+        for progress_bar in self.group_progress_bars.lock().unwrap().values() {
+            progress_bar.finish();
+        }
+    }
+}
 
 pub struct Progress {
     progress_bar: Option<ProgressBar>,
+    progress_groups: Option<ProgressGroups>,
+    counters: Arc<ProgressCounters>,
+    progress_json: Option<ProgressJson>,
 }
 
 impl Progress {
-    pub fn new(command_line_args: &CommandLineArgs) -> anyhow::Result<Arc<Self>> {
+    /// `completed` commands from the --joblog of a previous run start as done, for --resume.
+    pub fn new(command_line_args: &CommandLineArgs, completed: u64) -> anyhow::Result<Arc<Self>> {
+        let mut progress_groups = None;
+
         let progress_bar = if !command_line_args.progress_bar {
             None
         } else {
             let style_info = style::choose_progress_style()?;
 
-            let progress_bar = ProgressBar::new(0);
+            // set before the first tick, so completed commands do not count in the rate
+            let progress_bar = ProgressBar::new(completed).with_position(completed);
             if style_info.enable_steady_tick {
                 progress_bar.enable_steady_tick(Duration::from_millis(100));
             }
 
             progress_bar.set_style(style_info.progress_style);
 
+            progress_groups = ProgressGroups::new(command_line_args, &progress_bar)?;
+
             Some(progress_bar)
         };
 
-        Ok(Arc::new(Self { progress_bar }))
+        let counters = Arc::new(ProgressCounters::default());
+        counters.total.store(completed, Ordering::Relaxed);
+        counters.done.store(completed, Ordering::Relaxed);
+        counters
+            .jobs
+            .store(command_line_args.jobs as u64, Ordering::Relaxed);
+
+        let progress_json = match &command_line_args.progress_json {
+            None => None,
+            Some(fd_or_path) => Some(ProgressJson::new(
+                fd_or_path,
+                &counters,
+                run_json_fields(command_line_args),
+            )?),
+        };
+
+        Ok(Arc::new(Self {
+            progress_bar,
+            progress_groups,
+            counters,
+            progress_json,
+        }))
     }
 
-    pub fn increment_total_commands(&self, delta: usize) {
+    /// Show queued and running commands in the progress bar, to tell whether reading inputs or
+    /// running commands is the bottleneck.
+    fn update_gauges(&self) {
         if let Some(progress_bar) = &self.progress_bar {
-            progress_bar.inc_length(delta.try_into().unwrap_or_default());
+            progress_bar.set_message(format!(
+                "Queued: {} Running: {}",
+                self.counters.queued(),
+                self.counters.running.load(Ordering::Relaxed)
+            ));
         }
     }
 
-    pub fn command_finished(&self) {
+    pub fn increment_total_commands(&self, input_data: &str) {
+        self.counters.total.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(progress_bar) = &self.progress_bar {
+            progress_bar.inc_length(1);
+        }
+        self.update_gauges();
+        if let Some(progress_groups) = &self.progress_groups {
+            progress_groups
+                .with_group_progress_bar(input_data, |progress_bar| progress_bar.inc_length(1));
+        }
+    }
+
+    pub fn input_skipped(&self, skipped_input: SkippedInput) {
+        let counter = match skipped_input {
+            SkippedInput::Empty => &self.counters.skipped_empty,
+            SkippedInput::InvalidUtf8 => &self.counters.skipped_invalid_utf8,
+            SkippedInput::Unmatched => &self.counters.skipped_unmatched,
+            SkippedInput::Filtered => &self.counters.skipped_filtered,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Summary of skipped input lines, None if no lines were skipped.
+    pub fn skipped_input_summary(&self) -> Option<String> {
+        let empty = self.counters.skipped_empty.load(Ordering::Relaxed);
+        let invalid_utf8 = self.counters.skipped_invalid_utf8.load(Ordering::Relaxed);
+        let unmatched = self.counters.skipped_unmatched.load(Ordering::Relaxed);
+        let filtered = self.counters.skipped_filtered.load(Ordering::Relaxed);
+
+        let total = empty + invalid_utf8 + unmatched + filtered;
+        if total == 0 {
+            return None;
+        }
+
+        Some(format!(
+            "skipped {} input lines: empty={} invalid_utf8={} unmatched={} filtered={}",
+            total, empty, invalid_utf8, unmatched, filtered
+        ))
+    }
+
+    /// Count the historical duration of a queued command from --use-history in the ETA.
+    pub fn add_expected_duration(&self, duration: Duration) {
+        self.counters
+            .expected_commands
+            .fetch_add(1, Ordering::Relaxed);
+        self.counters.expected_micros.fetch_add(
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn command_started(&self) {
+        self.counters.running.fetch_add(1, Ordering::Relaxed);
+        self.update_gauges();
+    }
+
+    /// Record the time a command waited between being read from inputs and starting, reported
+    /// with --progress-json.
+    pub fn record_queue_wait(&self, queue_wait: Duration) {
+        if self.progress_json.is_some() {
+            self.counters.queue_waits.record(queue_wait);
+        }
+    }
+
+    pub fn command_finished(&self, input_data: &str, success: bool) {
+        self.counters.running.fetch_sub(1, Ordering::Relaxed);
+        if !success {
+            self.counters.failed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.command_done(input_data);
+    }
+
+    pub fn command_skipped(&self, input_data: &str) {
+        self.command_done(input_data);
+    }
+
+    /// Count an input completed in the joblog of a previous run, already counted as done by
+    /// [`Progress::new`].
+    pub fn command_resumed(&self, input_data: &str) {
+        self.counters.total.fetch_sub(1, Ordering::Relaxed);
+
+        if let Some(progress_bar) = &self.progress_bar {
+            progress_bar.update(|state| state.set_len(state.len().unwrap_or(0).saturating_sub(1)));
+        }
+        if let Some(progress_groups) = &self.progress_groups {
+            progress_groups.with_group_progress_bar(input_data, |progress_bar| progress_bar.inc(1));
+        }
+        self.update_gauges();
+    }
+
+    fn command_done(&self, input_data: &str) {
+        self.counters.done.fetch_add(1, Ordering::Relaxed);
+
         if let Some(progress_bar) = &self.progress_bar {
             progress_bar.inc(1);
         }
+        if let Some(progress_groups) = &self.progress_groups {
+            progress_groups.with_group_progress_bar(input_data, |progress_bar| progress_bar.inc(1));
+        }
+        self.update_gauges();
     }
 
-    pub fn finish(&self) {
+    pub async fn finish(&self) {
         if let Some(progress_bar) = &self.progress_bar {
             progress_bar.finish();
         }
+        if let Some(progress_groups) = &self.progress_groups {
+            progress_groups.finish();
+        }
+        if let Some(progress_json) = &self.progress_json {
+            progress_json.finish().await;
+        }
+
+        if self.progress_bar.is_some() || self.progress_json.is_some() {
+            if let Some(skipped_input_summary) = self.skipped_input_summary() {
+                info!("{}", skipped_input_summary);
+            }
+        }
+
+        if let Some(queue_wait_percentiles) = self.counters.queue_waits.percentiles() {
+            info!("{}", queue_wait_percentiles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress_groups() {
+        let command_line_args = CommandLineArgs {
+            progress_bar: true,
+            regex: Some("(.*),(.*)".to_owned()),
+            progress_group_by: Some("{1}".to_owned()),
+            ..Default::default()
+        };
+
+        let progress = Progress::new(&command_line_args, 0).unwrap();
+
+        progress.increment_total_commands("a,1");
+        progress.increment_total_commands("a,2");
+        progress.increment_total_commands("b,3");
+        progress.increment_total_commands("nomatch");
+
+        progress.command_started();
+        progress.command_finished("a,1", true);
+        progress.command_skipped("b,3");
+
+        assert_eq!(progress.progress_bar.as_ref().unwrap().length(), Some(4));
+        assert_eq!(
+            progress.progress_bar.as_ref().unwrap().message(),
+            "Queued: 2 Running: 0"
+        );
+
+        let progress_groups = progress.progress_groups.as_ref().unwrap();
+        let group_progress_bars = progress_groups.group_progress_bars.lock().unwrap();
+
+        assert_eq!(group_progress_bars.len(), 2);
+        assert_eq!(group_progress_bars["a"].length(), Some(2));
+        assert_eq!(group_progress_bars["a"].position(), 1);
+        assert_eq!(group_progress_bars["b"].length(), Some(1));
+        assert_eq!(group_progress_bars["b"].position(), 1);
+    }
+
+    #[test]
+    fn test_skipped_input_summary() {
+        let progress = Progress::new(&CommandLineArgs::default(), 0).unwrap();
+
+        assert_eq!(progress.skipped_input_summary(), None);
+
+        progress.input_skipped(SkippedInput::Empty);
+        progress.input_skipped(SkippedInput::Unmatched);
+        progress.input_skipped(SkippedInput::Unmatched);
+
+        assert_eq!(
+            progress.skipped_input_summary().as_deref(),
+            Some("skipped 3 input lines: empty=1 invalid_utf8=0 unmatched=2 filtered=0")
+        );
+    }
+
+    #[test]
+    fn test_command_resumed() {
+        let command_line_args = CommandLineArgs {
+            progress_bar: true,
+            ..Default::default()
+        };
+
+        let progress = Progress::new(&command_line_args, 2).unwrap();
+
+        progress.increment_total_commands("A");
+        progress.increment_total_commands("B");
+        progress.increment_total_commands("C");
+
+        progress.command_resumed("A");
+        progress.command_resumed("B");
+
+        let progress_bar = progress.progress_bar.as_ref().unwrap();
+        assert_eq!(progress_bar.length(), Some(3));
+        assert_eq!(progress_bar.position(), 2);
+        assert_eq!(progress.counters.total.load(Ordering::Relaxed), 3);
+        assert_eq!(progress.counters.queued(), 1);
     }
 }
@@ -0,0 +1,257 @@
+use anyhow::Context;
+
+use tokio::{io::AsyncWriteExt, sync::Mutex, task::JoinHandle, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+
+use tracing::{debug, warn};
+
+use std::{
+    fs::OpenOptions,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use super::{queue_wait::QueueWaits, rolling::RollingEstimator};
+
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+pub struct ProgressCounters {
+    pub total: AtomicU64,
+    pub done: AtomicU64,
+    pub running: AtomicU64,
+    pub failed: AtomicU64,
+    pub skipped_empty: AtomicU64,
+    pub skipped_invalid_utf8: AtomicU64,
+    pub skipped_unmatched: AtomicU64,
+    pub skipped_filtered: AtomicU64,
+    pub jobs: AtomicU64,
+    pub expected_commands: AtomicU64,
+    pub expected_micros: AtomicU64,
+    pub queue_waits: QueueWaits,
+}
+
+impl ProgressCounters {
+    /// Commands read from inputs that have not started yet.
+    pub fn queued(&self) -> u64 {
+        let total = self.total.load(Ordering::Relaxed);
+        let done = self.done.load(Ordering::Relaxed);
+        let running = self.running.load(Ordering::Relaxed);
+
+        total.saturating_sub(done).saturating_sub(running)
+    }
+}
+
+/// ETA from the mean historical duration of commands, used until commands have finished.
+fn history_eta(counters: &ProgressCounters, done: u64, total: u64) -> Option<Duration> {
+    let expected_commands = counters.expected_commands.load(Ordering::Relaxed);
+    if expected_commands == 0 {
+        return None;
+    }
+
+    let mean_micros = counters.expected_micros.load(Ordering::Relaxed) / expected_commands;
+    let jobs = counters.jobs.load(Ordering::Relaxed).max(1);
+
+    Some(Duration::from_micros(
+        mean_micros.saturating_mul(total.saturating_sub(done)) / jobs,
+    ))
+}
+
+fn open_output(fd_or_path: &str) -> anyhow::Result<std::fs::File> {
+    let mut open_options = OpenOptions::new();
+
+    let path = if fd_or_path.parse::<u32>().is_ok() {
+        // Opening the fd via /dev/fd keeps this free of unsafe code.
+        open_options.append(true);
+        format!("/dev/fd/{}", fd_or_path)
+    } else {
+        open_options.write(true).create(true).truncate(true);
+        fd_or_path.to_owned()
+    };
+
+    open_options
+        .open(&path)
+        .with_context(|| format!("error opening progress json output '{}'", path))
+}
+
+pub struct ProgressJson {
+    finished_token: CancellationToken,
+    task_join_handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ProgressJson {
+    pub fn new(
+        fd_or_path: &str,
+        counters: &Arc<ProgressCounters>,
+        run_fields: serde_json::Map<String, serde_json::Value>,
+    ) -> anyhow::Result<Self> {
+        let file = tokio::fs::File::from_std(open_output(fd_or_path)?);
+
+        let finished_token = CancellationToken::new();
+
+        let task_join_handle = tokio::spawn(run_task(
+            file,
+            Arc::clone(counters),
+            run_fields,
+            finished_token.clone(),
+        ));
+
+        Ok(Self {
+            finished_token,
+            task_join_handle: Mutex::new(Some(task_join_handle)),
+        })
+    }
+
+    pub async fn finish(&self) {
+        self.finished_token.cancel();
+
+        if let Some(task_join_handle) = self.task_join_handle.lock().await.take() {
+            if let Err(e) = task_join_handle.await {
+                warn!("progress json task_join_handle.await error: {}", e);
+            }
+        }
+    }
+}
+
+fn build_event(
+    counters: &ProgressCounters,
+    estimator: &mut RollingEstimator,
+    now: Instant,
+) -> serde_json::Value {
+    let done = counters.done.load(Ordering::Relaxed);
+    let total = counters.total.load(Ordering::Relaxed);
+
+    estimator.record(now, done);
+
+    serde_json::json!({
+        "done": done,
+        "total": total,
+        "queued": counters.queued(),
+        "running": counters.running.load(Ordering::Relaxed),
+        "failed": counters.failed.load(Ordering::Relaxed),
+        "eta_secs": estimator
+            .eta(now, done, total)
+            .or_else(|| history_eta(counters, done, total))
+            .map(|eta| eta.as_secs()),
+        "queue_wait_secs": counters.queue_waits.percentiles().map(|percentiles| {
+            serde_json::json!({
+                "p50": percentiles.p50.as_secs_f64(),
+                "p95": percentiles.p95.as_secs_f64(),
+                "max": percentiles.max.as_secs_f64(),
+            })
+        }),
+        "skipped_input": {
+            "empty": counters.skipped_empty.load(Ordering::Relaxed),
+            "invalid_utf8": counters.skipped_invalid_utf8.load(Ordering::Relaxed),
+            "unmatched": counters.skipped_unmatched.load(Ordering::Relaxed),
+            "filtered": counters.skipped_filtered.load(Ordering::Relaxed),
+        },
+    })
+}
+
+async fn run_task(
+    mut file: tokio::fs::File,
+    counters: Arc<ProgressCounters>,
+    run_fields: serde_json::Map<String, serde_json::Value>,
+    finished_token: CancellationToken,
+) {
+    debug!("begin progress json task");
+
+    let mut estimator = RollingEstimator::new_eta();
+
+    let mut interval = tokio::time::interval(EVENT_INTERVAL);
+
+    loop {
+        let finished = tokio::select! {
+            _ = finished_token.cancelled() => true,
+            _ = interval.tick() => false,
+        };
+
+        let mut event = build_event(&counters, &mut estimator, Instant::now());
+        if let Some(event) = event.as_object_mut() {
+            event.extend(run_fields.clone());
+        }
+
+        let mut line = event.to_string();
+        line.push('\n');
+
+        let result = match file.write_all(line.as_bytes()).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("progress json write error: {}", e);
+            break;
+        }
+
+        if finished {
+            break;
+        }
+    }
+
+    debug!("end progress json task");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_event() {
+        let counters = ProgressCounters::default();
+        counters.total.store(10, Ordering::Relaxed);
+        counters.running.store(2, Ordering::Relaxed);
+        counters.failed.store(1, Ordering::Relaxed);
+        counters.skipped_unmatched.store(3, Ordering::Relaxed);
+
+        let mut estimator = RollingEstimator::new_eta();
+
+        let start = Instant::now();
+
+        assert_eq!(
+            build_event(&counters, &mut estimator, start),
+            serde_json::json!({
+                "done": 0, "total": 10, "queued": 8, "running": 2, "failed": 1, "eta_secs": null,
+                "queue_wait_secs": null,
+                "skipped_input": {"empty": 0, "invalid_utf8": 0, "unmatched": 3, "filtered": 0},
+            })
+        );
+
+        counters.done.store(5, Ordering::Relaxed);
+        counters.queue_waits.record(Duration::from_millis(250));
+
+        assert_eq!(
+            build_event(&counters, &mut estimator, start + Duration::from_secs(5)),
+            serde_json::json!({
+                "done": 5, "total": 10, "queued": 3, "running": 2, "failed": 1, "eta_secs": 5,
+                "queue_wait_secs": {"p50": 0.25, "p95": 0.25, "max": 0.25},
+                "skipped_input": {"empty": 0, "invalid_utf8": 0, "unmatched": 3, "filtered": 0},
+            })
+        );
+    }
+
+    #[test]
+    fn test_history_eta() {
+        let counters = ProgressCounters::default();
+        counters.total.store(10, Ordering::Relaxed);
+        counters.jobs.store(2, Ordering::Relaxed);
+
+        assert_eq!(history_eta(&counters, 0, 10), None);
+
+        counters.expected_commands.store(2, Ordering::Relaxed);
+        counters.expected_micros.store(8_000_000, Ordering::Relaxed);
+
+        assert_eq!(history_eta(&counters, 0, 10), Some(Duration::from_secs(20)));
+        assert_eq!(history_eta(&counters, 6, 10), Some(Duration::from_secs(8)));
+
+        let mut estimator = RollingEstimator::new_eta();
+        assert_eq!(
+            build_event(&counters, &mut estimator, Instant::now())["eta_secs"],
+            serde_json::json!(20)
+        );
+    }
+}
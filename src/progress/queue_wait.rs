@@ -0,0 +1,81 @@
+use std::{sync::Mutex, time::Duration};
+
+/// Time commands waited between being read from inputs and starting, to tell waiting for a
+/// job slot apart from slow commands.
+#[derive(Debug, Default)]
+pub struct QueueWaits {
+    micros: Mutex<Vec<u64>>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QueueWaitPercentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl QueueWaits {
+    pub fn record(&self, queue_wait: Duration) {
+        let micros = u64::try_from(queue_wait.as_micros()).unwrap_or(u64::MAX);
+        self.micros.lock().unwrap().push(micros);
+    }
+
+    /// Percentiles of the recorded waits, None if no command has started.
+    pub fn percentiles(&self) -> Option<QueueWaitPercentiles> {
+        let mut micros = self.micros.lock().unwrap().clone();
+        if micros.is_empty() {
+            return None;
+        }
+        micros.sort_unstable();
+
+        let percentile = |percent: usize| {
+            let index = (micros.len() * percent).div_ceil(100).saturating_sub(1);
+            Duration::from_micros(micros[index])
+        };
+
+        Some(QueueWaitPercentiles {
+            p50: percentile(50),
+            p95: percentile(95),
+            max: percentile(100),
+        })
+    }
+}
+
+impl std::fmt::Display for QueueWaitPercentiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "queue wait: p50={:?} p95={:?} max={:?}",
+            self.p50, self.p95, self.max
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let queue_waits = QueueWaits::default();
+        assert_eq!(queue_waits.percentiles(), None);
+
+        for millis in (1..=20).rev() {
+            queue_waits.record(Duration::from_millis(millis));
+        }
+
+        let percentiles = queue_waits.percentiles().unwrap();
+        assert_eq!(
+            percentiles,
+            QueueWaitPercentiles {
+                p50: Duration::from_millis(10),
+                p95: Duration::from_millis(19),
+                max: Duration::from_millis(20),
+            }
+        );
+        assert_eq!(
+            percentiles.to_string(),
+            "queue wait: p50=10ms p95=19ms max=20ms"
+        );
+    }
+}
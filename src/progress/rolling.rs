@@ -0,0 +1,156 @@
+use indicatif::{style::ProgressTracker, ProgressState};
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+const WINDOW: Duration = Duration::from_secs(30);
+
+pub const ROLLING_RATE_KEY: &str = "rolling_rate";
+
+pub const ROLLING_ETA_KEY: &str = "rolling_eta";
+
+#[derive(Clone, Copy, Debug)]
+enum Display {
+    Rate,
+    Eta,
+}
+
+/// Estimates completion rate from a sliding window of recent progress samples
+/// rather than the average over the entire run.
+#[derive(Clone, Debug)]
+pub struct RollingEstimator {
+    display: Display,
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RollingEstimator {
+    fn new(display: Display, window: Duration) -> Self {
+        Self {
+            display,
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn new_rate() -> Self {
+        Self::new(Display::Rate, WINDOW)
+    }
+
+    pub fn new_eta() -> Self {
+        Self::new(Display::Eta, WINDOW)
+    }
+
+    pub fn record(&mut self, now: Instant, pos: u64) {
+        match self.samples.back() {
+            Some((_, last_pos)) if *last_pos == pos => {}
+            _ => self.samples.push_back((now, pos)),
+        }
+
+        while self.samples.len() > 1
+            && self
+                .samples
+                .get(1)
+                .is_some_and(|(time, _)| now.saturating_duration_since(*time) >= self.window)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    fn per_second(&self, now: Instant) -> f64 {
+        let (Some((first_time, first_pos)), Some((_, last_pos))) =
+            (self.samples.front(), self.samples.back())
+        else {
+            return 0f64;
+        };
+
+        let elapsed = now.saturating_duration_since(*first_time).as_secs_f64();
+        if elapsed <= 0f64 {
+            return 0f64;
+        }
+
+        (last_pos - first_pos) as f64 / elapsed
+    }
+
+    pub fn eta(&self, now: Instant, pos: u64, len: u64) -> Option<Duration> {
+        let remaining = len.saturating_sub(pos);
+        if remaining == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        let per_second = self.per_second(now);
+        if per_second <= 0f64 {
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(remaining as f64 / per_second))
+    }
+}
+
+impl ProgressTracker for RollingEstimator {
+    fn clone_box(&self) -> Box<dyn ProgressTracker> {
+        Box::new(self.clone())
+    }
+
+    fn tick(&mut self, state: &ProgressState, now: Instant) {
+        self.record(now, state.pos());
+    }
+
+    fn reset(&mut self, state: &ProgressState, now: Instant) {
+        self.samples.clear();
+        self.record(now, state.pos());
+    }
+
+    fn write(&self, state: &ProgressState, w: &mut dyn Write) {
+        let now = Instant::now();
+        let _ = match self.display {
+            Display::Rate => write!(w, "{:.2}/s", self.per_second(now)),
+            Display::Eta => match self.eta(now, state.pos(), state.len().unwrap_or_default()) {
+                None => write!(w, "--:--:--"),
+                Some(eta) => {
+                    let secs = eta.as_secs();
+                    write!(
+                        w,
+                        "{:02}:{:02}:{:02}",
+                        secs / 3600,
+                        (secs / 60) % 60,
+                        secs % 60
+                    )
+                }
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rolling_estimator() {
+        let start = Instant::now();
+
+        let mut estimator = RollingEstimator::new(Display::Eta, Duration::from_secs(10));
+
+        assert_eq!(estimator.per_second(start), 0f64);
+        assert_eq!(estimator.eta(start, 0, 100), None);
+
+        estimator.record(start, 0);
+        estimator.record(start + Duration::from_secs(5), 10);
+
+        let now = start + Duration::from_secs(5);
+        assert_eq!(estimator.per_second(now), 2f64);
+        assert_eq!(estimator.eta(now, 10, 100), Some(Duration::from_secs(45)));
+
+        // slow down: samples older than the window are dropped
+        estimator.record(start + Duration::from_secs(20), 12);
+        estimator.record(start + Duration::from_secs(25), 13);
+
+        let now = start + Duration::from_secs(25);
+        assert_eq!(estimator.per_second(now), 3f64 / 20f64);
+        assert_eq!(estimator.eta(now, 13, 13), Some(Duration::ZERO));
+    }
+}
@@ -4,22 +4,27 @@ use indicatif::ProgressStyle;
 
 use std::{borrow::Cow, env};
 
+use super::rolling::{RollingEstimator, ROLLING_ETA_KEY, ROLLING_RATE_KEY};
+
 const DEFAULT_PROGRESS_STYLE: &str = "default";
 
 const SIMPLE_PROGRESS_STYLE: &str = "simple";
 
 const SIMPLE_PROGRESS_STYLE_TEMPLATE: &str =
-    "[{elapsed_precise}] Commands Done/Total: {pos:>2}/{len:2} {wide_bar} ETA {eta_precise}";
+    "[{elapsed_precise}] Commands Done/Total: {pos:>2}/{len:2} {msg} {wide_bar} {rolling_rate} ETA {rolling_eta}";
 
 const LIGHT_BG_PROGRESS_STYLE: &str = "light_bg";
 
 const LIGHT_BG_PROGRESS_STYLE_TEMPLATE: &str =
-    "{spinner:.blue.bold} [{elapsed_precise}] Commands Done/Total: {pos:>2}/{len:2} [{wide_bar:.blue.bold/red}] ETA {eta_precise}";
+    "{spinner:.blue.bold} [{elapsed_precise}] Commands Done/Total: {pos:>2}/{len:2} {msg} [{wide_bar:.blue.bold/red}] {rolling_rate} ETA {rolling_eta}";
 
 const DARK_BG_PROGRESS_STYLE: &str = "dark_bg";
 
 const DARK_BG_PROGRESS_STYLE_TEMPLATE: &str =
-    "{spinner:.cyan.bold} [{elapsed_precise}] Commands Done/Total: {pos:>2}/{len:2} [{wide_bar:.cyan.bold/blue}] ETA {eta_precise}";
+    "{spinner:.cyan.bold} [{elapsed_precise}] Commands Done/Total: {pos:>2}/{len:2} {msg} [{wide_bar:.cyan.bold/blue}] {rolling_rate} ETA {rolling_eta}";
+
+const GROUP_PROGRESS_STYLE_TEMPLATE: &str =
+    "{prefix:>20} {pos:>2}/{len:2} {wide_bar} {rolling_rate} ETA {rolling_eta}";
 
 const PROGRESS_STYLE: &str = "PROGRESS_STYLE";
 
@@ -29,27 +34,35 @@ pub struct ProgressStyleInfo {
     pub enable_steady_tick: bool,
 }
 
+fn progress_style_with_template(template: &str) -> anyhow::Result<ProgressStyle> {
+    Ok(ProgressStyle::with_template(template)
+        .context("ProgressStyle::with_template error")?
+        .with_key(ROLLING_RATE_KEY, RollingEstimator::new_rate())
+        .with_key(ROLLING_ETA_KEY, RollingEstimator::new_eta()))
+}
+
+pub fn group_progress_style() -> anyhow::Result<ProgressStyle> {
+    progress_style_with_template(GROUP_PROGRESS_STYLE_TEMPLATE)
+}
+
 pub fn choose_progress_style() -> anyhow::Result<ProgressStyleInfo> {
     let setting = env::var(PROGRESS_STYLE).map_or(Cow::from(DEFAULT_PROGRESS_STYLE), Cow::from);
 
     match &*setting {
         SIMPLE_PROGRESS_STYLE => Ok(ProgressStyleInfo {
             _style_name: SIMPLE_PROGRESS_STYLE,
-            progress_style: ProgressStyle::with_template(SIMPLE_PROGRESS_STYLE_TEMPLATE)
-                .context("ProgressStyle::with_template error")?,
+            progress_style: progress_style_with_template(SIMPLE_PROGRESS_STYLE_TEMPLATE)?,
             enable_steady_tick: false,
         }),
         LIGHT_BG_PROGRESS_STYLE | DEFAULT_PROGRESS_STYLE => Ok(ProgressStyleInfo {
             _style_name: LIGHT_BG_PROGRESS_STYLE,
-            progress_style: ProgressStyle::with_template(LIGHT_BG_PROGRESS_STYLE_TEMPLATE)
-                .context("ProgressStyle::with_template error")?
+            progress_style: progress_style_with_template(LIGHT_BG_PROGRESS_STYLE_TEMPLATE)?
                 .progress_chars("#>-"),
             enable_steady_tick: true,
         }),
         DARK_BG_PROGRESS_STYLE => Ok(ProgressStyleInfo {
             _style_name: DARK_BG_PROGRESS_STYLE,
-            progress_style: ProgressStyle::with_template(DARK_BG_PROGRESS_STYLE_TEMPLATE)
-                .context("ProgressStyle::with_template error")?
+            progress_style: progress_style_with_template(DARK_BG_PROGRESS_STYLE_TEMPLATE)?
                 .progress_chars("#>-"),
             enable_steady_tick: true,
         }),
@@ -58,6 +71,7 @@ pub fn choose_progress_style() -> anyhow::Result<ProgressStyleInfo> {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::*;
 
@@ -0,0 +1,86 @@
+use std::{borrow::Cow, sync::OnceLock};
+
+use crate::command_line_args::CommandLineArgs;
+
+const MASK: &str = "***";
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+/// Masks secret values from --redact and --redact-env.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    fn new(secrets: impl IntoIterator<Item = String>) -> Self {
+        let mut secrets: Vec<_> = secrets
+            .into_iter()
+            .filter(|secret| !secret.is_empty())
+            .collect();
+
+        // longest first so a secret containing another secret is masked completely
+        secrets.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        secrets.dedup();
+
+        Self { secrets }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    pub fn redact<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let mut result = Cow::from(s);
+
+        for secret in &self.secrets {
+            if result.contains(secret.as_str()) {
+                result = Cow::from(result.replace(secret.as_str(), MASK));
+            }
+        }
+
+        result
+    }
+}
+
+pub fn init(command_line_args: &CommandLineArgs) {
+    let env_secrets = command_line_args
+        .redact_env
+        .iter()
+        .filter_map(|name| std::env::var(name).ok());
+
+    let _ = REDACTOR.set(Redactor::new(
+        command_line_args.redact.iter().cloned().chain(env_secrets),
+    ));
+}
+
+/// The redactor from init, or one that masks nothing if init was not called.
+pub fn redactor() -> &'static Redactor {
+    REDACTOR.get_or_init(Redactor::default)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let redactor = Redactor::new(
+            ["abc", "", "abcdef", "xyz", "abc"]
+                .into_iter()
+                .map(String::from),
+        );
+
+        assert!(!redactor.is_empty());
+        assert_eq!(redactor.secrets, vec!["abcdef", "abc", "xyz"]);
+
+        assert!(matches!(redactor.redact("nothing here"), Cow::Borrowed(_)));
+        assert_eq!(
+            redactor.redact("token=abcdef,other=abc;xyzxyz"),
+            "token=***,other=***;******"
+        );
+
+        assert!(Redactor::default().is_empty());
+        assert_eq!(Redactor::default().redact("abc"), "abc");
+    }
+}
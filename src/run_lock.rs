@@ -0,0 +1,83 @@
+use anyhow::Context;
+
+use tracing::{debug, info};
+
+use std::{
+    fs::{File, TryLockError},
+    io::{Read, Seek, Write},
+};
+
+use crate::command_line_args::CommandLineArgs;
+
+/// Advisory lock on --lock-file held for the whole run, so runs sharing state do not overlap.
+///
+/// The lock is released when the file is closed, including when this process dies.
+pub struct RunLock {
+    _file: File,
+}
+
+impl RunLock {
+    pub async fn acquire(command_line_args: &CommandLineArgs) -> anyhow::Result<Option<Self>> {
+        let Some(path) = &command_line_args.lock_file else {
+            return Ok(None);
+        };
+
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("error opening lock file '{}'", path))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) if command_line_args.wait_for_lock => {
+                info!(
+                    "waiting for lock file '{}' held by {}",
+                    path,
+                    Self::holder(&mut file)
+                );
+                file = tokio::task::spawn_blocking(move || file.lock().map(|_| file))
+                    .await?
+                    .with_context(|| format!("error locking lock file '{}'", path))?;
+            }
+            Err(TryLockError::WouldBlock) => {
+                anyhow::bail!(
+                    "lock file '{}' is held by {}, pass --wait-for-lock to wait for it",
+                    path,
+                    Self::holder(&mut file)
+                );
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("error locking lock file '{}'", path));
+            }
+        }
+
+        debug!("acquired lock file '{}'", path);
+
+        // record the holder for runs that find the file locked
+        let holder = format!(
+            "pid {} run_id {}\n",
+            std::process::id(),
+            command_line_args.run_id
+        );
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| file.write_all(holder.as_bytes()))
+            .with_context(|| format!("error writing lock file '{}'", path))?;
+
+        Ok(Some(Self { _file: file }))
+    }
+
+    /// Description of the run holding the lock, from the contents of the lock file.
+    fn holder(file: &mut File) -> String {
+        let mut contents = String::new();
+        let _ = file.read_to_string(&mut contents);
+
+        match contents.trim() {
+            "" => "another run".to_owned(),
+            holder => format!("another run ({})", holder),
+        }
+    }
+}
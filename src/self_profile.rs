@@ -0,0 +1,195 @@
+use tracing::{info, span, Subscriber};
+
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+static STAGE_TIMES: OnceLock<Mutex<[StageTime; Stage::ALL.len()]>> = OnceLock::new();
+
+/// Part of the controller measured by --self-profile, from the tracing span names.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Stage {
+    Parsing,
+    Scheduling,
+    Spawning,
+    OutputWriting,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [
+        Stage::Parsing,
+        Stage::Scheduling,
+        Stage::Spawning,
+        Stage::OutputWriting,
+    ];
+
+    fn from_span_name(name: &str) -> Option<Self> {
+        match name {
+            "process_buffered_input_line" | "process_next_command_line_arg" => Some(Stage::Parsing),
+            "CommandService::process_input_message" => Some(Stage::Scheduling),
+            "Command::run" => Some(Stage::Spawning),
+            "OutputTask::run" => Some(Stage::OutputWriting),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Parsing => "parsing",
+            Stage::Scheduling => "scheduling",
+            Stage::Spawning => "spawning",
+            Stage::OutputWriting => "output_writing",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct StageTime {
+    spans: u64,
+    busy: Duration,
+}
+
+/// Busy time of one span, the time its future was being polled.
+struct SpanTiming {
+    stage: Stage,
+    entered_at: Option<Instant>,
+    busy: Duration,
+}
+
+/// Adds the busy time of each closed span to its stage.
+pub struct SelfProfileLayer;
+
+impl<S> Layer<S> for SelfProfileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(stage) = Stage::from_span_name(attrs.metadata().name()) else {
+            return;
+        };
+
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanTiming {
+                stage,
+                entered_at: None,
+                busy: Duration::ZERO,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                timing.entered_at = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                if let Some(entered_at) = timing.entered_at.take() {
+                    timing.busy += entered_at.elapsed();
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<SpanTiming>() else {
+            return;
+        };
+
+        let mut stage_times = stage_times().lock().unwrap();
+        let stage_time = &mut stage_times[timing.stage as usize];
+        stage_time.spans += 1;
+        stage_time.busy += timing.busy;
+    }
+}
+
+fn stage_times() -> &'static Mutex<[StageTime; Stage::ALL.len()]> {
+    STAGE_TIMES.get_or_init(Default::default)
+}
+
+fn format_report(stage_times: &[StageTime], elapsed: Duration) -> Vec<String> {
+    let total_busy: Duration = stage_times.iter().map(|stage_time| stage_time.busy).sum();
+    let total_secs = total_busy.as_secs_f64().max(f64::EPSILON);
+
+    let mut lines: Vec<_> = Stage::ALL
+        .iter()
+        .zip(stage_times)
+        .map(|(stage, stage_time)| {
+            format!(
+                "self profile: {}: busy={:?} spans={} share={:.1}%",
+                stage.name(),
+                stage_time.busy,
+                stage_time.spans,
+                100.0 * stage_time.busy.as_secs_f64() / total_secs,
+            )
+        })
+        .collect();
+
+    lines.push(format!(
+        "self profile: total busy={:?} elapsed={:?}",
+        total_busy, elapsed
+    ));
+
+    lines
+}
+
+/// Log the controller time in each stage for --self-profile.
+pub fn report(elapsed: Duration) {
+    let stage_times = *stage_times().lock().unwrap();
+
+    for line in format_report(&stage_times, elapsed) {
+        info!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stage_from_span_name() {
+        assert_eq!(
+            Stage::from_span_name("process_buffered_input_line"),
+            Some(Stage::Parsing)
+        );
+        assert_eq!(Stage::from_span_name("Command::run"), Some(Stage::Spawning));
+        assert_eq!(Stage::from_span_name("try_main"), None);
+    }
+
+    #[test]
+    fn test_format_report() {
+        let stage_times = [
+            StageTime {
+                spans: 4,
+                busy: Duration::from_millis(30),
+            },
+            StageTime::default(),
+            StageTime {
+                spans: 2,
+                busy: Duration::from_millis(10),
+            },
+            StageTime::default(),
+        ];
+
+        assert_eq!(
+            format_report(&stage_times, Duration::from_secs(1)),
+            vec![
+                "self profile: parsing: busy=30ms spans=4 share=75.0%",
+                "self profile: scheduling: busy=0ns spans=0 share=0.0%",
+                "self profile: spawning: busy=10ms spans=2 share=25.0%",
+                "self profile: output_writing: busy=0ns spans=0 share=0.0%",
+                "self profile: total busy=40ms elapsed=1s",
+            ]
+        );
+    }
+}
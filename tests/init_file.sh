@@ -0,0 +1,5 @@
+GREETING=hello
+
+greet() {
+    echo "$GREETING $1"
+}
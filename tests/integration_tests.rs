@@ -105,7 +105,80 @@ fn timeout_sleep_commands_from_args() {
         .failure()
         .code(1)
         .stdout(
-            (predicate::str::contains("timeout: deadline has elapsed").count(1))
+            (predicate::str::contains("command timed out").count(1))
+                .and(predicate::str::contains(r#"args=["5"],line=command_line_args:2"#).count(1))
+                .and(predicate::str::contains("timeouts=1").count(1)),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn timeout_deadline_from_enqueue_includes_queue_time() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-t0.6")
+        .arg("--deadline-from=enqueue")
+        .arg("sleep")
+        .arg(":::")
+        .arg("0.4")
+        .arg("0.4")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains("command timed out").count(1))
+                .and(predicate::str::contains("line=command_line_args:2").count(1))
+                .and(predicate::str::contains("timeouts=1").count(1)),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn retries_failing_command_until_success() {
+    let path = std::env::temp_dir().join(format!("rust_parallel_retries_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // fails on the first two attempts
+    let command = format!(
+        "echo x >> {0}; test $(wc -l < {0}) -ge 3 && echo done",
+        path.display()
+    );
+
+    rust_parallel()
+        .write_stdin(command)
+        .arg("-s")
+        .arg("--retries=2")
+        .arg("--retry-delay=0.01")
+        .assert()
+        .success()
+        .stdout(
+            (predicate::str::contains("command failed, retry 1/2 in 10ms"))
+                .and(predicate::str::contains(
+                    "command failed, retry 2/2 in 20ms",
+                ))
+                .and(predicate::str::ends_with("done\n")),
+        )
+        .stderr(predicate::str::is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn retries_timed_out_command() {
+    rust_parallel()
+        .arg("-t0.1")
+        .arg("--retries=1")
+        .arg("--retry-delay=0.01")
+        .arg("sleep")
+        .arg(":::")
+        .arg("5")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains("command failed, retry 1/1 in 10ms").count(1))
+                .and(predicate::str::contains(": timeout"))
                 .and(predicate::str::contains("timeouts=1").count(1)),
         )
         .stderr(predicate::str::is_empty());
@@ -178,6 +251,83 @@ fn runs_file_j1() {
         .stderr(predicate::str::is_empty());
 }
 
+#[test]
+fn fails_missing_input_file() {
+    rust_parallel()
+        .arg("-i")
+        .arg("no_such_file.txt")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("error opening input file file_name = 'no_such_file.txt'")
+                .and(predicate::str::contains("hello").not()),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_missing_input_file_warn_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--missing-input=warn")
+        .arg("-i")
+        .arg("no_such_file.txt")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("error opening input file file_name = 'no_such_file.txt'")
+                .and(predicate::str::ends_with("hello\nfrom\ninput\nfile\n")),
+        )
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("--missing-input=ignore")
+        .arg("-i")
+        .arg("no_such_file.txt")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq("hello\nfrom\ninput\nfile\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_compressed_files_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-i")
+        .arg("file.txt.gz")
+        .arg("-i")
+        .arg("file.zst")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "hello\nfrom\ninput\nfile\nhello\nfrom\ninput\nfile\n",
+        ))
+        .stderr(predicate::str::is_empty());
+
+    // compressed stdin is detected by its first bytes
+    rust_parallel()
+        .arg("-j1")
+        .pipe_stdin("tests/file.txt.gz")
+        .unwrap()
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq("hello\nfrom\ninput\nfile\n"))
+        .stderr(predicate::str::is_empty());
+}
+
 #[test]
 fn fails_j0() {
     rust_parallel()
@@ -515,8 +665,10 @@ fn test_exit_status_on_failing_commands() {
         .code(1)
         .stdout(
             (predicate::str::contains("command failed").count(3))
+                .and(predicate::str::contains(" duration=").count(3))
                 .and(predicate::str::contains("command failures:"))
-                .and(predicate::str::contains("exit_status_errors=3")),
+                .and(predicate::str::contains("exit_status_errors=3"))
+                .and(predicate::str::contains("max_duration=")),
         )
         .stderr(
             (predicate::str::contains("cat: A: No such file or directory").count(1))
@@ -545,3 +697,2692 @@ fn test_exit_status_on_failing_commands_exit_on_error() {
         )
         .stderr(predicate::str::contains("cat: A: No such file or directory").count(1));
 }
+
+#[test]
+fn test_exit_on_error_cancels_remaining_commands() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--exit-on-error")
+        .arg("cat")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg("C")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains("command failed").count(1))
+                .and(predicate::str::contains("commands_run=1")),
+        )
+        .stderr(
+            (predicate::str::contains("cat: A: No such file or directory").count(1))
+                .and(predicate::str::contains("cat: B").not())
+                .and(predicate::str::contains("cat: C").not()),
+        );
+}
+
+#[test]
+fn halt_soon_after_failure_count() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--halt")
+        .arg("soon,fail=2")
+        .arg("cat")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg("C")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains(
+                "halting after 2 failed commands, no more commands are started",
+            ))
+            .and(predicate::str::contains("commands_run=2")),
+        )
+        .stderr(predicate::str::contains("cat: C").not());
+}
+
+#[test]
+fn halt_soon_after_failure_percent() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--halt")
+        .arg("soon,fail=50%")
+        .arg("-s")
+        .arg(":::")
+        .arg("exit 1")
+        .arg("exit 1")
+        .arg("echo ran C")
+        .arg("echo ran D")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains("halting after 2 failed commands"))
+                .and(predicate::str::contains("commands_run=2"))
+                .and(predicate::str::contains("ran C").not()),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn halt_soon_process_group_finishes_running_commands() {
+    rust_parallel()
+        .arg("-j2")
+        .arg("--halt")
+        .arg("soon,fail=1")
+        .arg("--process-group")
+        .arg("-s")
+        .arg("--shell-path=sh")
+        .arg(":::")
+        .arg("sleep 0.5; echo finished")
+        .arg("exit 1")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains("finished\n"))
+                .and(predicate::str::contains("killed command").not()),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn halt_now_kills_running_commands() {
+    rust_parallel()
+        .arg("-j2")
+        .arg("--halt")
+        .arg("now,fail=1")
+        .arg("-s")
+        .arg(":::")
+        .arg("sleep 30")
+        .arg("sleep 0.2; exit 1")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            (predicate::str::contains("halting after 1 failed commands, killing running commands"))
+                .and(predicate::str::contains(
+                    "killed command due to cancellation",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_kills_running_commands() {
+    rust_parallel()
+        .arg("-j2")
+        .arg("-s")
+        .arg(":::")
+        .arg("sleep 0.3; kill -INT $PPID")
+        .arg("sleep 30")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            predicate::str::contains("received ctrl-c, cancelling and killing running commands")
+                .and(predicate::str::contains(
+                    "killed command due to cancellation",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_drain_on_quit() {
+    use std::{io::Write, process::Stdio, time::Duration};
+
+    let mut child = rust_parallel_raw_command()
+        .arg("--drain-on-quit")
+        .arg("-s")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // stdin stays open, so the run only ends by draining
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"kill -QUIT $PPID; sleep 0.5; echo drained\n")
+        .unwrap();
+    stdin.flush().unwrap();
+
+    let mut status = None;
+    for _ in 0..100 {
+        status = child.try_wait().unwrap();
+        if status.is_some() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    if status.is_none() {
+        child.kill().unwrap();
+    }
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(status.is_some_and(|status| status.success()), "{stdout}");
+    assert!(stdout.contains("draining"), "{stdout}");
+    assert!(stdout.contains("drained"), "{stdout}");
+}
+
+#[test]
+fn fails_if_empty() {
+    rust_parallel()
+        .arg("--fail-if-empty")
+        .arg("--on-no-match=skip")
+        .arg("-r")
+        .arg("x(.*)")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "no commands were generated from the inputs",
+        ))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("--fail-if-empty")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "no commands were generated from the inputs",
+        ))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("--fail-if-empty")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_expect_jobs_out_of_range() {
+    rust_parallel()
+        .arg("--expect-jobs=1..4")
+        .arg("echo")
+        .arg(":::")
+        .arg("a")
+        .arg("b")
+        .arg(":::")
+        .arg("1")
+        .arg("2")
+        .arg("3")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            predicate::str::contains("--expect-jobs: expected 1..4 commands, inputs produced 6")
+                .and(predicate::str::contains("a 1").not()),
+        )
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("--expect-jobs=2")
+        .arg("echo")
+        .arg(":::")
+        .arg("a")
+        .arg("b")
+        .assert()
+        .success()
+        .stdout(predicate::eq("a\nb\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_reads_all_inputs_first_with_stdin() {
+    rust_parallel()
+        .write_stdin("a\n")
+        .arg("--expect-jobs=1")
+        .arg("echo")
+        .assert()
+        .failure()
+        .code(2)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "--expect-jobs reads all inputs before starting commands",
+        ));
+
+    rust_parallel()
+        .arg("--halt")
+        .arg("soon,fail=50%")
+        .arg("--input-cmd=echo a")
+        .arg("echo")
+        .assert()
+        .failure()
+        .code(2)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "--halt with fail=N% reads all inputs before starting commands",
+        ));
+}
+
+#[test]
+fn fails_enormous_cartesian_product_without_yes() {
+    let group: Vec<String> = (0..400).map(|i| i.to_string()).collect();
+
+    rust_parallel()
+        .arg("echo")
+        .arg(":::")
+        .args(&group)
+        .arg(":::")
+        .args(&group)
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "::: groups produce 160000 commands, pass --yes to run them",
+        ));
+}
+
+#[test]
+fn runs_verbose_results_logs_silent_commands() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--verbose-results")
+        .arg("true")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(
+            (predicate::str::contains("command succeeded").count(2))
+                .and(predicate::str::contains(r#"args=["A"],line=command_line_args:1"#).count(1))
+                .and(predicate::str::contains(r#"args=["B"],line=command_line_args:2"#).count(1)),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_verbose_commands_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--verbose-commands")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B C")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("^\\S*echo A\nA\n\\S*echo 'B C'\nB C\n$").unwrap())
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_verbose_commands_silent_shell_commands_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg("--verbose-commands")
+        .arg("--shell-path=sh")
+        .arg("true")
+        .arg(":::")
+        .arg("$HOME")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("^\\S*sh -c 'true \\$HOME'\n$").unwrap())
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_verbose_commands_reduce_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--verbose-commands")
+        .arg("--reduce=tr a-z A-Z")
+        .arg("echo")
+        .arg(":::")
+        .arg("a")
+        .arg("b")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\nB\n"))
+        .stderr(predicate::str::is_match("^\\S*echo a\n\\S*echo b\n$").unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_tag_j1() {
+    rust_parallel()
+        .write_stdin("A\nB C\n")
+        .arg("-j1")
+        .arg("--tag")
+        .arg("-s")
+        .arg("--shell-path=sh")
+        .arg("-r")
+        .arg("(.*)")
+        .arg("echo {1}; echo x >&2")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\tA\nB C\tB C\n"))
+        .stderr(predicate::eq("A\tx\nB C\tx\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_hash_output_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--hash-output=sha256")
+        .arg("printf")
+        .arg(":::")
+        .arg("abc")
+        .arg("")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains(
+                r#""line":"command_line_args:1","sha256":"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"}"#,
+            )
+            .and(predicate::str::contains(
+                r#""line":"command_line_args:2","sha256":"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"}"#,
+            ))
+            .and(predicate::str::contains(r#"{"bytes":3,"#))
+            .and(predicate::str::contains(r#"{"bytes":0,"#)),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_detect_duplicate_output_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--detect-duplicate-output")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("from 2 commands: command_line_args:1, command_line_args:3")
+                .and(predicate::str::contains(
+                    "found 1 groups of commands with duplicate output",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_results_j1() {
+    let dir = std::env::temp_dir().join(format!("rust_parallel_results_{}", std::process::id()));
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--results={}", dir.display()))
+        .arg("-s")
+        .arg("--shell-path=sh")
+        .arg("echo out; echo err >&2; exit {}")
+        .arg(":::")
+        .arg("0")
+        .arg("3")
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with("out\nout\n"))
+        .stderr(predicate::eq("err\nerr\n"));
+
+    let read = |path: &str| std::fs::read_to_string(dir.join(path)).unwrap();
+
+    assert!(read("1/cmd").ends_with("sh -c 'echo out; echo err >&2; exit 0'\n"));
+    assert_eq!(read("1/stdout"), "out\n");
+    assert_eq!(read("1/stderr"), "err\n");
+    assert_eq!(read("1/exitcode"), "0\n");
+    assert_eq!(read("2/exitcode"), "3\n");
+
+    let index: Vec<serde_json::Value> = read("index.jsonl")
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(index.len(), 2);
+    assert_eq!(index[1]["seq"], 2);
+    assert_eq!(index[1]["input"], "3");
+    assert_eq!(index[1]["input_line"], "command_line_args:2");
+    assert_eq!(index[1]["exit_code"], 3);
+    assert!(index[1]["duration_secs"].is_f64());
+    assert_eq!(
+        index[1]["stdout"],
+        dir.join("2").join("stdout").display().to_string()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_results_resume() {
+    let dir = std::env::temp_dir().join(format!(
+        "rust_parallel_results_resume_{}",
+        std::process::id()
+    ));
+    let joblog = std::env::temp_dir().join(format!(
+        "rust_parallel_results_resume_{}.joblog",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&joblog);
+
+    let run = || {
+        rust_parallel()
+            .arg("-j1")
+            .arg(format!("--results={}", dir.display()))
+            .arg(format!("--joblog={}", joblog.display()))
+            .arg("--resume")
+            .arg("-s")
+            .arg("--shell-path=sh")
+            .arg("echo {}; [ {} != b ]")
+            .arg(":::")
+            .arg("a")
+            .arg("b")
+            .arg("c")
+            .assert()
+    };
+
+    run().failure();
+    // only b runs again, into the directory of its Seq in the joblog
+    run().failure();
+
+    let read = |path: &str| std::fs::read_to_string(dir.join(path)).unwrap();
+
+    assert_eq!(read("1/stdout"), "a\n");
+    assert_eq!(read("2/stdout"), "b\n");
+    assert_eq!(read("2/exitcode"), "1\n");
+    assert_eq!(read("3/stdout"), "c\n");
+    assert!(!dir.join("0").exists());
+
+    // the index of the first run is appended to, the last line for b is its latest result
+    let index: Vec<serde_json::Value> = read("index.jsonl")
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(index.len(), 4);
+    assert_eq!(index[3]["seq"], 2);
+    assert_eq!(index[3]["input"], "b");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_file(&joblog).unwrap();
+}
+
+#[test]
+fn runs_results_keep() {
+    let root =
+        std::env::temp_dir().join(format!("rust_parallel_results_keep_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("other")).unwrap();
+
+    let run = |name: &str| {
+        rust_parallel()
+            .arg(format!("--results={}", root.join(name).display()))
+            .arg("--results-keep=2")
+            .arg("echo")
+            .arg(":::")
+            .arg(name)
+            .assert()
+            .success()
+    };
+
+    run("run1");
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    run("run2");
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    run("run3").stdout(predicate::str::contains(format!(
+        "removed old results directory '{}'",
+        root.join("run1").display()
+    )));
+
+    assert!(!root.join("run1").exists());
+    assert!(root.join("run2").join("index.jsonl").exists());
+    assert!(root.join("run3").join("index.jsonl").exists());
+    // directories not written by --results are kept
+    assert!(root.join("other").exists());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn runs_merge_output_j1() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_merge_output_{}.txt",
+        std::process::id()
+    ));
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--merge-output={}", path.display()))
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(contents, "A\nB\n");
+}
+
+#[test]
+fn runs_merge_output_sorted_by_key() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_merge_output_sorted_{}.txt",
+        std::process::id()
+    ));
+
+    rust_parallel()
+        .arg(format!("--merge-output={}:{{2}}", path.display()))
+        .arg("echo")
+        .arg("{1}")
+        .arg(":::")
+        .arg("x")
+        .arg("y")
+        .arg(":::")
+        .arg("3")
+        .arg("1")
+        .arg("2")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(contents, "x\ny\nx\ny\nx\ny\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_reduce() {
+    rust_parallel()
+        .arg("--reduce=tr a-z A-Z")
+        .arg("echo")
+        .arg(":::")
+        .arg("a")
+        .arg("b")
+        .arg("c")
+        .arg("d")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\nB\nC\nD\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_reduce_key() {
+    rust_parallel()
+        .arg("--reduce=echo \"$PARALLEL_REDUCE_KEY $(wc -l)\"")
+        .arg("--reduce-key={2}")
+        .arg("echo")
+        .arg("{1}")
+        .arg(":::")
+        .arg("a")
+        .arg("b")
+        .arg("c")
+        .arg(":::")
+        .arg("x")
+        .arg("y")
+        .assert()
+        .success()
+        .stdout(predicate::eq("x 3\ny 3\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn fails_reduce_command_failed() {
+    rust_parallel()
+        .arg("--reduce=cat; exit 3")
+        .arg("echo")
+        .arg(":::")
+        .arg("a")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(
+            predicate::str::starts_with("a\n").and(predicate::str::contains(
+                "reduce command 'cat; exit 3' failed: exit_status=3",
+            )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_shell_function_with_multiple_shell_arguments_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg("--shell-path=./dummy_shell.sh")
+        .arg("--shell-argument=-u")
+        .arg("--shell-argument=-c")
+        .arg("shell_function")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "dummy_shell arg1=-u arg2=-c\ndummy_shell arg1=-u arg2=-c\n",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_shell_auto_from_shell_env_var_j1() {
+    rust_parallel()
+        .env("SHELL", "/bin/sh")
+        .arg("-j1")
+        .arg("-s")
+        .arg("--shell-auto")
+        .arg("echo $0")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(predicate::eq("/bin/sh A\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_shell_auto_unknown_shell_kind() {
+    rust_parallel()
+        .env("SHELL", "./dummy_shell.sh")
+        .arg("-s")
+        .arg("--shell-auto")
+        .arg("shell_function")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "--shell-auto cannot tell the kind of shell $SHELL './dummy_shell.sh' is",
+        ));
+}
+
+#[test]
+fn fails_invalid_shell_path() {
+    rust_parallel()
+        .arg("-s")
+        .arg("--shell-path=./does_not_exist.sh")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "shell path './does_not_exist.sh' is not an executable file",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_shell_template_file_from_args_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg("--template-file=template.txt")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "template A\nsecond A\ntemplate B\nsecond B\n",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_template_file_quoted_argument_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--template-file=template_quoted.txt")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::eq("a b|A\na b|B\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_missing_template_file() {
+    rust_parallel()
+        .arg("--template-file=does_not_exist.txt")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "invalid value 'does_not_exist.txt' for '--template-file <FILE>'",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_shell_env_functions_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg("--env-functions")
+        .arg("--minimal-env")
+        .env(
+            "PARALLEL_ENV_FUNCTIONS",
+            "greet () \n{ \n    echo \"hello $1\"\n}",
+        )
+        .arg("greet")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::eq("hello A\nhello B\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_shell_init_file_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg("--init-file=init_file.sh")
+        .arg("greet")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::eq("hello A\nhello B\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_missing_init_file() {
+    rust_parallel()
+        .arg("-s")
+        .arg("--init-file=does_not_exist.sh")
+        .arg("greet")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "invalid value 'does_not_exist.sh' for '--init-file <FILE>'",
+        ));
+}
+
+#[test]
+fn fails_env_functions_without_env_var() {
+    rust_parallel()
+        .arg("-s")
+        .arg("--env-functions")
+        .env_remove("PARALLEL_ENV_FUNCTIONS")
+        .arg("greet")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "--env-functions requires $PARALLEL_ENV_FUNCTIONS",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_dispatch_commands_from_stdin_j1() {
+    let stdin = r#"upper hello
+        lower WORLD
+        unknown foo"#;
+
+    rust_parallel()
+        .write_stdin(stdin)
+        .arg("-j1")
+        .arg("-s")
+        .arg("--dispatch")
+        .arg("--cmd=upper=echo upper:")
+        .arg("--cmd=lower=echo lower:")
+        .assert()
+        .success()
+        .stdout(
+            (predicate::str::contains("upper: hello\nlower: WORLD\n")).and(
+                predicate::str::contains("unknown dispatch command name 'unknown'"),
+            ),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_skip_if_exists_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--skip-if-exists={}")
+        .arg("echo")
+        .arg(":::")
+        .arg("file.txt")
+        .arg("does_not_exist.txt")
+        .assert()
+        .success()
+        .stdout(
+            (predicate::str::contains(r#"skipping command for up to date output path "file.txt""#)
+                .count(1))
+            .and(predicate::str::contains("does_not_exist.txt\n").count(1))
+            .and(predicate::str::contains("file.txt\n").not())
+            .and(predicate::str::contains("skipped 1 up to date commands")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_skip_if_exists_newer_than_j1() {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    let dir = std::env::temp_dir().join(format!("rust_parallel_newer_than_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let now = SystemTime::now();
+    let older = now - Duration::from_secs(60);
+
+    let create_file = |name: &str, modified: SystemTime| {
+        File::create(dir.join(name))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    };
+
+    create_file("a.in", older);
+    create_file("a.out", now);
+    create_file("b.in", now);
+    create_file("b.out", older);
+
+    let a = dir.join("a").display().to_string();
+    let b = dir.join("b").display().to_string();
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("--skip-if-exists={}.out")
+        .arg("--newer-than={}.in")
+        .arg("echo")
+        .arg(":::")
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success()
+        .stdout(
+            (predicate::str::contains(format!("{}\n", b)).count(1))
+                .and(predicate::str::contains(format!("{}\n", a)).not())
+                .and(predicate::str::contains("skipped 1 up to date commands")),
+        )
+        .stderr(predicate::str::is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn runs_keep_order_commands_from_args() {
+    rust_parallel()
+        .arg("-j4")
+        .arg("-k")
+        .arg("-s")
+        .arg("sleep 0.{1}; echo {1} {2}")
+        .arg(":::")
+        .arg("3")
+        .arg("1")
+        .arg(":::")
+        .arg("a")
+        .arg("b")
+        .assert()
+        .success()
+        .stdout(predicate::eq("3 a\n3 b\n1 a\n1 b\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_keep_order_stdin() {
+    let stdin = "3\n1\n2\n";
+
+    rust_parallel()
+        .write_stdin(stdin)
+        .arg("-j4")
+        .arg("--keep-order")
+        .arg("-s")
+        .arg("-r")
+        .arg("(.*)")
+        .arg("sleep 0.{1}; echo {1}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("3\n1\n2\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_keep_order_output_channel_bytes() {
+    let stdin = "3\n1\n2\n";
+
+    rust_parallel()
+        .write_stdin(stdin)
+        .arg("-j4")
+        .arg("--keep-order")
+        .arg("--input-channel-capacity=1")
+        .arg("--output-channel-capacity=1")
+        .arg("--output-channel-bytes=1")
+        .arg("-s")
+        .arg("-r")
+        .arg("(.*)")
+        .arg("sleep 0.{1}; echo {1}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("3\n1\n2\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_stream_output_j2() {
+    // B is written while the first command is still running
+    rust_parallel()
+        .arg("-j2")
+        .arg("--stream-output")
+        .arg("--output-channel-bytes=1")
+        .arg("-s")
+        .arg(":::")
+        .arg("echo A1; sleep 0.5; echo A2; echo A3 >&2")
+        .arg("sleep 0.2; echo B")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A1\nB\nA2\n"))
+        .stderr(predicate::eq("A3\n"));
+}
+
+#[test]
+fn fails_stream_output_keep_order() {
+    rust_parallel()
+        .arg("--stream-output")
+        .arg("--keep-order")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "the argument '--stream-output' cannot be used with '--keep-order'",
+        ));
+}
+
+#[test]
+fn runs_order_by_input_lifo() {
+    rust_parallel()
+        .write_stdin("a\nb\nfile.txt\nc\nd\n")
+        .arg("-j1")
+        .arg("--schedule=lifo")
+        .arg("--order-by=input")
+        .arg("--skip-if-exists={}")
+        .arg("echo")
+        .assert()
+        .success()
+        // the skip is logged directly, so it may be written between command outputs
+        .stdout(predicate::function(|stdout: &str| {
+            stdout
+                .lines()
+                .filter(|line| !line.contains("INFO"))
+                .eq(["a", "b", "c", "d"])
+        }))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_order_by_completion() {
+    rust_parallel()
+        .arg("-j3")
+        .arg("--order-by=completion")
+        .arg("-s")
+        .arg("sleep 0.{1}; echo {1}")
+        .arg(":::")
+        .arg("5")
+        .arg("1")
+        .arg("3")
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n3\n5\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_order_by_with_keep_order() {
+    rust_parallel()
+        .arg("-k")
+        .arg("--order-by=start")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn runs_progress_group_by_j1() {
+    let stdin = "a,1\na,2\nb,3\n";
+
+    rust_parallel()
+        .write_stdin(stdin)
+        .arg("-j1")
+        .arg("-p")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("--progress-group-by={1}")
+        .arg("echo")
+        .arg("{2}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n2\n3\n"));
+}
+
+#[test]
+fn fails_progress_group_by_without_progress_bar() {
+    rust_parallel()
+        .arg("--progress-group-by={1}")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "the following required arguments were not provided",
+        ));
+}
+
+#[test]
+fn runs_progress_json_to_path() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_progress_json_{}.jsonl",
+        std::process::id()
+    ));
+
+    rust_parallel()
+        .arg(format!("--progress-json={}", path.display()))
+        .arg("--run-id=r1")
+        .arg("false")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .failure();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut event: serde_json::Value =
+        serde_json::from_str(contents.lines().last().unwrap()).unwrap();
+    let queue_wait_secs = event.as_object_mut().unwrap().remove("queue_wait_secs");
+    assert!(queue_wait_secs.unwrap()["max"].is_f64());
+
+    assert_eq!(
+        event.to_string(),
+        r#"{"done":2,"eta_secs":0,"failed":2,"queued":0,"run_id":"r1","run_name":null,"running":0,"skipped_input":{"empty":0,"filtered":0,"invalid_utf8":0,"unmatched":0},"total":2}"#
+    );
+}
+
+#[test]
+fn runs_progress_json_queue_wait_j1() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_progress_json_queue_wait_{}.jsonl",
+        std::process::id()
+    ));
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--progress-json={}", path.display()))
+        .arg("sleep")
+        .arg(":::")
+        .arg("0.3")
+        .arg("0.3")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("queue wait: p50=\\S+ p95=\\S+ max=\\S+").unwrap())
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // the second command waits for the first to finish
+    let event: serde_json::Value = serde_json::from_str(contents.lines().last().unwrap()).unwrap();
+    assert!(event["queue_wait_secs"]["max"].as_f64().unwrap() >= 0.3);
+}
+
+#[test]
+fn fails_invalid_statsd_address() {
+    rust_parallel()
+        .arg("--statsd=nonsense")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "error resolving statsd address 'nonsense'",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_self_profile() {
+    rust_parallel()
+        .arg("--self-profile")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg("C")
+        .assert()
+        .success()
+        .stdout(
+            (predicate::str::is_match("self profile: parsing: busy=.* spans=3 ").unwrap())
+                .and(predicate::str::is_match("self profile: spawning: busy=.* spans=3 ").unwrap())
+                .and(predicate::str::contains("self profile: total busy=")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_joblog_j1() {
+    let path = std::env::temp_dir().join(format!("rust_parallel_joblog_{}", std::process::id()));
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--joblog={}", path.display()))
+        .arg("-s")
+        .arg("--shell-path=sh")
+        .arg("exit {}")
+        .arg(":::")
+        .arg("0")
+        .arg("3")
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        predicate::str::is_match(
+            "^Seq\tStarttime\tJobRuntime\tExitval\tSignal\tCommand\tQueueWait\n\
+             1\t\\d+\\.\\d{3}\t\\d+\\.\\d{3}\t0\t0\t\\S*sh -c 'exit 0'\t\\d+\\.\\d{3}\n\
+             2\t\\d+\\.\\d{3}\t\\d+\\.\\d{3}\t3\t0\t\\S*sh -c 'exit 3'\t\\d+\\.\\d{3}\n$"
+        )
+        .unwrap()
+        .eval(&contents),
+        "{contents}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_resume_from_joblog() {
+    let path = std::env::temp_dir().join(format!("rust_parallel_resume_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let run = |command: &str| {
+        rust_parallel()
+            .arg("-j1")
+            .arg(format!("--joblog={}", path.display()))
+            .arg("--resume")
+            .arg("-s")
+            .arg("--shell-path=sh")
+            .arg(command)
+            .arg(":::")
+            .arg("A")
+            .arg("B")
+            .arg("C")
+            .assert()
+    };
+
+    run("echo {}; test {} != B")
+        .failure()
+        .stdout(predicate::str::contains("command failed").count(1));
+
+    run("echo again {}").success().stdout(
+        predicate::str::contains("again B\n")
+            .and(predicate::str::contains("again A").not())
+            .and(predicate::str::contains("again C").not())
+            .and(predicate::str::contains(
+                "resume: skipped 2 inputs completed in joblog",
+            )),
+    );
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(contents.matches("Seq\t").count(), 1);
+    assert_eq!(contents.lines().count(), 5);
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_resume_failed_from_joblog() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_resume_failed_{}",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "Seq\tStarttime\tJobRuntime\tExitval\tSignal\tCommand\n\
+         1\t1700000000.000\t0.010\t0\t0\techo A\n\
+         2\t1700000000.000\t0.010\t1\t0\techo B\n",
+    )
+    .unwrap();
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--joblog={}", path.display()))
+        .arg("--retry-failed")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg("C")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match("(^|\n)B\n")
+                .unwrap()
+                .and(predicate::str::contains(
+                    "resume: skipped 2 inputs that did not fail in joblog",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        predicate::str::is_match("\n2\t[^\t]*\t[^\t]*\t0\t0\t\\S*echo B\t[^\t]*\n$")
+            .unwrap()
+            .eval(&contents),
+        "{contents}"
+    );
+}
+
+#[test]
+fn runs_resume_progress_json() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_resume_progress_json_{}",
+        std::process::id()
+    ));
+    let progress_path = std::env::temp_dir().join(format!(
+        "rust_parallel_resume_progress_json_{}.jsonl",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "Seq\tStarttime\tJobRuntime\tExitval\tSignal\tCommand\n\
+         1\t1700000000.000\t0.010\t0\t0\techo A\n\
+         2\t1700000000.000\t0.010\t1\t0\techo B\n",
+    )
+    .unwrap();
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--joblog={}", path.display()))
+        .arg("--resume")
+        .arg(format!("--progress-json={}", progress_path.display()))
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg("C")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&progress_path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&progress_path).unwrap();
+
+    let events: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    // A from the previous run is done before any input is read, and not counted twice
+    assert!(events[0]["done"].as_u64().unwrap() >= 1, "{contents}");
+    let last = events.last().unwrap();
+    assert_eq!(last["done"], 3, "{contents}");
+    assert_eq!(last["total"], 3, "{contents}");
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_resume_order_by_input() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_resume_order_by_input_{}",
+        std::process::id()
+    ));
+    let output_path = std::env::temp_dir().join(format!(
+        "rust_parallel_resume_order_by_input_{}.out",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "Seq\tStarttime\tJobRuntime\tExitval\tSignal\tCommand\n\
+         1\t1700000000.000\t0.010\t0\t0\techo A\n",
+    )
+    .unwrap();
+
+    // the output of B is written before the run ends, although the resumed A precedes it
+    let status = rust_parallel_raw_command()
+        .arg("-j1")
+        .arg(format!("--joblog={}", path.display()))
+        .arg("--resume")
+        .arg("--order-by=input")
+        .arg("-s")
+        .arg("--shell-path=sh")
+        .arg(":::")
+        .arg("echo A")
+        .arg("echo B")
+        .arg(format!(
+            "sleep 0.5; grep -q B {} && echo C saw B",
+            output_path.display()
+        ))
+        .stdout(std::fs::File::create(&output_path).unwrap())
+        .status()
+        .unwrap();
+
+    let output = std::fs::read_to_string(&output_path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&output_path).unwrap();
+
+    assert!(status.success(), "{output}");
+    assert!(
+        predicate::str::is_match("(^|\n)B\nC saw B\n")
+            .unwrap()
+            .and(predicate::str::contains(
+                "resume: skipped 1 inputs completed in joblog"
+            ))
+            .and(predicate::str::contains("A\n").not())
+            .eval(&output),
+        "{output}"
+    );
+}
+
+#[test]
+fn runs_trace_json_j2() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_trace_json_{}.json",
+        std::process::id()
+    ));
+
+    rust_parallel()
+        .arg("-j2")
+        .arg(format!("--trace-json={}", path.display()))
+        .arg("sleep")
+        .arg(":::")
+        .arg("0.1")
+        .arg("0.2")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let trace: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+
+    assert_eq!(events.len(), 2);
+
+    let mut pids: Vec<_> = events.iter().map(|e| e["pid"].as_u64().unwrap()).collect();
+    pids.sort();
+    assert_eq!(pids, vec![1, 2]);
+
+    assert!(events.iter().all(|e| e["ph"] == "X"));
+
+    assert!(trace["metadata"]["run_id"].is_string());
+}
+
+#[test]
+fn runs_lock_file() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_lock_file_{}.lock",
+        std::process::id()
+    ));
+
+    let file = std::fs::File::create(&path).unwrap();
+    file.lock().unwrap();
+
+    rust_parallel()
+        .arg(format!("--lock-file={}", path.display()))
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("is held by another run"))
+        .stderr(predicate::str::is_empty());
+
+    let unlock_thread = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        drop(file);
+    });
+
+    rust_parallel()
+        .arg(format!("--lock-file={}", path.display()))
+        .arg("--wait-for-lock")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("waiting for lock file").and(predicate::str::ends_with("A\n")),
+        )
+        .stderr(predicate::str::is_empty());
+
+    unlock_thread.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_run_name_and_id() {
+    rust_parallel()
+        .arg("--run-name=nightly")
+        .arg("--run-id=r1")
+        .arg("-s")
+        .arg("--shell-path=/bin/sh")
+        .arg("echo $PARALLEL_RUN_NAME $PARALLEL_RUN_ID")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(predicate::eq("nightly r1 A\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_minimal_env() {
+    rust_parallel()
+        .arg("--minimal-env")
+        .env("CI_SECRET", "hunter2")
+        .env("LANG", "C")
+        .arg("sh")
+        .arg("-c")
+        .arg("echo \"secret=$CI_SECRET lang=$LANG path=${PATH:+set}\"")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(predicate::eq("secret= lang=C path=set\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_audit_log_j1() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_parallel_audit_log_{}.jsonl",
+        std::process::id()
+    ));
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(format!("--audit-log={}", path.display()))
+        .arg("echo")
+        .arg(":::")
+        .arg("a b")
+        .arg("C")
+        .assert()
+        .success()
+        .stdout(predicate::eq("a b\nC\n"))
+        .stderr(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let entries: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["argv"][1], "a b");
+    assert_eq!(entries[1]["argv"][1], "C");
+    assert!(entries
+        .iter()
+        .all(|e| e["program"].as_str().unwrap().ends_with("/echo")
+            && e["pid"].is_u64()
+            && e["cwd"].is_string()
+            && e["start_time"].is_string()));
+}
+
+#[test]
+fn runs_log_target_stderr() {
+    rust_parallel()
+        .arg("--log-target=stderr")
+        .arg("false")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(
+            predicate::str::contains("command failed")
+                .and(predicate::str::contains("fatal error in main")),
+        );
+}
+
+#[cfg(unix)]
+#[test]
+fn fails_commands_killed_by_signal_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg(":::")
+        .arg("kill -KILL $$")
+        .arg("kill -SEGV $$")
+        .arg("exit 1")
+        .assert()
+        .failure()
+        .stdout(
+            (predicate::str::contains("command killed by signal").count(2))
+                .and(predicate::str::contains("signal=SIGKILL"))
+                .and(predicate::str::contains("signal=SIGSEGV"))
+                .and(predicate::str::contains("command failed").count(1))
+                .and(predicate::str::contains("exit_status_errors=1"))
+                .and(predicate::str::contains("signal_kills=2")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fails_max_descendants_exceeded_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--max-descendants=2")
+        .arg("-s")
+        .arg(":::")
+        .arg("for i in 1 2 3 4; do sleep 30 & done; wait")
+        .arg("sleep 0.1; echo hello")
+        .assert()
+        .failure()
+        .stdout(
+            (predicate::str::contains("descendant process limit exceeded").count(1))
+                .and(predicate::str::contains("hello\n").count(1))
+                .and(predicate::str::contains("descendant_limit_kills=1")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_process_group_reports_surviving_processes_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--process-group")
+        .arg("-s")
+        .arg(":::")
+        .arg("sleep 5 >/dev/null 2>&1 & echo started")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("started\n")
+                .and(predicate::str::contains(
+                    "still has running processes after command exited",
+                ))
+                .and(predicate::str::contains(
+                    "1 process groups still running after commands exited",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_process_group_kills_group_on_halt_now() {
+    rust_parallel()
+        .arg("-j2")
+        .arg("--process-group")
+        .arg("--halt")
+        .arg("now,fail=1")
+        .arg("-s")
+        .arg(":::")
+        .arg("sleep 30")
+        .arg("sleep 0.2; exit 1")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("killed command due to cancellation")
+                .and(predicate::str::contains("command failed").count(1)),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fails_audit_children_leaked_process() {
+    rust_parallel()
+        .arg("--audit-children=kill")
+        .arg("-s")
+        .arg(":::")
+        .arg("sleep 5 >/dev/null 2>&1 & echo started")
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("started\n")
+                .and(predicate::str::contains(r#"leaked child process pid="#))
+                .and(predicate::str::contains(r#"command="sleep 5""#))
+                .and(predicate::str::contains("1 leaked child processes")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn runs_audit_children_without_leaks() {
+    rust_parallel()
+        .arg("--audit-children")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn runs_memsuspend() {
+    rust_parallel()
+        .arg("-j2")
+        .arg("--memsuspend=1000T")
+        .arg("sleep")
+        .arg(":::")
+        .arg("1.5")
+        .arg("1.5")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("suspending job pid")
+                .count(1)
+                .and(predicate::str::contains("resuming job pid").count(1)),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_adaptive_jobs() {
+    rust_parallel()
+        .arg("-j4")
+        .arg("--adaptive-jobs")
+        .arg("false")
+        .arg(":::")
+        .args(["A", "B", "C", "D", "E", "F"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "adaptive jobs: lowering concurrency from 4 to 2: command failed",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_admit_cmd_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg(r#"--admit-cmd=test "$PARALLEL_INPUT" != B"#)
+        .arg("--on-admit-fail=skip")
+        .arg("echo")
+        .arg(":::")
+        .args(["A", "B", "C"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("A\n")
+                .and(predicate::str::contains("C\n"))
+                .and(predicate::str::contains("B\n").not())
+                .and(predicate::str::contains(
+                    "skipped 1 commands by --admit-cmd",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("-j1")
+        .arg(r#"--admit-cmd=test -e "$PARALLEL_INPUT" || { touch "$PARALLEL_INPUT"; false; }"#)
+        .arg("--admit-retry-seconds=0.1")
+        .arg("rm")
+        .arg(":::")
+        .arg(std::env::temp_dir().join(format!("rust_parallel_admit_{}", std::process::id())))
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_schedule_sjf_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("-s")
+        .arg("--schedule=sjf")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("--job-cost={1}")
+        .arg("{2}")
+        .arg(":::")
+        .args(["0,sleep 0.3", "3,echo c", "1,echo a", "2,echo b"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("a\nb\nc\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_schedule_fair_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--schedule=fair")
+        .arg("--expect-jobs=9")
+        .arg("-i")
+        .arg("linked_file.txt")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            // the files take turns, starting with whichever file was read first
+            predicate::str::is_match(
+                "^(1\nhello\n2\nfrom\n3\ninput\n4\nfile\n|hello\n1\nfrom\n2\ninput\n3\nfile\n4\n)5\n$",
+            )
+            .unwrap(),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_schedule_sjf_without_job_cost() {
+    rust_parallel()
+        .arg("--schedule=sjf")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("--job-cost <TEMPLATE>"));
+}
+
+#[test]
+fn runs_use_history_j1() {
+    let history_file = std::env::temp_dir().join(format!(
+        "rust_parallel_use_history_j1_{}.json",
+        std::process::id()
+    ));
+    std::fs::write(&history_file, r#"{"a": 1.0, "b": 3.0, "c": 2.0}"#).unwrap();
+
+    rust_parallel()
+        .arg("-j1")
+        // all inputs are queued before the first command starts
+        .arg("--expect-jobs=4")
+        .arg("--use-history")
+        .arg("--history-file")
+        .arg(&history_file)
+        .arg("--history-key={}")
+        .arg("echo")
+        .arg(":::")
+        .args(["a", "b", "c", "d"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("d\nb\nc\na\n"))
+        .stderr(predicate::str::is_empty());
+
+    let history = std::fs::read_to_string(&history_file).unwrap();
+    std::fs::remove_file(&history_file).unwrap();
+
+    assert!(history.contains(r#""d":"#));
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_speculative() {
+    let marker =
+        std::env::temp_dir().join(format!("rust_parallel_speculative_{}", std::process::id()));
+
+    // the first run of the slow input hangs, a speculative copy finishes right away
+    let script = format!(
+        r#"if [ "$1" = slow ] && mkdir "{}" 2>/dev/null; then exec sleep 30; fi; echo "$1 done""#,
+        marker.display()
+    );
+
+    rust_parallel()
+        .arg("-j10")
+        .arg("--speculative")
+        .args(["sh", "-c", &script, "sh"])
+        .arg(":::")
+        .args(["1", "2", "3", "4", "5", "6", "7", "8", "9", "slow"])
+        .timeout(std::time::Duration::from_secs(20))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("slow done"))
+        .stdout(predicate::str::contains(
+            "speculatively re-running straggler command",
+        ))
+        .stdout(predicate::str::contains(
+            "speculatively re-ran 1 straggler commands, 1 copies finished first",
+        ))
+        .stderr(predicate::str::is_empty());
+
+    std::fs::remove_dir(&marker).unwrap();
+}
+
+/// Serve one canned HTTP response per connection, in order.  Returns the server address.
+fn spawn_http_server(responses: Vec<&'static str>) -> std::net::SocketAddr {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let _ = write!(
+                stream,
+                "{}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+                response
+            );
+        }
+    });
+
+    address
+}
+
+#[test]
+fn runs_fetch() {
+    let address = spawn_http_server(vec!["HTTP/1.1 503 Service Unavailable", "HTTP/1.1 200 OK"]);
+
+    rust_parallel()
+        .arg("--fetch")
+        .arg(":::")
+        .arg(format!("http://{}/file", address))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("retry 1/3"))
+        .stdout(predicate::str::ends_with("hello"))
+        .stderr(predicate::str::is_empty());
+
+    let output_file =
+        std::env::temp_dir().join(format!("rust_parallel_fetch_{}", std::process::id()));
+    let address = spawn_http_server(vec!["HTTP/1.1 200 OK"]);
+
+    rust_parallel()
+        .write_stdin(format!(
+            "http://{}/file,{}\n",
+            address,
+            output_file.display()
+        ))
+        .arg("--fetch")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("{1}")
+        .arg("{2}")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+
+    assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "hello");
+    std::fs::remove_file(&output_file).unwrap();
+}
+
+#[test]
+fn fails_fetch_not_found() {
+    let address = spawn_http_server(vec!["HTTP/1.1 404 Not Found"]);
+
+    rust_parallel()
+        .arg("--fetch")
+        .arg(":::")
+        .arg(format!("http://{}/missing", address))
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("exit_status=1"))
+        .stderr(predicate::str::contains("returned 404 Not Found"));
+}
+
+#[test]
+fn runs_dry_run_with_substitutions() {
+    rust_parallel()
+        .arg("--dry-run")
+        .arg("-s")
+        .arg("-r")
+        .arg("(?P<first>.*),(.*)")
+        .arg("echo {first} {2}")
+        .arg(":::")
+        .arg("a,b")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r#"cmd="/bin/bash",args=["-c", "echo a b"],line=command_line_args:1 input="a,b" substitutions=[{2}="b", {first}="a"]"#,
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_raw_exec() {
+    rust_parallel()
+        .arg("--raw-exec")
+        .arg("--dry-run")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("/bin/echo")
+        .arg("{1}")
+        .arg(":::")
+        .arg(r#"a "b",c"#)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r#"argv=["/bin/echo","a \"b\""] line=command_line_args:1"#,
+        ))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("--raw-exec")
+        .arg("echo")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\nB\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_raw_exec_not_executable() {
+    rust_parallel()
+        .arg("--raw-exec")
+        .arg("./file.txt")
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r#"error resolving path "./file.txt": file is not executable"#,
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_explain_auto_regex() {
+    rust_parallel()
+        .arg("--explain")
+        .arg("-s")
+        .arg("echo {2} {1}")
+        .arg(":::")
+        .arg("A")
+        .arg("B")
+        .arg(":::")
+        .arg("1")
+        .arg("2")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains(
+                r#"explain: auto-generated regex from 2 ::: argument groups: "(.*) (.*)""#,
+            )
+            .and(predicate::str::contains(
+                "explain: capture groups: 2 match keys: {} {0} {1} {2}",
+            ))
+            .and(predicate::str::contains(
+                r#"substitutions=[{1}="A", {2}="1"]"#,
+            ))
+            .and(predicate::str::contains("substitutions=").count(3))
+            .and(predicate::str::contains("A\n").not()),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_on_no_match_fail_j1() {
+    rust_parallel()
+        .write_stdin("a,1\nbad\nb,2\n")
+        .arg("-j1")
+        .arg("--on-no-match=fail")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("echo")
+        .arg("{2}")
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("regex did not match input data: bad")
+                .and(predicate::str::contains(
+                    "input did not match regex with --on-no-match=fail",
+                ))
+                .and(predicate::str::contains("2\n").not()),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_on_no_match_skip_and_run_unmodified_j1() {
+    rust_parallel()
+        .write_stdin("a,1\nbad\n")
+        .arg("-j1")
+        .arg("--on-no-match=skip")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("echo")
+        .arg("{2}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n"))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .write_stdin("a,1\nbad\n")
+        .arg("-j1")
+        .arg("--on-no-match=run-unmodified")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("echo")
+        .arg("{2}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n{2}\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_strict_placeholders_unknown_placeholder() {
+    rust_parallel()
+        .write_stdin("a,1\n")
+        .arg("--strict-placeholders")
+        .arg("-r")
+        .arg("(?P<file>.*),(.*)")
+        .arg("echo")
+        .arg("{flie}")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "unknown placeholders in command template with --strict-placeholders: {flie}",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_strict_placeholders_j1() {
+    rust_parallel()
+        .write_stdin("a,1\nb,2\n")
+        .arg("-j1")
+        .arg("--strict-placeholders")
+        .arg("-r")
+        .arg("(?P<file>.*),(.*)")
+        .arg("echo")
+        .arg("{file}")
+        .arg("{2}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("a 1\nb 2\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_regex_format_specs_j1() {
+    rust_parallel()
+        .write_stdin("7,2024-01-31\n12,20240201\n")
+        .arg("-j1")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("echo")
+        .arg("out-{1:03}-{2:%Y%m%d}.txt")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "out-007-20240131.txt\nout-012-20240201.txt\n",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_regex_expressions_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("echo")
+        .arg("{= {1} * 2 + 100 =}")
+        .arg(":::")
+        .arg("1")
+        .arg("2")
+        .assert()
+        .success()
+        .stdout(predicate::eq("102\n104\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_invalid_expression() {
+    rust_parallel()
+        .arg("-r")
+        .arg("(.*)")
+        .arg("echo")
+        .arg("{= {1} ** 2 =}")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "invalid placeholder {= {1} ** 2 =}",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_script_j1() {
+    rust_parallel()
+        .write_stdin("a\n#b\nc\n")
+        .arg("-j1")
+        .arg("--script=job_script.rhai")
+        .arg("/bin/bash")
+        .arg("-c")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::starts_with("hello a\nhello c\n")
+                .and(predicate::str::contains("skipped 1 commands by script")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_job_options_column_j1() {
+    rust_parallel()
+        .write_stdin("env.TAG=web\ta\nb\ntimeout=0.5 env.TAG=db\tc\nretries=2\td\n")
+        .arg("-j1")
+        .arg("--job-options-column")
+        .arg("-s")
+        .arg("--shell-path=/bin/sh")
+        .arg("echo \"$TAG\"")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("web a\n b\ndb c\n")
+                .and(predicate::str::contains("invalid job options column")),
+        )
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .write_stdin("timeout=0.5\tsleep 5\n")
+        .arg("--job-options-column")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("command timed out"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_script_without_job_function() {
+    rust_parallel()
+        .arg("--script=template.txt")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("error compiling script"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_placeholder_cmd_j1() {
+    rust_parallel()
+        .write_stdin("a\nb\n")
+        .arg("-j1")
+        .arg("--strict-placeholders")
+        .arg("--placeholder-cmd=upper=tr a-z A-Z")
+        .arg("echo")
+        .arg("{upper}")
+        .arg("{upper:x}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A X a\nB X b\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_dry_run_redact() {
+    rust_parallel()
+        .env("SECRET_TOKEN", "tok-456")
+        .arg("--dry-run")
+        .arg("--redact=secret123")
+        .arg("--redact-env=SECRET_TOKEN")
+        .arg("curl")
+        .arg("-H")
+        .arg("token: tok-456")
+        .arg(":::")
+        .arg("secret123")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("secret123")
+                .not()
+                .and(predicate::str::contains("tok-456").not())
+                .and(predicate::str::contains("\"token: ***\", \"***\"")),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_preprocess_j1() {
+    rust_parallel()
+        .write_stdin("b\n#skip\na\n")
+        .arg("-j1")
+        .arg("--preprocess=grep -v '^#' | sort")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq("a\nb\n"))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("--preprocess=head -n 1")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq("hello\n"))
+        .stderr(predicate::str::is_empty());
+
+    // compressed inputs are decompressed before the preprocess command reads them
+    rust_parallel()
+        .arg("-j1")
+        .arg("-i")
+        .arg("file.txt.gz")
+        .arg("--preprocess=grep -v from")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq("hello\ninput\nfile\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_preprocess_command_failed_warning() {
+    rust_parallel()
+        .write_stdin("a\n")
+        .arg("--preprocess=cat; exit 2")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("a\n").and(predicate::str::contains(
+                "preprocess command failed for input stdin: exit_status=2",
+            )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_input_cmd_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--input-cmd=printf 'A\\nB\\n'")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq("A\nB\n"))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("--input-cmd=echo A; exit 3")
+        .arg("--on-input-cmd-fail=warn")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("A\n").and(predicate::str::contains(
+                r#"input command "echo A; exit 3" failed: exit_status=3"#,
+            )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_input_cmd_restart_j1() {
+    let marker =
+        std::env::temp_dir().join(format!("rust_parallel_input_cmd_{}", std::process::id()));
+
+    // the first run of the input command fails after one line
+    let input_cmd = format!(
+        r#"if mkdir "{}" 2>/dev/null; then echo A; exit 1; fi; echo B"#,
+        marker.display()
+    );
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("--input-cmd")
+        .arg(&input_cmd)
+        .arg("--on-input-cmd-fail=restart")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("A\n")
+                .and(predicate::str::contains("restarting in 1s"))
+                .and(predicate::str::ends_with("B\n")),
+        )
+        .stderr(predicate::str::is_empty());
+
+    std::fs::remove_dir(&marker).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn fails_input_cmd_failed() {
+    rust_parallel()
+        .arg("--input-cmd=echo A; exit 3")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            r#"input command "echo A; exit 3" failed: exit_status=3"#,
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_max_line_bytes_j1() {
+    rust_parallel()
+        .write_stdin("abc\nabcdefgh\nxyz\n")
+        .arg("-j1")
+        .arg("--max-line-bytes=4")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("abc\nxyz\n").and(predicate::str::contains(
+                "skipping input line stdin:2 longer than --max-line-bytes 4",
+            )),
+        )
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .write_stdin("abc\nabcdefgh\nxyz\n")
+        .arg("-j1")
+        .arg("--max-line-bytes=4")
+        .arg("--on-oversized-line=truncate")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("abc\nabcd\nxyz\n").and(predicate::str::contains(
+                "truncating input line stdin:2 to --max-line-bytes 4",
+            )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_max_line_bytes_oversized_line_error() {
+    rust_parallel()
+        .write_stdin("abc\nabcdefgh\nxyz\n")
+        .arg("-j1")
+        .arg("--max-line-bytes=4")
+        .arg("--on-oversized-line=error")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("input line stdin:2 is longer than --max-line-bytes 4")
+                .and(predicate::str::contains("xyz").not()),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_skipped_input_summary_j1() {
+    rust_parallel()
+        .write_stdin(b"a,1\n\nnomatch\n\xff,2\nb,2\n".as_slice())
+        .arg("-j1")
+        .arg("--no-run-if-empty")
+        .arg("--progress-json=/dev/null")
+        .arg("-r")
+        .arg("(.*),(.*)")
+        .arg("echo")
+        .arg("{1}")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("a\nb\n").and(predicate::str::contains(
+                "skipped 3 input lines: empty=1 invalid_utf8=1 unmatched=1 filtered=0",
+            )),
+        )
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_job_tmpdir_j1() {
+    let assert = rust_parallel()
+        .arg("-j1")
+        .arg("--job-tmpdir")
+        .arg("-s")
+        .arg(r#"test -d {tmpdir} && test "$PARALLEL_TMPDIR" = {tmpdir} && echo {tmpdir}"#)
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let tmpdir = stdout.trim_end();
+    assert!(tmpdir.contains("rust-parallel-"));
+    assert!(!std::path::Path::new(tmpdir).exists());
+}
+
+#[test]
+fn runs_job_tmpdir_keep_tmp_on_fail_j1() {
+    let assert = rust_parallel()
+        .arg("-j1")
+        .arg("--job-tmpdir")
+        .arg("--keep-tmp-on-fail")
+        .arg("-s")
+        .arg(r#"echo "$PARALLEL_TMPDIR"; false"#)
+        .arg(":::")
+        .arg("A")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("keeping job tmpdir"))
+        .stderr(predicate::str::is_empty());
+
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let tmpdir = stdout
+        .lines()
+        .find(|line| line.contains("rust-parallel-") && !line.contains("job tmpdir"))
+        .map(std::path::Path::new)
+        .unwrap();
+    assert!(tmpdir.is_dir());
+    std::fs::remove_dir(tmpdir).unwrap();
+}
+
+#[test]
+fn runs_link_input_files_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--link")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("-i")
+        .arg("linked_file.txt")
+        .arg("echo")
+        .arg("{2}-{1}")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("1-hello\n")
+                .and(predicate::str::contains("2-from\n3-input\n4-file\n"))
+                .and(predicate::str::contains("5-").not())
+                .and(predicate::str::contains(
+                    "linked input files have different numbers of lines, stopping at the shortest",
+                )),
+        )
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .arg("-j1")
+        .arg("--link")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("echo")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "hello hello\nfrom from\ninput input\nfile file\n",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_cross_input_files_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--keep-order")
+        .arg("--cross")
+        .arg("-i")
+        .arg("csv_file.txt")
+        .arg("-i")
+        .arg("file.txt")
+        .arg("echo")
+        .arg("{2}:{1}")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "hello:1,2,3\nfrom:1,2,3\ninput:1,2,3\nfile:1,2,3\nhello:foo,bar,baz\nfrom:foo,bar,baz\ninput:foo,bar,baz\nfile:foo,bar,baz\n",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_cross_and_link() {
+    rust_parallel()
+        .arg("--cross")
+        .arg("--link")
+        .arg("-i")
+        .arg("file.txt")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn runs_header_named_placeholders_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--header")
+        .arg("-i")
+        .arg("header_file.tsv")
+        .arg("echo")
+        .arg("{host}:{port}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("example.com:80\nexample.org:443\n"))
+        .stderr(predicate::str::is_empty());
+
+    rust_parallel()
+        .write_stdin("host\tport\nexample.net\t8080\n")
+        .arg("--header")
+        .arg("echo")
+        .arg("{port}/{host}")
+        .assert()
+        .success()
+        .stdout(predicate::eq("8080/example.net\n"))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn runs_header_cross_j1() {
+    rust_parallel()
+        .arg("-j1")
+        .arg("--keep-order")
+        .arg("--header")
+        .arg("--cross")
+        .arg("-i")
+        .arg("header_file.tsv")
+        .arg("-i")
+        .arg("header_users.tsv")
+        .arg("echo")
+        .arg("{user}@{host}:{port}")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "alice@example.com:80\nbob@example.com:80\nalice@example.org:443\nbob@example.org:443\n",
+        ))
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn fails_header_different_headers() {
+    rust_parallel()
+        .arg("--header")
+        .arg("-i")
+        .arg("header_file.tsv")
+        .arg("-i")
+        .arg("header_users.tsv")
+        .arg("echo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "input files have different headers",
+        ));
+}